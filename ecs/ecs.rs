@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::ecs::component::*;
 use crate::ecs::entity::*;
@@ -83,6 +83,10 @@ pub enum Delta {
 pub struct ECS {
     component_storage: ComponentManager,
     entity_storage: EntityManager,
+    // bumped whenever line of sight blockers change
+    los_generation: usize,
+    // bumped whenever terrain blockers or hazards change, monsters excluded
+    nav_generation: usize,
 }
 
 impl ECS {
@@ -90,6 +94,8 @@ impl ECS {
         ECS {
             component_storage: ComponentManager::new(),
             entity_storage: EntityManager::new(bsp_graph),
+            los_generation: 0,
+            nav_generation: 0,
         }
     }
 
@@ -103,7 +109,7 @@ impl ECS {
 
     pub fn spawn_all_entities(&mut self, map: &GameMap) {
         for room in map.graph.node_weights() {
-            room.spawn_entities(self, map.depth);
+            room.spawn_entities(self, map.depth, map.difficulty);
         }
         self.print_counts();
     }
@@ -127,6 +133,9 @@ impl ECS {
 
             self.entity_storage.set_entity_position(entity_id, new_position, old_position);
         }
+        if let Component::Size(size) = component {
+            self.entity_storage.set_entity_size(entity_id, size.data.extra_tiles.clone());
+        }
 
         self.entity_storage
             .add_component(entity_id, component.get_id());
@@ -143,6 +152,10 @@ impl ECS {
             self.entity_storage.set_new_player(entity_id);
         }
 
+        // spawning a sight blocker mid-floor goes around apply_change, the cached lines still need to know
+        if component.is_of_type(&ComponentType::LineOfSight) {
+            self.los_generation += 1;
+        }
         self.component_storage.assign_id(&mut component);
         self.attatch_component(entity_id, &mut component);
         self.component_storage.register_new(component);
@@ -188,6 +201,11 @@ impl ECS {
     }
 
     pub fn remove_component(&mut self, entity_id: usize, component_id: usize) {
+        match self.get_component(component_id) {
+            Some(Component::Position(_)) => self.entity_storage.clear_entity_position(entity_id),
+            Some(Component::Size(_)) => self.entity_storage.set_entity_size(entity_id, vec![]),
+            _ => {}
+        }
         self.entity_storage
             .remove_component(entity_id, component_id);
         self.component_storage.remove_component(component_id);
@@ -200,20 +218,23 @@ impl ECS {
     }
 
     pub fn get_entities_matching_query(&self, query: &ComponentQuery) -> Vec<&Entity> {
-        let required = &query.required;
-        let optional = &query.optional;
-        let mut output: Vec<&Entity> = Vec::new();
-
-        for entity in self.entity_storage.get_all_entities() {
-            let components = self.component_storage.get_components(entity);
-            let (mut required_matches, missed) = get_matching_components(components, &required);
-            if required_matches.len() == required.len() {
-                let (mut optional_matches, _) = get_matching_components(missed, &optional);
-                required_matches.append(&mut optional_matches);
-                output.push(entity);
-            }
-        }
-        output
+        self.iter_entities_matching(query).collect()
+    }
+
+    // Optional components don't affect matching, they only matter to whoever reads the entity.
+    pub fn iter_entities_matching<'a, 'q>(
+        &'a self,
+        query: &'q ComponentQuery,
+    ) -> impl Iterator<Item = &'a Entity> + use<'a, 'q> {
+        self.entity_storage.iter_entities().filter(|entity| {
+            query.required.iter().all(|requirement| {
+                entity.data.iter().any(|id| {
+                    self.component_storage
+                        .get_component(id)
+                        .is_some_and(|component| ComponentType::from(component) == *requirement)
+                })
+            })
+        })
     }
 
     pub fn get_entity(&self, entity_id: usize) -> Option<&Entity> {
@@ -264,6 +285,11 @@ impl ECS {
             .copied()
     }
 
+    pub fn get_typed<T: ComponentData>(&self, entity_id: usize) -> Option<&IndexedData<T>> {
+        self.get_component_from_entity_id(entity_id, T::TYPE)
+            .and_then(T::from_component)
+    }
+
     pub fn get_component_from_entity(
         &self,
         entity: &Entity,
@@ -285,7 +311,7 @@ impl ECS {
 
     pub fn get_los_blocking_entity(&self, coord: Coordinate) -> Option<usize> {
         self.entity_storage
-            .get_entities_at_position(coord, &self.component_storage)
+            .get_entities_at_position(coord)
             .iter()
             .find_map(|entity| {
                 self.component_storage
@@ -327,13 +353,14 @@ impl ECS {
         match self.get_blocking_entity(coord) {
             Some(entity_id) => {
                 let components = self.get_components_from_entity_id(entity_id);
-                if let (Some(Component::Door(_)), _) =
-                    take_component_from_refs(ComponentType::Door, &components)
-                {
-                    true
-                } else {
-                    false
-                }
+                // undiscovered secret doors count as doors too
+                matches!(
+                    take_component_from_refs(ComponentType::Door, &components),
+                    (Some(Component::Door(_)), _)
+                ) || matches!(
+                    take_component_from_refs(ComponentType::Secret, &components),
+                    (Some(Component::Secret(_)), _)
+                )
             }
             None => false,
         }
@@ -346,6 +373,12 @@ impl ECS {
         }
     }
 
+    pub fn get_corpse(&self, coord: Coordinate) -> Option<(usize, CorpseKind)> {
+        self.get_all_entities_in_tile(coord)
+            .into_iter()
+            .find_map(|entity_id| self.get_typed::<CorpseKind>(entity_id).map(|kind| (entity_id, kind.data)))
+    }
+
     pub fn has_hazard(&self, coord: Coordinate) -> bool {
         match self.get_hazard_entity(coord) {
             Some(_) => true,
@@ -355,7 +388,7 @@ impl ECS {
 
     pub fn get_blocking_entity(&self, coord: Coordinate) -> Option<usize> {
         self.entity_storage
-            .get_entities_at_position(coord, &self.component_storage)
+            .get_entities_at_position(coord)
             .iter()
             .find_map(|entity| {
                 self.component_storage
@@ -372,9 +405,27 @@ impl ECS {
             })
     }
 
+    pub fn get_footprint(&self, entity_id: usize, position: Coordinate) -> Vec<Coordinate> {
+        self.entity_storage.get_footprint(entity_id, position)
+    }
+
+    pub fn is_blocked_for_entity(&self, coord: Coordinate, entity_id: usize) -> bool {
+        // like is_blocked_by_entity, but a large entity never blocks itself
+        self.entity_storage
+            .get_entities_at_position(coord)
+            .iter()
+            .filter(|entity| entity.index != entity_id)
+            .any(|entity| {
+                self.component_storage
+                    .get_components(entity)
+                    .iter()
+                    .any(|comp| matches!(comp, Component::Collision(data) if data.data == Collision::Blocking))
+            })
+    }
+
     pub fn get_hazard_entity(&self, coord: Coordinate) -> Option<usize> {
         self.entity_storage
-            .get_entities_at_position(coord, &self.component_storage)
+            .get_entities_at_position(coord)
             .iter()
             .find_map(|entity| {
                 self.component_storage
@@ -400,32 +451,56 @@ impl ECS {
 
     pub fn get_all_entities_in_tile(&self, coord: Coordinate) -> Vec<usize> {
         self.entity_storage
-            .get_entities_at_position(coord, &self.component_storage)
+            .get_entities_at_position(coord)
             .iter()
             .map(|entity| entity.index)
             .collect()
     }
 
     pub fn get_all_adjacent_entities(&self, coord: Coordinate) -> Vec<usize> {
-        let adjacent = [
-            coord + map::utils::UP,
-            coord + map::utils::DOWN,
-            coord + map::utils::LEFT,
-            coord + map::utils::RIGHT,
-        ];
-        adjacent
-            .iter()
-            .map(|pos| self.get_all_entities_in_tile(*pos))
+        map::utils::orthogonal_neighbors(coord)
+            .into_iter()
+            .map(|pos| self.get_all_entities_in_tile(pos))
             .flatten()
             .collect()
     }
 
+    // Entities on any tile within radius of center, nearest first.
+    pub fn get_entities_in_radius(&self, center: Coordinate, radius: f32) -> Vec<usize> {
+        if radius < 0.0 {
+            return vec![];
+        }
+        let reach = radius.floor() as i32;
+        let mut closest: HashMap<usize, f32> = HashMap::new();
+        for x in center.x - reach..=center.x + reach {
+            for y in center.y - reach..=center.y + reach {
+                let coord = Coordinate { x, y };
+                let distance = center.distance(coord);
+                if distance > radius {
+                    continue;
+                }
+                for entity_id in self.get_all_entities_in_tile(coord) {
+                    // large entities show up on several tiles, keep the closest one
+                    closest
+                        .entry(entity_id)
+                        .and_modify(|nearest| *nearest = nearest.min(distance))
+                        .or_insert(distance);
+                }
+            }
+        }
+        let mut found: Vec<(usize, f32)> = closest.into_iter().collect();
+        found.sort_by(|(a_id, a), (b_id, b)| a.total_cmp(b).then(a_id.cmp(b_id)));
+        found.into_iter().map(|(entity_id, _)| entity_id).collect()
+    }
+
     pub fn get_all_components(&self, comp_type: &ComponentType) -> Vec<&Component> {
         self.component_storage.get_all_components(comp_type)
     }
 
     pub fn get_player_attacks(&self) -> (Option<Attack>, Option<Attack>) {
-        let player_entity = self.entity_storage.get_player_entity().unwrap();
+        let Some(player_entity) = self.entity_storage.get_player_entity() else {
+            return (None, None);
+        };
         let player_components = self.get_components_from_entity_id(player_entity.index);
         if let (Some(Component::Combat(combat)), _) =
             take_component_from_refs(ComponentType::Combat, &player_components)
@@ -464,7 +539,7 @@ impl ECS {
     }
 
     pub fn get_player_position(&self) -> Option<Coordinate> {
-        let player_entity = self.entity_storage.get_player_entity().unwrap();
+        let player_entity = self.entity_storage.get_player_entity()?;
         let player_components = self.get_components_from_entity_id(player_entity.index);
 
         let (maybe_position, _) =
@@ -476,7 +551,9 @@ impl ECS {
     }
 
     pub fn set_player_position(&mut self, coord: Coordinate) {
-        let player_entity = self.entity_storage.get_player_entity().unwrap();
+        let Some(player_entity) = self.entity_storage.get_player_entity() else {
+            return;
+        };
         let player_components = self.get_components_from_entity_id(player_entity.index);
 
         let (maybe_position, _) =
@@ -488,7 +565,77 @@ impl ECS {
         }
     }
 
+    pub fn get_los_generation(&self) -> usize {
+        self.los_generation
+    }
+
+    fn changes_los(&self, change: &Delta) -> bool {
+        match change {
+            Delta::Change(Component::LineOfSight(_)) => true,
+            Delta::Change(Component::Position(position)) => self
+                .get_entity_id_from_component_id(position.index)
+                .is_some_and(|entity_id| self.entity_id_has_component(entity_id, ComponentType::LineOfSight)),
+            Delta::Change(_) => false,
+            Delta::MakeComponent(MakeComponentOrder { component, .. }) => {
+                matches!(component, Component::LineOfSight(_))
+            }
+            Delta::MakeEntity(MakeEntityOrder { components }) => components
+                .iter()
+                .any(|component| matches!(component, Component::LineOfSight(_))),
+            Delta::DeleteComponent(DeleteComponentOrder { component_id, .. }) => {
+                matches!(self.get_component(*component_id), Some(Component::LineOfSight(_)))
+            }
+            Delta::DeleteEntity(DeleteEntityOrder { entity }) => self
+                .get_entity_id_from_identifier(*entity)
+                .is_some_and(|entity_id| self.entity_id_has_component(entity_id, ComponentType::LineOfSight)),
+        }
+    }
+
+    // The map's own tiles changed, anything cached against the old layout is stale.
+    pub fn mark_terrain_changed(&mut self) {
+        self.los_generation += 1;
+        self.nav_generation += 1;
+    }
+
+    pub fn get_nav_generation(&self) -> usize {
+        self.nav_generation
+    }
+
+    fn is_nav_obstacle(&self, entity_id: usize) -> bool {
+        self.entity_id_has_component(entity_id, ComponentType::Collision)
+            && !self.entity_id_has_component(entity_id, ComponentType::Monster)
+    }
+
+    fn changes_navigation(&self, change: &Delta) -> bool {
+        match change {
+            Delta::Change(Component::Collision(_)) => true,
+            Delta::Change(Component::Position(position)) => self
+                .get_entity_id_from_component_id(position.index)
+                .is_some_and(|entity_id| self.is_nav_obstacle(entity_id)),
+            Delta::Change(_) => false,
+            Delta::MakeComponent(MakeComponentOrder { component, .. }) => {
+                matches!(component, Component::Collision(_))
+            }
+            Delta::MakeEntity(MakeEntityOrder { components }) => {
+                components.iter().any(|component| matches!(component, Component::Collision(_)))
+                    && !components.iter().any(|component| matches!(component, Component::Monster(_)))
+            }
+            Delta::DeleteComponent(DeleteComponentOrder { component_id, .. }) => {
+                matches!(self.get_component(*component_id), Some(Component::Collision(_)))
+            }
+            Delta::DeleteEntity(DeleteEntityOrder { entity }) => self
+                .get_entity_id_from_identifier(*entity)
+                .is_some_and(|entity_id| self.is_nav_obstacle(entity_id)),
+        }
+    }
+
     pub fn apply_change(&mut self, change: Delta) {
+        if self.changes_los(&change) {
+            self.los_generation += 1;
+        }
+        if self.changes_navigation(&change) {
+            self.nav_generation += 1;
+        }
         match change {
             Delta::Change(component) => {
                 if let Component::Position(indexed_position) = &component {
@@ -542,15 +689,18 @@ impl ECS {
         }
     }
 
-    fn add_component(&mut self, component: Component, entity: EntityIdentifier) {
+    // Returns whether the entity was there to take the component.
+    fn add_component(&mut self, component: Component, entity: EntityIdentifier) -> bool {
         if let Some(entity_id) = self.get_entity_id_from_identifier(entity) {
             self.add_component_to_entity(entity_id, component);
+            true
         } else {
             dbg!("Cannot find entity while adding component", entity);
+            false
         }
     }
 
-    fn get_entity_id_from_identifier(&self, entity: EntityIdentifier) -> Option<usize> {
+    pub fn get_entity_id_from_identifier(&self, entity: EntityIdentifier) -> Option<usize> {
         let EntityIdentifier {
             owned_component_id,
             entity_id,
@@ -611,3 +761,161 @@ impl IndexedData<HashSet<usize>> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{core::Game, difficulty::Difficulty, testing};
+
+    // The slow way, straight from every position component.
+    fn scan_positions(ecs: &ECS) -> HashMap<Coordinate, HashSet<usize>> {
+        let mut tiles: HashMap<Coordinate, HashSet<usize>> = HashMap::new();
+        let query = ComponentQuery::new_single(ComponentType::Position);
+        for entity in ecs.get_entities_matching_query(&query) {
+            let position = ecs.get_typed::<Coordinate>(entity.index).unwrap().data;
+            for tile in ecs.get_footprint(entity.index, position) {
+                tiles.entry(tile).or_default().insert(entity.index);
+            }
+        }
+        tiles
+    }
+
+    fn assert_index_matches_scan(ecs: &ECS, map: &GameMap) {
+        let scanned = scan_positions(ecs);
+        for x in -1..=map.width as i32 {
+            for y in -1..=map.height as i32 {
+                let tile = Coordinate { x, y };
+                let indexed: HashSet<usize> = ecs.get_all_entities_in_tile(tile).into_iter().collect();
+                assert_eq!(indexed, scanned.get(&tile).cloned().unwrap_or_default(), "{:?}", tile);
+            }
+        }
+    }
+
+    #[test]
+    fn query_builder_matches_the_legacy_struct() {
+        let game = Game::new(32, 18, Difficulty::default());
+        let built = ComponentQuery::new()
+            .require(ComponentType::Position)
+            .require(ComponentType::Health)
+            .optional(ComponentType::Image);
+        let legacy = ComponentQuery {
+            required: vec![ComponentType::Position, ComponentType::Health],
+            optional: vec![ComponentType::Image],
+        };
+        let matched = |query: &ComponentQuery| -> Vec<usize> {
+            game.ecs.get_entities_matching_query(query).iter().map(|entity| entity.index).collect()
+        };
+        let iterated: Vec<usize> = game.ecs.iter_entities_matching(&built).map(|entity| entity.index).collect();
+        assert!(!iterated.is_empty());
+        assert_eq!(matched(&built), matched(&legacy));
+        assert_eq!(iterated, matched(&legacy));
+    }
+
+    #[test]
+    fn position_index_matches_scan() {
+        let mut game = Game::new(32, 18, Difficulty::default());
+        assert_index_matches_scan(&game.ecs, &game.map);
+
+        let player_id = game.ecs.get_player_id();
+        let query = ComponentQuery::new_single(ComponentType::Turn);
+        let movers: Vec<usize> = game
+            .ecs
+            .get_entities_matching_query(&query)
+            .into_iter()
+            .map(|entity| entity.index)
+            .filter(|&entity_id| entity_id != player_id)
+            .collect();
+        assert!(!movers.is_empty());
+
+        for (step, &entity_id) in movers.iter().enumerate() {
+            let position = game.ecs.get_typed::<Coordinate>(entity_id).unwrap();
+            let offset = Coordinate { x: 1, y: (step % 2) as i32 };
+            game.ecs.apply_change(Delta::Change(Component::Position(position.make_change(offset))));
+        }
+        assert_index_matches_scan(&game.ecs, &game.map);
+
+        for &entity_id in movers.iter().step_by(2) {
+            game.ecs.apply_change(Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(entity_id)));
+        }
+        assert_index_matches_scan(&game.ecs, &game.map);
+
+        // recycled ids must not pick up their old tiles
+        let position = IndexedData::new_with(Coordinate { x: 2, y: 2 });
+        game.ecs.apply_change(Delta::MakeEntity(MakeEntityOrder {
+            components: vec![Component::Position(position)],
+        }));
+        assert_index_matches_scan(&game.ecs, &game.map);
+    }
+
+    #[test]
+    fn freed_ids_get_reused() {
+        let mut game = Game::new(32, 18, Difficulty::default());
+        let tile = Coordinate { x: 2, y: 2 };
+        let count = game.ecs.entity_storage.get_entity_count();
+        for _ in 0..1000 {
+            let before: HashSet<usize> = game.ecs.get_all_entities_in_tile(tile).into_iter().collect();
+            game.ecs.apply_change(Delta::MakeEntity(MakeEntityOrder {
+                components: vec![Component::Position(IndexedData::new_with(tile))],
+            }));
+            for entity_id in game.ecs.get_all_entities_in_tile(tile) {
+                if !before.contains(&entity_id) {
+                    game.ecs.apply_change(Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(entity_id)));
+                }
+            }
+        }
+        assert!(game.ecs.entity_storage.get_entity_count() <= count + 1);
+    }
+
+    #[test]
+    fn radius_queries_sort_by_distance() {
+        let mut game = testing::arena();
+        let center = Coordinate { x: 10, y: 8 };
+        let on_center = testing::spawn(&mut game, "Gold", center);
+        let beside = testing::spawn(&mut game, "Gold", center + Coordinate { x: 1, y: 0 });
+        let diagonal = testing::spawn(&mut game, "Gold", center + Coordinate { x: 1, y: 1 });
+        let far = testing::spawn(&mut game, "Gold", center + Coordinate { x: 0, y: 3 });
+
+        assert_eq!(game.ecs.get_entities_in_radius(center, 0.0), vec![on_center]);
+        assert_eq!(game.ecs.get_entities_in_radius(center, 1.0), vec![on_center, beside]);
+        // the diagonal sits at ~1.41
+        assert_eq!(game.ecs.get_entities_in_radius(center, 1.5), vec![on_center, beside, diagonal]);
+        assert_eq!(game.ecs.get_entities_in_radius(center, 3.0), vec![on_center, beside, diagonal, far]);
+        assert!(game.ecs.get_entities_in_radius(center, -1.0).is_empty());
+    }
+
+    #[test]
+    fn radius_queries_list_large_entities_once() {
+        let mut game = testing::arena();
+        let anchor = Coordinate { x: 10, y: 8 };
+        let colossus = testing::spawn(&mut game, "Stone colossus", anchor);
+        assert_eq!(game.ecs.get_entities_in_radius(anchor, 2.0), vec![colossus]);
+        // the closest covered tile counts
+        assert_eq!(game.ecs.get_entities_in_radius(anchor + Coordinate { x: 2, y: 0 }, 1.0), vec![colossus]);
+    }
+
+    #[test]
+    fn player_lookups_survive_a_missing_player() {
+        let mut game = testing::arena();
+        game.ecs.remove_entity(game.ecs.get_player_id());
+
+        assert_eq!(game.ecs.get_player_position(), None);
+        assert!(matches!(game.ecs.get_player_attacks(), (None, None)));
+        assert!(game.ecs.get_player_report().is_none());
+        assert!(game.ecs.get_player_spells().is_empty());
+        game.ecs.set_player_position(testing::PLAYER_START);
+        game.get_player_info();
+        game.get_visible_tiles();
+        game.get_minimap();
+    }
+
+    #[test]
+    fn adding_components_only_complains_about_missing_entities() {
+        let mut game = testing::arena();
+        let player_id = game.ecs.get_player_id();
+        let component = || Component::DurationEffect(IndexedData::new_with(DurationEffect(3, EffectType::Haste)));
+
+        assert!(game.ecs.add_component(component(), EntityIdentifier::new_from_entity(player_id)));
+        assert!(game.ecs.entity_id_has_component(player_id, ComponentType::DurationEffect));
+        assert!(!game.ecs.add_component(component(), EntityIdentifier::new_from_component(usize::MAX)));
+    }
+}
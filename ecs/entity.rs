@@ -1,12 +1,14 @@
 use petgraph::{graph::NodeIndex, visit::IntoNodeReferences, Graph};
 
-use super::component::ComponentManager;
 use crate::{
     ecs::ecs::*,
     game::components::core::*,
     map::{boxextends::{BoxExtends, Room}, mapbuilder::RoomGraph, utils::Coordinate},
 };
-use std::collections::HashSet;
+use std::{
+    borrow::Borrow,
+    collections::{BTreeSet, HashMap, HashSet},
+};
 
 pub type Entity = IndexedData<HashSet<usize>>;
 pub type StorageGraph = Graph<StorageRoom, (), petgraph::Undirected>;
@@ -32,8 +34,11 @@ impl From<&Room> for StorageRoom {
 #[derive(Debug, Default)]
 pub struct EntityManager {
     entities: Vec<Entity>,
-    ids_to_reuse: Vec<usize>,
+    ids_to_reuse: BTreeSet<usize>,
     room_graph: StorageGraph,
+    position_index: HashMap<Coordinate, HashSet<usize>>,
+    entity_positions: HashMap<usize, Coordinate>,
+    entity_sizes: HashMap<usize, Vec<Coordinate>>,
     player_id: usize, // TODO: refactor as option type
 }
 
@@ -74,8 +79,8 @@ impl EntityManager {
             .map(|entity| entity.index)
     }
 
-    pub fn get_all_entities(&self) -> Vec<&Entity> {
-        self.entities.iter().map(|elem| elem).collect()
+    pub fn iter_entities(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter()
     }
 
     pub fn get_player_id(&self) -> usize {
@@ -86,31 +91,14 @@ impl EntityManager {
         self.entities.get(id)
     }
 
-    pub fn get_entities_at_position(
-        &self,
-        position: Coordinate,
-        component_manager: &ComponentManager,
-    ) -> Vec<&Entity> {
-        let room = self.get_room_at_coordinate(position);
-        room.entities
-            .iter()
-            .filter(|entity_id| {
-                self.entities[**entity_id]
-                    .data
-                    .iter()
-                    .find(|comp_id| {
-                        if let Some(Component::Position(data)) =
-                            component_manager.get_component(comp_id)
-                        {
-                            data.data == position
-                        } else {
-                            false
-                        }
-                    })
-                    .is_some()
-            })
-            .map(|entity_id| &self.entities[*entity_id]) 
-            .collect()
+    pub fn get_entities_at_position(&self, position: Coordinate) -> Vec<&Entity> {
+        match self.position_index.get(&position) {
+            Some(entity_ids) => entity_ids
+                .iter()
+                .filter_map(|entity_id| self.entities.get(*entity_id))
+                .collect(),
+            None => vec![],
+        }
     }
 
     pub(super) fn add_component(&mut self, id: usize, component_id: usize) {
@@ -133,6 +121,67 @@ impl EntityManager {
 
         let new_room = self.get_room_at_coordinate_mut(new_position);
         new_room.entities.insert(entity_id);
+
+        if let Some(indexed_position) = self.entity_positions.insert(entity_id, new_position) {
+            self.unindex_footprint(entity_id, indexed_position);
+        }
+        self.index_footprint(entity_id, new_position);
+    }
+
+    pub(super) fn clear_entity_position(&mut self, entity_id: usize) {
+        if let Some(position) = self.entity_positions.remove(&entity_id) {
+            self.get_room_at_coordinate_mut(position)
+                .entities
+                .remove(&entity_id);
+            self.unindex_footprint(entity_id, position);
+        }
+    }
+
+    pub(super) fn set_entity_size(&mut self, entity_id: usize, extra_tiles: Vec<Coordinate>) {
+        let position = self.entity_positions.get(&entity_id).copied();
+        if let Some(position) = position {
+            self.unindex_footprint(entity_id, position);
+        }
+        if extra_tiles.is_empty() {
+            self.entity_sizes.remove(&entity_id);
+        } else {
+            self.entity_sizes.insert(entity_id, extra_tiles);
+        }
+        if let Some(position) = position {
+            self.index_footprint(entity_id, position);
+        }
+    }
+
+    pub fn get_footprint(&self, entity_id: usize, position: Coordinate) -> Vec<Coordinate> {
+        let mut tiles = vec![position];
+        if let Some(extra_tiles) = self.entity_sizes.get(&entity_id) {
+            tiles.extend(extra_tiles.iter().map(|offset| position + *offset));
+        }
+        tiles
+    }
+
+    fn index_footprint(&mut self, entity_id: usize, position: Coordinate) {
+        for tile in self.get_footprint(entity_id, position) {
+            self.position_index
+                .entry(tile)
+                .or_default()
+                .insert(entity_id);
+        }
+    }
+
+    fn unindex_footprint(&mut self, entity_id: usize, position: Coordinate) {
+        for tile in self.get_footprint(entity_id, position) {
+            self.remove_from_position_index(entity_id, tile);
+        }
+    }
+
+    fn remove_from_position_index(&mut self, entity_id: usize, position: Coordinate) {
+        if let Some(entity_ids) = self.position_index.get_mut(&position) {
+            entity_ids.remove(&entity_id);
+            if entity_ids.is_empty() {
+                self.position_index.remove(&position);
+            }
+        }
     }
 
     pub fn get_room_at_coordinate(&self, coord: Coordinate) -> &StorageRoom {
@@ -175,9 +224,11 @@ impl EntityManager {
     }
 
     pub(super) fn remove_entity(&mut self, id: usize) {
+        self.clear_entity_position(id);
+        self.entity_sizes.remove(&id);
         if let Some(entity) = self.entities.get_mut(id) {
             entity.data = HashSet::new();
-            self.ids_to_reuse.push(entity.index);
+            self.ids_to_reuse.insert(entity.index);
         }
     }
 
@@ -201,30 +252,15 @@ impl EntityManager {
     }
 
     fn next_id(&mut self) -> usize {
-        self.entities.len()
-        // todo! make use of recycling system in ids_to_reuse
+        self.ids_to_reuse.pop_first().unwrap_or(self.entities.len())
     }
 }
 
-pub fn get_matching_components<'a>(
-    components: Vec<&'a Component>,
-    requirements: &[ComponentType],
-) -> (Vec<&'a Component>, Vec<&'a Component>) {
-    let mut requested_left: HashSet<&ComponentType> = requirements.into_iter().collect();
-    let mut matched: Vec<&Component> = Vec::new();
-    let mut missed: Vec<&Component> = Vec::new();
-
-    let mut collection = components.into_iter();
-    while let Some(component) = collection.next() {
-        let comp_type: ComponentType = component.into();
-        if requested_left.contains(&comp_type) {
-            requested_left.remove(&comp_type);
-            matched.push(component);
-        } else {
-            missed.push(component);
-        }
-    }
-    (matched, missed)
+// First component holding T, works on borrowed lists and event payloads alike.
+pub fn find_typed<T: ComponentData, C: Borrow<Component>>(components: &[C]) -> Option<&IndexedData<T>> {
+    components
+        .iter()
+        .find_map(|component| T::from_component(component.borrow()))
 }
 
 pub fn take_component_from_refs<'a>(
@@ -1,5 +1,6 @@
 use crate::ecs::ecs::*;
 use crate::ecs::entity::Entity;
+use crate::game::action::GameEvent;
 use crate::game::components::core::*;
 use crate::map::gamemap::GameMap;
 
@@ -10,9 +11,23 @@ pub struct ComponentQuery {
 }
 
 impl ComponentQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
     pub fn new_single(requirement: ComponentType) -> Self {
         Self { required: vec![requirement], optional: vec![] }
     }
+
+    pub fn require(mut self, requirement: ComponentType) -> Self {
+        self.required.push(requirement);
+        self
+    }
+
+    pub fn optional(mut self, option: ComponentType) -> Self {
+        self.optional.push(option);
+        self
+    }
 }
 
 impl Default for ComponentQuery {
@@ -30,6 +45,10 @@ pub trait System {
 
     fn run_pre_loop(&mut self, _ecs: &ECS, _map: &GameMap) {}
     fn new_floor_update(&mut self, _ecs: &ECS, _map: &GameMap) {}
+    // Anything outside listeners should hear about from the last run.
+    fn take_events(&mut self) -> Vec<GameEvent> {
+        vec![]
+    }
 }
 
 #[derive(Default)]
@@ -45,11 +64,7 @@ impl SystemManager {
 
     pub fn run_system(system: &mut Box<dyn System>, ecs: &mut ECS, map: &GameMap) {
         let query = system.get_requirements();
-        let matches: Vec<Entity> = ecs
-            .get_entities_matching_query(&query)
-            .iter()
-            .map(|&data| data.to_owned())
-            .collect();
+        let matches: Vec<Entity> = ecs.iter_entities_matching(&query).cloned().collect();
 
         system.run_pre_loop(ecs, map);
         for entity in matches {
@@ -65,10 +80,13 @@ impl SystemManager {
         }
     }
 
-    pub fn run_turn_systems(&mut self, ecs: &mut ECS, map: &GameMap) {
+    pub fn run_turn_systems(&mut self, ecs: &mut ECS, map: &GameMap) -> Vec<GameEvent> {
+        let mut events = vec![];
         for system in self.turn_systems.iter_mut() {
             Self::run_system(system, ecs, map);
+            events.extend(system.take_events());
         }
+        events
     }
 
     pub fn update_systems(&mut self, ecs: &ECS, map: &GameMap) {
@@ -0,0 +1,134 @@
+use crate::{
+    ecs::event::EventType,
+    game::components::{core::EffectType, inventory::EquipSlot, stats::RunStats},
+    map::utils::Coordinate,
+};
+
+// Mirrors the frontend's InputCommand without depending on slint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerAction {
+    Direction(Coordinate),
+    Position(Coordinate),
+    Travel,
+    AutoExplore,
+    Shoot(Coordinate),
+    Throw(Coordinate),
+    Wait,
+    Undo,
+    Pickup,
+    Rest,
+    Quit,
+    Restart,
+    LevelUp(i32, i32), // stat choice, amount
+    Descend,
+    CloseDoors,
+    Search,
+    Examine(Coordinate),
+    Disarm(Coordinate),
+    Start,
+    Spell(i32, Option<Coordinate>), // spell index, target for aimed spells
+    UseItem(usize),
+    ReadScroll(usize, Option<Coordinate>), // item index, target for aimed spells
+    Equip(usize), // item index
+    Unequip(EquipSlot),
+    Buy(usize), // index into the nearby shop's stock
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TurnOutcome {
+    pub player_alive: bool,
+    pub depth: usize,
+    pub turn: usize,
+    pub messages: Vec<String>,
+}
+
+// What outside listeners hear about, coarser than the ecs's own events.
+#[derive(Debug, Clone)]
+pub enum GameEvent {
+    Interaction { event_type: EventType, entity_id: usize },
+    MonsterDied { name: String },
+    PlayerLeveled { level: isize },
+    FloorChanged { depth: usize },
+}
+
+// Coarse per-tile summary for drawing a minimap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MinimapCell {
+    Unexplored,
+    Floor,
+    Wall,
+    Door,
+    Stairs,
+    PlayerHere,
+}
+
+// How a fight with a monster is likely to go, for tinting names on the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ThreatLevel {
+    Trivial,
+    Even,
+    Dangerous,
+    Deadly,
+}
+
+impl ThreatLevel {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            ThreatLevel::Trivial => "harmless",
+            ThreatLevel::Even => "evenly matched",
+            ThreatLevel::Dangerous => "dangerous",
+            ThreatLevel::Deadly => "deadly",
+        }
+    }
+}
+
+// What the player can learn about a tile by looking at it.
+#[derive(Debug, Clone, Default)]
+pub struct ExamineReport {
+    pub terrain: &'static str,
+    pub name: Option<String>,
+    pub health: Option<(isize, isize)>, // current, max
+    pub effects: Vec<EffectType>,
+    pub threat: Option<ThreatLevel>,
+}
+
+impl ExamineReport {
+    pub fn describe(&self) -> String {
+        let Some(name) = &self.name else {
+            return format!("You see {}.", self.terrain);
+        };
+        let mut parts = vec![name.clone()];
+        if let Some((current, max)) = self.health {
+            parts.push(format!("({}/{} HP)", current, max));
+        }
+        let effects: Vec<&str> = self.effects.iter().map(|effect| effect.describe()).collect();
+        if !effects.is_empty() {
+            parts.push(format!("- {}", effects.join(", ")));
+        }
+        let sighting = format!("You see {} on {}.", parts.join(" "), self.terrain);
+        match self.threat {
+            Some(threat) => format!("{} It looks {}.", sighting, threat.describe()),
+            None => sighting,
+        }
+    }
+}
+
+// Everything the death screen has to say about how the run went.
+#[derive(Debug, Clone, Default)]
+pub struct DeathReport {
+    pub killer: String,
+    pub depth: usize,
+    pub turns: usize,
+    pub stats: RunStats,
+}
+
+impl DeathReport {
+    pub fn describe(&self) -> String {
+        format!("{} on depth {}.", self.killer, self.depth)
+    }
+
+    pub fn summary(&self) -> String {
+        let kills: usize = self.stats.kills.values().sum();
+        format!("{} turns, {} kills, {} gold.", self.turns, kills, self.stats.gold_collected)
+    }
+}
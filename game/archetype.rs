@@ -15,19 +15,14 @@ use crate::{
 };
 
 thread_local! {
-    pub static TURNTAKER: ComponentQuery = ComponentQuery {
-        required: vec![
-            ComponentType::Name,
-            ComponentType::Image,
-            ComponentType::Position,
-            ComponentType::Turn,
-        ],
-        optional: vec![
-            ComponentType::Attributes,
-            ComponentType::Health,
-            ComponentType::Combat,
-        ],
-    };
+    pub static TURNTAKER: ComponentQuery = ComponentQuery::new()
+        .require(ComponentType::Name)
+        .require(ComponentType::Image)
+        .require(ComponentType::Position)
+        .require(ComponentType::Turn)
+        .optional(ComponentType::Attributes)
+        .optional(ComponentType::Health)
+        .optional(ComponentType::Combat);
 
     pub static PLAYER: ComponentQuery = ComponentQuery::new_single(ComponentType::Player);
 
@@ -87,8 +82,13 @@ pub fn make_unit_report<'a>(unit_components: &'a [&'a Component]) -> Option<Unit
         .map(|&elem| elem.to_owned())
         .collect();
 
+    // a disarmed unit still bumps, it just can't hurt anything
+    let armed = match combat::is_disarmed(unit_components) {
+        true => Combat::new_unarmed(),
+        false => combat.data.clone(),
+    };
     let attack =
-        combat::calculate_melee_attack(&combat.data, IndexedData::unwrap_data(stats.as_ref()));
+        combat::calculate_melee_attack(&armed, IndexedData::unwrap_data(stats.as_ref()));
     let bump = InteractionEvent {
         event_type: EventType::Bump,
         attack,
@@ -96,7 +96,7 @@ pub fn make_unit_report<'a>(unit_components: &'a [&'a Component]) -> Option<Unit
     };
 
     let attack =
-        combat::calculate_ranged_attack(&combat.data, IndexedData::unwrap_data(stats.as_ref()));
+        combat::calculate_ranged_attack(&armed, IndexedData::unwrap_data(stats.as_ref()));
     let shoot = InteractionEvent {
         event_type: EventType::Shot,
         attack,
@@ -4,9 +4,12 @@ use crate::ecs::component::Diffable;
 pub struct Attributes {
     pub strength: isize,
     pub dexterity: isize,
+    pub armor: isize,
     pub level: isize,
     pub xp: isize,
     pub level_pending: bool,
+    pub mana: isize,
+    pub mana_max: isize,
 }
 
 impl Diffable for Attributes {
@@ -14,13 +17,40 @@ impl Diffable for Attributes {
         // raw attributes
         self.strength += other.strength;
         self.dexterity += other.dexterity;
+        self.armor += other.armor;
         // leveling up
         self.level += other.level;
         self.xp += other.xp;
         self.level_pending = other.level_pending;
+        // spell points
+        self.mana += other.mana;
+        self.mana_max += other.mana_max;
     }
 }
 
-pub fn get_xp_to_next(attr: &Attributes) -> isize {
-    100 * attr.level
+pub const MANA_REGEN_TURNS: usize = 4;
+
+// XP granted per point of max health on a killed monster.
+pub const XP_PER_KILLED_HP: isize = 2;
+
+// XP needed for the next level is base * level^growth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XpCurve {
+    pub base: isize,
+    pub growth: f64,
+}
+
+impl XpCurve {
+    pub fn xp_to_next(&self, attr: &Attributes) -> isize {
+        (self.base as f64 * (attr.level as f64).powf(self.growth)) as isize
+    }
+}
+
+impl Default for XpCurve {
+    fn default() -> Self {
+        XpCurve {
+            base: 100,
+            growth: 1.0,
+        }
+    }
 }
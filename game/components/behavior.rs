@@ -1,21 +1,24 @@
 use core::fmt::Debug;
-use std::{borrow::BorrowMut, cell::Cell};
-use rand::thread_rng;
+use std::{borrow::BorrowMut, cell::Cell, collections::HashSet};
+use rand::{thread_rng, Rng};
 use rand::seq::SliceRandom;
 
 use crate::{
     ecs::{
         component::Diffable,
-        ecs::{Delta, IndexedData, ECS},
-        event::{propagate_event, InteractionEvent},
+        ecs::{DeleteEntityOrder, Delta, IndexedData, MakeEntityOrder, ECS},
+        event::{propagate_event, EventType, InteractionEvent},
     },
     game::{
+        action::GameEvent,
         archetype::{make_unit_report, UnitReport},
-        components::core::*,
+        components::{combat::{AttackReport, Health}, core::*},
+        responses,
+        spawning,
         system::NavigationGrid,
     },
     map::{self, gamemap::GameMap, utils::{Coordinate, Euclidian}},
-    utils::{logger, los::line_of_sight},
+    utils::{logger, los::{self, line_of_sight}, pathfinding},
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -27,8 +30,28 @@ pub enum AIAction {
     Wander,
     Sleep,
     Awake,
+    Stumble,
+    ChargeAttack,
+    Flank,
+    Summon,
+    HealAlly,
+    EatCorpse,
 }
 
+const CHARGE_STEPS: usize = 3;
+const CHARGE_DAMAGE_MULTIPLIER: f32 = 1.5;
+const WADING_SKIP_CHANCE: f64 = 0.5;
+const FLANK_RADIUS: f32 = 4.0;
+pub const SUMMON_LIMIT: usize = 4;
+pub const SUMMON_MINION_CAP: usize = 3;
+const SUMMON_RADIUS: f32 = 5.0;
+const ALLY_SIGHT_RADIUS: f32 = 6.0;
+// turns a healer waits between heals
+pub const HEAL_COOLDOWN: usize = 4;
+const HEAL_FRACTION: f32 = 0.3;
+// share of its max health a ghoul gets back from a meal
+const FEED_FRACTION: f32 = 0.5;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AIState {
     Alert,
@@ -50,6 +73,11 @@ pub(crate) trait Behavior: Debug + CloneBehavior {
         map: &GameMap,
         ecs: &ECS,
     ) -> Vec<AIAction>;
+
+    // hunters go after the nearest monster instead of the player
+    fn hunts_monsters(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -57,6 +85,10 @@ pub struct TurnTaker {
     pub(crate) behavior: Box<dyn Behavior>,
     pub(crate) state: AIState,
     pub(crate) avoid_hazards: bool,
+    // fraction of max health below which the unit runs instead of fighting
+    pub(crate) flee_threshold: f32,
+    // opens closed doors in its way instead of walking around them
+    pub(crate) opens_doors: bool,
 }
 
 impl TurnTaker {
@@ -88,6 +120,22 @@ impl TurnTaker {
         }
     }
 
+    pub fn new_pack(avoid_hazards: bool) -> Self {
+        Self {
+            behavior: Box::new(PackBehavior::default()),
+            avoid_hazards,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_charger(avoid_hazards: bool) -> Self {
+        Self {
+            behavior: Box::new(ChargeBehavior::default()),
+            avoid_hazards,
+            ..Default::default()
+        }
+    }
+
     pub fn new_archer() -> Self {
         Self {
             behavior: Box::new(ArcherBehavior::default()),
@@ -103,6 +151,38 @@ impl TurnTaker {
         }
     }
 
+    pub fn new_healer(avoid_hazards: bool) -> Self {
+        Self {
+            behavior: Box::new(HealerBehavior::default()),
+            avoid_hazards,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_summoner(avoid_hazards: bool) -> Self {
+        Self {
+            behavior: Box::new(SummonBehavior::default()),
+            avoid_hazards,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_ghoul(avoid_hazards: bool) -> Self {
+        Self {
+            behavior: Box::new(GhoulBehavior::default()),
+            avoid_hazards,
+            ..Default::default()
+        }
+    }
+
+    pub fn new_ally(avoid_hazards: bool) -> Self {
+        Self {
+            behavior: Box::new(AllyBehavior::default()),
+            avoid_hazards,
+            ..Default::default()
+        }
+    }
+
     pub fn new_wander(delay: usize) -> Self {
         Self {
             behavior: Box::new(WanderBehavior::new(delay)),
@@ -111,6 +191,34 @@ impl TurnTaker {
         }
     }
 
+    pub fn cowardly(self, flee_threshold: f32) -> Self {
+        Self {
+            flee_threshold,
+            ..self
+        }
+    }
+
+    pub fn opening_doors(self) -> Self {
+        Self {
+            opens_doors: true,
+            ..self
+        }
+    }
+
+    fn is_cowering(&self, self_report: &UnitReport) -> bool {
+        self_report.health.as_ref().is_some_and(|health| {
+            (health.data.current as f32) < health.data.max as f32 * self.flee_threshold
+        })
+    }
+
+    pub fn asleep(self) -> Self {
+        // sleeps until alerted
+        Self {
+            state: AIState::Sleeping(-1),
+            ..self
+        }
+    }
+
     pub fn process_turn(
         &self,
         components: &[&Component],
@@ -118,38 +226,127 @@ impl TurnTaker {
         map: &GameMap,
         safe_grid: &NavigationGrid,
         hazard_grid: &NavigationGrid,
-    ) -> Vec<Delta> {
+        claimed_tiles: &mut HashSet<Coordinate>,
+    ) -> (Vec<Delta>, Vec<GameEvent>) {
         let Some(player_report) = ecs.get_player_report() else {
-            return vec![];
+            return (vec![], vec![]);
         };
         let Some(mut self_report) = make_unit_report(components) else {
-            return vec![];
+            return (vec![], vec![]);
         };
+        if is_frozen(components) || is_grappled(components) {
+            return (vec![], vec![]);
+        }
+        // wading through water costs every other step
+        let wading = map.tile_is_water(self_report.position.data) && !is_levitating(components);
+        if wading && thread_rng().gen_bool(WADING_SKIP_CHANCE) {
+            return (vec![], vec![]);
+        }
         let grid = match self.avoid_hazards {
             true => safe_grid,
             false => hazard_grid,
         };
         let player_index = ecs.get_player_id();
 
+        // allies treat their prey as "the player" and path to it on their own grid
+        let prey = match self.behavior.hunts_monsters() {
+            true => find_prey(&self_report, ecs, map),
+            false => None,
+        };
+        let prey_grid;
+        let (player_report, player_index, grid) = match prey {
+            Some((prey_id, prey_report)) => {
+                // the ally itself blocks like a unit, so it's named as the destination to stay on the grid
+                prey_grid = pathfinding::calculate_pathing_grid(
+                    prey_report.position.data,
+                    self_report.position.data,
+                    map,
+                    ecs,
+                    |_| 0,
+                    true,
+                    false,
+                    !self.avoid_hazards,
+                );
+                (prey_report, prey_id, &prey_grid)
+            }
+            None => (player_report, player_index, grid),
+        };
+
         let mut output: Vec<Delta> = Vec::new(); 
-        let actions = self.behavior.select_action(&self_report, &player_report, self.state, map, ecs);
+        // blows aimed at the player, or the prey, for outside listeners
+        let mut events: Vec<GameEvent> = Vec::new();
+        let mut strike = |event: &InteractionEvent| {
+            events.push(GameEvent::Interaction { event_type: event.event_type, entity_id: player_index });
+            propagate_event(event, player_index, ecs)
+        };
+        let mut actions = self.behavior.select_action(&self_report, &player_report, self.state, map, ecs);
+        let cowering = self.is_cowering(&self_report);
+        if cowering {
+            actions = actions
+                .into_iter()
+                .map(|action| match action {
+                    AIAction::Approach | AIAction::Flank | AIAction::Attack | AIAction::ChargeAttack => AIAction::Flee,
+                    other => other,
+                })
+                .collect();
+        }
+        if is_confused(components) {
+            actions = confuse_actions(actions);
+        }
         for action in actions {
             let deltas = match action {
                 AIAction::Approach => {
-                    let (deltas, dir) = approach_player(&self_report.position, &self_report.bump, ecs, grid);
+                    let (deltas, dir) = approach_player(&self_report.position, &self_report.bump, ecs, map, grid);
+                    self_report.position.data += dir;
+                    if dir == Coordinate::default() && self.opens_doors {
+                        open_door_ahead(&self_report, ecs, grid)
+                    } else {
+                        deltas
+                    }
+                }
+                AIAction::Flank => {
+                    let (deltas, dir) = flank_player(
+                        &self_report.position,
+                        &self_report.bump,
+                        player_report.position.data,
+                        claimed_tiles,
+                        ecs,
+                        map,
+                        grid,
+                    );
                     self_report.position.data += dir;
                     deltas
                 }
                 AIAction::Flee => {
-                    let (deltas, dir) = flee(&self_report.position, &self_report.bump, ecs, map, grid);
+                    let leaving = self_report.position.data;
+                    let (mut deltas, dir) = flee(&self_report.position, &self_report.bump, ecs, map, grid);
                     self_report.position.data += dir;
-                    deltas
+                    if let Some(my_id) = ecs.get_entity_id_from_component_id(self_report.position.index) {
+                        let arriving = self_report.position.data;
+                        deltas.extend(attack_of_opportunity(leaving, arriving, my_id, &player_report, ecs, map));
+                    }
+                    // a cornered coward fights back
+                    let cornered = dir == Coordinate::default()
+                        && self_report.position.data.distance(player_report.position.data) <= map.adjacent_range();
+                    if cowering && cornered {
+                        strike(&self_report.bump)
+                    } else {
+                        deltas
+                    }
                 }
                 AIAction::Attack => {
-                    propagate_event(&self_report.bump, player_index, ecs)
+                    let mut deltas = strike(&self_report.bump);
+                    if let Some(attack) = self_report.bump.attack {
+                        deltas.extend(knockback(self_report.position.data, player_index, attack.knockback, ecs, map));
+                    }
+                    deltas
                 }
                 AIAction::Shoot => {
-                    propagate_event(&self_report.shoot, player_index, ecs)
+                    let mut deltas = strike(&self_report.shoot);
+                    if let Some(combat) = self_report.combat.data.spend_ammo() {
+                        deltas.push(Delta::Change(Component::Combat(self_report.combat.make_change(combat))));
+                    }
+                    deltas
                 }
                 AIAction::Awake => {
                     wake_up(&self_report.position, ecs)
@@ -162,13 +359,42 @@ impl TurnTaker {
                 AIAction::Sleep => {
                     sleep(&self_report.position, ecs)
                 }
+                AIAction::ChargeAttack => {
+                    // only lands if the charge actually reached the player
+                    if self_report.position.data.distance(player_report.position.data) > map.adjacent_range() {
+                        vec![]
+                    } else {
+                        if let Some(name) = &self_report.name {
+                            logger::log_message(&format!("{} charges into you!", name.data.subject()));
+                        }
+                        let mut charge = self_report.bump.clone();
+                        if let Some(attack) = charge.attack.as_mut() {
+                            attack.damage = (attack.damage as f32 * CHARGE_DAMAGE_MULTIPLIER) as isize;
+                        }
+                        strike(&charge)
+                    }
+                }
+                AIAction::Summon => {
+                    summon_minion(&self_report, ecs, map)
+                }
+                AIAction::HealAlly => {
+                    heal_ally(&self_report, ecs, map)
+                }
+                AIAction::EatCorpse => {
+                    eat_corpse(&self_report, ecs, map)
+                }
+                AIAction::Stumble => {
+                    let (deltas, dir) = stumble(&self_report.position, &self_report.bump, ecs, map);
+                    self_report.position.data += dir;
+                    deltas
+                }
                 _ => {
                     vec![]
                 }
             };
             output.extend(deltas.into_iter());
         }
-        output
+        (output, events)
     }
 }
 
@@ -178,6 +404,8 @@ impl Default for TurnTaker {
             behavior: Box::new(MeleeBehavior::default()),
             state: AIState::default(),
             avoid_hazards: false,
+            flee_threshold: 0.0,
+            opens_doors: false,
         }
     }
 }
@@ -204,21 +432,15 @@ impl Behavior for MeleeBehavior {
         let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
         let distance = my_pos.distance(pl_pos);
 
-        if let Some(Component::DurationEffect(indexed_data)) = ecs.get_component_from_entity_id(ecs.get_player_id(), ComponentType::DurationEffect) {
-            if let DurationEffect(_, EffectType::Invisible) = indexed_data.data {
-                if line_of_sight(my_pos, pl_pos, map, ecs) {
-                    return vec![AIAction::Wander]
-                } else {
-                    return vec![AIAction::Sleep]
-                }
-            }
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
         }
 
         if let Some(action) = handle_sleep(state) {
             return vec![action];
         }
 
-        if distance > 1.1 {
+        if distance > map.adjacent_range() {
             vec![AIAction::Approach]
         } else {
             vec![AIAction::Attack]
@@ -226,6 +448,38 @@ impl Behavior for MeleeBehavior {
     }
 }
 
+// Melee that spreads out around the player instead of queueing up behind packmates.
+#[derive(Debug, Clone, Default)]
+struct PackBehavior { }
+
+impl Behavior for PackBehavior {
+    fn select_action(
+        &self,
+        self_report: &UnitReport,
+        player_report: &UnitReport,
+        state: AIState,
+        map: &GameMap,
+        ecs: &ECS,
+    ) -> Vec<AIAction> {
+        let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
+        let distance = my_pos.distance(pl_pos);
+
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
+        }
+
+        if let Some(action) = handle_sleep(state) {
+            return vec![action];
+        }
+
+        if distance > map.adjacent_range() {
+            vec![AIAction::Flank]
+        } else {
+            vec![AIAction::Attack]
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 struct FastMeleeBehavior { }
 
@@ -241,14 +495,8 @@ impl Behavior for FastMeleeBehavior {
         let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
         let distance = my_pos.distance(pl_pos);
 
-        if let Some(Component::DurationEffect(indexed_data)) = ecs.get_component_from_entity_id(ecs.get_player_id(), ComponentType::DurationEffect) {
-            if let DurationEffect(_, EffectType::Invisible) = indexed_data.data {
-                if line_of_sight(my_pos, pl_pos, map, ecs) {
-                    return vec![AIAction::Wander]
-                } else {
-                    return vec![AIAction::Sleep]
-                }
-            }
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
         }
 
         if let Some(action) = handle_sleep(state) {
@@ -257,7 +505,7 @@ impl Behavior for FastMeleeBehavior {
 
         if distance > 2.1 {
             vec![AIAction::Approach, AIAction::Approach]
-        } else if distance > 1.1 {
+        } else if distance > map.adjacent_range() {
             vec![AIAction::Approach, AIAction::Attack]
         } else {
             vec![AIAction::Attack, AIAction::Wander]
@@ -265,6 +513,45 @@ impl Behavior for FastMeleeBehavior {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+struct ChargeBehavior { }
+
+impl Behavior for ChargeBehavior {
+    fn select_action(
+        &self,
+        self_report: &UnitReport,
+        player_report: &UnitReport,
+        state: AIState,
+        map: &GameMap,
+        ecs: &ECS,
+    ) -> Vec<AIAction> {
+        let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
+        let distance = my_pos.distance(pl_pos);
+
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
+        }
+
+        if let Some(action) = handle_sleep(state) {
+            return vec![action];
+        }
+
+        if distance <= map.adjacent_range() {
+            return vec![AIAction::Attack];
+        }
+
+        let straight_line = my_pos.x == pl_pos.x || my_pos.y == pl_pos.y;
+        if straight_line && line_of_sight(my_pos, pl_pos, map, ecs) {
+            let steps = (distance.round() as usize - 1).min(CHARGE_STEPS);
+            let mut actions = vec![AIAction::Approach; steps];
+            actions.push(AIAction::ChargeAttack);
+            actions
+        } else {
+            vec![AIAction::Approach]
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 struct ArcherBehavior {}
 
@@ -280,14 +567,8 @@ impl Behavior for ArcherBehavior {
         let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
         let my_range = self_report.combat.data.ranged.unwrap().max_range;
 
-        if let Some(Component::DurationEffect(indexed_data)) = ecs.get_component_from_entity_id(ecs.get_player_id(), ComponentType::DurationEffect) {
-            if let DurationEffect(_, EffectType::Invisible) = indexed_data.data {
-                if line_of_sight(my_pos, pl_pos, map, ecs) {
-                    return vec![AIAction::Wander]
-                } else {
-                    return vec![AIAction::Sleep]
-                }
-            }
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
         }
 
         if let Some(action) = handle_sleep(state) {
@@ -298,8 +579,10 @@ impl Behavior for ArcherBehavior {
             vec![AIAction::Approach]
         } else if !line_of_sight(my_pos, pl_pos, map, ecs) {
             vec![AIAction::Approach]
-        } else if my_pos.distance(pl_pos) <= 1.0 {
+        } else if my_pos.distance(pl_pos) <= map.adjacent_range() {
             vec![AIAction::Attack]
+        } else if !self_report.combat.data.has_ammo() {
+            vec![AIAction::Approach]
         } else {
             vec![AIAction::Shoot]
         }
@@ -329,8 +612,10 @@ impl Behavior for TrueSightArcherBehavior {
             vec![AIAction::Approach]
         } else if !line_of_sight(my_pos, pl_pos, map, ecs) {
             vec![AIAction::Approach]
-        } else if my_pos.distance(pl_pos) <= 1.0 {
+        } else if my_pos.distance(pl_pos) <= map.adjacent_range() {
             vec![AIAction::Attack]
+        } else if !self_report.combat.data.has_ammo() {
+            vec![AIAction::Approach]
         } else {
             vec![AIAction::Shoot]
         }
@@ -354,14 +639,8 @@ impl Behavior for SlowBehavior {
         let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
         let distance = my_pos.distance(pl_pos);
 
-        if let Some(Component::DurationEffect(indexed_data)) = ecs.get_component_from_entity_id(ecs.get_player_id(), ComponentType::DurationEffect) {
-            if let DurationEffect(_, EffectType::Invisible) = indexed_data.data {
-                if line_of_sight(my_pos, pl_pos, map, ecs) {
-                    return vec![AIAction::Wander]
-                } else {
-                    return vec![AIAction::Sleep]
-                }
-            }
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
         }
 
         if let Some(action) = handle_sleep(state) {
@@ -372,10 +651,10 @@ impl Behavior for SlowBehavior {
             self.acted_last_turn.set(false);
             logger::log_message(&format!(
                 "{} is reeling from impact.",
-                self_report.name.as_ref().unwrap().data.raw
+                self_report.name.as_ref().unwrap().data.subject()
             ));
             vec![AIAction::Sleep]
-        } else if distance > 1.1 {
+        } else if distance > map.adjacent_range() {
             vec![AIAction::Approach]
         } else {
             self.acted_last_turn.set(true);
@@ -384,6 +663,147 @@ impl Behavior for SlowBehavior {
     }
 }
 
+// Fights for the player: chases monsters it can see, otherwise sticks close to the player.
+#[derive(Debug, Clone, Default)]
+struct AllyBehavior { }
+
+impl Behavior for AllyBehavior {
+    fn select_action(
+        &self,
+        self_report: &UnitReport,
+        player_report: &UnitReport,
+        _state: AIState,
+        map: &GameMap,
+        ecs: &ECS,
+    ) -> Vec<AIAction> {
+        let (my_pos, target_pos) = (self_report.position.data, player_report.position.data);
+        let distance = my_pos.distance(target_pos);
+        let following = ecs.get_player_position() == Some(target_pos);
+
+        match (following, distance > map.adjacent_range()) {
+            (true, true) => vec![AIAction::Approach],
+            (true, false) => vec![AIAction::Sleep],
+            (false, true) => vec![AIAction::Approach],
+            (false, false) => vec![AIAction::Attack],
+        }
+    }
+
+    fn hunts_monsters(&self) -> bool {
+        true
+    }
+}
+
+// Raises skeletons while it can see the player, up to a lifetime limit.
+#[derive(Debug, Clone)]
+struct SummonBehavior {
+    summons_left: Cell<usize>,
+}
+
+impl Behavior for SummonBehavior {
+    fn select_action(
+        &self,
+        self_report: &UnitReport,
+        player_report: &UnitReport,
+        state: AIState,
+        map: &GameMap,
+        ecs: &ECS,
+    ) -> Vec<AIAction> {
+        let (my_pos, pl_pos) = (self_report.position.data, player_report.position.data);
+        let distance = my_pos.distance(pl_pos);
+
+        if let Some(action) = handle_invisible_player(my_pos, pl_pos, map, ecs) {
+            return vec![action];
+        }
+
+        if let Some(action) = handle_sleep(state) {
+            return vec![action];
+        }
+
+        let minions = ecs
+            .get_entities_in_radius(my_pos, SUMMON_RADIUS)
+            .into_iter()
+            .filter(|&entity_id| ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+            .count()
+            .saturating_sub(1); // itself
+        // a summon only counts once there is room to raise it
+        let can_summon = self.summons_left.get() > 0
+            && minions < SUMMON_MINION_CAP
+            && summon_spot(self_report, ecs, map).is_some();
+        if can_summon && line_of_sight(my_pos, pl_pos, map, ecs) {
+            self.summons_left.set(self.summons_left.get() - 1);
+            return vec![AIAction::Summon];
+        }
+
+        if distance > map.adjacent_range() {
+            vec![AIAction::Approach]
+        } else {
+            vec![AIAction::Attack]
+        }
+    }
+}
+
+impl Default for SummonBehavior {
+    fn default() -> Self {
+        Self {
+            summons_left: Cell::new(SUMMON_LIMIT),
+        }
+    }
+}
+
+// Casts like a mage, but every few turns patches up a wounded ally instead.
+#[derive(Debug, Clone, Default)]
+struct HealerBehavior {
+    cooldown: Cell<usize>,
+}
+
+impl Behavior for HealerBehavior {
+    fn select_action(
+        &self,
+        self_report: &UnitReport,
+        player_report: &UnitReport,
+        state: AIState,
+        map: &GameMap,
+        ecs: &ECS,
+    ) -> Vec<AIAction> {
+        if let Some(action) = handle_sleep(state) {
+            return vec![action];
+        }
+
+        let cooldown = self.cooldown.get();
+        if cooldown > 0 {
+            self.cooldown.set(cooldown - 1);
+        } else if find_wounded_ally(self_report, ecs, map).is_some() {
+            self.cooldown.set(HEAL_COOLDOWN);
+            return vec![AIAction::HealAlly];
+        }
+        TrueSightArcherBehavior::default().select_action(self_report, player_report, state, map, ecs)
+    }
+}
+
+// Fights like any melee unit, but stops to feed on a corpse next to it when hurt.
+#[derive(Debug, Clone, Default)]
+struct GhoulBehavior { }
+
+impl Behavior for GhoulBehavior {
+    fn select_action(
+        &self,
+        self_report: &UnitReport,
+        player_report: &UnitReport,
+        state: AIState,
+        map: &GameMap,
+        ecs: &ECS,
+    ) -> Vec<AIAction> {
+        if let Some(action) = handle_sleep(state) {
+            return vec![action];
+        }
+        let wounded = health_fraction(self_report) < 1.0;
+        if wounded && find_corpse_nearby(self_report, ecs, map).is_some() {
+            return vec![AIAction::EatCorpse];
+        }
+        MeleeBehavior::default().select_action(self_report, player_report, state, map, ecs)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct WanderBehavior {
     wander_counter: Cell<usize>,
@@ -436,12 +856,13 @@ fn approach_player(
     my_pos: &IndexedData<Coordinate>,
     my_bump: &InteractionEvent,
     ecs: &ECS,
+    map: &GameMap,
     grid: &NavigationGrid,
 ) -> (Vec<Delta>, Coordinate) {
     let direction = grid.get(&my_pos.data);
 
     if let Some(&dir) = direction {
-        if !ecs.is_blocked_by_entity(my_pos.data + dir) {
+        if can_step(my_pos, dir, ecs, map) {
             // propagate bump event to everything on the space
             let entities = ecs.get_all_entities_in_tile(my_pos.data + dir);
             let mut deltas: Vec<Delta> = entities
@@ -461,6 +882,305 @@ fn approach_player(
     }
 }
 
+// Spends the turn opening the closed door the path runs through, walking through comes next turn.
+fn open_door_ahead(self_report: &UnitReport, ecs: &ECS, grid: &NavigationGrid) -> Vec<Delta> {
+    let Some(&dir) = grid.get(&self_report.position.data) else {
+        return vec![];
+    };
+    let ahead = self_report.position.data + dir;
+    if !ecs.is_blocked_by_door(ahead) {
+        return vec![];
+    }
+    let Some(door_id) = ecs.get_blocking_entity(ahead) else {
+        return vec![];
+    };
+    // locked and secret doors stay shut
+    if ecs.entity_id_has_component(door_id, ComponentType::Locked)
+        || ecs.entity_id_has_component(door_id, ComponentType::Secret)
+    {
+        return vec![];
+    }
+    let bump = InteractionEvent {
+        attack: None,
+        ..self_report.bump.clone()
+    };
+    propagate_event(&bump, door_id, ecs)
+}
+
+// Heads for the closest tile next to the player that no packmate has claimed this turn.
+fn flank_player(
+    my_pos: &IndexedData<Coordinate>,
+    my_bump: &InteractionEvent,
+    player_pos: Coordinate,
+    claimed_tiles: &mut HashSet<Coordinate>,
+    ecs: &ECS,
+    map: &GameMap,
+    grid: &NavigationGrid,
+) -> (Vec<Delta>, Coordinate) {
+    let Some(entity_id) = ecs.get_entity_id_from_component_id(my_pos.index) else {
+        return (vec![], Coordinate::default());
+    };
+    let spot = map
+        .neighbors(player_pos)
+        .into_iter()
+        .filter(|tile| !claimed_tiles.contains(tile) && is_footprint_free(entity_id, *tile, ecs, map))
+        .min_by(|a, b| my_pos.data.distance(*a).total_cmp(&my_pos.data.distance(*b)));
+    let Some(spot) = spot.filter(|spot| my_pos.data.distance(*spot) <= FLANK_RADIUS) else {
+        return approach_player(my_pos, my_bump, ecs, map, grid);
+    };
+    claimed_tiles.insert(spot);
+
+    let current_distance = my_pos.data.distance(spot);
+    let step = map
+        .movement_directions()
+        .into_iter()
+        .filter(|&dir| grid.contains_key(&(my_pos.data + dir)) && can_step(my_pos, dir, ecs, map))
+        .filter(|&dir| (my_pos.data + dir).distance(spot) < current_distance)
+        .min_by(|a, b| (my_pos.data + *a).distance(spot).total_cmp(&(my_pos.data + *b).distance(spot)));
+    let Some(dir) = step else {
+        return approach_player(my_pos, my_bump, ecs, map, grid);
+    };
+
+    let mut deltas: Vec<Delta> = ecs
+        .get_all_entities_in_tile(my_pos.data + dir)
+        .into_iter()
+        .flat_map(|entity_id| propagate_event(my_bump, entity_id, ecs))
+        .collect();
+    deltas.push(Delta::Change(Component::Position(my_pos.make_change(dir))));
+    (deltas, dir)
+}
+
+fn find_prey(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Option<(usize, UnitReport)> {
+    let my_pos = self_report.position.data;
+    ecs.get_entities_in_radius(my_pos, ALLY_SIGHT_RADIUS)
+        .into_iter()
+        .filter(|&entity_id| ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+        .find_map(|entity_id| {
+            let components = ecs.get_components_from_entity_id(entity_id);
+            let report = make_unit_report(&components)?;
+            let alive = report.health.as_ref().is_none_or(|health| !health.data.is_dead());
+            (alive && line_of_sight(my_pos, report.position.data, map, ecs)).then_some((entity_id, report))
+        })
+}
+
+// The most badly hurt monster in sight, never the healer itself.
+fn find_wounded_ally(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Option<UnitReport> {
+    let my_pos = self_report.position.data;
+    ecs.get_entities_in_radius(my_pos, ALLY_SIGHT_RADIUS)
+        .into_iter()
+        .filter(|&entity_id| ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+        .filter_map(|entity_id| make_unit_report(&ecs.get_components_from_entity_id(entity_id)))
+        .filter(|report| report.position.data != my_pos)
+        .filter(|report| {
+            report.health.as_ref().is_some_and(|health| {
+                !health.data.is_dead() && health.data.current < health.data.max
+            })
+        })
+        .filter(|report| line_of_sight(my_pos, report.position.data, map, ecs))
+        .min_by(|a, b| health_fraction(a).total_cmp(&health_fraction(b)))
+}
+
+fn health_fraction(report: &UnitReport) -> f32 {
+    report.health.as_ref().map_or(1.0, |health| health.data.current as f32 / health.data.max as f32)
+}
+
+fn heal_ally(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(ally) = find_wounded_ally(self_report, ecs, map) else {
+        return vec![];
+    };
+    let Some(health) = ally.health else {
+        return vec![];
+    };
+    let missing = health.data.max - health.data.current;
+    let healed = ((health.data.max as f32 * HEAL_FRACTION).ceil() as isize).min(missing);
+    if let (Some(name), Some(ally_name)) = (&self_report.name, &ally.name) {
+        logger::log_message(&format!("{} mends {}'s wounds.", name.data.subject(), ally_name.data.definite()));
+    }
+    vec![Delta::Change(Component::Health(health.make_change(Health { current: healed, max: 0 })))]
+}
+
+fn find_corpse_nearby(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Option<usize> {
+    ecs.get_entities_in_radius(self_report.position.data, map.adjacent_range())
+        .into_iter()
+        .find(|&entity_id| ecs.get_typed::<CorpseKind>(entity_id).is_some())
+}
+
+fn eat_corpse(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let (Some(corpse_id), Some(health)) = (find_corpse_nearby(self_report, ecs, map), &self_report.health) else {
+        return vec![];
+    };
+    let missing = health.data.max - health.data.current;
+    let healed = ((health.data.max as f32 * FEED_FRACTION).ceil() as isize).min(missing);
+    if let Some(name) = &self_report.name {
+        logger::log_message(&format!("{} feeds on a corpse.", name.data.subject()));
+    }
+    let mut deltas = responses::drop_carried_loot(corpse_id, ecs);
+    deltas.push(Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(corpse_id)));
+    deltas.push(Delta::Change(Component::Health(health.make_change(Health { current: healed, max: 0 }))));
+    deltas
+}
+
+fn summon_spot(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Option<Coordinate> {
+    map::utils::ORTHOGONAL_DIRECTIONS
+        .into_iter()
+        .find(|&dir| can_step(&self_report.position, dir, ecs, map))
+}
+
+fn summon_minion(self_report: &UnitReport, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(dir) = summon_spot(self_report, ecs, map) else {
+        return vec![];
+    };
+    if let Some(name) = &self_report.name {
+        logger::log_message(&format!("{} raises a skeleton!", name.data.subject()));
+    }
+    let components = spawning::skelly_components(self_report.position.data + dir, map.depth, map.difficulty);
+    vec![Delta::MakeEntity(MakeEntityOrder { components })]
+}
+
+pub fn knockback(
+    attacker: Coordinate,
+    target_id: usize,
+    distance: u32,
+    ecs: &ECS,
+    map: &GameMap,
+) -> Vec<Delta> {
+    shove(attacker, target_id, distance, false, ecs, map)
+}
+
+pub fn pull(
+    puller: Coordinate,
+    target_id: usize,
+    distance: u32,
+    ecs: &ECS,
+    map: &GameMap,
+) -> Vec<Delta> {
+    shove(puller, target_id, distance, true, ecs, map)
+}
+
+// Moves a unit in a line away from (or towards) origin until something blocks it.
+fn shove(
+    origin: Coordinate,
+    target_id: usize,
+    distance: u32,
+    towards: bool,
+    ecs: &ECS,
+    map: &GameMap,
+) -> Vec<Delta> {
+    if distance == 0 {
+        return vec![];
+    }
+    // only units get shoved around, not chests or doors
+    if !ecs.entity_id_has_component(target_id, ComponentType::Turn)
+        && !ecs.entity_id_has_component(target_id, ComponentType::Player)
+    {
+        return vec![];
+    }
+    let components = ecs.get_components_from_entity_id(target_id);
+    let Some(report) = make_unit_report(&components) else {
+        return vec![];
+    };
+    if report.health.is_some_and(|health| health.data.is_dead()) {
+        return vec![];
+    }
+
+    let offset = report.position.data - origin;
+    let mut dir = Coordinate { x: offset.x.signum(), y: offset.y.signum() };
+    if towards {
+        dir = dir * -1;
+    }
+    let bump = InteractionEvent {
+        attack: None,
+        ..report.bump.clone()
+    };
+
+    let mut deltas = vec![];
+    let mut moved = Coordinate::default();
+    for _ in 0..distance {
+        let next = report.position.data + moved + dir;
+        if !is_footprint_free(target_id, next, ecs, map) {
+            break;
+        }
+        moved += dir;
+        // hazards along the way trigger as if stepped on
+        for entity_id in ecs.get_all_entities_in_tile(next) {
+            deltas.extend(propagate_event(&bump, entity_id, ecs));
+        }
+    }
+
+    if moved != Coordinate::default() {
+        if let Some(name) = report.name {
+            let verb = if towards { "is pulled in." } else { "is knocked back." };
+            logger::log_message(&[&name.data.subject(), verb].join(" "));
+        }
+        deltas.push(Delta::Change(Component::Position(report.position.make_change(moved))));
+    }
+    deltas
+}
+
+// Throws a unit towards destination, it and whatever stops it short both take the hit.
+pub fn fling(
+    target_id: usize,
+    destination: Coordinate,
+    damage: isize,
+    ecs: &ECS,
+    map: &GameMap,
+) -> Vec<Delta> {
+    let components = ecs.get_components_from_entity_id(target_id);
+    let Some(report) = make_unit_report(&components) else {
+        return vec![];
+    };
+    let start = report.position.data;
+    let mut landing = start;
+    let mut obstacle = None;
+    for tile in los::linetrace(start, destination).into_iter().skip(1) {
+        if !is_footprint_free(target_id, tile, ecs, map) {
+            obstacle = Some(ecs.get_blocking_entity(tile));
+            break;
+        }
+        landing = tile;
+    }
+
+    let mut deltas = vec![];
+    if landing != start {
+        deltas.push(Delta::Change(Component::Position(report.position.make_change(landing - start))));
+    }
+
+    let subject = report.name.map_or("Something".to_string(), |name| name.data.subject());
+    let collision = InteractionEvent {
+        event_type: EventType::Shot,
+        attack: Some(AttackReport {
+            damage,
+            hit_message: "slams into",
+            ..Default::default()
+        }),
+        payload: vec![],
+    };
+    match obstacle {
+        Some(Some(struck_id)) => {
+            let hit = InteractionEvent {
+                payload: report.bump.payload.clone(),
+                ..collision.clone()
+            };
+            deltas.extend(propagate_event(&hit, struck_id, ecs));
+            deltas.extend(knockback(landing, struck_id, 1, ecs, map));
+        }
+        Some(None) => logger::log_message(&format!("{} slams into the wall!", subject)),
+        None => logger::log_message(&format!("{} crashes to the ground!", subject)),
+    }
+    // whatever waits where it lands goes off as if stepped on
+    if landing != start {
+        let bump = InteractionEvent {
+            attack: None,
+            ..report.bump.clone()
+        };
+        for entity_id in ecs.get_all_entities_in_tile(landing) {
+            deltas.extend(propagate_event(&bump, entity_id, ecs));
+        }
+    }
+    deltas.extend(propagate_event(&collision, target_id, ecs));
+    deltas
+}
+
 fn flee(
     my_pos: &IndexedData<Coordinate>,
     my_bump: &InteractionEvent,
@@ -473,9 +1193,7 @@ fn flee(
     if let Some(&dir) = direction {
         let dir = map::utils::reverse_direction(&dir);
         let destination = my_pos.data + dir;
-        if !ecs.is_blocked_by_entity(destination) 
-            && map.is_tile_passable(destination)
-        {
+        if can_step(my_pos, dir, ecs, map) {
             // propagate bump event to everything on the space without attacking
             let bump = InteractionEvent {
                 attack: None,
@@ -501,6 +1219,26 @@ fn flee(
     }
 }
 
+// Slipping out of a hostile's reach gives it a free swing at the one leaving.
+pub fn attack_of_opportunity(
+    leaving: Coordinate,
+    arriving: Coordinate,
+    leaver_id: usize,
+    hostile: &UnitReport,
+    ecs: &ECS,
+    map: &GameMap,
+) -> Vec<Delta> {
+    let reach = map.adjacent_range();
+    let hostile_position = hostile.position.data;
+    if leaving == arriving || hostile_position.distance(leaving) > reach || hostile_position.distance(arriving) <= reach {
+        return vec![];
+    }
+    if hostile.health.as_ref().is_some_and(|health| health.data.is_dead()) {
+        return vec![];
+    }
+    propagate_event(&hostile.bump, leaver_id, ecs)
+}
+
 fn wake_up(my_pos: &IndexedData<Coordinate>, ecs: &ECS) -> Vec<Delta> {
     let entity_id = ecs.get_entity_id_from_component_id(my_pos.index).unwrap();
     let Some(Component::Turn(data)) = ecs.get_component_from_entity_id(entity_id, ComponentType::Turn)
@@ -522,27 +1260,76 @@ fn wander(
     ecs: &ECS,
     map: &GameMap,
 ) -> (Vec<Delta>, Coordinate) {
-    let direction = [
-        map::utils::UP, 
-        map::utils::DOWN,
-        map::utils::LEFT,
-        map::utils::RIGHT,
-    ].choose(thread_rng().borrow_mut());
+    let directions = map.movement_directions();
+    let direction = directions.choose(thread_rng().borrow_mut());
 
-    if let Some(&dir) = direction {
-        let destination = my_pos.data + dir;
-        if !ecs.is_blocked_by_entity(destination) 
-            && map.is_tile_passable(destination)
-        {
-            (vec![Delta::Change(Component::Position(my_pos.make_change(dir)))], dir)
-        } else {
-            (vec![], Coordinate::default())
-        }
+    match direction {
+        Some(&dir) => step_if_free(my_pos, dir, ecs, map),
+        None => (vec![], Coordinate::default()),
+    }
+}
+
+fn step_if_free(
+    my_pos: &IndexedData<Coordinate>,
+    dir: Coordinate,
+    ecs: &ECS,
+    map: &GameMap,
+) -> (Vec<Delta>, Coordinate) {
+    if can_step(my_pos, dir, ecs, map) {
+        (vec![Delta::Change(Component::Position(my_pos.make_change(dir)))], dir)
     } else {
         (vec![], Coordinate::default())
     }
 }
 
+fn can_step(my_pos: &IndexedData<Coordinate>, dir: Coordinate, ecs: &ECS, map: &GameMap) -> bool {
+    let Some(entity_id) = ecs.get_entity_id_from_component_id(my_pos.index) else {
+        return false;
+    };
+    map.is_step_allowed(my_pos.data, dir) && is_footprint_free(entity_id, my_pos.data + dir, ecs, map)
+}
+
+fn is_footprint_free(entity_id: usize, destination: Coordinate, ecs: &ECS, map: &GameMap) -> bool {
+    // large units need every covered tile clear
+    ecs.get_footprint(entity_id, destination)
+        .into_iter()
+        .all(|tile| map.is_tile_passable(tile) && !ecs.is_blocked_for_entity(tile, entity_id))
+}
+
+fn stumble(
+    my_pos: &IndexedData<Coordinate>,
+    bump: &InteractionEvent,
+    ecs: &ECS,
+    map: &GameMap,
+) -> (Vec<Delta>, Coordinate) {
+    let Some(&dir) = map.movement_directions().choose(thread_rng().borrow_mut()) else {
+        return (vec![], Coordinate::default());
+    };
+
+    // stumbling into the player still lands a hit
+    let destination = my_pos.data + dir;
+    if ecs.get_player_position() == Some(destination) {
+        return (propagate_event(bump, ecs.get_player_id(), ecs), Coordinate::default());
+    }
+    step_if_free(my_pos, dir, ecs, map)
+}
+
+fn confuse_actions(actions: Vec<AIAction>) -> Vec<AIAction> {
+    // any purposeful actions collapse into a single stumble
+    let mut stumbled = false;
+    actions
+        .into_iter()
+        .filter_map(|action| match action {
+            AIAction::Approach | AIAction::Flank | AIAction::Attack | AIAction::Shoot | AIAction::HealAlly => {
+                let first = !stumbled;
+                stumbled = true;
+                first.then_some(AIAction::Stumble)
+            }
+            other => Some(other),
+        })
+        .collect()
+}
+
 fn sleep(my_pos: &IndexedData<Coordinate>, ecs: &ECS) -> Vec<Delta> {
     let entity_id = ecs.get_entity_id_from_component_id(my_pos.index).unwrap();
     let Some(Component::Turn(data)) = ecs.get_component_from_entity_id(entity_id, ComponentType::Turn)
@@ -579,6 +1366,72 @@ impl Clone for Box<dyn Behavior> {
     }
 }
 
+fn is_confused(components: &[&Component]) -> bool {
+    components.iter().any(|component| {
+        matches!(
+            component,
+            Component::DurationEffect(IndexedData {
+                data: DurationEffect(_, EffectType::Confused),
+                ..
+            })
+        )
+    })
+}
+
+pub fn is_frozen(components: &[&Component]) -> bool {
+    components.iter().any(|component| {
+        matches!(
+            component,
+            Component::DurationEffect(IndexedData {
+                data: DurationEffect(_, EffectType::Frozen),
+                ..
+            })
+        )
+    })
+}
+
+pub fn is_grappled(components: &[&Component]) -> bool {
+    components.iter().any(|component| {
+        matches!(
+            component,
+            Component::DurationEffect(IndexedData {
+                data: DurationEffect(_, EffectType::Grappled),
+                ..
+            })
+        )
+    })
+}
+
+pub fn is_levitating(components: &[&Component]) -> bool {
+    components.iter().any(|component| {
+        matches!(
+            component,
+            Component::DurationEffect(IndexedData {
+                data: DurationEffect(_, EffectType::Levitate),
+                ..
+            })
+        )
+    })
+}
+
+pub fn is_player_invisible(ecs: &ECS) -> bool {
+    ecs.get_components_from_entity_id(ecs.get_player_id())
+        .iter()
+        .any(|component| matches!(component, Component::DurationEffect(effect) if effect.data.1 == EffectType::Invisible))
+}
+
+// Monsters that saw the player vanish look around, the rest stay put.
+fn handle_invisible_player(my_pos: Coordinate, pl_pos: Coordinate, map: &GameMap, ecs: &ECS) -> Option<AIAction> {
+    if !is_player_invisible(ecs) {
+        return None;
+    }
+    if line_of_sight(my_pos, pl_pos, map, ecs) {
+        Some(AIAction::Wander)
+    } else {
+        Some(AIAction::Sleep)
+    }
+}
+
 fn handle_sleep(state: AIState) -> Option<AIAction> {
     match state {
         AIState::Sleeping(i) if i == 1 => Some(AIAction::Awake),
@@ -586,3 +1439,223 @@ fn handle_sleep(state: AIState) -> Option<AIAction> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::system::System;
+    use crate::game::{action::PlayerAction, core::Game, spelldefinitions, system::Duration, testing::{self, PLAYER_START}};
+
+    fn position(ecs: &ECS, entity_id: usize) -> Coordinate {
+        ecs.get_typed::<Coordinate>(entity_id).unwrap().data
+    }
+
+    #[test]
+    fn knockback_stops_at_walls() {
+        let mut game = testing::arena();
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 1, y: 0 });
+        testing::wall(&mut game, PLAYER_START + Coordinate { x: 4, y: 0 });
+
+        let deltas = knockback(PLAYER_START, dog, 5, &game.ecs, &game.map);
+        game.ecs.apply_changes(deltas);
+        assert_eq!(position(&game.ecs, dog), PLAYER_START + Coordinate { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn knockback_into_spikes_hurts() {
+        let mut game = testing::arena();
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 1, y: 0 });
+        testing::spawn(&mut game, "Spikes", PLAYER_START + Coordinate { x: 2, y: 0 });
+        let health = game.ecs.get_typed::<Health>(dog).unwrap().data.current;
+
+        let deltas = knockback(PLAYER_START, dog, 1, &game.ecs, &game.map);
+        game.ecs.apply_changes(deltas);
+        assert_eq!(position(&game.ecs, dog), PLAYER_START + Coordinate { x: 2, y: 0 });
+        assert!(game.ecs.get_typed::<Health>(dog).unwrap().data.current < health);
+    }
+
+    #[test]
+    fn large_units_block_their_whole_footprint() {
+        let mut game = testing::arena();
+        let anchor = Coordinate { x: 10, y: 8 };
+        let colossus = testing::spawn(&mut game, "Stone colossus", anchor);
+        for offset in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let tile = anchor + Coordinate { x: offset.0, y: offset.1 };
+            assert_eq!(game.ecs.get_blocking_entity(tile), Some(colossus));
+        }
+        assert_eq!(game.ecs.get_blocking_entity(anchor + Coordinate { x: 2, y: 0 }), None);
+    }
+
+    #[test]
+    fn large_units_cant_squeeze_through_corridors() {
+        let mut game = testing::arena();
+        let gap = Coordinate { x: 12, y: 8 };
+        for y in 1..game.map.height as i32 - 1 {
+            if y != gap.y {
+                testing::wall(&mut game, Coordinate { x: gap.x, y });
+            }
+        }
+        let colossus = testing::spawn(&mut game, "Stone colossus", Coordinate { x: 10, y: 8 });
+        let dog = testing::spawn(&mut game, "Doggo", Coordinate { x: 10, y: 11 });
+        assert!(!is_footprint_free(colossus, Coordinate { x: 11, y: 8 }, &game.ecs, &game.map));
+        assert!(is_footprint_free(dog, gap, &game.ecs, &game.map));
+
+        game.ecs.set_player_position(gap + Coordinate { x: 4, y: 0 });
+        for _ in 0..10 {
+            game.apply_action(PlayerAction::Wait);
+        }
+        assert!(position(&game.ecs, colossus).x < gap.x - 1);
+    }
+
+    fn summons(game: &Game, summoner: &SummonBehavior, necromancer: usize) -> bool {
+        let own = game.ecs.get_components_from_entity_id(necromancer);
+        let player = game.ecs.get_components_from_entity_id(game.ecs.get_player_id());
+        let (own, player) = (make_unit_report(&own).unwrap(), make_unit_report(&player).unwrap());
+        let actions = summoner.select_action(&own, &player, AIState::Alert, &game.map, &game.ecs);
+        matches!(actions.as_slice(), [AIAction::Summon])
+    }
+
+    #[test]
+    fn fleeing_out_of_reach_takes_a_hit() {
+        let mut game = testing::arena();
+        let coward = testing::spawn(&mut game, "Pewpewpet", PLAYER_START + Coordinate { x: 1, y: 0 });
+        // well below the flee threshold, but with enough left to survive the parting blow
+        let health = game.ecs.get_typed::<Health>(coward).unwrap();
+        let hurt = Health { current: 20 - health.data.current, max: 100 - health.data.max };
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(hurt))));
+        game.apply_action(PlayerAction::Wait);
+        let position = game.ecs.get_typed::<Coordinate>(coward).unwrap().data;
+        assert!(position.distance(PLAYER_START) > game.map.adjacent_range());
+        assert!(game.ecs.get_typed::<Health>(coward).unwrap().data.current < 20);
+    }
+
+    // Alert and already seen, so it acts from across the map.
+    fn roused(game: &mut Game, name: &str, coord: Coordinate) -> usize {
+        let unit = testing::spawn(game, name, coord);
+        let turn = game.ecs.get_typed::<TurnTaker>(unit).unwrap();
+        let alert = TurnTaker { state: AIState::Alert, ..turn.data.clone() };
+        game.ecs.apply_change(Delta::Change(Component::Turn(turn.make_change(alert))));
+        game.map.explored.borrow_mut().insert(coord);
+        unit
+    }
+
+    #[test]
+    fn only_door_openers_get_through_a_shut_door() {
+        let mut game = testing::arena();
+        for y in 1..17 {
+            let tile = Coordinate { x: 10, y };
+            if y == 4 {
+                testing::spawn(&mut game, "Door", tile);
+            } else {
+                testing::wall(&mut game, tile);
+            }
+        }
+        let skeleton = roused(&mut game, "Pewpewpet", Coordinate { x: 14, y: 4 });
+        let dog = roused(&mut game, "Doggo", Coordinate { x: 14, y: 10 });
+        for _ in 0..8 {
+            game.apply_action(PlayerAction::Wait);
+        }
+        assert!(position(&game.ecs, skeleton).x < 10);
+        assert!(position(&game.ecs, dog).x > 10);
+    }
+
+    fn shoots(game: &Game, archer: usize) -> bool {
+        let own = game.ecs.get_components_from_entity_id(archer);
+        let player = game.ecs.get_components_from_entity_id(game.ecs.get_player_id());
+        let (own, player) = (make_unit_report(&own).unwrap(), make_unit_report(&player).unwrap());
+        let actions = ArcherBehavior::default().select_action(&own, &player, AIState::Alert, &game.map, &game.ecs);
+        matches!(actions.as_slice(), [AIAction::Shoot])
+    }
+
+    #[test]
+    fn smoke_spoils_the_shot_until_it_clears() {
+        let mut game = testing::arena();
+        let cultist = testing::spawn(&mut game, "Pewpew", PLAYER_START + Coordinate { x: 3, y: 0 });
+        assert!(shoots(&game, cultist));
+
+        let smoke = testing::spawn(&mut game, "Smoke", PLAYER_START + Coordinate { x: 2, y: 0 });
+        let mut duration = Duration::default();
+        while game.ecs.get_typed::<Coordinate>(smoke).is_some() {
+            assert!(!shoots(&game, cultist));
+            let components = game.ecs.get_components_from_entity_id(smoke);
+            let deltas = duration.run_next(&components, &game.ecs, &game.map);
+            game.ecs.apply_changes(deltas);
+        }
+        assert!(shoots(&game, cultist));
+    }
+
+    fn heals(game: &Game, healer: &HealerBehavior, caster: usize) -> bool {
+        let own = game.ecs.get_components_from_entity_id(caster);
+        let player = game.ecs.get_components_from_entity_id(game.ecs.get_player_id());
+        let (own, player) = (make_unit_report(&own).unwrap(), make_unit_report(&player).unwrap());
+        let actions = healer.select_action(&own, &player, AIState::Alert, &game.map, &game.ecs);
+        matches!(actions.as_slice(), [AIAction::HealAlly])
+    }
+
+    #[test]
+    fn healers_wait_out_their_cooldown() {
+        let mut game = testing::arena();
+        let spot = PLAYER_START + Coordinate { x: 6, y: 0 };
+        let caster = testing::spawn(&mut game, "Pewpew", spot);
+        let ally = testing::spawn(&mut game, "Doggo", spot + Coordinate { x: 1, y: 0 });
+        let healer = HealerBehavior::default();
+        assert!(!heals(&game, &healer, caster));
+
+        let health = game.ecs.get_typed::<Health>(ally).unwrap();
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(Health { current: -1, max: 0 }))));
+        assert!(heals(&game, &healer, caster));
+        assert_eq!(healer.cooldown.get(), HEAL_COOLDOWN);
+        for _ in 0..HEAL_COOLDOWN {
+            assert!(!heals(&game, &healer, caster));
+        }
+        assert!(heals(&game, &healer, caster));
+    }
+
+    #[test]
+    fn summoners_stop_at_the_minion_cap() {
+        let mut game = testing::arena();
+        let spot = PLAYER_START + Coordinate { x: 6, y: 0 };
+        let necromancer = testing::spawn(&mut game, "Necromancer", spot);
+        let summoner = SummonBehavior::default();
+        for i in 0..SUMMON_MINION_CAP as i32 {
+            assert!(summons(&game, &summoner, necromancer));
+            testing::spawn(&mut game, "Pewpewpet", spot + Coordinate { x: i, y: 2 });
+        }
+        assert!(!summons(&game, &summoner, necromancer));
+        assert_eq!(summoner.summons_left.get(), SUMMON_LIMIT - SUMMON_MINION_CAP);
+    }
+
+    #[test]
+    fn boxed_in_summoners_keep_their_summons() {
+        let mut game = testing::arena();
+        let spot = PLAYER_START + Coordinate { x: 6, y: 0 };
+        let necromancer = testing::spawn(&mut game, "Necromancer", spot);
+        for dir in map::utils::ORTHOGONAL_DIRECTIONS {
+            testing::wall(&mut game, spot + dir);
+        }
+        let summoner = SummonBehavior::default();
+        assert!(!summons(&game, &summoner, necromancer));
+        assert_eq!(summoner.summons_left.get(), SUMMON_LIMIT);
+    }
+
+    #[test]
+    fn allies_hunt_monsters_and_spare_the_player() {
+        let mut game = testing::arena();
+        let spot = PLAYER_START + Coordinate { x: 4, y: 0 };
+        spawning::make_sleeping_doggo(&mut game.ecs, spot, 1, game.map.difficulty);
+        let dog = game.ecs.get_blocking_entity(spot).unwrap();
+        let dog_health = game.ecs.get_typed::<Health>(dog).unwrap().data.current;
+        let player_health = game.ecs.get_typed::<Health>(game.ecs.get_player_id()).unwrap().data.current;
+        let index = testing::give_spell(&mut game, spelldefinitions::SUMMON_WOLF.with(|spell| spell.clone()));
+        game.cast_spell_command(index, None);
+
+        for _ in 0..6 {
+            if game.ecs.get_typed::<Health>(dog).unwrap().data.current < dog_health {
+                break;
+            }
+            game.apply_action(PlayerAction::Wait);
+        }
+        assert!(game.ecs.get_typed::<Health>(dog).unwrap().data.current < dog_health);
+        assert_eq!(game.ecs.get_typed::<Health>(game.ecs.get_player_id()).unwrap().data.current, player_health);
+    }
+}
@@ -3,9 +3,15 @@ use rand::{thread_rng, Rng};
 use crate::{
     ecs::{
         component::Diffable,
-        ecs::{Delta, IndexedData},
+        ecs::{Delta, EntityIdentifier, IndexedData},
     },
-    game::components::{attributes::Attributes, core::Component, inventory::Inventory},
+    game::components::{
+        attributes::Attributes,
+        core::{Component, DurationEffect, EffectType},
+        effects::{self, StackPolicy},
+        inventory::Inventory,
+    },
+    utils::logger::{self, MessageKind},
 };
 
 pub const DEX_BONUS_DMG_MULTIPLIER: f32 = 0.7;
@@ -13,7 +19,11 @@ pub const STR_BONUS_DMG_MULTIPLIER: f32 = 0.2;
 pub const BONUS_DMG_SCALE: f32 = 0.7;
 pub const DMG_SPAN_FACTOR: f32 = 1.25;
 pub const BASE_CRIT_CHANCE: f64 = 0.05;
-pub const BASE_CRIT_MULTIPLIER: f32 = 1.5;
+pub const CRIT_MULTIPLIER: f32 = 1.5;
+pub const BASE_MISS_CHANCE: f64 = 0.25;
+pub const DEX_MISS_REDUCTION: f64 = 0.03;
+pub const BASE_DISARM_CHANCE: f64 = 0.5;
+pub const DEX_DISARM_BONUS: f64 = 0.1;
 
 #[derive(Debug, Clone)]
 pub struct Combat {
@@ -29,6 +39,34 @@ impl Combat {
             ..Default::default()
         }
     }
+
+    pub fn new_unarmed() -> Self {
+        Combat {
+            melee: None,
+            ranged: None,
+        }
+    }
+
+    // a ranged attack without ammo never runs out
+    pub fn has_ammo(&self) -> bool {
+        self.ranged
+            .is_some_and(|attack| attack.ammo.is_none_or(|ammo| ammo.current > 0))
+    }
+
+    // None when there is no ammo to keep track of
+    pub fn spend_ammo(&self) -> Option<Combat> {
+        let mut ranged = self.ranged?;
+        let ammo = ranged.ammo.as_mut()?;
+        ammo.current = (ammo.current - 1).max(0);
+        Some(Combat { melee: self.melee, ranged: Some(ranged) })
+    }
+
+    pub fn restock_ammo(&self) -> Option<Combat> {
+        let mut ranged = self.ranged?;
+        let ammo = ranged.ammo.as_mut()?;
+        ammo.current = ammo.max;
+        Some(Combat { melee: self.melee, ranged: Some(ranged) })
+    }
 }
 
 impl Default for Combat {
@@ -67,6 +105,11 @@ impl Health {
             ..Default::default()
         }
     }
+
+    // The one death threshold, for the player and everything else alike.
+    pub fn is_dead(&self) -> bool {
+        self.current <= 0
+    }
 }
 
 impl Diffable for Health {
@@ -101,6 +144,28 @@ pub struct AttackReport {
     pub damage_type: DamageType,
     pub hit_message: &'static str,
     pub range: Option<f32>,
+    pub knockback: u32,
+    // crits are rolled by whoever takes the hit
+    pub crit_chance: f64,
+    pub crit_multiplier: f32,
+    pub crit_message: &'static str,
+    pub lifesteal: f32,
+    pub bleed: isize,
+}
+
+impl AttackReport {
+    pub fn message(&self, critical: bool) -> &'static str {
+        match critical {
+            true => self.crit_message,
+            false => self.hit_message,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ammo {
+    pub current: isize,
+    pub max: isize,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -112,6 +177,13 @@ pub struct Attack {
     pub damage_type: DamageType,
     pub hit_messages: HitMessages,
     pub max_range: f32,
+    pub knockback: u32,
+    // fraction of the damage dealt that heals the attacker
+    pub lifesteal: f32,
+    // bleed stacks added by every hit that gets through
+    pub bleed: isize,
+    // shots left before restocking, None never runs out
+    pub ammo: Option<Ammo>,
 }
 
 impl Attack {
@@ -134,6 +206,22 @@ impl Attack {
             ..Default::default()
         }
     }
+
+    pub fn with_knockback(self, knockback: u32) -> Self {
+        Attack { knockback, ..self }
+    }
+
+    pub fn with_lifesteal(self, lifesteal: f32) -> Self {
+        Attack { lifesteal, ..self }
+    }
+
+    pub fn with_bleed(self, bleed: isize) -> Self {
+        Attack { bleed, ..self }
+    }
+
+    pub fn with_ammo(self, max: isize) -> Self {
+        Attack { ammo: Some(Ammo { current: max, max }), ..self }
+    }
 }
 
 pub fn get_bonus_dmg(attr: &Attributes, attack: &Attack) -> (isize, isize) {
@@ -154,37 +242,47 @@ pub fn get_bonus_dmg(attr: &Attributes, attack: &Attack) -> (isize, isize) {
     )
 }
 
-pub fn get_damage_multiplier(critical: bool) -> f32 {
-    if critical {
-        BASE_CRIT_MULTIPLIER
-    } else {
-        1.0
+pub fn get_crit_multiplier(attack: &Attack) -> f32 {
+    CRIT_MULTIPLIER + attack.crit_multiplier_bonus
+}
+
+// only attackers with stats can land crits
+pub fn get_crit_chance(attack: &Attack, attributes: Option<&Attributes>) -> f64 {
+    match attributes {
+        Some(_) => (BASE_CRIT_CHANCE + attack.crit_chance_bonus).clamp(0.0, 1.0),
+        None => 0.0,
     }
 }
 
-fn get_damage(attack: &Attack, attributes: Option<&Attributes>, critical: bool) -> isize {
+fn get_damage(attack: &Attack, attributes: Option<&Attributes>) -> isize {
     let mut bonus_damage = (0, 0);
-    let mut damage_multiplier = 1.0;
     if let Some(stats) = attributes {
         bonus_damage = get_bonus_dmg(&stats, attack);
-        damage_multiplier = get_damage_multiplier(critical);
-        if critical {
-            damage_multiplier += attack.crit_multiplier_bonus;
-        }
     }
     let rand_factor =
         thread_rng().gen_range(0..=attack.damage_spread + (bonus_damage.1 - bonus_damage.0));
-    let raw_damage = attack.damage_base + bonus_damage.0 + rand_factor;
-    (raw_damage as f32 * damage_multiplier) as isize
+    attack.damage_base + bonus_damage.0 + rand_factor
 }
 
-pub fn crit_roll(attack: &Attack, attributes: Option<&Attributes>) -> bool {
-    if let Some(stats) = attributes {
-        let crit_chance = BASE_CRIT_CHANCE + attack.crit_chance_bonus;
-        thread_rng().gen_bool(crit_chance)
-    } else {
-        false
-    }
+// The midpoint of get_damage's roll, crits left out.
+pub fn average_damage(attack: &Attack, attributes: Option<&Attributes>) -> f32 {
+    let bonus_damage = attributes.map_or((0, 0), |stats| get_bonus_dmg(stats, attack));
+    let spread = attack.damage_spread + (bonus_damage.1 - bonus_damage.0);
+    (attack.damage_base + bonus_damage.0) as f32 + spread as f32 / 2.0
+}
+
+pub fn crit_roll(attack: &AttackReport, rng: &mut impl Rng) -> bool {
+    attack.crit_chance > 0.0 && rng.gen_bool(attack.crit_chance.min(1.0))
+}
+
+pub fn ranged_miss_chance(attributes: Option<&Attributes>) -> f64 {
+    let adj_dexterity = attributes.map_or(0, |stats| stats.dexterity - 5);
+    (BASE_MISS_CHANCE - DEX_MISS_REDUCTION * adj_dexterity as f64).clamp(0.05, 0.5)
+}
+
+pub fn disarm_chance(attributes: Option<&Attributes>) -> f64 {
+    let adj_dexterity = attributes.map_or(0, |stats| stats.dexterity - 5);
+    (BASE_DISARM_CHANCE + DEX_DISARM_BONUS * adj_dexterity as f64).clamp(0.1, 1.0)
 }
 
 pub fn calculate_melee_attack(
@@ -218,28 +316,132 @@ fn calculate_attack(
     attributes: Option<&Attributes>,
     range: Option<f32>,
 ) -> AttackReport {
-    let critical: bool = crit_roll(attack, attributes);
     AttackReport {
-        damage: get_damage(attack, attributes, critical),
+        damage: get_damage(attack, attributes),
         damage_type: attack.damage_type,
-        hit_message: if critical {
-            attack.hit_messages.crit
-        } else {
-            attack.hit_messages.default
-        },
+        hit_message: attack.hit_messages.default,
         range,
+        knockback: attack.knockback,
+        crit_chance: get_crit_chance(attack, attributes),
+        crit_multiplier: get_crit_multiplier(attack),
+        crit_message: attack.hit_messages.crit,
+        lifesteal: attack.lifesteal,
+        bleed: attack.bleed,
     }
 }
 pub fn default_calculate_armor(
-    _damage_type: DamageType,
-    _maybe_stats: Option<&IndexedData<Attributes>>,
+    damage_type: DamageType,
+    maybe_stats: Option<&IndexedData<Attributes>>,
     _maybe_items: Option<&IndexedData<Inventory>>,
 ) -> f32 {
-    0.0
+    // armor only stops blows, spells go straight through
+    match (damage_type, maybe_stats) {
+        (DamageType::Physical, Some(stats)) => stats.data.armor as f32,
+        _ => 0.0,
+    }
 }
 
-pub fn default_calculate_reduction(damage: isize, _armor: f32) -> isize {
-    damage
+pub fn default_calculate_reduction(damage: isize, armor: f32) -> isize {
+    if damage <= 0 {
+        return damage;
+    }
+    // armor never fully negates a hit
+    (damage - armor as isize).max(1)
+}
+
+pub fn find_shield<'a>(components: &[&'a Component]) -> Option<&'a IndexedData<DurationEffect>> {
+    components.iter().find_map(|component| match component {
+        Component::DurationEffect(effect) if matches!(effect.data.1, EffectType::Shield(_)) => {
+            Some(effect)
+        }
+        _ => None,
+    })
+}
+
+pub fn is_disarmed(components: &[&Component]) -> bool {
+    components.iter().any(|component| {
+        matches!(component, Component::DurationEffect(effect) if effect.data.1 == EffectType::Disarmed)
+    })
+}
+
+// a shield soaks up damage before it reaches health
+fn apply_damage(
+    damage: isize,
+    health: &IndexedData<Health>,
+    maybe_shield: Option<&IndexedData<DurationEffect>>,
+) -> (Vec<Delta>, isize) {
+    let mut delta = vec![];
+    let mut damage = damage;
+
+    if let Some(shield) = maybe_shield {
+        if let DurationEffect(_, EffectType::Shield(left)) = shield.data {
+            let absorbed = left.min(damage).max(0);
+            if absorbed > 0 {
+                logger::log_message(&format!("The shield absorbs {} damage.", absorbed));
+                delta.push(Delta::Change(Component::DurationEffect(
+                    shield.make_change(DurationEffect(0, EffectType::Shield(-absorbed))),
+                )));
+                damage -= absorbed;
+            }
+        }
+    }
+
+    if damage > 0 {
+        let damage_taken = Health {
+            current: -damage,
+            max: 0,
+        };
+        delta.push(Delta::Change(Component::Health(
+            health.make_change(damage_taken),
+        )));
+    }
+    (delta, damage)
+}
+
+// The attacker's components ride along in the event payload.
+fn lifesteal_heal(attack: &AttackReport, damage_taken: isize, attacker: &[Component]) -> Vec<Delta> {
+    if attack.lifesteal <= 0.0 || damage_taken <= 0 {
+        return vec![];
+    }
+    let Some(Component::Health(health)) = attacker.iter().find(|comp| matches!(comp, Component::Health(_))) else {
+        return vec![];
+    };
+    let missing = (health.data.max - health.data.current).max(0);
+    let heal = ((damage_taken as f32 * attack.lifesteal) as isize).min(missing);
+    if heal <= 0 {
+        return vec![];
+    }
+    vec![Delta::Change(Component::Health(health.make_change(Health {
+        current: heal,
+        max: 0,
+    })))]
+}
+
+// Fresh wounds stack onto whatever is already bleeding.
+pub fn inflict_bleed(
+    attack: &AttackReport,
+    damage_taken: isize,
+    health: &IndexedData<Health>,
+    defender: &[&Component],
+) -> Vec<Delta> {
+    if attack.bleed <= 0 || damage_taken <= 0 {
+        return vec![];
+    }
+    effects::apply_effect(
+        EntityIdentifier::new_from_component(health.index),
+        defender,
+        DurationEffect(0, EffectType::Bleed(attack.bleed)),
+        StackPolicy::Stack,
+    )
+}
+
+// Returns the raw damage of the hit and whether it was critical.
+fn roll_damage(attack: &AttackReport, rng: &mut impl Rng) -> (isize, bool) {
+    if !crit_roll(attack, rng) {
+        return (attack.damage, false);
+    }
+    logger::log_message_with("Critical hit!", MessageKind::Combat);
+    ((attack.damage as f32 * attack.crit_multiplier) as isize, true)
 }
 
 pub fn default_take_damage(
@@ -247,20 +449,16 @@ pub fn default_take_damage(
     health: &IndexedData<Health>,
     maybe_stats: Option<&IndexedData<Attributes>>,
     maybe_items: Option<&IndexedData<Inventory>>,
-) -> (Vec<Delta>, isize) {
+    maybe_shield: Option<&IndexedData<DurationEffect>>,
+    attacker: &[Component],
+    rng: &mut impl Rng,
+) -> (Vec<Delta>, isize, bool) {
+    let (damage, critical) = roll_damage(attack, rng);
     let armor = default_calculate_armor(attack.damage_type, maybe_stats, maybe_items);
-    let reduced_damage = default_calculate_reduction(attack.damage, armor);
-    let damage_taken = Health {
-        current: -reduced_damage,
-        max: 0,
-    };
-
-    (
-        vec![Delta::Change(Component::Health(
-            health.make_change(damage_taken),
-        ))],
-        reduced_damage,
-    )
+    let reduced_damage = default_calculate_reduction(damage, armor);
+    let (mut delta, damage_taken) = apply_damage(reduced_damage, health, maybe_shield);
+    delta.extend(lifesteal_heal(attack, damage_taken, attacker));
+    (delta, damage_taken, critical)
 }
 
 pub fn default_take_half_damage(
@@ -268,20 +466,16 @@ pub fn default_take_half_damage(
     health: &IndexedData<Health>,
     maybe_stats: Option<&IndexedData<Attributes>>,
     maybe_items: Option<&IndexedData<Inventory>>,
-) -> (Vec<Delta>, isize) {
+    maybe_shield: Option<&IndexedData<DurationEffect>>,
+    attacker: &[Component],
+    rng: &mut impl Rng,
+) -> (Vec<Delta>, isize, bool) {
+    let (damage, critical) = roll_damage(attack, rng);
     let armor = default_calculate_armor(attack.damage_type, maybe_stats, maybe_items);
-    let reduced_damage = default_calculate_reduction(attack.damage, armor) / 2;
-    let damage_taken = Health {
-        current: -reduced_damage,
-        max: 0,
-    };
-
-    (
-        vec![Delta::Change(Component::Health(
-            health.make_change(damage_taken),
-        ))],
-        reduced_damage,
-    )
+    let reduced_damage = (default_calculate_reduction(damage, armor) / 2).max(1);
+    let (mut delta, damage_taken) = apply_damage(reduced_damage, health, maybe_shield);
+    delta.extend(lifesteal_heal(attack, damage_taken, attacker));
+    (delta, damage_taken, critical)
 }
 
 pub fn default_take_double_damage(
@@ -289,18 +483,117 @@ pub fn default_take_double_damage(
     health: &IndexedData<Health>,
     maybe_stats: Option<&IndexedData<Attributes>>,
     maybe_items: Option<&IndexedData<Inventory>>,
-) -> (Vec<Delta>, isize) {
+    maybe_shield: Option<&IndexedData<DurationEffect>>,
+    attacker: &[Component],
+    rng: &mut impl Rng,
+) -> (Vec<Delta>, isize, bool) {
+    let (damage, critical) = roll_damage(attack, rng);
     let armor = default_calculate_armor(attack.damage_type, maybe_stats, maybe_items);
-    let reduced_damage = default_calculate_reduction(attack.damage, armor) * 2;
-    let damage_taken = Health {
-        current: -reduced_damage,
-        max: 0,
-    };
+    let reduced_damage = default_calculate_reduction(damage, armor) * 2;
+    let (mut delta, damage_taken) = apply_damage(reduced_damage, health, maybe_shield);
+    delta.extend(lifesteal_heal(attack, damage_taken, attacker));
+    (delta, damage_taken, critical)
+}
 
-    (
-        vec![Delta::Change(Component::Health(
-            health.make_change(damage_taken),
-        ))],
-        reduced_damage,
-    )
+#[cfg(test)]
+mod tests {
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+
+    fn armored(armor: isize) -> IndexedData<Attributes> {
+        IndexedData::new_with(Attributes { armor, ..Default::default() })
+    }
+
+    #[test]
+    fn armor_reduces_physical_damage() {
+        let stats = armored(3);
+        let armor = default_calculate_armor(DamageType::Physical, Some(&stats), None);
+        assert_eq!(default_calculate_reduction(5, armor), 2);
+        // but never below a single point
+        assert_eq!(default_calculate_reduction(2, armor), 1);
+    }
+
+    #[test]
+    fn armor_takes_its_share_of_a_hit() {
+        let health = IndexedData::new_with(Health { current: 20, max: 20 });
+        let stats = armored(2);
+        let mut never = StepRng::new(u64::MAX, 0);
+        let attack = AttackReport { damage: 5, crit_chance: 0.0, ..Default::default() };
+        let (_, damage_taken, _) = default_take_damage(&attack, &health, Some(&stats), None, None, &[], &mut never);
+        assert_eq!(damage_taken, 3);
+
+        // halving a single point still leaves a scratch
+        let attack = AttackReport { damage: 3, ..attack };
+        let (_, damage_taken, _) = default_take_half_damage(&attack, &health, Some(&stats), None, None, &[], &mut never);
+        assert_eq!(damage_taken, 1);
+    }
+
+    #[test]
+    fn armor_ignores_magical_damage() {
+        let stats = armored(3);
+        let armor = default_calculate_armor(DamageType::Magical, Some(&stats), None);
+        assert_eq!(default_calculate_reduction(5, armor), 5);
+    }
+
+    fn shield_left(deltas: &[Delta]) -> isize {
+        deltas.iter().find_map(|delta| match delta {
+            Delta::Change(Component::DurationEffect(effect)) => match effect.data.1 {
+                EffectType::Shield(change) => Some(change),
+                _ => None,
+            },
+            _ => None,
+        }).unwrap_or(0)
+    }
+
+    fn health_lost(deltas: &[Delta]) -> isize {
+        deltas.iter().find_map(|delta| match delta {
+            Delta::Change(Component::Health(health)) => Some(-health.data.current),
+            _ => None,
+        }).unwrap_or(0)
+    }
+
+    #[test]
+    fn shield_absorbs_hits_before_health() {
+        let health = IndexedData::new_with(Health { current: 20, max: 20 });
+        let mut shield = IndexedData::new_with(DurationEffect(10, EffectType::Shield(10)));
+
+        let (deltas, through) = apply_damage(6, &health, Some(&shield));
+        assert_eq!((shield_left(&deltas), health_lost(&deltas), through), (-6, 0, 0));
+
+        shield.data = DurationEffect(10, EffectType::Shield(4));
+        let (deltas, through) = apply_damage(6, &health, Some(&shield));
+        assert_eq!((shield_left(&deltas), health_lost(&deltas), through), (-4, 2, 2));
+    }
+
+    #[test]
+    fn crits_follow_the_rng() {
+        let health = IndexedData::new_with(Health { current: 50, max: 50 });
+        let attack = AttackReport { damage: 10, crit_chance: 0.5, crit_multiplier: 2.0, ..Default::default() };
+        let mut always = StepRng::new(0, 0);
+        let mut never = StepRng::new(u64::MAX, 0);
+
+        let (_, damage_taken, critical) = default_take_damage(&attack, &health, None, None, None, &[], &mut always);
+        assert_eq!((damage_taken, critical), (20, true));
+        let (_, damage_taken, critical) = default_take_damage(&attack, &health, None, None, None, &[], &mut never);
+        assert_eq!((damage_taken, critical), (10, false));
+    }
+
+    fn vampire_heal(current: isize) -> isize {
+        let health = IndexedData::new_with(Health { current: 20, max: 20 });
+        let vampire = [Component::Health(IndexedData::new_with(Health { current, max: 10 }))];
+        let attack = AttackReport { damage: 4, lifesteal: 0.5, crit_chance: 0.0, ..Default::default() };
+        let (deltas, _, _) = default_take_damage(&attack, &health, None, None, None, &vampire, &mut thread_rng());
+        deltas.iter().filter_map(|delta| match delta {
+            Delta::Change(Component::Health(change)) if change.data.current > 0 => Some(change.data.current),
+            _ => None,
+        }).sum()
+    }
+
+    #[test]
+    fn lifesteal_heals_up_to_max_health() {
+        assert_eq!(vampire_heal(5), 2);
+        assert_eq!(vampire_heal(9), 1);
+        assert_eq!(vampire_heal(10), 0);
+    }
 }
@@ -7,8 +7,12 @@ use crate::{
         attributes::Attributes,
         behavior::TurnTaker,
         combat::{Combat, Health},
-        inventory::Inventory,
+        hunger::Hunger,
+        inventory::{DropTable, Equipment, Inventory, Shop},
+        projectile::Projectile,
+        stats::RunStats,
     },
+    game::tileatlas,
     map::utils::Coordinate,
 };
 
@@ -21,7 +25,11 @@ pub enum Component {
     Player(IndexedData<()>),
     Monster(IndexedData<()>),
     Door(IndexedData<()>),
+    Secret(IndexedData<()>),
+    Locked(IndexedData<()>),
     Stairs(IndexedData<()>),
+    Swappable(IndexedData<()>),
+    Trap(IndexedData<TrapKind>),
     Name(IndexedData<Name>),
     Spell(IndexedData<Spell>),
     Inventory(IndexedData<Inventory>),
@@ -38,6 +46,14 @@ pub enum Component {
     DeathResponse(IndexedData<EventResponse>),
     FireResponse(IndexedData<EventResponse>),
     DurationEffect(IndexedData<DurationEffect>),
+    Size(IndexedData<Size>),
+    Hunger(IndexedData<Hunger>),
+    Projectile(IndexedData<Projectile>),
+    RunStats(IndexedData<RunStats>),
+    Equipment(IndexedData<Equipment>),
+    Shop(IndexedData<Shop>),
+    DropTable(IndexedData<DropTable>),
+    Corpse(IndexedData<CorpseKind>),
 }
 
 impl Component {
@@ -46,7 +62,11 @@ impl Component {
             Component::Player(data) => data.index.borrow_mut(),
             Component::Monster(data) => data.index.borrow_mut(),
             Component::Door(data) => data.index.borrow_mut(),
+            Component::Secret(data) => data.index.borrow_mut(),
+            Component::Locked(data) => data.index.borrow_mut(),
             Component::Stairs(data) => data.index.borrow_mut(),
+            Component::Swappable(data) => data.index.borrow_mut(),
+            Component::Trap(data) => data.index.borrow_mut(),
             Component::Name(data) => data.index.borrow_mut(),
             Component::Spell(data) => data.index.borrow_mut(),
             Component::Inventory(data) => data.index.borrow_mut(),
@@ -63,6 +83,14 @@ impl Component {
             Component::DeathResponse(data) => data.index.borrow_mut(),
             Component::FireResponse(data) => data.index.borrow_mut(),
             Component::DurationEffect(data) => data.index.borrow_mut(),
+            Component::Size(data) => data.index.borrow_mut(),
+            Component::Hunger(data) => data.index.borrow_mut(),
+            Component::Projectile(data) => data.index.borrow_mut(),
+            Component::RunStats(data) => data.index.borrow_mut(),
+            Component::Equipment(data) => data.index.borrow_mut(),
+            Component::Shop(data) => data.index.borrow_mut(),
+            Component::DropTable(data) => data.index.borrow_mut(),
+            Component::Corpse(data) => data.index.borrow_mut(),
         };
         *stored_id = id;
     }
@@ -72,7 +100,11 @@ impl Component {
             Component::Player(data) => data.index,
             Component::Monster(data) => data.index,
             Component::Door(data) => data.index,
+            Component::Secret(data) => data.index,
+            Component::Locked(data) => data.index,
             Component::Stairs(data) => data.index,
+            Component::Swappable(data) => data.index,
+            Component::Trap(data) => data.index,
             Component::Name(data) => data.index,
             Component::Spell(data) => data.index,
             Component::Inventory(data) => data.index,
@@ -89,6 +121,14 @@ impl Component {
             Component::DeathResponse(data) => data.index,
             Component::FireResponse(data) => data.index,
             Component::DurationEffect(data) => data.index,
+            Component::Size(data) => data.index,
+            Component::Hunger(data) => data.index,
+            Component::Projectile(data) => data.index,
+            Component::RunStats(data) => data.index,
+            Component::Equipment(data) => data.index,
+            Component::Shop(data) => data.index,
+            Component::DropTable(data) => data.index,
+            Component::Corpse(data) => data.index,
         }
     }
 
@@ -97,6 +137,55 @@ impl Component {
     }
 }
 
+// Payload types that belong to exactly one variant, so they can be looked up by type.
+// Markers and event responses share their payload between variants and stay untyped.
+pub trait ComponentData: Default {
+    const TYPE: ComponentType;
+
+    fn from_component(component: &Component) -> Option<&IndexedData<Self>>;
+}
+
+macro_rules! component_data {
+    ($($data:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl ComponentData for $data {
+                const TYPE: ComponentType = ComponentType::$variant;
+
+                fn from_component(component: &Component) -> Option<&IndexedData<Self>> {
+                    match component {
+                        Component::$variant(data) => Some(data),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+component_data!(
+    TrapKind => Trap,
+    Name => Name,
+    Spell => Spell,
+    Inventory => Inventory,
+    Combat => Combat,
+    ImageHandle => Image,
+    Coordinate => Position,
+    Health => Health,
+    TurnTaker => Turn,
+    Collision => Collision,
+    LoSBlocking => LineOfSight,
+    Attributes => Attributes,
+    DurationEffect => DurationEffect,
+    Size => Size,
+    Hunger => Hunger,
+    Projectile => Projectile,
+    RunStats => RunStats,
+    Equipment => Equipment,
+    Shop => Shop,
+    DropTable => DropTable,
+    CorpseKind => Corpse,
+);
+
 impl Diffable for Component {
     fn apply_diff(&mut self, other: &Self) {
         assert!(self.get_id() == other.get_id());
@@ -115,6 +204,12 @@ impl Diffable for Component {
             (Self::Inventory(data), Self::Inventory(other_data)) => {
                 data.data.apply_diff(&other_data.data);
             }
+            (Self::Hunger(data), Self::Hunger(other_data)) => {
+                data.data.apply_diff(&other_data.data);
+            }
+            (Self::RunStats(data), Self::RunStats(other_data)) => {
+                data.data.apply_diff(&other_data.data);
+            }
             (Self::Position(data), Self::Position(other_data)) => {
                 data.data.apply_diff(&other_data.data);
             }
@@ -126,9 +221,16 @@ impl Diffable for Component {
             (Self::Name(data), Self::Name(other_data)) => data.data = other_data.data.clone(),
             (Self::Turn(data), Self::Turn(other_data)) => data.data = other_data.data.clone(),
             (Self::Spell(data), Self::Spell(other_data)) => data.data = other_data.data.clone(),
+            (Self::Size(data), Self::Size(other_data)) => data.data = other_data.data.clone(),
+            (Self::Projectile(data), Self::Projectile(other_data)) => data.data = other_data.data.clone(),
+            (Self::Shop(data), Self::Shop(other_data)) => data.data = other_data.data.clone(),
+            (Self::DropTable(data), Self::DropTable(other_data)) => data.data = other_data.data.clone(),
             // Copy overwrite types
             (Self::Collision(data), Self::Collision(other_data)) => data.data = other_data.data,
             (Self::LineOfSight(data), Self::LineOfSight(other_data)) => data.data = other_data.data,
+            (Self::Trap(data), Self::Trap(other_data)) => data.data = other_data.data,
+            (Self::Corpse(data), Self::Corpse(other_data)) => data.data = other_data.data,
+            (Self::Equipment(data), Self::Equipment(other_data)) => data.data = other_data.data,
 
             (Self::BumpResponse(data), Self::BumpResponse(other_data)) => {
                 data.data = other_data.data;
@@ -200,15 +302,124 @@ impl ImageData {
     }
 }
 
+// Traps stay hidden (and imageless) until triggered or found by searching
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrapKind {
+    #[default]
+    Dart,
+    Pit,
+}
+
+impl TrapKind {
+    pub fn image(&self) -> ImageData {
+        match self {
+            TrapKind::Dart => tileatlas::image("dart_trap"),
+            TrapKind::Pit => tileatlas::image("pit_trap"),
+        }
+    }
+}
+
+// What a corpse used to be, which decides whether it can be raised
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CorpseKind {
+    #[default]
+    Humanoid,
+    Beast,
+}
+
+// Tiles a large entity covers beyond its position, as offsets from it
+#[derive(Debug, Clone, Default)]
+pub struct Size {
+    pub extra_tiles: Vec<Coordinate>,
+}
+
+impl Size {
+    pub fn square(side: i32) -> Self {
+        let extra_tiles = (0..side)
+            .flat_map(|x| (0..side).map(move |y| Coordinate { x, y }))
+            .filter(|offset| *offset != Coordinate::default())
+            .collect();
+        Self { extra_tiles }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Article {
+    #[default]
+    A,
+    An,
+    Some, // plurals
+}
+
+impl Article {
+    fn for_word(word: &str) -> Self {
+        match word.chars().next().map(|c| c.to_ascii_lowercase()) {
+            Some('a' | 'e' | 'i' | 'o' | 'u') => Article::An,
+            _ => Article::A,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Article::A => "a",
+            Article::An => "an",
+            Article::Some => "some",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Name {
     pub raw: String,
+    pub article: Article,
+    // proper nouns never take an article
+    pub proper: bool,
 }
 
 impl Name {
+    // "a" or "an" is guessed from the first letter
     pub fn new(name: &str) -> Self {
         Self {
             raw: name.to_string(),
+            article: Article::for_word(name),
+            proper: false,
+        }
+    }
+
+    pub fn proper(name: &str) -> Self {
+        Self {
+            proper: true,
+            ..Name::new(name)
+        }
+    }
+
+    pub fn with_article(self, article: Article) -> Self {
+        Self { article, ..self }
+    }
+
+    // "the skeleton", or just "Bartholomew"
+    pub fn definite(&self) -> String {
+        match self.proper {
+            true => self.raw.clone(),
+            false => format!("the {}", self.raw.to_lowercase()),
+        }
+    }
+
+    // "a skeleton", "an acid pool"
+    pub fn indefinite(&self) -> String {
+        match self.proper {
+            true => self.raw.clone(),
+            false => format!("{} {}", self.article.as_str(), self.raw.to_lowercase()),
+        }
+    }
+
+    // the definite form for the start of a sentence
+    pub fn subject(&self) -> String {
+        let definite = self.definite();
+        let mut chars = definite.chars();
+        match chars.next() {
+            Some(first) => first.to_uppercase().chain(chars).collect(),
+            None => definite,
         }
     }
 }
@@ -237,7 +448,20 @@ pub struct DurationEffect(pub isize, pub EffectType);
 impl Add<DurationEffect> for DurationEffect {
     type Output = DurationEffect;
     fn add(self, rhs: DurationEffect) -> Self::Output {
-        DurationEffect(self.0 + rhs.0, self.1)
+        // shields also carry how much damage they have left to soak up
+        match (self.1, rhs.1) {
+            (EffectType::Shield(left), EffectType::Shield(change)) => {
+                DurationEffect(self.0 + rhs.0, EffectType::Shield(left + change))
+            }
+            // and bleeding how many stacks are left to tick down
+            (EffectType::Bleed(stacks), EffectType::Bleed(change)) => {
+                DurationEffect(self.0 + rhs.0, EffectType::Bleed(stacks + change))
+            }
+            (EffectType::Regen(amount), EffectType::Regen(change)) => {
+                DurationEffect(self.0 + rhs.0, EffectType::Regen(amount + change))
+            }
+            _ => DurationEffect(self.0 + rhs.0, self.1),
+        }
     }
 
 }
@@ -248,7 +472,7 @@ impl AddAssign<DurationEffect> for DurationEffect {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum EffectType {
     #[default]
     None,
@@ -257,4 +481,55 @@ pub enum EffectType {
     Levitate,
     Stoneskin,
     Acid,
+    Frozen,
+    Haste,
+    Confused,
+    Regen(isize),
+    Shield(isize),
+    Bleed(isize), // stacks, also the damage of the next tick
+    Disarmed,
+    Summoned, // the entity vanishes when this runs out
+    Grappled, // held aloft by a spell, can't act
+}
+
+impl EffectType {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            EffectType::None => "unaffected",
+            EffectType::Burning => "burning",
+            EffectType::Invisible => "invisible",
+            EffectType::Levitate => "levitating",
+            EffectType::Stoneskin => "stoneskinned",
+            EffectType::Acid => "covered in acid",
+            EffectType::Frozen => "frozen",
+            EffectType::Haste => "hasted",
+            EffectType::Confused => "confused",
+            EffectType::Regen(_) => "regenerating",
+            EffectType::Shield(_) => "shielded",
+            EffectType::Bleed(_) => "bleeding",
+            EffectType::Disarmed => "disarmed",
+            EffectType::Summoned => "summoned",
+            EffectType::Grappled => "held aloft",
+        }
+    }
+
+    // The icon drawn on top of an affected entity, if the effect shows at all.
+    pub fn overlay_image(&self) -> Option<ImageData> {
+        let name = match self {
+            EffectType::Burning => "burning_overlay",
+            EffectType::Stoneskin => "stoneskin_overlay",
+            EffectType::Levitate | EffectType::Grappled => "levitate_overlay",
+            EffectType::Acid => "acid_overlay",
+            EffectType::Frozen => "frozen_overlay",
+            EffectType::Haste => "haste_overlay",
+            EffectType::Confused => "confused_overlay",
+            EffectType::Regen(_) => "regen_overlay",
+            EffectType::Shield(_) => "shield_overlay",
+            EffectType::Invisible => "invisible_overlay",
+            EffectType::Bleed(_) => "bleed_overlay",
+            EffectType::Disarmed => "disarmed_overlay",
+            EffectType::None | EffectType::Summoned => return None,
+        };
+        Some(tileatlas::image(name))
+    }
 }
\ No newline at end of file
@@ -0,0 +1,81 @@
+use std::mem;
+
+use crate::{
+    ecs::ecs::{Delta, EntityIdentifier, IndexedData, MakeComponentOrder},
+    game::components::core::{Component, DurationEffect, EffectType},
+};
+
+// What happens when an effect lands on someone who already has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackPolicy {
+    Refresh, // duration goes back up to the new one, never down
+    Extend,  // durations add up
+    Stack,   // potency adds up, duration refreshes
+    Ignore,  // the first application is all there is
+}
+
+// Adds the effect or folds it into the matching one already on the entity.
+pub fn apply_effect(
+    entity: EntityIdentifier,
+    components: &[&Component],
+    effect: DurationEffect,
+    policy: StackPolicy,
+) -> Vec<Delta> {
+    let existing = components.iter().find_map(|component| match component {
+        Component::DurationEffect(current) if mem::discriminant(&current.data.1) == mem::discriminant(&effect.1) => {
+            Some(current)
+        }
+        _ => None,
+    });
+    let Some(current) = existing else {
+        return vec![Delta::MakeComponent(MakeComponentOrder {
+            component: Component::DurationEffect(IndexedData::new_with(effect)),
+            entity,
+        })];
+    };
+
+    let DurationEffect(duration, _) = current.data;
+    let refreshed = effect.0.max(duration) - duration;
+    // EffectType::None leaves the kind and potency alone when added
+    let mut change = match policy {
+        StackPolicy::Ignore => return vec![],
+        StackPolicy::Refresh => DurationEffect(refreshed, EffectType::None),
+        StackPolicy::Extend => DurationEffect(effect.0, EffectType::None),
+        StackPolicy::Stack => DurationEffect(refreshed, effect.1),
+    };
+    // negative durations never run out
+    if duration < 0 {
+        change.0 = 0;
+    }
+    vec![Delta::Change(Component::DurationEffect(current.make_change(change)))]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::testing, map::utils::Coordinate};
+
+    // Lands a 5 turn, 1 stack bleed on a doggo already bleeding 2 stacks for 3 turns.
+    fn reapply(policy: StackPolicy) -> (isize, EffectType) {
+        let mut game = testing::arena();
+        let dog = testing::spawn(&mut game, "Doggo", Coordinate { x: 10, y: 4 });
+        let entity = EntityIdentifier::new_from_entity(dog);
+        let components = game.ecs.get_components_from_entity_id(dog);
+        let deltas = apply_effect(entity.clone(), &components, DurationEffect(3, EffectType::Bleed(2)), policy);
+        game.ecs.apply_changes(deltas);
+
+        let components = game.ecs.get_components_from_entity_id(dog);
+        let deltas = apply_effect(entity, &components, DurationEffect(5, EffectType::Bleed(1)), policy);
+        game.ecs.apply_changes(deltas);
+        let DurationEffect(duration, effect) = game.ecs.get_typed::<DurationEffect>(dog).unwrap().data;
+        (duration, effect)
+    }
+
+    #[test]
+    fn each_policy_folds_reapplication_its_own_way() {
+        assert_eq!(reapply(StackPolicy::Refresh), (5, EffectType::Bleed(2)));
+        assert_eq!(reapply(StackPolicy::Extend), (8, EffectType::Bleed(2)));
+        assert_eq!(reapply(StackPolicy::Stack), (5, EffectType::Bleed(3)));
+        assert_eq!(reapply(StackPolicy::Ignore), (3, EffectType::Bleed(2)));
+    }
+}
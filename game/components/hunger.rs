@@ -0,0 +1,34 @@
+use crate::ecs::component::Diffable;
+
+pub const HUNGER_MAX: isize = 600;
+pub const HUNGRY_THRESHOLD: isize = 100;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hunger {
+    pub current: isize,
+    pub max: isize,
+}
+
+impl Hunger {
+    pub fn new(max: isize) -> Self {
+        Hunger { current: max, max }
+    }
+
+    pub fn feed_diff(&self, amount: isize) -> Self {
+        Hunger {
+            current: amount.min(self.max - self.current),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_starving(&self) -> bool {
+        self.current <= 0
+    }
+}
+
+impl Diffable for Hunger {
+    fn apply_diff(&mut self, other: &Self) {
+        self.current += other.current;
+        self.max += other.max;
+    }
+}
@@ -1,8 +1,23 @@
+use rand::{thread_rng, Rng};
+
 use crate::ecs::component::Diffable;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemStack {
+    pub item_id: u32,
+    pub count: isize,
+}
+
+impl ItemStack {
+    pub fn new(item_id: u32, count: isize) -> Self {
+        Self { item_id, count }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Inventory {
     pub coins: isize,
+    pub items: Vec<ItemStack>,
 }
 
 impl Inventory {
@@ -16,19 +31,110 @@ impl Inventory {
     pub fn inverse(&self) -> Self {
         Inventory {
             coins: -self.coins,
-            ..Default::default()
+            items: self
+                .items
+                .iter()
+                .map(|stack| ItemStack::new(stack.item_id, -stack.count))
+                .collect(),
         }
     }
 }
 
 impl Default for Inventory {
     fn default() -> Self {
-        Inventory { coins: 0 }
+        Inventory {
+            coins: 0,
+            items: vec![],
+        }
     }
 }
 
 impl Diffable for Inventory {
     fn apply_diff(&mut self, other: &Self) {
         self.coins += other.coins;
+        for other_stack in &other.items {
+            match self
+                .items
+                .iter_mut()
+                .find(|stack| stack.item_id == other_stack.item_id)
+            {
+                Some(stack) => stack.count += other_stack.count,
+                None => self.items.push(*other_stack),
+            }
+        }
+        self.items.retain(|stack| stack.count > 0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+}
+
+// Item ids currently worn, their bonuses live in the wearer's Combat and Attributes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Equipment {
+    pub weapon: Option<u32>,
+    pub armor: Option<u32>,
+}
+
+impl Equipment {
+    pub fn get(&self, slot: EquipSlot) -> Option<u32> {
+        match slot {
+            EquipSlot::Weapon => self.weapon,
+            EquipSlot::Armor => self.armor,
+        }
+    }
+
+    pub fn with(self, slot: EquipSlot, item_id: Option<u32>) -> Self {
+        match slot {
+            EquipSlot::Weapon => Equipment { weapon: item_id, ..self },
+            EquipSlot::Armor => Equipment { armor: item_id, ..self },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Offer {
+    pub item_id: u32,
+    pub price: isize,
+}
+
+// Wares a shopkeeper sells, each offer goes once it's bought.
+#[derive(Debug, Clone, Default)]
+pub struct Shop {
+    pub stock: Vec<Offer>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LootDrop {
+    pub item_id: u32,
+    pub chance: f64,
+}
+
+impl LootDrop {
+    pub fn new(item_id: u32, chance: f64) -> Self {
+        Self { item_id, chance }
+    }
+}
+
+// Items a monster might leave behind, each rolled on its own when it dies.
+#[derive(Debug, Clone, Default)]
+pub struct DropTable {
+    pub drops: Vec<LootDrop>,
+}
+
+impl DropTable {
+    pub fn new(drops: Vec<LootDrop>) -> Self {
+        Self { drops }
+    }
+
+    pub fn roll(&self) -> Vec<ItemStack> {
+        self.drops
+            .iter()
+            .filter(|drop| thread_rng().gen_bool(drop.chance.clamp(0.0, 1.0)))
+            .map(|drop| ItemStack::new(drop.item_id, 1))
+            .collect()
     }
 }
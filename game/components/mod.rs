@@ -2,5 +2,9 @@ pub mod attributes;
 pub mod behavior;
 pub mod combat;
 pub mod core;
+pub mod effects;
+pub mod hunger;
 pub mod inventory;
+pub mod projectile;
 pub mod spells;
+pub mod stats;
@@ -0,0 +1,36 @@
+use crate::{ecs::event::InteractionEvent, map::utils::Coordinate};
+
+#[derive(Debug, Clone, Default)]
+pub struct Projectile {
+    // tiles still ahead, in flight order
+    pub path: Vec<Coordinate>,
+    pub speed: usize,
+    pub event: InteractionEvent,
+    pub astray: bool,
+}
+
+impl Projectile {
+    pub fn new(path: Vec<Coordinate>, speed: usize, event: InteractionEvent) -> Self {
+        Self {
+            path,
+            speed,
+            event,
+            astray: false,
+        }
+    }
+
+    // a missed shot can still hit whatever is in the way
+    pub fn astray(self) -> Self {
+        Self {
+            astray: true,
+            ..self
+        }
+    }
+
+    pub fn advanced(&self, steps: usize) -> Self {
+        Self {
+            path: self.path.iter().skip(steps).copied().collect(),
+            ..self.clone()
+        }
+    }
+}
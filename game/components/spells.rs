@@ -2,21 +2,47 @@
 use serde::{Deserialize, Serialize};
 
 use crate::ecs::component::Diffable;
-use crate::ecs::ecs::{Delta,ECS};
+use crate::ecs::ecs::{Delta, EntityIdentifier, ECS};
 
 use crate::ecs::entity::Entity;
 use crate::ecs::system::ComponentQuery;
+use crate::map::gamemap::GameMap;
+use crate::map::utils::Coordinate;
 use crate::utils::logger;
 
 use super::core::{ImageData,  ImageHandle};
 
-type EffectFunction = fn(&[&Entity], &ECS) -> Vec<Delta>;
+type EffectFunction = fn(&[&Entity], Option<Coordinate>, &ECS, &GameMap) -> Vec<Delta>;
+// checked before the spell goes on cooldown, logs why it fails
+type ConditionFunction = fn(Option<Coordinate>, &ECS, &GameMap) -> bool;
+// reshapes the floor itself, returns whether the layout changed
+type TerrainFunction = fn(Option<Coordinate>, &mut GameMap) -> bool;
 
 #[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
 pub enum CooldownState {
     #[default]
     Available,
+    // until the next floor
     Cooldown,
+    // turns left
+    OnCooldown(usize),
+}
+
+// what a spell needs from the caster besides the spell itself
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Targeting {
+    #[default]
+    SelfCast,
+    Tile,
+    Entity,
+    // any tile, no line of sight needed
+    Remote,
+}
+
+impl Targeting {
+    pub fn needs_target(&self) -> bool {
+        *self != Targeting::SelfCast
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -26,30 +52,113 @@ pub struct Spell {
     query: ComponentQuery,
     effect: EffectFunction,
     pub castable: CooldownState,
+    pub targeting: Targeting,
+    condition: Option<ConditionFunction>,
+    cooldown_turns: Option<usize>,
+    // spells with a mana cost skip cooldowns entirely
+    pub cost: isize,
+    // two-step spells take hold of the target first, the next cast acts on what they hold
+    release: Option<EffectFunction>,
+    pub held: Option<EntityIdentifier>,
+    terrain: Option<TerrainFunction>,
 }
 
 impl Spell {
     pub fn new(name: &'static str, image: ImageHandle, query: ComponentQuery, effect: EffectFunction) -> Self {
-        Self {name, image, query, effect,  castable: CooldownState::Available}
+        Self {name, image, query, effect,  castable: CooldownState::Available, targeting: Targeting::SelfCast, condition: None, cooldown_turns: None, cost: 0, release: None, held: None, terrain: None}
+    }
+
+    pub fn with_targeting(self, targeting: Targeting) -> Self {
+        Self { targeting, ..self }
+    }
+
+    pub fn with_condition(self, condition: ConditionFunction) -> Self {
+        Self { condition: Some(condition), ..self }
+    }
+
+    pub fn with_cost(self, cost: isize) -> Self {
+        Self { cost, ..self }
+    }
+
+    // recovers after this many turns instead of on the next floor
+    pub fn with_cooldown_turns(self, turns: usize) -> Self {
+        Self { cooldown_turns: Some(turns), ..self }
+    }
+
+    pub fn with_release(self, release: EffectFunction) -> Self {
+        Self { release: Some(release), ..self }
     }
 
-    pub fn cast(&self, ecs: &ECS) -> Vec<Delta> {
+    pub fn with_terrain(self, terrain: TerrainFunction) -> Self {
+        Self { terrain: Some(terrain), ..self }
+    }
+
+    // the next cast only takes hold, cost and cooldown wait for the release
+    pub fn grabs_next(&self) -> bool {
+        self.release.is_some() && self.held.is_none()
+    }
+
+    pub fn holding(&self, held: Option<EntityIdentifier>) -> Self {
+        Self { held, ..self.clone() }
+    }
+
+    pub fn can_cast(&self, target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> bool {
+        if self.held.is_some() {
+            return true;
+        }
+        match self.condition {
+            Some(condition) => condition(target, ecs, map),
+            None => true,
+        }
+    }
+
+    pub fn cast(&self, target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
         let CooldownState::Available = self.castable else {
             return vec![];
         };
+        // the release acts on the held entity instead of the caster
+        if let (Some(release), Some(held)) = (self.release, self.held) {
+            return match ecs.get_entity_id_from_identifier(held).and_then(|held| ecs.get_entity(held)) {
+                Some(entity) => release(&[entity], target, ecs, map),
+                None => vec![],
+            };
+        }
         let entities = ecs.get_entities_matching_query(&self.query);
-        (self.effect)(&entities, ecs)
+        (self.effect)(&entities, target, ecs, map)
+    }
+
+    // the map half of a cast, components go through cast as usual
+    pub fn reshape(&self, target: Option<Coordinate>, map: &mut GameMap) -> bool {
+        self.terrain.is_some_and(|terrain| terrain(target, map))
     }
 
     pub fn on_cooldown(&self) -> Self {
+        let castable = match self.cooldown_turns {
+            // the casting turn ends with a tick too, which shouldn't count
+            Some(turns) => CooldownState::OnCooldown(turns + 1),
+            None => CooldownState::Cooldown,
+        };
         Self { 
-            castable: CooldownState::Cooldown, 
+            castable, 
+            held: None,
             image: self.image.change_state( "cooldown"),
             ..self.clone() }
     }
 
+    pub fn tick_cooldown(&self) -> Self {
+        match self.castable {
+            CooldownState::OnCooldown(turns) if turns > 1 => Self {
+                castable: CooldownState::OnCooldown(turns - 1),
+                ..self.clone()
+            },
+            CooldownState::OnCooldown(_) => self.off_cooldown(),
+            _ => self.clone(),
+        }
+    }
+
     pub fn off_cooldown(&self) -> Self {
         Self { castable: CooldownState::Available, 
+            held: None,
             image: self.image.change_state( "available"),
             ..self.clone() }
     }
@@ -57,7 +166,7 @@ impl Spell {
 
 impl Default for Spell {
     fn default() -> Self {
-        Self {name: "Spell", image: ImageHandle::default(), query: ComponentQuery::default(), effect: |_, _| vec![], castable: CooldownState::default() }
+        Self {name: "Spell", image: ImageHandle::default(), query: ComponentQuery::default(), effect: |_, _, _, _| vec![], castable: CooldownState::default(), targeting: Targeting::default(), condition: None, cooldown_turns: None, cost: 0, release: None, held: None, terrain: None }
     }
 }
 
@@ -66,6 +175,13 @@ impl Diffable for Spell {
         self.query = other.query.clone();
         self.effect = other.effect;
         self.castable = other.castable;
+        self.targeting = other.targeting;
+        self.condition = other.condition;
+        self.cooldown_turns = other.cooldown_turns;
+        self.cost = other.cost;
+        self.release = other.release;
+        self.held = other.held;
+        self.terrain = other.terrain;
     }
 }
 
@@ -90,4 +206,26 @@ pub struct SpellData {
     cooldown_icon: ImageData,
     query: ComponentQuery,
     effect: EffectFunction,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{action::PlayerAction, core::Game, spelldefinitions::BLINK, testing};
+
+    #[test]
+    fn turn_cooldowns_last_their_full_length() {
+        let mut game = testing::arena();
+        let blink = BLINK.with(|spell| spell.clone());
+        let turns = blink.cooldown_turns.unwrap();
+        let index = testing::give_spell(&mut game, blink);
+        game.cast_spell_command(index, Some(testing::PLAYER_START + Coordinate { x: 2, y: 0 }));
+
+        let castable = |game: &Game| game.ecs.get_player_spells()[index as usize].data.castable;
+        for _ in 1..turns {
+            game.apply_action(PlayerAction::Wait);
+        }
+        assert!(matches!(castable(&game), CooldownState::OnCooldown(1)));
+        game.apply_action(PlayerAction::Wait);
+        assert!(matches!(castable(&game), CooldownState::Available));
+    }
+}
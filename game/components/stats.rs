@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ecs::{component::Diffable, ecs::Delta, entity::find_typed},
+    game::components::{
+        combat::Health,
+        core::{Component, ComponentType, Name},
+    },
+};
+
+// Running tally for the end of run summary, carried by the player between floors.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    pub kills: HashMap<String, usize>,
+    pub gold_collected: isize,
+    pub spells_cast: usize,
+    pub damage_dealt: isize,
+    pub damage_taken: isize,
+    pub deepest_depth: usize,
+    pub turns: usize,
+    // how the run ended, set by whatever dealt the lethal damage
+    pub killed_by: Option<String>,
+}
+
+impl RunStats {
+    pub fn new_kill(name: &str) -> Self {
+        RunStats {
+            kills: HashMap::from([(name.to_string(), 1)]),
+            ..Default::default()
+        }
+    }
+}
+
+impl Diffable for RunStats {
+    fn apply_diff(&mut self, other: &Self) {
+        for (name, count) in &other.kills {
+            *self.kills.entry(name.clone()).or_default() += count;
+        }
+        self.gold_collected += other.gold_collected;
+        self.spells_cast += other.spells_cast;
+        self.damage_dealt += other.damage_dealt;
+        self.damage_taken += other.damage_taken;
+        self.deepest_depth = self.deepest_depth.max(other.deepest_depth);
+        self.turns += other.turns;
+        if other.killed_by.is_some() {
+            self.killed_by = other.killed_by.clone();
+        }
+    }
+}
+
+// Only the player keeps a tally, so only their lethal hits get a cause.
+pub fn record_death_cause(
+    victim: &[&Component],
+    health: &Health,
+    damage: isize,
+    cause: impl FnOnce() -> String,
+) -> Option<Delta> {
+    let tally = find_typed::<RunStats, _>(victim)?;
+    if damage < health.current {
+        return None;
+    }
+    let change = RunStats { killed_by: Some(cause()), ..Default::default() };
+    Some(Delta::Change(Component::RunStats(tally.make_change(change))))
+}
+
+pub fn describe_attacker(attacker: &[Component]) -> String {
+    let has = |comp_type: ComponentType| attacker.iter().any(|comp| comp.is_of_type(&comp_type));
+    let Some(name) = find_typed::<Name, _>(attacker) else {
+        return "Killed by something unseen".to_string();
+    };
+    if has(ComponentType::Player) {
+        "Killed by their own hand".to_string()
+    } else if has(ComponentType::Monster) {
+        format!("Slain by {}", name.data.indefinite())
+    } else if has(ComponentType::Trap) {
+        format!("Killed by {}", name.data.indefinite())
+    } else {
+        format!("Killed by {}", name.data.raw.to_lowercase())
+    }
+}
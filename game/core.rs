@@ -1,83 +1,322 @@
-use std::{cmp::Reverse, vec};
+use std::{cmp::Reverse, collections::{HashMap, HashSet, VecDeque}, vec};
 
 use rand::{thread_rng, Rng};
 
 use crate::{
     ecs::{
-        ecs::{Delta, EntityIdentifier, IndexedData, MakeComponentOrder, ECS},
-        entity::take_component_from_refs,
+        ecs::{DeleteEntityOrder, Delta, EntityIdentifier, IndexedData, MakeComponentOrder, MakeEntityOrder, ECS},
+        entity::{find_typed, take_component_from_refs},
         event::{propagate_event, EventType, InteractionEvent},
         system::{ComponentQuery, SystemManager},
     },
     game::{
+        action::{DeathReport, ExamineReport, GameEvent, MinimapCell, PlayerAction, ThreatLevel, TurnOutcome},
+        archetype,
         components::{
-            attributes::{self, Attributes},
-            combat::{self, Health},
-            core::{Component, ComponentType},
+            attributes::Attributes,
+            behavior::{self, AIState, TurnTaker},
+            combat::{self, Combat, Health},
+            core::{Collision, Component, ComponentType},
+            inventory::{EquipSlot, Equipment, Inventory, ItemStack, Shop},
+            projectile::Projectile,
+            stats::RunStats,
         },
-        system::{Exploration, MonsterTurns, PlayerCheck, UnitCull},
+        difficulty::Difficulty,
+        itemdefinitions::{self, ITEM_REGISTRY},
+        spawning::{self, OBJECT_SPAWN_NAMES},
+        system::{Alert, Exploration, MonsterTurns, Projectiles, Terrain, UnitCull},
     },
     map::{
-        self, gamemap::GameMap, mapbuilder::MapBuilder, utils::{Coordinate, Euclidian}
+        self, gamemap::{GameMap, EXPLORE_FILL_BUDGET}, mapbuilder::{MapBuilder, MapParams, MapStyle}, utils::{Coordinate, Euclidian}
     },
     utils::{
-        logger::{self, MessageLog},
+        logger::{self, MessageLog, SoundCue},
         los,
+        pathfinding::{self, PathOptions},
     },
 };
 
-use super::{components::{attributes::get_xp_to_next, core::{DurationEffect, EffectType}, spells::{CooldownState, Spell}}, spelldefinitions::SPELL_REGISTRY, system::{Acid, Cooldowns, Duration, Fire, Stoneskin}};
+use super::{components::{attributes::XpCurve, core::{EffectType, Name, TrapKind}, hunger::Hunger, spells::{CooldownState, Spell, Targeting}}, spelldefinitions::SPELL_REGISTRY, system::{self, Acid, Bleed, Cooldowns, Duration, Fire, Regen, Restock, Shield, SpellCooldowns, Stoneskin}};
+
+pub const VISION_RADIUS: i32 = 8;
+const REST_TURN_LIMIT: usize = 100;
+// give up resting when nothing heals the player
+const REST_STALL_TURNS: usize = 10;
+const SPELL_RANGE: f32 = 8.0;
+// tiles a shot covers each turn
+const PROJECTILE_SPEED: usize = 3;
+// how many times longer a monster needs to kill the player than the other way around
+const TRIVIAL_THREAT_RATIO: f32 = 3.0;
+const EVEN_THREAT_RATIO: f32 = 1.0;
+const DANGEROUS_THREAT_RATIO: f32 = 0.5;
+// searching in place looks one tile further every few turns, up to a limit
+const SEARCHES_PER_RING: usize = 2;
+const MAX_SEARCH_RADIUS: usize = 3;
+
+pub type Listener = Box<dyn Fn(&GameEvent)>;
+
+// Where the last step started, and whether its turn stayed clean enough to take back.
+struct UndoSnapshot {
+    position: Coordinate,
+    clean: bool,
+}
 
 pub struct Game {
     pub ecs: ECS,
     pub systems: SystemManager,
     pub map: GameMap,
     pub log: MessageLog,
+    pub turn: usize,
+    hasted_action_taken: bool,
+    travel_path: Vec<Coordinate>,
+    auto_exploring: bool,
+    explore_stops_at_stairs: bool,
+    // off leaves loot on the floor until picked up on purpose
+    auto_pickup: bool,
+    // whether stepping away from an adjacent monster gives it a free swing
+    player_provokes_attacks: bool,
+    // the world before the player's last step, while taking it back is still harmless
+    undo_snapshot: Option<UndoSnapshot>,
+    // back-to-back searches from the same tile, anything else starts over
+    search_streak: usize,
+    // the first floor's size, deeper ones grow from it
+    map_params: MapParams,
+    // how much xp each level takes, kept across floors and restarts
+    xp_curve: XpCurve,
+    listeners: Vec<Listener>,
 }
 
 impl Game {
-    pub fn new(size_x: usize, size_y: usize) -> Game {
-        let (map, bsp_tree) = MapBuilder::generate_new(size_x, size_y, 1);
+    pub fn new(size_x: usize, size_y: usize, difficulty: Difficulty) -> Game {
+        let map_params = MapParams::new(size_x, size_y);
+        let (map, ecs) = Game::build_floor(map_params.for_depth(1), 1, difficulty, None);
         let mut game = Game {
-            ecs: ECS::new(bsp_tree),
+            ecs,
             systems: SystemManager::new(),
             log: MessageLog::new(),
             map,
+            turn: 0,
+            hasted_action_taken: false,
+            travel_path: vec![],
+            auto_exploring: false,
+            explore_stops_at_stairs: false,
+            auto_pickup: true,
+            player_provokes_attacks: false,
+            undo_snapshot: None,
+            search_streak: 0,
+            map_params,
+            xp_curve: XpCurve::default(),
+            listeners: vec![],
         };
 
-        game.ecs.spawn_all_entities(&game.map);
         game.add_default_systems();
         game.explore_first_room();
+        game.update_visible_tiles();
         game
     }
 
+    pub fn apply_action(&mut self, action: PlayerAction) {
+        // the body is left in place for the death report, but it can't act
+        if !self.is_player_alive() && !matches!(action, PlayerAction::Restart | PlayerAction::Quit) {
+            return;
+        }
+        // doing anything else cancels a travel in progress
+        if action != PlayerAction::Travel {
+            self.travel_path.clear();
+            self.auto_exploring = false;
+        }
+        // only the very next action can undo a step
+        if action != PlayerAction::Undo {
+            self.undo_snapshot = None;
+        }
+        if action != PlayerAction::Search {
+            self.search_streak = 0;
+        }
+        match action {
+            PlayerAction::Direction(direction) => self.step_command(direction),
+            PlayerAction::Position(coord) => self.target_command(coord),
+            PlayerAction::Travel if self.auto_exploring => {
+                self.auto_explore_command();
+            }
+            PlayerAction::Travel => {
+                self.travel_step();
+            }
+            PlayerAction::AutoExplore => {
+                self.auto_explore_command();
+            }
+            PlayerAction::Shoot(coord) => self.shoot_command(coord, &mut thread_rng()),
+            PlayerAction::Throw(coord) => self.throw_command(coord),
+            PlayerAction::Spell(spell_id, target) => self.cast_spell_command(spell_id, target),
+            PlayerAction::UseItem(item_index) => self.use_item_command(item_index),
+            PlayerAction::ReadScroll(item_index, target) => self.use_scroll_command(item_index, target),
+            PlayerAction::Equip(item_index) => self.equip_command(item_index),
+            PlayerAction::Unequip(slot) => self.unequip_command(slot),
+            PlayerAction::Buy(item_index) => self.buy_command(item_index),
+            PlayerAction::Descend => self.descend_command(),
+            PlayerAction::CloseDoors => self.close_doors_command(),
+            PlayerAction::Search => self.search_command(),
+            PlayerAction::Examine(coord) => self.log_examine(coord),
+            PlayerAction::Wait => self.wait_command(),
+            PlayerAction::Undo => self.undo_command(),
+            PlayerAction::Pickup => self.pickup_command(),
+            PlayerAction::Disarm(coord) => self.disarm_command(coord, &mut thread_rng()),
+            PlayerAction::Rest => {
+                self.rest_command();
+            }
+            PlayerAction::LevelUp(stat, amount) => self.level_up_command(stat, amount),
+            PlayerAction::Restart => {
+                let diagonal_movement = self.map.diagonal_movement;
+                let reveal_all = self.map.reveal_all;
+                let xp_curve = self.xp_curve;
+                let provokes_attacks = self.player_provokes_attacks;
+                let auto_pickup = self.auto_pickup;
+                let listeners = std::mem::take(&mut self.listeners);
+                *self = Game::new(self.map_params.width, self.map_params.height, self.map.difficulty);
+                self.set_diagonal_movement(diagonal_movement);
+                self.map.reveal_all = reveal_all;
+                self.set_xp_curve(xp_curve);
+                self.set_player_provokes_attacks(provokes_attacks);
+                self.set_auto_pickup(auto_pickup);
+                self.listeners = listeners;
+            }
+            // quitting is up to the frontend
+            PlayerAction::Quit | PlayerAction::Start => {}
+        }
+        self.update_visible_tiles();
+    }
+
+    pub fn step_headless(&mut self, action: PlayerAction) -> TurnOutcome {
+        self.apply_action(action);
+
+        let mut messages = vec![];
+        logger::LOG.with(|log| {
+            while let Some((msg, _)) = log.next_message() {
+                messages.push(msg);
+            }
+        });
+
+        TurnOutcome {
+            player_alive: self.is_player_alive(),
+            depth: self.map.depth,
+            turn: self.turn,
+            messages,
+        }
+    }
+
+    // Lives on the map so systems and monster AI can see it too.
+    pub fn set_diagonal_movement(&mut self, enabled: bool) {
+        self.map.diagonal_movement = enabled;
+        // the monsters' nav grids were built for the other set of directions
+        self.ecs.mark_terrain_changed();
+    }
+
+    pub fn is_diagonal_movement(&self) -> bool {
+        self.map.diagonal_movement
+    }
+
+    // Debug view of the whole floor, spawns and monster AI don't notice.
+    pub fn toggle_reveal_all(&mut self) {
+        self.map.reveal_all = !self.map.reveal_all;
+    }
+
+    pub fn is_revealing_all(&self) -> bool {
+        self.map.reveal_all
+    }
+
+    pub fn set_xp_curve(&mut self, curve: XpCurve) {
+        self.xp_curve = curve;
+    }
+
+    pub fn subscribe(&mut self, listener: impl Fn(&GameEvent) + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    fn notify(&self, event: GameEvent) {
+        for listener in &self.listeners {
+            listener(&event);
+        }
+    }
+
+    // Sounds queued since the last drain, for the frontend to play.
+    pub fn drain_sound_cues(&self) -> Vec<SoundCue> {
+        logger::LOG.with(|log| log.drain_sound_cues())
+    }
+
     pub fn wait_command(&mut self) {
         self.end_turn();
     }
 
-    pub fn shoot_command(&mut self, coord: Coordinate) {
+    // Waits until healed or disturbed, returns the number of turns spent.
+    pub fn rest_command(&mut self) -> usize {
+        if self.is_monster_visible() {
+            logger::log_message("You can't rest with enemies in sight.");
+            return 0;
+        }
+        let mut turns = 0;
+        let mut stalled = 0;
+        while turns < REST_TURN_LIMIT && stalled < REST_STALL_TURNS {
+            let Some(before) = self.get_player_health() else {
+                break;
+            };
+            if before.current >= before.max {
+                break;
+            }
+            self.end_turn();
+            self.update_visible_tiles();
+            turns += 1;
+
+            let Some(after) = self.get_player_health() else {
+                break;
+            };
+            // taking damage means something is threatening the player
+            if after.current < before.current || !self.is_player_alive() || self.is_monster_visible() {
+                break;
+            }
+            stalled = if after.current > before.current { 0 } else { stalled + 1 };
+        }
+        match turns {
+            0 => logger::log_message("You don't need to rest."),
+            _ => logger::log_message(&format!("Rested {} turns.", turns)),
+        }
+        turns
+    }
+
+    fn get_player_health(&self) -> Option<Health> {
+        match self.ecs.get_component_from_entity_id(self.ecs.get_player_id(), ComponentType::Health) {
+            Some(Component::Health(health)) => Some(health.data),
+            _ => None,
+        }
+    }
+
+    fn is_monster_visible(&self) -> bool {
+        self.map.visible.borrow().iter().any(|&coord| {
+            self.ecs
+                .get_all_entities_in_tile(coord)
+                .into_iter()
+                .any(|entity_id| self.ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+        })
+    }
+
+    pub fn shoot_command(&mut self, coord: Coordinate, rng: &mut impl Rng) {
         let player_report = match self.ecs.get_player_report() {
             Some(report) => report,
             _ => return,
         };
 
-        let maybe_target = self.ecs.get_blocking_entity(coord);
-        let target = match maybe_target {
-            Some(entity) => entity,
-            None => return,
-        };
-        let event = player_report.shoot;
-        let distance = coord.distance(player_report.position.data);
-        let range = event.attack.unwrap().range.unwrap();
-        let line_of_sight =
-            los::line_of_sight(player_report.position.data, coord, &self.map, &self.ecs);
-
-        if !line_of_sight {
-            logger::log_message("Target is out of sight.");
+        if self.ecs.get_blocking_entity(coord).is_none() {
             return;
         }
-        if distance > range {
-            logger::log_message("Target is out of range.");
+        if !player_report.combat.data.has_ammo() {
+            logger::log_message("Out of arrows.");
+            return;
+        }
+        let event = player_report.shoot;
+        let distance = coord.distance(player_report.position.data);
+        let range = match event.attack.and_then(|attack| attack.range) {
+            Some(range) => range,
+            _ => return,
+        };
+        if !self.is_target_in_reach(player_report.position.data, coord, range) {
             return;
         }
         
@@ -85,30 +324,271 @@ impl Game {
             logger::log_message("Target is too close.");
             return;
         }
-        self.propagate_and_apply_event(&event, target);
+
+        let stats = player_report.stats.map(|stats| stats.data);
+        let missed = rng.gen_bool(combat::ranged_miss_chance(stats.as_ref()));
+
+        // the shot flies along the line and hits the first thing in its way,
+        // a missed one falls short of the target
+        let origin = player_report.position.data;
+        let mut path = los::linetrace(origin, coord);
+        path.remove(0);
+        let projectile = if missed {
+            path.pop();
+            Projectile::new(path, PROJECTILE_SPEED, event).astray()
+        } else {
+            Projectile::new(path, PROJECTILE_SPEED, event)
+        };
+        let mut deltas = vec![Delta::MakeEntity(MakeEntityOrder {
+            components: spawning::projectile_components(origin, projectile),
+        })];
+        if let Some(combat) = player_report.combat.data.spend_ammo() {
+            deltas.push(Delta::Change(Component::Combat(player_report.combat.make_change(combat))));
+        }
+        self.ecs.apply_changes(deltas);
+        self.end_hasted_turn();
+    }
+
+    pub fn throw_command(&mut self, coord: Coordinate) {
+        let Some(player_report) = self.ecs.get_player_report() else {
+            return;
+        };
+        let Some(items) = player_report.items else {
+            return;
+        };
+        let Some((stack, item_name, spawn_name)) = items.data.items.iter().find_map(|stack| {
+            let item = ITEM_REGISTRY.get(&stack.item_id)?;
+            item.thrown_spawn.map(|spawn_name| (stack, item.name, spawn_name))
+        }) else {
+            logger::log_message("You have nothing to throw.");
+            return;
+        };
+
+        let origin = player_report.position.data;
+        let Some(range) = player_report.shoot.attack.and_then(|attack| attack.range) else {
+            return;
+        };
+        if !self.is_target_in_reach(origin, coord, range) {
+            return;
+        }
+        let Some(landing) = self.find_landing_tile(origin, coord) else {
+            logger::log_message("There is no room to throw that there.");
+            return;
+        };
+        let Some(spawn_func) = OBJECT_SPAWN_NAMES.get(spawn_name) else {
+            return;
+        };
+
+        let thrown = Inventory {
+            items: vec![ItemStack::new(stack.item_id, -1)],
+            ..Default::default()
+        };
+        self.ecs.apply_change(Delta::Change(Component::Inventory(items.make_change(thrown))));
+        logger::log_message(&format!("You throw a {}.", item_name.to_lowercase()));
+
+        let mut blast = vec![landing];
+        blast.extend(map::utils::orthogonal_neighbors(landing));
+        let event = InteractionEvent {
+            event_type: EventType::Fire,
+            attack: None,
+            payload: vec![],
+        };
+        // only fire bombs set what they land on alight
+        let ignites = stack.item_id == itemdefinitions::FIRE_BOMB_ID;
+        for tile in blast {
+            if !self.map.is_tile_passable(tile) {
+                continue;
+            }
+            if ignites {
+                for entity_id in self.ecs.get_all_entities_in_tile(tile) {
+                    self.propagate_and_apply_event(&event, entity_id);
+                }
+            }
+            spawn_func(&mut self.ecs, tile, self.map.depth, self.map.difficulty);
+        }
         self.end_turn();
     }
 
     pub fn target_command(&mut self, coord: Coordinate) {
-        let player_id = self.ecs.get_player_id();
-        let components = self.ecs.get_components_from_entity_id(player_id);
-
-        let (maybe_position, _) = take_component_from_refs(ComponentType::Position, &components);
-        let position = match maybe_position {
-            Some(Component::Position(data)) => data,
-            _ => panic!("Player found without a position component."),
+        let Some(position) = self.ecs.get_player_position() else {
+            return;
         };
 
-        if coord == position.data {
+        if coord == position {
             self.wait_command();
-        } else if coord.distance(position.data) <= 1.05 {
+        } else if coord.distance(position) <= self.map.adjacent_range() {
             // clicked adjacent <=> wasd command
             let direction = Coordinate {
-                x: coord.x - position.data.x,
-                y: coord.y - position.data.y,
+                x: coord.x - position.x,
+                y: coord.y - position.y,
             };
             self.step_command(direction);
+        } else if let Some(path) = self.path_to(coord) {
+            self.travel_path = path;
+            self.travel_step();
+        }
+    }
+
+    // Auto-explore counts, the frontend keeps sending Travel until it's done.
+    pub fn is_traveling(&self) -> bool {
+        self.auto_exploring || !self.travel_path.is_empty()
+    }
+
+    // Takes the next step of a queued travel, returns whether there's more to go.
+    pub fn travel_step(&mut self) -> bool {
+        let (Some(position), Some(&next)) = (self.ecs.get_player_position(), self.travel_path.first()) else {
+            self.travel_path.clear();
+            return false;
+        };
+        if position.distance(next) > self.map.adjacent_range() {
+            self.travel_path.clear();
+            return false;
+        }
+
+        self.step_command(next - position);
+        match self.ecs.get_player_position() {
+            Some(coord) if coord == next => {
+                self.travel_path.remove(0);
+            }
+            // bumped a door open, walk through it next time
+            Some(coord) if coord == position && self.ecs.get_blocking_entity(next).is_none() => {}
+            _ => self.travel_path.clear(),
+        }
+
+        self.update_visible_tiles();
+        if !self.travel_path.is_empty() && self.is_monster_visible() {
+            logger::log_message("You stop, an enemy is in sight.");
+            self.travel_path.clear();
+        }
+        !self.travel_path.is_empty()
+    }
+
+    pub fn set_explore_stops_at_stairs(&mut self, enabled: bool) {
+        self.explore_stops_at_stairs = enabled;
+    }
+
+    pub fn set_auto_pickup(&mut self, enabled: bool) {
+        self.auto_pickup = enabled;
+    }
+
+    pub fn set_player_provokes_attacks(&mut self, enabled: bool) {
+        self.player_provokes_attacks = enabled;
+    }
+
+    // Walks toward the nearest unexplored tile, one step per call, returns whether there's more to go.
+    pub fn auto_explore_command(&mut self) -> bool {
+        if self.is_monster_visible() {
+            logger::log_message("You can't explore with enemies in sight.");
+            self.stop_auto_explore();
+            return false;
+        }
+        if self.travel_path.is_empty() {
+            let Some(path) = self.next_explore_path() else {
+                logger::log_message("There's nothing left to explore.");
+                self.stop_auto_explore();
+                return false;
+            };
+            self.travel_path = path;
+        }
+        self.auto_exploring = true;
+
+        let Some(&destination) = self.travel_path.last() else {
+            return false;
+        };
+        let start = self.ecs.get_player_position();
+        let more_to_go = self.travel_step();
+        let position = self.ecs.get_player_position();
+
+        if !more_to_go && position != Some(destination) {
+            // interrupted or blocked, let the player take over
+            self.stop_auto_explore();
+        } else if !more_to_go && !self.map.explored.borrow().contains(&destination) {
+            // walking somewhere shows you it
+            self.map.explore_flood_fill(destination, &self.ecs, EXPLORE_FILL_BUDGET);
+        }
+
+        let on_stairs = position.is_some_and(|coord| {
+            self.ecs
+                .get_all_entities_in_tile(coord)
+                .into_iter()
+                .any(|entity_id| self.ecs.entity_id_has_component(entity_id, ComponentType::Stairs))
+        });
+        if self.explore_stops_at_stairs && on_stairs && position != start {
+            logger::log_message("You find the stairs down.");
+            self.stop_auto_explore();
         }
+        self.auto_exploring
+    }
+
+    pub fn is_floor_explored(&self) -> bool {
+        self.next_explore_path().is_none()
+    }
+
+    fn stop_auto_explore(&mut self) {
+        self.auto_exploring = false;
+        self.travel_path.clear();
+    }
+
+    // Breadth-first over known ground until something borders an unexplored tile.
+    fn next_explore_path(&self) -> Option<Vec<Coordinate>> {
+        let start = self.ecs.get_player_position()?;
+        let explored = self.map.explored.borrow();
+
+        let mut parents: HashMap<Coordinate, Coordinate> = HashMap::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            let frontier = map::utils::orthogonal_neighbors(current)
+                .into_iter()
+                .find(|coord| !explored.contains(coord) && self.is_explore_walkable(*coord));
+            if let Some(goal) = frontier {
+                let mut path = vec![goal];
+                let mut tile = current;
+                while tile != start {
+                    path.push(tile);
+                    tile = parents[&tile];
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for dir in self.map.movement_directions() {
+                let next = current + dir;
+                if next == start || parents.contains_key(&next) || !explored.contains(&next) {
+                    continue;
+                }
+                if self.map.is_step_allowed(current, dir) && self.is_explore_walkable(next) {
+                    parents.insert(next, current);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    // Doors get bumped open and units move, anything else blocking is in the way.
+    fn is_explore_walkable(&self, coord: Coordinate) -> bool {
+        if !self.map.is_tile_passable(coord) || self.map.tile_is_lava(coord) || self.ecs.has_hazard(coord) {
+            return false;
+        }
+        match self.ecs.get_blocking_entity(coord) {
+            Some(entity_id) => {
+                !self.ecs.entity_id_has_component(entity_id, ComponentType::Secret)
+                    && (self.ecs.entity_id_has_component(entity_id, ComponentType::Door)
+                        || self.ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+            }
+            None => true,
+        }
+    }
+
+    // Path for the player over explored ground, doors get bumped open on the way.
+    pub fn path_to(&self, coord: Coordinate) -> Option<Vec<Coordinate>> {
+        let position = self.ecs.get_player_position()?;
+        let options = PathOptions {
+            ignore_doors: true,
+            explored_only: true,
+            ..Default::default()
+        };
+        pathfinding::find_path(position, coord, &self.map, &self.ecs, options)
     }
 
     pub fn step_command(&mut self, direction: Coordinate) {
@@ -118,24 +598,183 @@ impl Game {
         };
 
         let coord = player_report.position.data + direction;
-        if !self.map.is_tile_passable(coord) {
+        // walls, and secret doors posing as walls, don't cost a turn
+        let hidden_door = self
+            .ecs
+            .get_blocking_entity(coord)
+            .is_some_and(|id| self.ecs.entity_id_has_component(id, ComponentType::Secret));
+        if !self.map.is_tile_passable(coord) || hidden_door {
+            logger::log_message_throttled("You bump into the wall.");
+            return;
+        }
+        if !self.map.is_step_allowed(player_report.position.data, direction) {
             return;
         }
 
         let event = player_report.bump;
-        if let Some(entity_id) = self.ecs.get_blocking_entity(coord) {
+        if let Some(entity_id) = self.ecs.get_blocking_entity(coord).filter(|&id| self.is_swappable(id)) {
+            self.swap_with(entity_id, direction);
+        } else if let Some(entity_id) = self.ecs.get_blocking_entity(coord) {
             self.propagate_and_apply_event(&event, entity_id);
+            if let Some(attack) = event.attack {
+                let deltas = behavior::knockback(player_report.position.data, entity_id, attack.knockback, &self.ecs, &self.map);
+                self.ecs.apply_changes(deltas);
+            }
         } else {
             let entities = self.ecs.get_all_entities_in_tile(coord);
+            // nothing underfoot to pick up or set off, and no one around to react
+            let mut snapshot = UndoSnapshot {
+                position: player_report.position.data,
+                clean: entities.is_empty() && !self.is_monster_visible(),
+            };
             for entity_id in entities {
+                if !self.auto_pickup && self.is_loot(entity_id) {
+                    continue;
+                }
                 self.propagate_and_apply_event(&event, entity_id);
             }
+            if self.player_provokes_attacks {
+                self.provoke_attacks_of_opportunity(player_report.position.data, coord);
+            }
             self.move_player(direction);
+            if self.is_open_pit(coord) && !self.player_has_effect(EffectType::Levitate) {
+                self.fall_into_pit();
+                return;
+            }
+            // wading costs the player an extra turn
+            if self.map.tile_is_water(coord) && !self.player_has_effect(EffectType::Levitate) {
+                logger::log_message("You wade through the water.");
+                self.end_turn();
+                self.end_hasted_turn();
+                return;
+            }
+            let health_before = self.get_player_health().map(|health| health.current);
+            self.end_hasted_turn();
+            snapshot.clean &= self.get_player_health().map(|health| health.current) == health_before;
+            self.undo_snapshot = Some(snapshot);
+            return;
+        }
+        self.end_hasted_turn();
+    }
+
+    // Anything lying around that hands over its inventory when walked over.
+    fn is_loot(&self, entity_id: usize) -> bool {
+        self.ecs.entity_id_has_component(entity_id, ComponentType::Inventory)
+            && self.ecs.get_typed::<Collision>(entity_id).is_some_and(|collision| collision.data == Collision::Walkable)
+    }
+
+    pub fn pickup_command(&mut self) {
+        let Some(player_report) = self.ecs.get_player_report() else {
+            return;
+        };
+        let loot: Vec<usize> = self
+            .ecs
+            .get_all_entities_in_tile(player_report.position.data)
+            .into_iter()
+            .filter(|&entity_id| self.is_loot(entity_id))
+            .collect();
+        if loot.is_empty() {
+            logger::log_message("There is nothing here to pick up.");
+            return;
+        }
+        let event = InteractionEvent {
+            attack: None,
+            ..player_report.bump
+        };
+        for entity_id in loot {
+            self.propagate_and_apply_event(&event, entity_id);
+        }
+        self.end_turn();
+    }
+
+    // Dexterity decides the odds, a slip sets the trap off on the player.
+    pub fn disarm_command(&mut self, coord: Coordinate, rng: &mut impl Rng) {
+        let Some(player_report) = self.ecs.get_player_report() else {
+            return;
+        };
+        if !self.map.neighbors(player_report.position.data).contains(&coord) {
+            logger::log_message("You can only disarm a trap next to you.");
+            return;
+        }
+        // hidden traps have to be found by searching first
+        let trap = self.ecs.get_all_entities_in_tile(coord).into_iter().find(|&entity_id| {
+            self.ecs.entity_id_has_component(entity_id, ComponentType::Trap)
+                && !self.ecs.entity_id_has_component(entity_id, ComponentType::Secret)
+        });
+        let Some(trap_id) = trap else {
+            logger::log_message("There is no trap there that you know of.");
+            return;
+        };
+        let name = self
+            .ecs
+            .get_typed::<Name>(trap_id)
+            .map_or("the trap".to_string(), |name| name.data.definite());
+
+        let stats = player_report.stats.map(|stats| stats.data);
+        if rng.gen_bool(combat::disarm_chance(stats.as_ref())) {
+            logger::log_message(&format!("You disarm {}.", name));
+            self.ecs.apply_changes(vec![Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(trap_id))]);
+            self.end_turn();
+            return;
+        }
+
+        logger::log_message_with(&format!("You fumble with {} and set it off!", name), logger::MessageKind::Warning);
+        let is_pit = self.ecs.get_typed::<TrapKind>(trap_id).is_some_and(|kind| kind.data == TrapKind::Pit);
+        let event = InteractionEvent {
+            attack: None,
+            ..player_report.bump
+        };
+        self.propagate_and_apply_event(&event, trap_id);
+        if is_pit && !self.player_has_effect(EffectType::Levitate) {
+            self.fall_into_pit();
+            return;
         }
         self.end_turn();
     }
 
-    pub fn cast_spell_command(&mut self, spell_id: i32) {
+    // Awake monsters next to where the player stood swing at them if the step takes them out of reach.
+    fn provoke_attacks_of_opportunity(&mut self, leaving: Coordinate, arriving: Coordinate) {
+        let player_id = self.ecs.get_player_id();
+        let neighbors: Vec<usize> = self
+            .map
+            .neighbors(leaving)
+            .into_iter()
+            .flat_map(|tile| self.ecs.get_all_entities_in_tile(tile))
+            .filter(|&entity_id| self.ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+            .collect();
+        for entity_id in neighbors {
+            let components = self.ecs.get_components_from_entity_id(entity_id);
+            let awake = find_typed::<TurnTaker, _>(&components).is_some_and(|turn| turn.data.state == AIState::Alert);
+            if !awake || behavior::is_frozen(&components) || behavior::is_grappled(&components) {
+                continue;
+            }
+            let Some(report) = archetype::make_unit_report(&components) else {
+                continue;
+            };
+            let deltas = behavior::attack_of_opportunity(leaving, arriving, player_id, &report, &self.ecs, &self.map);
+            self.ecs.apply_changes(deltas);
+        }
+    }
+
+    // Takes back a clean step only: onto an empty tile, with nothing in sight and no harm done.
+    // Just the player goes back, the turn stays spent and the world keeps what it did in it.
+    pub fn undo_command(&mut self) {
+        let Some(snapshot) = self.undo_snapshot.take().filter(|snapshot| snapshot.clean) else {
+            logger::log_message("There is no step to take back.");
+            return;
+        };
+        // whatever came into view has been seen, taking the step back won't unsee it
+        if self.is_monster_visible() {
+            logger::log_message("It's too late to take that step back.");
+            return;
+        }
+        let Some(position) = self.ecs.get_player_position() else {
+            return;
+        };
+        self.move_player(snapshot.position - position);
+    }
+
+    pub fn cast_spell_command(&mut self, spell_id: i32, target: Option<Coordinate>) {
         let spells = self.ecs.get_player_spells();
         if spells.len() <= spell_id as usize {
             let msg = "You don't have that spell you buffon!";
@@ -143,32 +782,334 @@ impl Game {
             return;
         };
 
-        let spell = spells[spell_id as usize];
-        let CooldownState::Available = spell.data.castable else {
-            logger::log_message("Spell is on cooldown! Try again next floor.");
+        let spell = spells[spell_id as usize].clone();
+        match spell.data.castable {
+            CooldownState::Available => {}
+            CooldownState::Cooldown => {
+                logger::log_message("Spell is on cooldown! Try again next floor.");
+                return;
+            }
+            CooldownState::OnCooldown(turns) => {
+                logger::log_message(&format!("Spell is on cooldown for {} more turns.", turns));
+                return;
+            }
+        }
+        let target = if spell.data.targeting.needs_target() {
+            let Some(target) = self.resolve_spell_target(spell.data.targeting, target) else {
+                return;
+            };
+            Some(target)
+        } else {
+            None
+        };
+        let cost = spell.data.cost;
+        let stats = self.get_player_stats();
+        if cost > 0 && stats.is_none_or(|stats| stats.data.mana < cost) {
+            logger::log_message("You don't have enough mana.");
+            return;
+        }
+        if !spell.data.can_cast(target, &self.ecs, &self.map) {
+            return;
+        }
+        let origin = self.ecs.get_player_position();
+        let mut deltas = spell.data.cast(target, &self.ecs, &self.map);
+        match stats {
+            _ if spell.data.grabs_next() => {
+                // held by a component id, entity ids get reused once the entity is gone
+                let held = target
+                    .and_then(|target| self.ecs.get_blocking_entity(target))
+                    .and_then(|held| self.ecs.get_typed::<Coordinate>(held))
+                    .map(|position| EntityIdentifier::new_from_component(position.index));
+                deltas.push(Delta::Change(Component::Spell(spell.make_change(spell.data.holding(held)))));
+            }
+            Some(stats) if cost > 0 => {
+                let spent = Attributes { mana: -cost, level_pending: stats.data.level_pending, ..Default::default() };
+                deltas.push(Delta::Change(Component::Attributes(stats.make_change(spent))));
+                if spell.data.held.is_some() {
+                    deltas.push(Delta::Change(Component::Spell(spell.make_change(spell.data.holding(None)))));
+                }
+            }
+            _ => deltas.push(Delta::Change(Component::Spell(spell.make_change(spell.data.on_cooldown())))),
+        }
+        if let Some(tally) = self.ecs.get_typed::<RunStats>(self.ecs.get_player_id()) {
+            let change = RunStats { spells_cast: 1, ..Default::default() };
+            deltas.push(Delta::Change(Component::RunStats(tally.make_change(change))));
+        }
+        self.ecs.apply_changes(deltas);
+        if spell.data.reshape(target, &mut self.map) {
+            self.ecs.mark_terrain_changed();
+        }
+        self.explore_after_teleport(origin);
+        logger::play_sound(SoundCue::Spell);
+        self.end_turn();
+    }
+
+    // Landing somewhere new shows it the same as walking through a door.
+    fn explore_after_teleport(&self, origin: Option<Coordinate>) {
+        let (Some(origin), Some(position)) = (origin, self.ecs.get_player_position()) else {
             return;
         };
-        let mut deltas = spell.data.cast(&self.ecs);
-        deltas.push(Delta::Change(Component::Spell(spell.make_change(spell.data.on_cooldown()))));
+        if origin.distance(position) > self.map.adjacent_range() {
+            self.map.explore_room(position);
+            self.map.explore_flood_fill(position, &self.ecs, EXPLORE_FILL_BUDGET);
+        }
+    }
+
+    fn get_player_stats(&self) -> Option<&IndexedData<Attributes>> {
+        match self
+            .ecs
+            .get_component_from_entity_id(self.ecs.get_player_id(), ComponentType::Attributes)
+        {
+            Some(Component::Attributes(data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    // Turns the clicked tile into what the spell's targeting expects, logs why if it can't.
+    fn resolve_spell_target(&self, targeting: Targeting, target: Option<Coordinate>) -> Option<Coordinate> {
+        let (Some(target), Some(origin)) = (target, self.ecs.get_player_position()) else {
+            logger::log_message("That spell needs a target.");
+            return None;
+        };
+
+        match targeting {
+            Targeting::SelfCast => Some(origin),
+            Targeting::Remote => Some(target),
+            Targeting::Tile | Targeting::Entity => {
+                if !self.is_target_in_reach(origin, target, SPELL_RANGE) {
+                    return None;
+                }
+                if targeting == Targeting::Entity && self.ecs.get_blocking_entity(target).is_none() {
+                    logger::log_message("There is nothing there to target.");
+                    return None;
+                }
+                Some(target)
+            }
+        }
+    }
+
+    pub fn spell_needs_target(&self, spell_id: i32) -> bool {
+        self.ecs
+            .get_player_spells()
+            .get(spell_id as usize)
+            .is_some_and(|spell| spell.data.targeting.needs_target())
+    }
+
+    pub fn use_item_command(&mut self, item_index: usize) {
+        let Some(report) = self.ecs.get_player_report() else {
+            return;
+        };
+        let Some(items) = report.items else {
+            return;
+        };
+        let Some(stack) = items.data.items.get(item_index) else {
+            logger::log_message("You don't have that item.");
+            return;
+        };
+        let Some(item) = ITEM_REGISTRY.get(&stack.item_id) else {
+            return;
+        };
+        if item.gear.is_some() {
+            self.equip_command(item_index);
+            return;
+        }
+        if item.scroll.is_some() {
+            self.use_scroll_command(item_index, None);
+            return;
+        }
+
+        let Some(effect) = item.effect else {
+            match item.thrown_spawn {
+                Some(_) => logger::log_message("You need to throw that."),
+                None => logger::log_message("You can't use that on its own."),
+            }
+            return;
+        };
+
+        let mut deltas = effect(self.ecs.get_player_id(), &self.ecs);
+        let used_up = Inventory {
+            items: vec![ItemStack::new(stack.item_id, -1)],
+            ..Default::default()
+        };
+        deltas.push(Delta::Change(Component::Inventory(items.make_change(used_up))));
         self.ecs.apply_changes(deltas);
         self.end_turn();
     }
 
-    pub fn close_doors_command(&mut self) {
-        let Some(player_position) = self.ecs.get_player_position() else {
+    // Casts a fresh copy of the spell, so the player's own cooldowns and mana don't come into it.
+    pub fn use_scroll_command(&mut self, item_index: usize, target: Option<Coordinate>) {
+        let Some(items) = self.ecs.get_typed::<Inventory>(self.ecs.get_player_id()) else {
+            return;
+        };
+        let Some(stack) = items.data.items.get(item_index) else {
+            logger::log_message("You don't have that item.");
+            return;
+        };
+        let Some((item, spell)) = ITEM_REGISTRY
+            .get(&stack.item_id)
+            .and_then(|item| item.scroll.map(|spell_id| (item, spell_id)))
+            .and_then(|(item, spell_id)| SPELL_REGISTRY.get(&spell_id).map(|spell| (item, spell.with(|spell| spell.clone()))))
+        else {
+            logger::log_message("You can't read that.");
+            return;
+        };
+
+        let target = if spell.targeting.needs_target() {
+            let Some(target) = self.resolve_spell_target(spell.targeting, target) else {
+                return;
+            };
+            Some(target)
+        } else {
+            None
+        };
+        if !spell.can_cast(target, &self.ecs, &self.map) {
+            return;
+        }
+        logger::log_message(&format!("You read the {}.", item.name.to_lowercase()));
+        let origin = self.ecs.get_player_position();
+        let mut deltas = spell.cast(target, &self.ecs, &self.map);
+        let used_up = Inventory {
+            items: vec![ItemStack::new(stack.item_id, -1)],
+            ..Default::default()
+        };
+        deltas.push(Delta::Change(Component::Inventory(items.make_change(used_up))));
+        self.ecs.apply_changes(deltas);
+        self.explore_after_teleport(origin);
+        logger::play_sound(SoundCue::Spell);
+        self.end_turn();
+    }
+
+    pub fn item_needs_target(&self, item_index: usize) -> bool {
+        let Some(items) = self.ecs.get_typed::<Inventory>(self.ecs.get_player_id()) else {
+            return false;
+        };
+        items.data.items
+            .get(item_index)
+            .and_then(|stack| ITEM_REGISTRY.get(&stack.item_id))
+            .and_then(|item| item.scroll)
+            .and_then(|spell_id| SPELL_REGISTRY.get(&spell_id))
+            .is_some_and(|spell| spell.with(|spell| spell.targeting.needs_target()))
+    }
+
+    pub fn item_is_gear(&self, item_index: usize) -> bool {
+        let Some(items) = self.ecs.get_typed::<Inventory>(self.ecs.get_player_id()) else {
+            return false;
+        };
+        items.data.items
+            .get(item_index)
+            .and_then(|stack| ITEM_REGISTRY.get(&stack.item_id))
+            .is_some_and(|item| item.gear.is_some())
+    }
+
+    // Whatever already sits in the slot goes back into the pack.
+    pub fn equip_command(&mut self, item_index: usize) {
+        let player_id = self.ecs.get_player_id();
+        let (Some(items), Some(equipment)) = (
+            self.ecs.get_typed::<Inventory>(player_id),
+            self.ecs.get_typed::<Equipment>(player_id),
+        ) else {
+            return;
+        };
+        let Some(stack) = items.data.items.get(item_index) else {
+            logger::log_message("You don't have that item.");
+            return;
+        };
+        let Some((item, gear)) = ITEM_REGISTRY
+            .get(&stack.item_id)
+            .and_then(|item| item.gear.map(|gear| (item, gear)))
+        else {
+            logger::log_message("You can't equip that.");
+            return;
+        };
+
+        let mut moved = vec![ItemStack::new(stack.item_id, -1)];
+        let old_id = equipment.data.get(gear.slot);
+        let old_gear = old_id.and_then(|id| ITEM_REGISTRY.get(&id)).and_then(|item| item.gear);
+        if let Some(old_id) = old_id {
+            moved.push(ItemStack::new(old_id, 1));
+        }
+
+        let mut deltas = itemdefinitions::swap_gear(player_id, &self.ecs, old_gear, Some(gear));
+        deltas.push(Delta::Change(Component::Inventory(items.make_change(Inventory { items: moved, ..Default::default() }))));
+        deltas.push(Delta::Change(Component::Equipment(equipment.make_change(equipment.data.with(gear.slot, Some(stack.item_id))))));
+        logger::log_message(&format!("You equip the {}.", item.name.to_lowercase()));
+        self.ecs.apply_changes(deltas);
+        self.end_turn();
+    }
+
+    pub fn unequip_command(&mut self, slot: EquipSlot) {
+        let player_id = self.ecs.get_player_id();
+        let (Some(items), Some(equipment)) = (
+            self.ecs.get_typed::<Inventory>(player_id),
+            self.ecs.get_typed::<Equipment>(player_id),
+        ) else {
             return;
         };
+        let Some((item_id, item)) = equipment.data
+            .get(slot)
+            .and_then(|id| ITEM_REGISTRY.get(&id).map(|item| (id, item)))
+        else {
+            logger::log_message("You have nothing equipped there.");
+            return;
+        };
+
+        let mut deltas = itemdefinitions::swap_gear(player_id, &self.ecs, item.gear, None);
+        deltas.push(Delta::Change(Component::Inventory(items.make_change(Inventory { items: vec![ItemStack::new(item_id, 1)], ..Default::default() }))));
+        deltas.push(Delta::Change(Component::Equipment(equipment.make_change(equipment.data.with(slot, None)))));
+        logger::log_message(&format!("You take off the {}.", item.name.to_lowercase()));
+        self.ecs.apply_changes(deltas);
+        self.end_turn();
+    }
 
-        let adjacent = vec![
-            map::utils::UP,
-            map::utils::DOWN,
-            map::utils::LEFT,
-            map::utils::RIGHT,
+    // Only works standing next to a shopkeeper.
+    pub fn buy_command(&mut self, item_index: usize) {
+        let player_id = self.ecs.get_player_id();
+        let Some(position) = self.ecs.get_player_position() else {
+            return;
+        };
+        let Some(shop) = self.ecs
+            .get_entities_in_radius(position, self.map.adjacent_range())
+            .into_iter()
+            .find_map(|entity_id| self.ecs.get_typed::<Shop>(entity_id))
+        else {
+            logger::log_message("There is no one here to buy from.");
+            return;
+        };
+        let Some(items) = self.ecs.get_typed::<Inventory>(player_id) else {
+            return;
+        };
+        let Some(offer) = shop.data.stock.get(item_index).copied() else {
+            logger::log_message("The shopkeeper doesn't have that.");
+            return;
+        };
+        let name = ITEM_REGISTRY.get(&offer.item_id).map_or("item", |item| item.name).to_lowercase();
+        if items.data.coins < offer.price {
+            logger::log_message(&format!("You can't afford the {}, it costs {} gold.", name, offer.price));
+            return;
+        }
+
+        let mut stock = shop.data.stock.clone();
+        stock.remove(item_index);
+        let bought = Inventory {
+            coins: -offer.price,
+            items: vec![ItemStack::new(offer.item_id, 1)],
+        };
+        let deltas = vec![
+            Delta::Change(Component::Inventory(items.make_change(bought))),
+            Delta::Change(Component::Shop(shop.make_change(Shop { stock }))),
         ];
+        logger::log_message(&format!("You buy the {} for {} gold.", name, offer.price));
+        logger::play_sound(SoundCue::Gold);
+        self.ecs.apply_changes(deltas);
+        self.end_turn();
+    }
 
-        let neighbors = adjacent.iter().map(|dir| player_position + *dir);
+    pub fn close_doors_command(&mut self) {
+        let Some(player_position) = self.ecs.get_player_position() else {
+            return;
+        };
 
-        let doors: Vec<usize> = neighbors
+        let doors: Vec<usize> = map::utils::orthogonal_neighbors(player_position)
             .into_iter()
             .map(|pos| self.ecs.get_all_entities_in_tile(pos))
             .flatten()
@@ -186,24 +1127,116 @@ impl Game {
         };
 
         for door in doors {
-            let delta = propagate_event(event, door, &self.ecs);
-            self.ecs.apply_changes(delta);
+            self.propagate_and_apply_event(event, door);
         }
         self.end_turn();
     }
 
-    fn make_new_map(&mut self, size_x: usize, size_y: usize, depth: usize) {
-        let (new_map, new_bsp) = MapBuilder::generate_new(size_x, size_y, depth);
-        let mut new_ecs = ECS::new(new_bsp);
+    pub fn search_command(&mut self) {
+        let Some(player_position) = self.ecs.get_player_position() else {
+            return;
+        };
+
+        if self.search_streak > 0 && self.is_monster_visible() {
+            logger::log_message("A monster interrupts your search!");
+            self.search_streak = 0;
+        }
+        let radius = (1 + self.search_streak / SEARCHES_PER_RING).min(MAX_SEARCH_RADIUS) as i32;
+        self.search_streak += 1;
 
-        let player_id = self.ecs.get_player_id();
-        new_ecs.copy_entity_from_other(&self.ecs, player_id);
-        new_ecs.spawn_all_entities(&new_map);
+        let secrets: Vec<usize> = (-radius..=radius)
+            .flat_map(|dx| (-radius..=radius).map(move |dy| Coordinate { x: dx, y: dy }))
+            .filter(|offset| *offset != Coordinate::default())
+            .filter(|offset| offset.distance(Coordinate::default()) <= radius as f32)
+            .map(|offset| player_position + offset)
+            // nothing is found through a wall
+            .filter(|tile| los::line_of_sight(player_position, *tile, &self.map, &self.ecs))
+            .flat_map(|tile| self.ecs.get_all_entities_in_tile(tile))
+            .filter(|entity_id| {
+                self.ecs
+                    .entity_id_has_component(*entity_id, ComponentType::Secret)
+            })
+            .collect();
+
+        if secrets.is_empty() {
+            logger::log_message("You search but find nothing.");
+        }
+
+        // like closing doors, searching is a bump without a position
+        let event = &InteractionEvent {
+            event_type: EventType::Bump,
+            attack: None,
+            payload: vec![],
+        };
+
+        for secret in secrets {
+            self.propagate_and_apply_event(event, secret);
+        }
+        self.end_turn();
+    }
+
+    fn make_new_map(&mut self, depth: usize) {
+        let params = self.map_params.for_depth(depth);
+        let (mut new_map, new_ecs) = Game::build_floor(params, depth, self.map.difficulty, Some(&self.ecs));
+        new_map.diagonal_movement = self.map.diagonal_movement;
+        new_map.reveal_all = self.map.reveal_all;
 
         self.ecs = new_ecs;
         self.map = new_map;
+        self.travel_path.clear();
         self.update_systems();
         self.explore_first_room();
+        self.notify(GameEvent::FloorChanged { depth });
+    }
+
+    // Generates and populates a floor, the old ecs hands its player over.
+    fn build_floor(
+        params: MapParams,
+        depth: usize,
+        difficulty: Difficulty,
+        old_ecs: Option<&ECS>,
+    ) -> (GameMap, ECS) {
+        let style = MapStyle::from_depth(depth);
+        loop {
+            let (map, bsp) = MapBuilder::generate(params, depth, style, difficulty);
+            let mut ecs = ECS::new(bsp);
+            if let Some(old_ecs) = old_ecs {
+                ecs.copy_entity_from_other(old_ecs, old_ecs.get_player_id());
+            }
+            ecs.spawn_all_entities(&map);
+            // the corridors connect every room, so a retry is rare and cheap
+            if Game::is_floor_reachable(&map, &ecs) {
+                return (map, ecs);
+            }
+        }
+    }
+
+    // Everything spawned on open floor has to be reachable from the player.
+    fn is_floor_reachable(map: &GameMap, ecs: &ECS) -> bool {
+        let Some(start) = ecs.get_player_position() else {
+            return false;
+        };
+        let reachable = map.reachable_tiles(start);
+        let query = ComponentQuery::new_single(ComponentType::Position);
+        let mut entities = ecs.iter_entities_matching(&query);
+        entities.all(|entity| {
+            match ecs.get_component_from_entity_id(entity.index, ComponentType::Position) {
+                Some(Component::Position(position)) => {
+                    !map.is_tile_passable(position.data) || reachable.contains(&position.data)
+                }
+                _ => true,
+            }
+        })
+    }
+
+    fn update_visible_tiles(&self) {
+        if let Some(player_position) = self.ecs.get_player_position() {
+            self.map.update_visible(player_position, VISION_RADIUS, &self.ecs);
+        }
+    }
+
+    pub fn get_visible_tiles(&self) -> HashSet<Coordinate> {
+        self.map.visible.borrow().clone()
     }
 
     fn explore_first_room(&mut self) {
@@ -212,11 +1245,26 @@ impl Game {
         }
     }
 
+    fn is_open_pit(&self, coord: Coordinate) -> bool {
+        self.ecs.get_all_entities_in_tile(coord).into_iter().any(|entity_id| {
+            matches!(
+                self.ecs.get_component_from_entity_id(entity_id, ComponentType::Trap),
+                Some(Component::Trap(IndexedData { data: TrapKind::Pit, .. }))
+            ) && !self.ecs.entity_id_has_component(entity_id, ComponentType::Secret)
+        })
+    }
+
+    fn fall_into_pit(&mut self) {
+        self.make_new_map(self.map.depth + 1);
+        self.run_descend_systems();
+        logger::log_message("You land hard on the floor below.");
+    }
+
     pub fn descend_command(&mut self) {
         // check if player is on staircase
         if let Some(player_position) = self.ecs.get_player_position() {
             if self.ecs.position_has_stairs(player_position) {
-                self.make_new_map(self.map.width, self.map.height, self.map.depth + 1);
+                self.make_new_map(self.map.depth + 1);
                 self.run_descend_systems();
                 
             }
@@ -255,12 +1303,25 @@ impl Game {
                         None => None,
                     };
                 }
+                3 => {
+                    stat_change = stats.make_change(Attributes {
+                        armor: amount as isize,
+                        ..Default::default()
+                    });
+                }
+                4 => {
+                    stat_change = stats.make_change(Attributes {
+                        mana: amount as isize,
+                        mana_max: amount as isize,
+                        ..Default::default()
+                    });
+                }
                 _ => {}
             }
 
             let xp_change = stats.make_change(Attributes {
                 level: 1,
-                xp: -get_xp_to_next(&stats.data),
+                xp: -self.xp_curve.xp_to_next(&stats.data),
                 level_pending: false,
                 ..Default::default()
             });
@@ -274,9 +1335,12 @@ impl Game {
             let mut change_list = vec![
                 Delta::Change(Component::Attributes(stat_change)),
                 Delta::Change(Component::Attributes(xp_change)),
-                Delta::Change(Component::Health(restore_health)),
                 Delta::Change(Component::Health(health_increase)),
             ];
+            // a starving player doesn't get patched up by leveling
+            if !self.is_player_starving() {
+                change_list.push(Delta::Change(Component::Health(restore_health)));
+            }
             if let Some(spell) = spell {
                 change_list.push(
                     Delta::MakeComponent(MakeComponentOrder{
@@ -285,7 +1349,9 @@ impl Game {
                     })
                 );
             }
+            let level = stats.data.level + 1;
             self.ecs.apply_changes(change_list);
+            self.notify(GameEvent::PlayerLeveled { level });
         }
     }
 
@@ -300,6 +1366,44 @@ impl Game {
         })
     }
 
+    // friendly blockers trade places instead of stopping the player, hostiles never do
+    fn is_swappable(&self, entity_id: usize) -> bool {
+        self.ecs.entity_id_has_component(entity_id, ComponentType::Swappable)
+            && !self.ecs.entity_id_has_component(entity_id, ComponentType::Monster)
+            && !self.ecs.entity_id_has_component(entity_id, ComponentType::Size)
+    }
+
+    fn swap_with(&mut self, entity_id: usize, direction: Coordinate) {
+        let Some(player_report) = self.ecs.get_player_report() else {
+            return;
+        };
+        let components = self.ecs.get_components_from_entity_id(entity_id);
+        let Some(other_report) = archetype::make_unit_report(&components) else {
+            return;
+        };
+        let (player_tile, other_tile) = (player_report.position.data, other_report.position.data);
+
+        // both sides set off whatever lies on their new tile
+        let mut deltas = vec![];
+        for hazard_id in self.ecs.get_all_entities_in_tile(other_tile) {
+            if hazard_id != entity_id {
+                deltas.extend(propagate_event(&player_report.bump, hazard_id, &self.ecs));
+            }
+        }
+        let other_bump = InteractionEvent {
+            attack: None,
+            ..other_report.bump.clone()
+        };
+        for hazard_id in self.ecs.get_all_entities_in_tile(player_tile) {
+            if hazard_id != self.ecs.get_player_id() {
+                deltas.extend(propagate_event(&other_bump, hazard_id, &self.ecs));
+            }
+        }
+        deltas.push(Delta::Change(Component::Position(other_report.position.make_change(direction * -1))));
+        deltas.push(Delta::Change(Component::Position(player_report.position.make_change(direction))));
+        self.ecs.apply_changes(deltas);
+    }
+
     fn move_player(&mut self, direction: Coordinate) {
         let player_report = match self.ecs.get_player_report() {
             Some(report) => report,
@@ -310,44 +1414,133 @@ impl Game {
     }
 
     fn end_turn(&mut self) {
+        self.hasted_action_taken = false;
+        self.turn += 1;
+        self.map.tick_revealed();
         self.run_turn_systems();
+        self.check_level_up();
+    }
+
+    // Enough xp marks a level up as pending until the player picks a stat.
+    fn check_level_up(&mut self) {
+        let Some(stats) = self.ecs.get_typed::<Attributes>(self.ecs.get_player_id()) else {
+            return;
+        };
+        if stats.data.level_pending || stats.data.xp < self.xp_curve.xp_to_next(&stats.data) {
+            return;
+        }
+        let new_level = stats.data.level + 1;
+        logger::log_message_with(&format!("You have reached level {}!", new_level), logger::MessageKind::Reward);
+        let pending = stats.make_change(Attributes {
+            level_pending: true,
+            ..Default::default()
+        });
+        self.ecs.apply_change(Delta::Change(Component::Attributes(pending)));
+    }
+
+    fn end_hasted_turn(&mut self) {
+        // hasted players get a free action before the world moves
+        if self.player_has_effect(EffectType::Haste) && !self.hasted_action_taken {
+            self.hasted_action_taken = true;
+            return;
+        }
+        self.end_turn();
+    }
+
+    fn player_has_effect(&self, effect: EffectType) -> bool {
+        self.ecs
+            .get_components_from_entity_id(self.ecs.get_player_id())
+            .iter()
+            .any(|component| match component {
+                Component::DurationEffect(indexed_effect) => indexed_effect.data.1 == effect,
+                _ => false,
+            })
+    }
+
+    fn is_target_in_reach(&self, origin: Coordinate, target: Coordinate, range: f32) -> bool {
+        let line_of_sight =
+            origin == target || los::line_of_sight(origin, target, &self.map, &self.ecs);
+
+        if !line_of_sight {
+            logger::log_message("Target is out of sight.");
+            return false;
+        }
+        if target.distance(origin) > range {
+            logger::log_message("Target is out of range.");
+            return false;
+        }
+        true
+    }
+
+    fn find_landing_tile(&self, origin: Coordinate, target: Coordinate) -> Option<Coordinate> {
+        let is_open = |tile: Coordinate| {
+            self.map.is_tile_passable(tile) && self.ecs.get_blocking_entity(tile).is_none()
+        };
+        if is_open(target) {
+            return Some(target);
+        }
+
+        // land as close to the target as possible, on a tile the throw can reach
+        (-1..=1)
+            .flat_map(|x| (-1..=1).map(move |y| target + Coordinate { x, y }))
+            .filter(|tile| is_open(*tile))
+            .filter(|tile| {
+                *tile == origin || los::line_of_sight(origin, *tile, &self.map, &self.ecs)
+            })
+            .min_by(|a, b| {
+                let a_key = (a.distance(target), a.distance(origin));
+                let b_key = (b.distance(target), b.distance(origin));
+                a_key.partial_cmp(&b_key).unwrap_or(std::cmp::Ordering::Equal)
+            })
     }
 
     fn propagate_and_apply_event(&mut self, event: &InteractionEvent, entity_id: usize) {
         let change_list = propagate_event(&event, entity_id, &self.ecs);
         self.ecs.apply_changes(change_list);
+        self.notify(GameEvent::Interaction { event_type: event.event_type, entity_id });
     }
 
     fn add_entity_images(&self, mut images: Vec<Vec<Vec<i32>>>) -> Vec<Vec<i32>> {
-        let query = ComponentQuery {
-            required: vec![ComponentType::Position, ComponentType::Image],
-            optional: vec![],
-        };
-        let matches = self.ecs.get_entities_matching_query(&query);
-        for entity in matches {
+        let query = ComponentQuery::new()
+            .require(ComponentType::Position)
+            .require(ComponentType::Image);
+        for entity in self.ecs.iter_entities_matching(&query) {
             let component_list = &self.ecs.get_components_from_entity_id(entity.index);
             let (maybe_position, components) =
                 take_component_from_refs(ComponentType::Position, component_list);
             let (maybe_image, components) =
                 take_component_from_refs(ComponentType::Image, &components);
-            let (maybe_burning, _components) =
-                    take_component_from_refs(ComponentType::DurationEffect, &components);
+            let (maybe_turn, _components) =
+                    take_component_from_refs(ComponentType::Turn, &components);
+            let overlays: Vec<_> = component_list
+                .iter()
+                .filter_map(|component| match component {
+                    Component::DurationEffect(effect) => effect.data.1.overlay_image(),
+                    _ => None,
+                })
+                .collect();
             if let (Some(Component::Position(position)), Some(Component::Image(image))) =
                 (maybe_position, maybe_image)
             {
-                if !self.map.explored.borrow().contains(&position.data) {
-                    continue;
-                }
+                // large entities are drawn on every tile they cover
+                for tile in self.ecs.get_footprint(entity.index, position.data) {
+                    if !self.map.is_tile_shown(tile) {
+                        continue;
+                    }
+                    // furniture is drawn from memory, monsters only while in view
+                    if maybe_turn.is_some() && !self.map.is_tile_shown_in_view(tile) {
+                        continue;
+                    }
 
-                let (index, image, depth) = (
-                    position.data.y as usize * self.map.width + position.data.x as usize, 
-                    image.data.current.id,
-                    image.data.current.depth,
-                );
-                images[index].push(vec![image, depth]);
-                
-                if let Some(Component::DurationEffect(IndexedData{index: _, data: DurationEffect(_, EffectType::Burning)})) = maybe_burning {
-                    images[index].push(vec![19, 6]);
+                    let (index, image, depth) = (
+                        tile.y as usize * self.map.width + tile.x as usize, 
+                        image.data.current.id,
+                        image.data.current.depth,
+                    );
+                    images[index].push(vec![image, depth]);
+                    for overlay in &overlays {
+                        images[index].push(vec![overlay.id, overlay.depth]);
+                    }
                 }
             }
         }
@@ -372,6 +1565,192 @@ impl Game {
         self.add_entity_images(tile_images)
     }
 
+    // The box around the player's room as (left, top, right, bottom), walls included.
+    pub fn get_current_room_bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let position = self.ecs.get_player_position()?;
+        let extends = self.map.get_room_at(position)?.extends;
+        Some((
+            extends.top_left.x,
+            extends.top_left.y,
+            extends.bottom_right.x,
+            extends.bottom_right.y,
+        ))
+    }
+
+    pub fn get_minimap(&self) -> Vec<Vec<MinimapCell>> {
+        let player_position = self.ecs.get_player_position();
+        (0..self.map.height as i32)
+            .map(|y| {
+                (0..self.map.width as i32)
+                    .map(|x| self.get_minimap_cell(Coordinate { x, y }, player_position))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn get_minimap_cell(&self, coord: Coordinate, player_position: Option<Coordinate>) -> MinimapCell {
+        if player_position == Some(coord) {
+            return MinimapCell::PlayerHere;
+        }
+        if !self.map.explored.borrow().contains(&coord) {
+            return MinimapCell::Unexplored;
+        }
+        if !self.map.is_tile_passable(coord) {
+            return MinimapCell::Wall;
+        }
+
+        let entities = self.ecs.get_all_entities_in_tile(coord);
+        let has = |component_type| {
+            entities
+                .iter()
+                .any(|entity_id| self.ecs.entity_id_has_component(*entity_id, component_type))
+        };
+        if has(ComponentType::Stairs) {
+            MinimapCell::Stairs
+        } else if has(ComponentType::Door) {
+            MinimapCell::Door
+        } else if has(ComponentType::Secret) && !has(ComponentType::Trap) {
+            // undiscovered secret doors look like any other wall, hidden traps like the floor
+            MinimapCell::Wall
+        } else {
+            MinimapCell::Floor
+        }
+    }
+
+    // Looking around is free, no turn passes.
+    fn log_examine(&self, coord: Coordinate) {
+        match self.examine_command(coord) {
+            Some(report) => logger::log_message(&report.describe()),
+            None => logger::log_message("You haven't explored there."),
+        }
+    }
+
+    // Only reports on explored tiles, and only on what's there while it's in sight.
+    pub fn examine_command(&self, coord: Coordinate) -> Option<ExamineReport> {
+        if !self.map.explored.borrow().contains(&coord) {
+            return None;
+        }
+        let mut report = ExamineReport {
+            terrain: self.map.describe_tile(coord),
+            ..Default::default()
+        };
+        if !self.map.is_tile_visible(coord) {
+            return Some(report);
+        }
+
+        // the entity drawn on top, hidden doors stay hidden
+        let topmost = self.ecs
+            .get_all_entities_in_tile(coord)
+            .into_iter()
+            .filter(|entity_id| !self.ecs.entity_id_has_component(*entity_id, ComponentType::Secret))
+            .filter_map(|entity_id| match self.ecs.get_component_from_entity_id(entity_id, ComponentType::Image) {
+                Some(Component::Image(image)) => Some((entity_id, image.data.current.depth)),
+                _ => None,
+            })
+            .min_by_key(|(_, depth)| *depth)
+            .map(|(entity_id, _)| entity_id);
+        let Some(entity_id) = topmost else {
+            return Some(report);
+        };
+
+        for component in self.ecs.get_components_from_entity_id(entity_id) {
+            match component {
+                Component::Name(name) => report.name = Some(name.data.raw.clone()),
+                Component::Health(health) => report.health = Some((health.data.current, health.data.max)),
+                Component::DurationEffect(effect) => report.effects.push(effect.data.1),
+                _ => {}
+            }
+        }
+        if report.health.is_some() && entity_id != self.ecs.get_player_id() {
+            report.threat = Some(self.assess_threat(entity_id));
+        }
+        Some(report)
+    }
+
+    // Compares how many average hits each side needs to bring the other down.
+    pub fn assess_threat(&self, entity_id: usize) -> ThreatLevel {
+        let player_id = self.ecs.get_player_id();
+        let player_stats = self.ecs.get_typed::<Attributes>(player_id).map(|stats| &stats.data);
+        let monster_components = self.ecs.get_components_from_entity_id(entity_id);
+        let monster_stats = find_typed::<Attributes, _>(&monster_components).map(|stats| &stats.data);
+        let (Some(player_health), Some(monster_health)) = (
+            self.ecs.get_typed::<Health>(player_id),
+            find_typed::<Health, _>(&monster_components),
+        ) else {
+            return ThreatLevel::Trivial;
+        };
+
+        let player_damage = match combat::is_disarmed(&self.ecs.get_components_from_entity_id(player_id)) {
+            true => 0.0,
+            false => self.ecs
+                .get_player_attacks()
+                .0
+                .map_or(0.0, |attack| combat::average_damage(&attack, player_stats)),
+        };
+        let monster_damage = match find_typed::<Combat, _>(&monster_components) {
+            Some(monster_combat) if !combat::is_disarmed(&monster_components) => [monster_combat.data.melee, monster_combat.data.ranged]
+                .iter()
+                .flatten()
+                .map(|attack| combat::average_damage(attack, monster_stats))
+                .fold(0.0, f32::max),
+            _ => 0.0,
+        };
+        if monster_damage <= 0.0 {
+            return ThreatLevel::Trivial;
+        }
+        // a fight the player can't win, but one they can still walk away from
+        if player_damage <= 0.0 {
+            return ThreatLevel::Dangerous;
+        }
+
+        let player_armor = player_stats.map_or(0, |stats| stats.armor);
+        let monster_armor = monster_stats.map_or(0, |stats| stats.armor);
+        let turns_to_win = Game::turns_to_kill(monster_health.data.current, player_damage, monster_armor);
+        let turns_to_lose = Game::turns_to_kill(player_health.data.current, monster_damage, player_armor);
+        match turns_to_lose / turns_to_win {
+            ratio if ratio >= TRIVIAL_THREAT_RATIO => ThreatLevel::Trivial,
+            ratio if ratio >= EVEN_THREAT_RATIO => ThreatLevel::Even,
+            ratio if ratio >= DANGEROUS_THREAT_RATIO => ThreatLevel::Dangerous,
+            _ => ThreatLevel::Deadly,
+        }
+    }
+
+    // Armor never fully negates a hit, but a side with nothing to hit with never wins.
+    fn turns_to_kill(health: isize, damage: f32, armor: isize) -> f32 {
+        if damage <= 0.0 {
+            return f32::INFINITY;
+        }
+        let per_hit = (damage - armor as f32).max(1.0);
+        (health as f32 / per_hit).ceil().max(1.0)
+    }
+
+    // Depth and turns live on the game, the rest is tallied on the player.
+    pub fn get_run_stats(&self) -> RunStats {
+        let tally = self.ecs.get_typed::<RunStats>(self.ecs.get_player_id());
+        RunStats {
+            deepest_depth: self.map.depth,
+            turns: self.turn,
+            ..tally.map(|tally| tally.data.clone()).unwrap_or_default()
+        }
+    }
+
+    pub fn get_death_report(&self) -> Option<DeathReport> {
+        if self.is_player_alive() {
+            return None;
+        }
+        let stats = self.get_run_stats();
+        Some(DeathReport {
+            killer: stats.killed_by.clone().unwrap_or_else(|| "Died".to_string()),
+            depth: stats.deepest_depth,
+            turns: stats.turns,
+            stats,
+        })
+    }
+
+    pub fn get_difficulty(&self) -> Difficulty {
+        self.map.difficulty
+    }
+
     pub fn get_map_info(&self) -> i32 {
         self.map.depth as i32
     }
@@ -388,12 +1767,21 @@ impl Game {
         i32,      // max hp
         i32,      // strength
         i32,      // dexterity
+        i32,      // armor
         [i32; 2], // melee damage
         f32,      // melee crit chance
         [i32; 2], // ranged damage
         f32,      // ranged crit chance
         Vec<String>,// spell names
         Vec<i32>, // spell icons
+        Vec<i32>, // spell cooldown turns, -1 until next floor
+        Vec<String>,// item names
+        Vec<i32>, // item icons
+        i32,      // current hunger
+        i32,      // max hunger
+        i32,      // current mana
+        i32,      // max mana
+        i32,      // arrows left, -1 if they never run out
     ) {
         let report = match self.ecs.get_player_report() {
             Some(report) => report,
@@ -408,25 +1796,35 @@ impl Game {
                     0,
                     0,
                     0,
+                    0,
                     [0, 0],
                     0.0,
                     [0, 0],
                     0.0,
                     vec![],
                     vec![],
+                    vec![],
+                    vec![],
+                    vec![],
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
                 )
             }
         };
 
-        let name = report.name.unwrap().data;
-        let health = report.health.unwrap().data;
-        let stats = report.stats.unwrap().data;
-        let items = report.items.unwrap().data;
+        let name = report.name.map(|data| data.data).unwrap_or_default();
+        let health = report.health.map(|data| data.data).unwrap_or_default();
+        let stats = report.stats.map(|data| data.data).unwrap_or_default();
+        let items = report.items.map(|data| data.data).unwrap_or_default();
 
         let mut melee_damage = [0, 0];
         let mut melee_crit = 0.0;
         let mut ranged_damage = [0, 0];
         let mut ranged_crit = 0.0;
+        let mut arrows = -1;
 
         let (melee, ranged) = self.ecs.get_player_attacks();
 
@@ -446,6 +1844,9 @@ impl Game {
                 (attack.damage_base + bonus_damage.1 + attack.damage_spread) as i32,
             ];
             ranged_crit = combat::BASE_CRIT_CHANCE + attack.crit_chance_bonus;
+            if let Some(ammo) = attack.ammo {
+                arrows = ammo.current as i32;
+            }
         }
 
         let (spell_names, spell_images): (Vec<String>, Vec<i32>) = self.ecs
@@ -455,26 +1856,72 @@ impl Game {
             .map(|spell| (spell.name.to_string(), spell.image.current.id))
             .unzip();
 
+        let spell_cooldowns: Vec<i32> = self.ecs
+            .get_player_spells()
+            .iter()
+            .map(|indexed_spell| match indexed_spell.data.castable {
+                CooldownState::Available => 0,
+                CooldownState::Cooldown => -1,
+                CooldownState::OnCooldown(turns) => turns as i32,
+            })
+            .collect();
+
+        let (item_names, item_images): (Vec<String>, Vec<i32>) = items
+            .items
+            .iter()
+            .filter_map(|stack| {
+                ITEM_REGISTRY
+                    .get(&stack.item_id)
+                    .map(|item| (format!("{} x{}", item.name, stack.count), item.icon))
+            })
+            .unzip();
+
+        let hunger = self.get_player_hunger().unwrap_or_default();
+
         // frontend requires i32:s
         (
             name.raw,
             stats.level as i32,
             items.coins as i32,
             stats.xp as i32,
-            attributes::get_xp_to_next(&stats) as i32,
+            self.xp_curve.xp_to_next(&stats) as i32,
             health.current as i32,
             health.max as i32,
             stats.strength as i32,
             stats.dexterity as i32,
+            stats.armor as i32,
             melee_damage,
             melee_crit as f32,
             ranged_damage,
             ranged_crit as f32,
             spell_names,
             spell_images,
+            spell_cooldowns,
+            item_names,
+            item_images,
+            hunger.current as i32,
+            hunger.max as i32,
+            stats.mana as i32,
+            stats.mana_max as i32,
+            arrows,
         )
     }
 
+    fn get_player_hunger(&self) -> Option<Hunger> {
+        match self
+            .ecs
+            .get_component_from_entity_id(self.ecs.get_player_id(), ComponentType::Hunger)
+        {
+            Some(Component::Hunger(data)) => Some(data.data),
+            _ => None,
+        }
+    }
+
+    fn is_player_starving(&self) -> bool {
+        self.get_player_hunger()
+            .is_some_and(|hunger| hunger.is_starving())
+    }
+
     pub fn is_player_alive(&self) -> bool {
         let components = &self
             .ecs
@@ -485,7 +1932,7 @@ impl Game {
             Some(Component::Health(data)) => data.data,
             _ => return false,
         };
-        health.current >= 0
+        !health.is_dead()
     }
 
     pub fn is_player_ready_for_level(&self) -> bool {
@@ -497,28 +1944,45 @@ impl Game {
             Some(Component::Attributes(data)) => data.data,
             _ => return false,
         };
-        stats.xp >= attributes::get_xp_to_next(&stats)
+        self.is_player_alive() && stats.xp >= self.xp_curve.xp_to_next(&stats)
     }
 
     pub fn add_default_systems(&mut self) {
         self.systems
             .add_turn_system(Box::new(Exploration::default()));
+        self.systems
+            .add_turn_system(Box::new(Projectiles::default()));
+        self.systems
+            .add_turn_system(Box::new(Terrain::default()));
         self.systems
             .add_turn_system(Box::new(Fire::default()));
         self.systems
             .add_turn_system(Box::new(Acid::default()));
+        self.systems
+            .add_turn_system(Box::new(Bleed::default()));
+        self.systems
+            .add_turn_system(Box::new(Regen::default()));
+        self.systems
+            .add_turn_system(Box::new(system::Hunger::default()));
+        self.systems
+            .add_turn_system(Box::new(system::ManaRegen::default()));
         self.systems
             .add_turn_system(Box::new(Stoneskin::default()));
+        self.systems
+            .add_turn_system(Box::new(Shield::default()));
         self.systems
             .add_turn_system(Box::new(Duration::default()));
+        self.systems
+            .add_turn_system(Box::new(SpellCooldowns::default()));
         self.systems
             .add_turn_system(Box::new(UnitCull::default()));
         self.systems
-            .add_turn_system(Box::new(PlayerCheck::default()));
+            .add_turn_system(Box::new(Alert::default()));
         self.systems
             .add_turn_system(Box::new(MonsterTurns::default()));
 
         self.systems.add_descend_system(Box::new(Cooldowns::default()));
+        self.systems.add_descend_system(Box::new(Restock::default()));
     }
 
     pub fn run_descend_systems(&mut self) {
@@ -526,10 +1990,444 @@ impl Game {
     }
 
     pub fn run_turn_systems(&mut self) {
-        self.systems.run_turn_systems(&mut self.ecs, &self.map);
+        for event in self.systems.run_turn_systems(&mut self.ecs, &self.map) {
+            self.notify(event);
+        }
     }
 
     pub fn update_systems(&mut self) {
         self.systems.update_systems(&self.ecs, &self.map);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    use rand::{rngs::{mock::StepRng, StdRng}, SeedableRng};
+
+    use crate::game::{
+        components::{combat::AttackReport, core::DurationEffect},
+        responses,
+        testing::{self, PLAYER_START},
+        tileatlas,
+    };
+
+    #[test]
+    fn the_xp_curve_sets_the_level_up_threshold() {
+        let mut game = testing::arena();
+        game.set_xp_curve(XpCurve { base: 10, growth: 1.0 });
+        let stats = game.ecs.get_typed::<Attributes>(game.ecs.get_player_id()).unwrap();
+        let xp = Attributes { xp: 10 - stats.data.xp, ..Default::default() };
+        game.ecs.apply_change(Delta::Change(Component::Attributes(stats.make_change(xp))));
+        assert!(game.is_player_ready_for_level());
+
+        game.apply_action(PlayerAction::Wait);
+        let stats = game.ecs.get_typed::<Attributes>(game.ecs.get_player_id()).unwrap();
+        assert!(stats.data.level_pending);
+    }
+
+    #[test]
+    fn walled_off_spawns_make_a_floor_unreachable() {
+        let mut game = testing::arena();
+        let spot = Coordinate { x: 20, y: 10 };
+        testing::spawn(&mut game, "Doggo", spot);
+        assert!(Game::is_floor_reachable(&game.map, &game.ecs));
+
+        for x in 19..=21 {
+            for y in 9..=11 {
+                if (Coordinate { x, y }) != spot {
+                    testing::wall(&mut game, Coordinate { x, y });
+                }
+            }
+        }
+        assert!(!Game::is_floor_reachable(&game.map, &game.ecs));
+    }
+
+    #[test]
+    fn listeners_hear_monsters_die() {
+        let mut game = testing::arena();
+        let heard = Rc::new(RefCell::new(vec![]));
+        let log = heard.clone();
+        game.subscribe(move |event| {
+            if let GameEvent::MonsterDied { name } = event {
+                log.borrow_mut().push(name.clone());
+            }
+        });
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let health = game.ecs.get_typed::<Health>(dog).unwrap();
+        let lethal = Health { current: -health.data.current, max: 0 };
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(lethal))));
+        game.apply_action(PlayerAction::Wait);
+        assert_eq!(*heard.borrow(), vec!["Doggo".to_string()]);
+    }
+
+    #[test]
+    fn listeners_hear_monsters_strike() {
+        let mut game = testing::arena();
+        let heard = Rc::new(RefCell::new(vec![]));
+        let log = heard.clone();
+        game.subscribe(move |event| {
+            if let GameEvent::Interaction { event_type: EventType::Bump, entity_id } = event {
+                log.borrow_mut().push(*entity_id);
+            }
+        });
+        testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 1, y: 0 });
+        game.apply_action(PlayerAction::Wait);
+        assert!(heard.borrow().contains(&game.ecs.get_player_id()));
+    }
+
+    #[test]
+    fn death_reports_name_the_killer() {
+        let mut game = testing::arena();
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 1, y: 0 });
+        assert!(game.get_death_report().is_none());
+
+        let bite = InteractionEvent {
+            event_type: EventType::Bump,
+            attack: Some(AttackReport { damage: 1000, crit_chance: 0.0, ..Default::default() }),
+            payload: game.ecs.get_components_from_entity_id(dog).into_iter().cloned().collect(),
+        };
+        let player = game.ecs.get_components_from_entity_id(game.ecs.get_player_id());
+        let deltas = responses::take_damage_response(&bite, &player, &game.ecs);
+        game.ecs.apply_changes(deltas);
+        let report = game.get_death_report().unwrap();
+        assert_eq!(report.describe(), "Slain by a doggo on depth 1.");
+        assert_eq!(report.summary(), "0 turns, 0 kills, 0 gold.");
+    }
+
+    fn set_player_health(game: &mut Game, current: isize) {
+        let health = game.ecs.get_typed::<Health>(game.ecs.get_player_id()).unwrap();
+        let change = Health { current: current - health.data.current, max: 0 };
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(change))));
+    }
+
+    #[test]
+    fn the_player_dies_at_zero_health() {
+        let mut game = testing::arena();
+        set_player_health(&mut game, 1);
+        assert!(game.is_player_alive());
+
+        set_player_health(&mut game, 0);
+        assert!(!game.is_player_alive());
+        // culling leaves the body for the death popup
+        game.run_turn_systems();
+        assert!(game.ecs.get_player_position().is_some());
+        assert!(!game.is_player_alive());
+    }
+
+    fn melee_damage(game: &Game) -> isize {
+        game.ecs.get_player_attacks().0.unwrap().damage_base
+    }
+
+    #[test]
+    fn swords_add_damage_until_unequipped() {
+        let mut game = testing::arena();
+        let items = game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap();
+        let sword = Inventory { items: vec![ItemStack::new(itemdefinitions::RUSTY_SWORD_ID, 1)], ..Default::default() };
+        game.ecs.apply_change(Delta::Change(Component::Inventory(items.make_change(sword))));
+        let items = &game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap().data.items;
+        let sword_index = items.iter().position(|stack| stack.item_id == itemdefinitions::RUSTY_SWORD_ID).unwrap();
+        let unarmed = melee_damage(&game);
+
+        assert!(game.item_is_gear(sword_index));
+        game.apply_action(PlayerAction::Equip(sword_index));
+        assert_eq!(melee_damage(&game), unarmed + 2);
+        game.apply_action(PlayerAction::Unequip(EquipSlot::Weapon));
+        assert_eq!(melee_damage(&game), unarmed);
+        let items = &game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap().data.items;
+        assert!(items.iter().any(|stack| stack.item_id == itemdefinitions::RUSTY_SWORD_ID && stack.count == 1));
+    }
+
+    fn player_inventory(game: &Game) -> Inventory {
+        game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap().data.clone()
+    }
+
+    #[test]
+    fn buying_needs_enough_gold() {
+        let mut game = testing::arena();
+        let keeper = testing::spawn(&mut game, "Shopkeeper", PLAYER_START + Coordinate { x: 1, y: 0 });
+        let offer = game.ecs.get_typed::<Shop>(keeper).unwrap().data.stock[0];
+        let items = game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap();
+        let broke = Inventory { coins: offer.price - 1 - items.data.coins, ..Default::default() };
+        game.ecs.apply_change(Delta::Change(Component::Inventory(items.make_change(broke))));
+
+        game.apply_action(PlayerAction::Buy(0));
+        assert_eq!(player_inventory(&game).coins, offer.price - 1);
+        assert!(!player_inventory(&game).items.iter().any(|stack| stack.item_id == offer.item_id));
+
+        let items = game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap();
+        game.ecs.apply_change(Delta::Change(Component::Inventory(items.make_change(Inventory { coins: 1, ..Default::default() }))));
+        game.apply_action(PlayerAction::Buy(0));
+        assert_eq!(player_inventory(&game).coins, 0);
+        assert!(player_inventory(&game).items.iter().any(|stack| stack.item_id == offer.item_id));
+    }
+
+    #[test]
+    fn threats_scale_with_the_monster() {
+        let mut game = testing::arena();
+        let rat = testing::spawn(&mut game, "Rat", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let make_heavy = spawning::OBJECT_SPAWN_NAMES.get("Heavy").unwrap();
+        let heavy_spot = PLAYER_START + Coordinate { x: 6, y: 4 };
+        make_heavy(&mut game.ecs, heavy_spot, 20, game.map.difficulty);
+        let heavy = game.ecs.get_blocking_entity(heavy_spot).unwrap();
+        assert_eq!(game.assess_threat(rat), ThreatLevel::Trivial);
+        assert_eq!(game.assess_threat(heavy), ThreatLevel::Deadly);
+
+        game.ecs.apply_change(Delta::MakeComponent(MakeComponentOrder {
+            component: Component::DurationEffect(IndexedData::new_with(DurationEffect(3, EffectType::Disarmed))),
+            entity: EntityIdentifier::new_from_entity(game.ecs.get_player_id()),
+        }));
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 2 });
+        assert_eq!(game.assess_threat(dog), ThreatLevel::Dangerous);
+    }
+
+    #[test]
+    fn fireball_scrolls_burn_once() {
+        let mut game = testing::arena();
+        let scroll_id = itemdefinitions::FIREBALL_SCROLL_ID;
+        let items = game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap();
+        let scrolls = Inventory { items: vec![ItemStack::new(scroll_id, 2)], ..Default::default() };
+        game.ecs.apply_change(Delta::Change(Component::Inventory(items.make_change(scrolls))));
+        let scroll_count = |game: &Game| -> isize {
+            let items = &game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap().data.items;
+            items.iter().filter(|stack| stack.item_id == scroll_id).map(|stack| stack.count).sum()
+        };
+        let index = player_inventory(&game).items.iter().position(|stack| stack.item_id == scroll_id).unwrap();
+        assert!(game.item_needs_target(index));
+
+        let target = PLAYER_START + Coordinate { x: 5, y: 3 };
+        game.apply_action(PlayerAction::ReadScroll(index, Some(target)));
+        let is_flame = |entity_id| game.ecs.get_typed::<Name>(entity_id).is_some_and(|name| name.data.raw == "Flame");
+        let mut burning: Vec<Coordinate> = (0..game.map.width * game.map.height)
+            .map(|i| Coordinate { x: (i % game.map.width) as i32, y: (i / game.map.width) as i32 })
+            .filter(|&tile| game.ecs.get_all_entities_in_tile(tile).into_iter().any(is_flame))
+            .collect();
+        burning.sort_by_key(|tile| (tile.x, tile.y));
+        // the target and the four tiles beside it
+        let mut footprint: Vec<Coordinate> = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)]
+            .into_iter()
+            .map(|(x, y)| target + Coordinate { x, y })
+            .collect();
+        footprint.sort_by_key(|tile| (tile.x, tile.y));
+        assert_eq!(burning, footprint);
+        assert_eq!(scroll_count(&game), 1);
+    }
+
+    #[test]
+    fn revealing_all_shows_unexplored_tiles() {
+        let mut game = Game::new(32, 18, Difficulty::default());
+        let width = game.map.width;
+        let hidden = (0..game.map.width * game.map.height)
+            .map(|i| Coordinate { x: (i % width) as i32, y: (i / width) as i32 })
+            .find(|coord| game.map.map.contains_key(coord) && !game.map.explored.borrow().contains(coord))
+            .unwrap();
+        let index = hidden.y as usize * width + hidden.x as usize;
+        let before = game.get_image_ids_for_map()[index].clone();
+
+        game.toggle_reveal_all();
+        assert!(game.is_revealing_all());
+        assert_ne!(game.get_image_ids_for_map()[index], before);
+        assert!(!game.map.explored.borrow().contains(&hidden));
+    }
+
+    #[test]
+    fn undo_takes_back_a_clean_step() {
+        let mut game = testing::arena();
+        let turn = game.turn;
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        game.apply_action(PlayerAction::Undo);
+        assert_eq!(game.ecs.get_player_position(), Some(PLAYER_START));
+        // the turn it took stays spent
+        assert_eq!(game.turn, turn + 1);
+    }
+
+    #[test]
+    fn undo_leaves_the_monsters_be() {
+        let mut game = testing::arena();
+        // out of sight behind a wall, but on explored ground so it keeps moving
+        for y in 1..16 {
+            testing::wall(&mut game, Coordinate { x: 10, y });
+        }
+        let spot = Coordinate { x: 20, y: 8 };
+        game.map.explored.borrow_mut().insert(spot);
+        let dog = testing::spawn(&mut game, "Doggo", spot);
+        let dog_position = |game: &Game| game.ecs.get_typed::<Coordinate>(dog).unwrap().data;
+
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        let moved_to = dog_position(&game);
+        assert_ne!(moved_to, spot);
+        game.apply_action(PlayerAction::Undo);
+        assert_eq!(game.ecs.get_player_position(), Some(PLAYER_START));
+        assert_eq!(dog_position(&game), moved_to);
+        game.apply_action(PlayerAction::Wait);
+        assert_ne!(dog_position(&game), moved_to);
+    }
+
+    #[test]
+    fn steps_onto_loot_cant_be_undone() {
+        let mut game = testing::arena();
+        testing::spawn(&mut game, "Gold", PLAYER_START + Coordinate { x: 1, y: 0 });
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        testing::drain_log();
+        game.apply_action(PlayerAction::Undo);
+        assert_eq!(game.ecs.get_player_position(), Some(PLAYER_START + Coordinate { x: 1, y: 0 }));
+        assert!(testing::drain_log().contains(&"There is no step to take back.".to_string()));
+    }
+
+    #[test]
+    fn manual_pickup_waits_for_the_command() {
+        for auto_pickup in [true, false] {
+            let mut game = testing::arena();
+            game.set_auto_pickup(auto_pickup);
+            let gold = testing::spawn(&mut game, "Gold", PLAYER_START + Coordinate { x: 1, y: 0 });
+            let coins = game.ecs.get_typed::<Inventory>(gold).unwrap().data.coins;
+            let before = player_inventory(&game).coins;
+            game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+            let picked_up = if auto_pickup { coins } else { 0 };
+            assert_eq!(player_inventory(&game).coins, before + picked_up);
+            if !auto_pickup {
+                game.apply_action(PlayerAction::Pickup);
+                assert_eq!(player_inventory(&game).coins, before + coins);
+            }
+        }
+    }
+
+    #[test]
+    fn searching_longer_finds_secrets_further_off() {
+        let mut game = testing::arena();
+        let near = PLAYER_START + Coordinate { x: 2, y: 0 };
+        // a wall between the player and this one
+        let hidden = PLAYER_START + Coordinate { x: 0, y: 2 };
+        testing::wall(&mut game, PLAYER_START + Coordinate { x: 0, y: 1 });
+        for door in [near, hidden] {
+            spawning::make_secret_door(&mut game.ecs, door, 1, game.map.difficulty);
+        }
+        let is_secret = |game: &Game, door| {
+            let door_id = game.ecs.get_blocking_entity(door).unwrap();
+            game.ecs.entity_id_has_component(door_id, ComponentType::Secret)
+        };
+
+        game.apply_action(PlayerAction::Search);
+        assert!(is_secret(&game, near));
+        for _ in 0..MAX_SEARCH_RADIUS * SEARCHES_PER_RING {
+            game.apply_action(PlayerAction::Search);
+        }
+        assert!(!is_secret(&game, near));
+        assert!(is_secret(&game, hidden));
+    }
+
+    fn arrows_left(game: &Game) -> isize {
+        let combat = game.ecs.get_typed::<Combat>(game.ecs.get_player_id()).unwrap();
+        combat.data.ranged.and_then(|ranged| ranged.ammo).unwrap().current
+    }
+
+    #[test]
+    fn shots_use_up_arrows() {
+        let mut game = testing::arena();
+        let target = PLAYER_START + Coordinate { x: 3, y: 0 };
+        // a door stays put to be shot at again
+        testing::spawn(&mut game, "Door", target);
+        let full = arrows_left(&game);
+        let turn = game.turn;
+        game.apply_action(PlayerAction::Shoot(target));
+        assert_eq!(arrows_left(&game), full - 1);
+        assert_ne!(game.turn, turn);
+
+        let combat = game.ecs.get_typed::<Combat>(game.ecs.get_player_id()).unwrap();
+        let mut empty = combat.data.clone();
+        if let Some(ammo) = empty.ranged.as_mut().and_then(|ranged| ranged.ammo.as_mut()) {
+            ammo.current = 0;
+        }
+        game.ecs.apply_change(Delta::Change(Component::Combat(combat.make_change(empty))));
+        testing::drain_log();
+        let turn = game.turn;
+        game.apply_action(PlayerAction::Shoot(target));
+        assert_eq!(game.turn, turn);
+        assert!(testing::drain_log().contains(&"Out of arrows.".to_string()));
+    }
+
+    // Finds a dart trap next to the player, then tries to disarm it.
+    // Says whether the trap is gone and how much health the attempt cost.
+    fn try_disarming(dexterity: isize, rng: &mut impl Rng) -> (bool, isize) {
+        let mut game = testing::arena();
+        let stats = game.ecs.get_typed::<Attributes>(game.ecs.get_player_id()).unwrap();
+        let nimble = Attributes { dexterity, level_pending: stats.data.level_pending, ..Default::default() };
+        game.ecs.apply_change(Delta::Change(Component::Attributes(stats.make_change(nimble))));
+        let spot = PLAYER_START + Coordinate { x: 1, y: 0 };
+        let trap = testing::spawn(&mut game, "Dart trap", spot);
+        game.apply_action(PlayerAction::Search);
+        assert!(!game.ecs.entity_id_has_component(trap, ComponentType::Secret));
+
+        let full = game.get_player_health().unwrap().current;
+        game.disarm_command(spot, rng);
+        let gone = game.ecs.get_typed::<TrapKind>(trap).is_none();
+        (gone, full - game.get_player_health().unwrap().current)
+    }
+
+    #[test]
+    fn dexterity_disarms_and_fumbles_set_traps_off() {
+        for seed in 0..5 {
+            assert_eq!(try_disarming(20, &mut StdRng::seed_from_u64(seed)), (true, 0));
+        }
+        let (gone, lost) = try_disarming(0, &mut StepRng::new(u64::MAX, 0));
+        assert!(!gone);
+        assert!(lost > 0);
+    }
+
+    // Steps away from an alert doggo and says how much that cost.
+    fn health_lost_stepping_away(provokes: bool) -> isize {
+        let mut game = testing::arena();
+        game.set_player_provokes_attacks(provokes);
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 1, y: 0 });
+        let turn = game.ecs.get_typed::<TurnTaker>(dog).unwrap();
+        let alert = TurnTaker { state: AIState::Alert, ..turn.data.clone() };
+        game.ecs.apply_change(Delta::Change(Component::Turn(turn.make_change(alert))));
+        let full = game.get_player_health().unwrap().current;
+        game.apply_action(PlayerAction::Direction(Coordinate { x: -1, y: 0 }));
+        full - game.get_player_health().unwrap().current
+    }
+
+    #[test]
+    fn stepping_away_provokes_only_when_enabled() {
+        assert_eq!(health_lost_stepping_away(false), 0);
+        assert!(health_lost_stepping_away(true) > 0);
+    }
+
+    #[test]
+    fn effect_overlays_stack() {
+        let mut game = testing::arena();
+        let spot = PLAYER_START + Coordinate { x: 2, y: 0 };
+        let dog = testing::spawn(&mut game, "Doggo", spot);
+        for effect in [EffectType::Burning, EffectType::Frozen] {
+            game.ecs.apply_change(Delta::MakeComponent(MakeComponentOrder {
+                component: Component::DurationEffect(IndexedData::new_with(DurationEffect(3, effect))),
+                entity: EntityIdentifier::new_from_entity(dog),
+            }));
+        }
+        game.map.explored.borrow_mut().insert(spot);
+        game.update_visible_tiles();
+
+        let images = &game.get_image_ids_for_map()[spot.y as usize * game.map.width + spot.x as usize];
+        for overlay in ["burning_overlay", "frozen_overlay"] {
+            assert!(images.contains(&tileatlas::image(overlay).id), "{}", overlay);
+        }
+    }
+
+    #[test]
+    fn minimap_keeps_secrets() {
+        let mut game = testing::arena();
+        let door = Coordinate { x: 6, y: 6 };
+        let trap = Coordinate { x: 8, y: 6 };
+        spawning::make_secret_door(&mut game.ecs, door, 1, game.map.difficulty);
+        testing::spawn(&mut game, "Dart trap", trap);
+        game.map.explored.borrow_mut().extend([door, trap]);
+
+        let minimap = game.get_minimap();
+        let cell = |coord: Coordinate| minimap[coord.y as usize][coord.x as usize];
+        assert_eq!(cell(door), MinimapCell::Wall);
+        assert_eq!(cell(trap), MinimapCell::Floor);
+        assert_eq!(cell(PLAYER_START), MinimapCell::PlayerHere);
+    }
+}
@@ -0,0 +1,62 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+        }
+    }
+
+    // multiplier for how fast enemy health grows with depth
+    pub fn enemy_scaling(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+        }
+    }
+
+    // multiplier for how fast gold grows with depth
+    pub fn gold_scaling(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 1.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 0.75,
+        }
+    }
+
+    // fraction of max health at which cowardly monsters turn and run
+    pub fn flee_threshold(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.4,
+            Difficulty::Normal => 0.3,
+            Difficulty::Hard => 0.2,
+        }
+    }
+
+    // Easy rolls in the lower half of a spawn range, Hard in the upper half
+    pub fn scale_spawn_range(&self, (min, max): (usize, usize)) -> (usize, usize) {
+        match self {
+            Difficulty::Easy => (min, (min + max) / 2),
+            Difficulty::Normal => (min, max),
+            Difficulty::Hard => ((min + max).div_ceil(2), max),
+        }
+    }
+}
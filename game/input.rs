@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+
+use crate::{
+    game::{action::PlayerAction, components::inventory::EquipSlot, core::Game},
+    map::utils::Coordinate,
+};
+
+// What a key press means, independent of slint and of which key it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputAction {
+    Move(Coordinate),
+    Wait,
+    Undo,
+    Pickup,
+    Rest,
+    AutoExplore,
+    Descend,
+    CloseDoors,
+    Search,
+    Examine,
+    Disarm,
+    Buy(usize),
+    Unequip(EquipSlot),
+    RevealAll,
+    ToggleDiagonal,
+}
+
+impl InputAction {
+    // Examining and disarming still need a tile picked, revealing the map and switching
+    // movement modes aren't turns, all up to the frontend.
+    pub fn to_player_action(self) -> Option<PlayerAction> {
+        match self {
+            InputAction::Move(direction) => Some(PlayerAction::Direction(direction)),
+            InputAction::Wait => Some(PlayerAction::Wait),
+            InputAction::Undo => Some(PlayerAction::Undo),
+            InputAction::Pickup => Some(PlayerAction::Pickup),
+            InputAction::Rest => Some(PlayerAction::Rest),
+            InputAction::AutoExplore => Some(PlayerAction::AutoExplore),
+            InputAction::Descend => Some(PlayerAction::Descend),
+            InputAction::CloseDoors => Some(PlayerAction::CloseDoors),
+            InputAction::Search => Some(PlayerAction::Search),
+            InputAction::Examine => None,
+            InputAction::Disarm => None,
+            InputAction::Buy(index) => Some(PlayerAction::Buy(index)),
+            InputAction::Unequip(slot) => Some(PlayerAction::Unequip(slot)),
+            InputAction::RevealAll => None,
+            InputAction::ToggleDiagonal => None,
+        }
+    }
+}
+
+pub trait ActionHandler {
+    fn handle_action(&mut self, action: PlayerAction);
+}
+
+impl ActionHandler for Game {
+    fn handle_action(&mut self, action: PlayerAction) {
+        self.apply_action(action);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputMap {
+    bindings: HashMap<String, InputAction>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        InputMap {
+            bindings: HashMap::new(),
+        }
+    }
+
+    // The original wasd layout.
+    pub fn wasd() -> Self {
+        let mut map = InputMap::new();
+        map.bind("w", InputAction::Move(Coordinate { x: 0, y: -1 }));
+        map.bind("a", InputAction::Move(Coordinate { x: -1, y: 0 }));
+        map.bind("s", InputAction::Move(Coordinate { x: 0, y: 1 }));
+        map.bind("d", InputAction::Move(Coordinate { x: 1, y: 0 }));
+        // diagonals only go anywhere in diagonal mode, c is taken by closing doors
+        map.bind("q", InputAction::Move(Coordinate { x: -1, y: -1 }));
+        map.bind("e", InputAction::Move(Coordinate { x: 1, y: -1 }));
+        map.bind("z", InputAction::Move(Coordinate { x: -1, y: 1 }));
+        map.bind("v", InputAction::Move(Coordinate { x: 1, y: 1 }));
+        map.bind(" ", InputAction::Wait);
+        map.bind("r", InputAction::Rest);
+        map.bind("u", InputAction::Undo);
+        map.bind("g", InputAction::Pickup);
+        map.bind("o", InputAction::AutoExplore);
+        map.bind(">", InputAction::Descend);
+        map.bind("c", InputAction::CloseDoors);
+        map.bind("f", InputAction::Search);
+        map.bind("x", InputAction::Examine);
+        map.bind("t", InputAction::Disarm);
+        map.bind("1", InputAction::Buy(0));
+        map.bind("2", InputAction::Buy(1));
+        map.bind("3", InputAction::Buy(2));
+        map.bind("[", InputAction::Unequip(EquipSlot::Weapon));
+        map.bind("]", InputAction::Unequip(EquipSlot::Armor));
+        map.bind("`", InputAction::RevealAll);
+        map.bind("m", InputAction::ToggleDiagonal);
+        map
+    }
+
+    pub fn bind(&mut self, key: &str, action: InputAction) {
+        self.bindings.insert(key.to_string(), action);
+    }
+
+    pub fn get(&self, key: &str) -> Option<InputAction> {
+        self.bindings.get(key).copied()
+    }
+
+    // Runs whatever the key is bound to and hands the action back,
+    // so the frontend can follow up on the ones it handles itself.
+    pub fn dispatch(&self, key: &str, handler: &mut impl ActionHandler) -> Option<InputAction> {
+        let action = self.get(key)?;
+        if let Some(player_action) = action.to_player_action() {
+            handler.handle_action(player_action);
+        }
+        Some(action)
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        InputMap::wasd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        actions: Vec<PlayerAction>,
+    }
+
+    impl ActionHandler for Recorder {
+        fn handle_action(&mut self, action: PlayerAction) {
+            self.actions.push(action);
+        }
+    }
+
+    #[test]
+    fn keys_dispatch_their_actions() {
+        let map = InputMap::default();
+        let expected = [
+            ("d", PlayerAction::Direction(Coordinate { x: 1, y: 0 })),
+            (" ", PlayerAction::Wait),
+            ("u", PlayerAction::Undo),
+            ("g", PlayerAction::Pickup),
+            ("r", PlayerAction::Rest),
+            ("o", PlayerAction::AutoExplore),
+            (">", PlayerAction::Descend),
+            ("c", PlayerAction::CloseDoors),
+            ("f", PlayerAction::Search),
+            ("2", PlayerAction::Buy(1)),
+            ("[", PlayerAction::Unequip(EquipSlot::Weapon)),
+        ];
+        for (key, action) in expected {
+            let mut recorder = Recorder::default();
+            assert!(map.dispatch(key, &mut recorder).is_some());
+            assert_eq!(recorder.actions, vec![action], "{}", key);
+        }
+
+        // the frontend follows up on these itself
+        for key in ["x", "t", "`", "m"] {
+            let mut recorder = Recorder::default();
+            assert!(map.dispatch(key, &mut recorder).is_some());
+            assert!(recorder.actions.is_empty(), "{}", key);
+        }
+    }
+
+    #[test]
+    fn wasd_moves_and_unbound_keys_do_nothing() {
+        let map = InputMap::default();
+        let mut recorder = Recorder::default();
+        map.dispatch("w", &mut recorder);
+        map.dispatch("k", &mut recorder);
+        assert!(matches!(recorder.actions.as_slice(), [PlayerAction::Direction(Coordinate { x: 0, y: -1 })]));
+    }
+}
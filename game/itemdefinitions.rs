@@ -0,0 +1,116 @@
+use phf::{phf_map, Map};
+
+use crate::ecs::ecs::{Delta, EntityIdentifier, ECS};
+use crate::game::components::attributes::Attributes;
+use crate::game::components::combat::Combat;
+use crate::game::components::core::{Component, ComponentType, DurationEffect, EffectType};
+use crate::game::components::effects::{self, StackPolicy};
+use crate::game::components::inventory::EquipSlot;
+use crate::utils::logger;
+
+type ItemEffect = fn(usize, &ECS) -> Vec<Delta>;
+
+#[derive(Debug)]
+pub struct ItemDefinition {
+    pub name: &'static str,
+    pub icon: i32,
+    pub effect: Option<ItemEffect>,
+    // spawn name placed on and around the landing tile when thrown
+    pub thrown_spawn: Option<&'static str>,
+    pub gear: Option<Gear>,
+    // spell cast once when read, whether or not the reader knows it
+    pub scroll: Option<u32>,
+    // base shop price, shops don't carry anything priced at 0
+    pub price: isize,
+}
+
+// What an item adds to its wearer while equipped.
+#[derive(Debug, Clone, Copy)]
+pub struct Gear {
+    pub slot: EquipSlot,
+    pub damage: isize,
+    pub armor: isize,
+}
+
+pub const HEALTH_POTION_ID: u32 = 0;
+pub const FIRE_BOMB_ID: u32 = 1;
+pub const REGEN_POTION_ID: u32 = 2;
+pub const RATION_ID: u32 = 3;
+pub const KEY_ID: u32 = 4;
+pub const RUSTY_SWORD_ID: u32 = 5;
+pub const LEATHER_ARMOR_ID: u32 = 6;
+pub const FIREBALL_SCROLL_ID: u32 = 7;
+pub const BLINK_SCROLL_ID: u32 = 8;
+pub const SHIELD_SCROLL_ID: u32 = 9;
+pub const SMOKE_BOMB_ID: u32 = 10;
+
+const RATION_NOURISHMENT: isize = 300;
+
+pub static ITEM_REGISTRY: Map<u32, &'static ItemDefinition> = phf_map!(
+    0u32 => &ItemDefinition { name: "Health potion", icon: 18, effect: Some(drink_health_potion), thrown_spawn: None, gear: None, scroll: None, price: 30 },
+    1u32 => &ItemDefinition { name: "Fire bomb", icon: 19, effect: None, thrown_spawn: Some("Fire"), gear: None, scroll: None, price: 35 },
+    2u32 => &ItemDefinition { name: "Regen potion", icon: 22, effect: Some(drink_regen_potion), thrown_spawn: None, gear: None, scroll: None, price: 40 },
+    3u32 => &ItemDefinition { name: "Ration", icon: 23, effect: Some(eat_ration), thrown_spawn: None, gear: None, scroll: None, price: 15 },
+    4u32 => &ItemDefinition { name: "Key", icon: 42, effect: None, thrown_spawn: None, gear: None, scroll: None, price: 0 },
+    5u32 => &ItemDefinition { name: "Rusty sword", icon: 45, effect: None, thrown_spawn: None, gear: Some(Gear { slot: EquipSlot::Weapon, damage: 2, armor: 0 }), scroll: None, price: 60 },
+    6u32 => &ItemDefinition { name: "Leather armor", icon: 46, effect: None, thrown_spawn: None, gear: Some(Gear { slot: EquipSlot::Armor, damage: 0, armor: 1 }), scroll: None, price: 60 },
+    7u32 => &ItemDefinition { name: "Scroll of fireball", icon: 24, effect: None, thrown_spawn: None, gear: None, scroll: Some(9), price: 45 },
+    8u32 => &ItemDefinition { name: "Scroll of blink", icon: 26, effect: None, thrown_spawn: None, gear: None, scroll: Some(10), price: 40 },
+    9u32 => &ItemDefinition { name: "Scroll of shield", icon: 34, effect: None, thrown_spawn: None, gear: None, scroll: Some(14), price: 40 },
+    10u32 => &ItemDefinition { name: "Smoke bomb", icon: 47, effect: None, thrown_spawn: Some("Smoke"), gear: None, scroll: None, price: 30 },
+);
+
+pub fn scroll_for_spell(spell_id: u32) -> Option<u32> {
+    ITEM_REGISTRY
+        .entries()
+        .find(|(_, item)| item.scroll == Some(spell_id))
+        .map(|(item_id, _)| *item_id)
+}
+
+pub fn drink_health_potion(user: usize, ecs: &ECS) -> Vec<Delta> {
+    let Some(Component::Health(health)) = ecs.get_component_from_entity_id(user, ComponentType::Health) else {
+        return vec![];
+    };
+    logger::log_message("You drink a health potion.");
+    vec![Delta::Change(Component::Health(health.make_change(health.data.health_reset_diff())))]
+}
+
+// A second potion on top of the first keeps the regeneration going for longer.
+pub fn drink_regen_potion(user: usize, ecs: &ECS) -> Vec<Delta> {
+    logger::log_message("You drink a regen potion.");
+    let components = ecs.get_components_from_entity_id(user);
+    effects::apply_effect(
+        EntityIdentifier::new_from_entity(user),
+        &components,
+        DurationEffect(10, EffectType::Regen(1)),
+        StackPolicy::Extend,
+    )
+}
+
+pub fn eat_ration(user: usize, ecs: &ECS) -> Vec<Delta> {
+    let Some(Component::Hunger(hunger)) = ecs.get_component_from_entity_id(user, ComponentType::Hunger) else {
+        return vec![];
+    };
+    logger::log_message("You eat a ration.");
+    vec![Delta::Change(Component::Hunger(hunger.make_change(hunger.data.feed_diff(RATION_NOURISHMENT))))]
+}
+
+// Swaps one piece of gear for another in the wearer's stats, either side may be empty.
+pub fn swap_gear(user: usize, ecs: &ECS, removed: Option<Gear>, added: Option<Gear>) -> Vec<Delta> {
+    let damage = added.map_or(0, |gear| gear.damage) - removed.map_or(0, |gear| gear.damage);
+    let armor = added.map_or(0, |gear| gear.armor) - removed.map_or(0, |gear| gear.armor);
+
+    let mut deltas = vec![];
+    if let Some(combat) = ecs.get_typed::<Combat>(user) {
+        if let Some(mut melee) = combat.data.melee {
+            melee.damage_base += damage;
+            let new_combat = Combat { melee: Some(melee), ..combat.data };
+            deltas.push(Delta::Change(Component::Combat(combat.make_change(new_combat))));
+        }
+    }
+    if let Some(stats) = ecs.get_typed::<Attributes>(user) {
+        let change = Attributes { armor, level_pending: stats.data.level_pending, ..Default::default() };
+        deltas.push(Delta::Change(Component::Attributes(stats.make_change(change))));
+    }
+    deltas
+}
@@ -1,7 +1,15 @@
+pub mod action;
 pub mod archetype;
 pub mod components;
 pub mod core;
+pub mod difficulty;
+pub mod input;
+pub mod itemdefinitions;
 pub mod responses;
 pub mod spawning;
 pub mod system;
 pub mod spelldefinitions;
+pub mod tileatlas;
+
+#[cfg(test)]
+pub mod testing;
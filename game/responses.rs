@@ -4,63 +4,65 @@ use rand::{thread_rng, Rng};
 
 use crate::{
     ecs::{
+        component::Diffable,
         ecs::{
             DeleteComponentOrder, DeleteEntityOrder, Delta, EntityIdentifier, IndexedData,
             MakeComponentOrder, MakeEntityOrder, ECS,
         },
-        entity::{take_component_from_owned, take_component_from_refs},
+        entity::{find_typed, take_component_from_owned, take_component_from_refs},
         event::{propagate_event, EventResponse, EventType, InteractionEvent},
     },
-    game::components::{attributes::Attributes, combat::Health, core::*},
-    utils::logger,
+    game::components::{attributes::{Attributes, XP_PER_KILLED_HP}, combat::Health, core::*},
+    game::components::inventory::{DropTable, Inventory, ItemStack, Shop},
+    game::components::stats::{self, RunStats},
+    game::itemdefinitions::{ITEM_REGISTRY, KEY_ID},
+    game::spawning,
+    game::tileatlas,
+    map::utils::Coordinate,
+    utils::logger::{self, SoundCue},
 };
 
+use super::components::behavior;
+use super::components::effects::{self, StackPolicy};
 use super::components::combat::{self, calculate_melee_attack, default_take_damage, default_take_double_damage, default_take_half_damage};
 
+const BURNING_TURNS: isize = 4;
+const ACID_TURNS: isize = 3;
+
 pub fn take_damage_response(event: &InteractionEvent, own_components: &[&Component], _ecs: &ECS) -> Vec<Delta> {
     let Some(attack) = event.attack else {
         return vec![];
     };
-
-    let (maybe_health, own_components) =
-        take_component_from_refs(ComponentType::Health, own_components);
-    let Some(Component::Health(health)) = maybe_health else {
+    let Some(health) = find_typed::<Health, _>(own_components) else {
         return vec![];
     };
+    let maybe_stats = find_typed::<Attributes, _>(own_components);
+    let maybe_items = find_typed::<Inventory, _>(own_components);
 
-    let (maybe_stats, own_components) =
-        take_component_from_refs(ComponentType::Attributes, &own_components);
-    let maybe_stats = match maybe_stats {
-        Some(Component::Attributes(stats)) => Some(stats),
-        _ => None,
-    };
-
-    let (maybe_items, _) = take_component_from_refs(ComponentType::Inventory, &own_components);
-    let maybe_items = match maybe_items {
-        Some(Component::Inventory(items)) => Some(items),
-        _ => None,
-    };
-
-    let (delta, damage_taken) = default_take_damage(&attack, health, maybe_stats, maybe_items);
+    let maybe_shield = combat::find_shield(own_components);
+    let (delta, damage_taken, critical) =
+        default_take_damage(&attack, health, maybe_stats, maybe_items, maybe_shield, &event.payload, &mut thread_rng());
 
-    let (maybe_my_name, _own_components) =
-        take_component_from_refs(ComponentType::Name, &own_components);
-    let (maybe_their_name, _) =
-        take_component_from_owned(ComponentType::Name, event.payload.clone());
-    if let (Some(Component::Name(my_name)), Some(Component::Name(their_name))) =
-        (maybe_my_name, maybe_their_name)
-    {
-        let msg = logger::generate_attack_message(
+    let maybe_my_name = find_typed::<Name, _>(own_components);
+    let maybe_their_name = find_typed::<Name, _>(&event.payload);
+    if let (Some(my_name), Some(their_name)) = (maybe_my_name, maybe_their_name) {
+        let (msg, kind) = logger::generate_attack_message(
             &their_name.data,
             &my_name.data,
-            attack.hit_message,
+            attack.message(critical),
             damage_taken,
+            damage_taken >= health.data.current,
         );
-        logger::log_message(&msg);
-    } else if let Some(Component::Name(my_name)) = maybe_my_name {
-        let msg = logger::generate_take_damage_message(&my_name.data, damage_taken);
-        logger::log_message(&msg);
+        logger::log_message_with(&msg, kind);
+    } else if let Some(my_name) = maybe_my_name {
+        let (msg, kind) =
+            logger::generate_take_damage_message(&my_name.data, damage_taken, damage_taken >= health.data.current);
+        logger::log_message_with(&msg, kind);
     }
+    logger::play_damage_sound(damage_taken >= health.data.current);
+    let bleed_delta = combat::inflict_bleed(&attack, damage_taken, health, own_components);
+    let mut delta = [delta, bleed_delta, record_damage(event, own_components, damage_taken)].concat();
+    delta.extend(stats::record_death_cause(own_components, &health.data, damage_taken, || stats::describe_attacker(&event.payload)));
     delta
 }
 
@@ -92,7 +94,9 @@ pub fn take_half_damage_response(
         _ => None,
     };
 
-    let (delta, damage_taken) = default_take_half_damage(&attack, health, maybe_stats, maybe_items);
+    let maybe_shield = combat::find_shield(&own_components);
+    let (delta, damage_taken, critical) =
+        default_take_half_damage(&attack, health, maybe_stats, maybe_items, maybe_shield, &event.payload, &mut thread_rng());
 
     let (maybe_my_name, _own_components) =
         take_component_from_refs(ComponentType::Name, &own_components);
@@ -101,17 +105,23 @@ pub fn take_half_damage_response(
     if let (Some(Component::Name(my_name)), Some(Component::Name(their_name))) =
         (maybe_my_name, maybe_their_name)
     {
-        let msg = logger::generate_attack_message(
+        let (msg, kind) = logger::generate_attack_message(
             &their_name.data,
             &my_name.data,
-            attack.hit_message,
+            attack.message(critical),
             damage_taken,
+            damage_taken >= health.data.current,
         );
-        logger::log_message(&msg);
+        logger::log_message_with(&msg, kind);
     } else if let Some(Component::Name(my_name)) = maybe_my_name {
-        let msg = logger::generate_take_damage_message(&my_name.data, damage_taken);
-        logger::log_message(&msg);
+        let (msg, kind) =
+            logger::generate_take_damage_message(&my_name.data, damage_taken, damage_taken >= health.data.current);
+        logger::log_message_with(&msg, kind);
     }
+    logger::play_damage_sound(damage_taken >= health.data.current);
+    let bleed_delta = combat::inflict_bleed(&attack, damage_taken, health, &own_components);
+    let mut delta = [delta, bleed_delta, record_damage(event, &own_components, damage_taken)].concat();
+    delta.extend(stats::record_death_cause(&own_components, &health.data, damage_taken, || stats::describe_attacker(&event.payload)));
     delta
 }
 
@@ -143,7 +153,9 @@ pub fn take_double_damage_response(
         _ => None,
     };
 
-    let (delta, damage_taken) = default_take_double_damage(&attack, health, maybe_stats, maybe_items);
+    let maybe_shield = combat::find_shield(&own_components);
+    let (delta, damage_taken, critical) =
+        default_take_double_damage(&attack, health, maybe_stats, maybe_items, maybe_shield, &event.payload, &mut thread_rng());
 
     let (maybe_my_name, _own_components) =
         take_component_from_refs(ComponentType::Name, &own_components);
@@ -152,93 +164,195 @@ pub fn take_double_damage_response(
     if let (Some(Component::Name(my_name)), Some(Component::Name(their_name))) =
         (maybe_my_name, maybe_their_name)
     {
-        let msg = logger::generate_attack_message(
+        let (msg, kind) = logger::generate_attack_message(
             &their_name.data,
             &my_name.data,
-            attack.hit_message,
+            attack.message(critical),
             damage_taken,
+            damage_taken >= health.data.current,
         );
-        logger::log_message(&msg);
+        logger::log_message_with(&msg, kind);
     } else if let Some(Component::Name(my_name)) = maybe_my_name {
-        let msg = logger::generate_take_damage_message(&my_name.data, damage_taken);
-        logger::log_message(&msg);
+        let (msg, kind) =
+            logger::generate_take_damage_message(&my_name.data, damage_taken, damage_taken >= health.data.current);
+        logger::log_message_with(&msg, kind);
     }
+    logger::play_damage_sound(damage_taken >= health.data.current);
+    let bleed_delta = combat::inflict_bleed(&attack, damage_taken, health, &own_components);
+    let mut delta = [delta, bleed_delta, record_damage(event, &own_components, damage_taken)].concat();
+    delta.extend(stats::record_death_cause(&own_components, &health.data, damage_taken, || stats::describe_attacker(&event.payload)));
     delta
 }
 
+// The player keeps the run's tally, on whichever side of the hit they were.
+fn record_damage(event: &InteractionEvent, own_components: &[&Component], damage_taken: isize) -> Vec<Delta> {
+    let taken = find_typed::<RunStats, _>(own_components).map(|tally| {
+        let change = RunStats { damage_taken, ..Default::default() };
+        Delta::Change(Component::RunStats(tally.make_change(change)))
+    });
+    // only the player carries a tally, so only their lethal hits count as kills
+    let lethal = find_typed::<Health, _>(own_components)
+        .is_some_and(|health| health.data.current > 0 && damage_taken >= health.data.current);
+    let is_monster = own_components.iter().any(|comp| comp.is_of_type(&ComponentType::Monster));
+    let dealt = find_typed::<RunStats, _>(&event.payload).map(|tally| {
+        let kill = find_typed::<Name, _>(own_components).filter(|_| lethal && is_monster);
+        let change = RunStats {
+            damage_dealt: damage_taken,
+            ..kill.map(|name| RunStats::new_kill(&name.data.raw)).unwrap_or_default()
+        };
+        Delta::Change(Component::RunStats(tally.make_change(change)))
+    });
+    taken.into_iter().chain(dealt).collect()
+}
+
 pub fn award_inventory_response(
     event: &InteractionEvent,
     own_components: &[&Component],
     ecs: &ECS,
 ) -> Vec<Delta> {
-    let (maybe_player, components) =
-        take_component_from_owned(ComponentType::Player, event.payload.clone());
-    let (maybe_inventory, components) =
-        take_component_from_owned(ComponentType::Inventory, components);
-    let (maybe_stats, _) = take_component_from_owned(ComponentType::Attributes, components);
-    let (maybe_my_inventory, _) =
-        take_component_from_refs(ComponentType::Inventory, own_components);
-
-    if let (
-        Some(Component::Player(_)),
-        Some(Component::Inventory(their_items)),
-        Some(Component::Inventory(my_items)),
-        Some(Component::Attributes(their_stats)),
-    ) = (
-        maybe_player,
-        maybe_inventory,
-        maybe_my_inventory,
-        maybe_stats,
+    let is_player = event.payload.iter().any(|comp| comp.is_of_type(&ComponentType::Player));
+    let has_stats = find_typed::<Attributes, _>(&event.payload).is_some();
+    if let (true, true, Some(their_items), Some(my_items)) = (
+        is_player,
+        has_stats,
+        find_typed::<Inventory, _>(&event.payload),
+        find_typed::<Inventory, _>(own_components),
     ) {
         let my_change = my_items.data.inverse();
         let their_change = my_items.data.clone();
-        let their_xp_change = Attributes {
-            xp: their_change.coins,
-            ..Default::default()
-        };
-        let msg = logger::generate_receive_gold_message(their_change.coins);
-        logger::log_message(&msg);
+        if their_change.coins != 0 {
+            let (msg, kind) = logger::generate_receive_gold_message(their_change.coins);
+            logger::log_message_with(&msg, kind);
+            logger::play_sound(SoundCue::Gold);
+        }
+        for stack in &their_change.items {
+            if let Some(item) = ITEM_REGISTRY.get(&stack.item_id) {
+                let (msg, kind) = logger::generate_receive_item_message(item.name, stack.count);
+                logger::log_message_with(&msg, kind);
+            }
+        }
 
-        vec![
+        let mut deltas = vec![
             Delta::Change(Component::Inventory(their_items.make_change(their_change))),
             Delta::Change(Component::Inventory(my_items.make_change(my_change))),
-            Delta::Change(Component::Attributes(
-                their_stats.make_change(their_xp_change),
-            )),
-        ]
+        ];
+        if let Some(tally) = find_typed::<RunStats, _>(&event.payload) {
+            let change = RunStats { gold_collected: my_items.data.coins, ..Default::default() };
+            deltas.push(Delta::Change(Component::RunStats(tally.make_change(change))));
+        }
+        deltas
     } else {
         vec![]
     }
 }
 
+// Leaves a pile with whatever the entity carried plus anything its drop table rolls.
 pub fn drop_inventory_response(
-    event: &InteractionEvent,
-    own_components: &[&Component], 
-    ecs: &ECS,
+    _event: &InteractionEvent,
+    own_components: &[&Component],
+    _ecs: &ECS,
 ) -> Vec<Delta> {
-    let (maybe_my_inventory, own_components) =
-        take_component_from_refs(ComponentType::Inventory, own_components);
-    let (maybe_my_position, _) = take_component_from_refs(ComponentType::Position, &own_components);
+    let Some(my_position) = find_typed::<Coordinate, _>(own_components) else {
+        return vec![];
+    };
+    let drops = find_typed::<DropTable, _>(own_components)
+        .map(|table| table.data.roll())
+        .unwrap_or_default();
+    let mut my_items = match find_typed::<Inventory, _>(own_components) {
+        Some(inventory) => inventory.data.clone(),
+        None if drops.is_empty() => return vec![],
+        None => Inventory::default(),
+    };
+    my_items.apply_diff(&Inventory { coins: 0, items: drops });
+    vec![loot_pile(my_position.data, my_items)]
+}
 
-    if let (Some(Component::Inventory(my_items)), Some(Component::Position(my_position))) =
-        (maybe_my_inventory, maybe_my_position)
-    {
-        let image = ImageData { id: 15, depth: 6 };
-        let response = EventResponse::new_with(pickup_loot_response);
-
-        let new_components = vec![
-            Component::Collision(IndexedData::new_with(Collision::Walkable)),
-            Component::Image(IndexedData::new_with(ImageHandle::new(image))),
-            Component::Position(IndexedData::new_with(my_position.data)),
-            Component::Inventory(IndexedData::new_with(my_items.data.clone())),
-            Component::BumpResponse(IndexedData::new_with(response)),
-        ];
-        vec![Delta::MakeEntity(MakeEntityOrder {
-            components: new_components,
-        })]
-    } else {
-        vec![]
+// Whatever a corpse held stays on the floor when the corpse itself is used up.
+pub fn drop_carried_loot(entity_id: usize, ecs: &ECS) -> Vec<Delta> {
+    let (Some(position), Some(inventory)) = (ecs.get_typed::<Coordinate>(entity_id), ecs.get_typed::<Inventory>(entity_id)) else {
+        return vec![];
+    };
+    if inventory.data.coins == 0 && inventory.data.items.is_empty() {
+        return vec![];
     }
+    vec![loot_pile(position.data, inventory.data.clone())]
+}
+
+fn loot_pile(position: Coordinate, items: Inventory) -> Delta {
+    let image = tileatlas::image("drop_pile");
+    let response = EventResponse::new_with(pickup_loot_response);
+
+    let new_components = vec![
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(position)),
+        Component::Inventory(IndexedData::new_with(items)),
+        Component::BumpResponse(IndexedData::new_with(response)),
+    ];
+    Delta::MakeEntity(MakeEntityOrder {
+        components: new_components,
+    })
+}
+
+fn leave_corpse(own_components: &[&Component], kind: CorpseKind) -> Vec<Delta> {
+    let Some(position) = find_typed::<Coordinate, _>(own_components) else {
+        return vec![];
+    };
+    vec![Delta::MakeEntity(MakeEntityOrder {
+        components: spawning::corpse_components(position.data, kind),
+    })]
+}
+
+// Whoever made the kill, the player gets XP for the monster's toughness.
+pub fn grant_xp_response(_event: &InteractionEvent, own_components: &[&Component], ecs: &ECS) -> Vec<Delta> {
+    let (maybe_health, _) = take_component_from_refs(ComponentType::Health, own_components);
+    let Some(Component::Health(health)) = maybe_health else {
+        return vec![];
+    };
+    let Some(Component::Attributes(stats)) =
+        ecs.get_component_from_entity_id(ecs.get_player_id(), ComponentType::Attributes)
+    else {
+        return vec![];
+    };
+
+    let xp = health.data.max * XP_PER_KILLED_HP;
+    vec![Delta::Change(Component::Attributes(stats.make_change(Attributes {
+        xp,
+        level_pending: stats.data.level_pending,
+        ..Default::default()
+    })))]
+}
+
+pub fn monster_death_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS) -> Vec<Delta> {
+    [
+        grant_xp_response(event, own_components, ecs),
+        drop_inventory_response(event, own_components, ecs),
+        leave_corpse(own_components, CorpseKind::Humanoid),
+    ]
+    .concat()
+}
+
+// Animals carry nothing, what's left of them is only good for ghouls.
+pub fn beast_death_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS) -> Vec<Delta> {
+    [
+        grant_xp_response(event, own_components, ecs),
+        leave_corpse(own_components, CorpseKind::Beast),
+    ]
+    .concat()
+}
+
+// The stairs show up where the boss falls, next to its loot.
+pub fn boss_death_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS) -> Vec<Delta> {
+    let (maybe_position, _) = take_component_from_refs(ComponentType::Position, own_components);
+    let Some(Component::Position(position)) = maybe_position else {
+        return vec![];
+    };
+    logger::log_message("A way down opens up.");
+
+    let stairs = Delta::MakeEntity(MakeEntityOrder {
+        components: spawning::stairs_down_components(position.data),
+    });
+    [monster_death_response(event, own_components, ecs), vec![stairs]].concat()
 }
 
 pub fn open_image_response(_event: &InteractionEvent, own_components: &[&Component], _ecs: &ECS) -> Vec<Delta> {
@@ -429,26 +543,28 @@ pub fn default_burn_response(
     own_components: &[&Component],
     _ecs: &ECS,
 ) -> Vec<Delta> {
-    let (maybe_burn, components) = take_component_from_refs(ComponentType::DurationEffect, own_components);
-    let (maybe_health, components) = take_component_from_refs(ComponentType::Health, &components);
+    let maybe_burn = own_components.iter().find(|component| {
+        matches!(component, Component::DurationEffect(IndexedData { data: DurationEffect(_, EffectType::Burning), .. }))
+    });
+    let (maybe_health, components) = take_component_from_refs(ComponentType::Health, own_components);
     let (maybe_name, _components) = take_component_from_refs(ComponentType::Name, &components);
     match (maybe_burn, maybe_health) {
-        (
-            Some(Component::DurationEffect(IndexedData{index, data: DurationEffect(_, EffectType::Burning)})),
-            Some(Component::Health(health_data)),
-        ) => {
+        (Some(_), Some(Component::Health(health_data))) => {
             let damage_taken = Health {
                 current: -thread_rng().gen_range(1..=3),
                 ..Default::default()
             };
             if let Some(Component::Name(name_data)) = maybe_name {
-                let msg = logger::generate_is_burning_message(&name_data.data, damage_taken.current.abs());
-                logger::log_message(&msg);
+                let (msg, kind) = logger::generate_is_burning_message(&name_data.data, damage_taken.current.abs());
+                logger::log_message_with(&msg, kind);
             }
-            
-            vec![
+            let death_delta = stats::record_death_cause(own_components, &health_data.data, -damage_taken.current, || "Burned to death".to_string());
+
+            let mut delta = vec![
                 Delta::Change(Component::Health(health_data.make_change(damage_taken)))
-            ]
+            ];
+            delta.extend(death_delta);
+            delta
         }
         (
             None,
@@ -456,13 +572,15 @@ pub fn default_burn_response(
         ) => {
             if let Some(component) = own_components.first() {
                 if let Some(Component::Name(name_data)) = maybe_name {
-                    let msg = logger::generate_on_fire_message(&name_data.data);
-                    logger::log_message(&msg);
+                    let (msg, kind) = logger::generate_on_fire_message(&name_data.data);
+                    logger::log_message_with(&msg, kind);
                 }
-                vec![Delta::MakeComponent(MakeComponentOrder {
-                    component: Component::DurationEffect(IndexedData::new_with(DurationEffect(4, EffectType::Burning))),
-                    entity: EntityIdentifier::new_from_component(component.get_id()),
-                })]
+                effects::apply_effect(
+                    EntityIdentifier::new_from_component(component.get_id()),
+                    own_components,
+                    DurationEffect(BURNING_TURNS, EffectType::Burning),
+                    StackPolicy::Refresh,
+                )
             } else {
                 vec![]
             }
@@ -475,25 +593,38 @@ pub fn empty_response(_event: &InteractionEvent, _own_components: &[&Component],
     vec![]
 }
 
+// ground hazards don't reach whoever floats over them
+fn is_bumper_levitating(event: &InteractionEvent) -> bool {
+    let payload: Vec<&Component> = event.payload.iter().collect();
+    behavior::is_levitating(&payload)
+}
+
 pub fn spread_acid_response(event: &InteractionEvent, _own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
+    if is_bumper_levitating(event) {
+        return vec![];
+    }
     let Some(payload_component) = event.payload.first() else {
         return vec![];
     };
     let Some(entity_id) = ecs.get_entity_id_from_component_id(payload_component.get_id()) else {
         return vec![];
     };
-    vec![
-        Delta::MakeComponent(MakeComponentOrder {
-            component: Component::DurationEffect(IndexedData::new_with(DurationEffect(3, EffectType::Acid))),
-            entity: EntityIdentifier::new_from_entity(entity_id),
-        })
-    ]
+    let components = ecs.get_components_from_entity_id(entity_id);
+    effects::apply_effect(
+        EntityIdentifier::new_from_entity(entity_id),
+        &components,
+        DurationEffect(ACID_TURNS, EffectType::Acid),
+        StackPolicy::Refresh,
+    )
 }
 
 pub fn spread_fire_response(event: &InteractionEvent, _own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
     let EventType::Bump = event.event_type else {
         return vec![];
     };
+    if is_bumper_levitating(event) {
+        return vec![];
+    }
     let Some(payload_component) = event.payload.first() else {
         return vec![];
     };
@@ -521,14 +652,121 @@ pub fn spread_if_on_fire_response(event: &InteractionEvent, own_components: &[&C
 }
 
 pub fn spikes_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
-    let (other_levitate, _) = take_component_from_owned(ComponentType::DurationEffect, event.payload.clone());
-    if let Some(Component::DurationEffect(IndexedData { index:_, data:DurationEffect(_, EffectType::Levitate) })) = other_levitate {
+    // levitation may not be the bumper's first effect
+    if is_bumper_levitating(event) {
         vec![]
     } else {
         retaliate_response(event, own_components, ecs)
     }
 }
 
+pub fn trap_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
+    let (maybe_trap, _) = take_component_from_refs(ComponentType::Trap, own_components);
+    let Some(Component::Trap(trap)) = maybe_trap else {
+        return vec![];
+    };
+    let name = match take_component_from_refs(ComponentType::Name, own_components) {
+        (Some(Component::Name(name)), _) => name.data.indefinite(),
+        _ => "a trap".to_string(),
+    };
+
+    let (other_pos, _) = take_component_from_owned(ComponentType::Position, event.payload.clone());
+    if other_pos.is_none() {
+        // searching is a bump without a position
+        let reveal_delta = reveal_trap(trap, own_components, ecs);
+        if !reveal_delta.is_empty() {
+            logger::log_message(&format!("You find {}!", name));
+        }
+        return reveal_delta;
+    }
+    // levitating units float over pressure plates and pits alike
+    if is_bumper_levitating(event) {
+        return vec![];
+    }
+
+    let reveal_delta = reveal_trap(trap, own_components, ecs);
+    let trap_delta = match trap.data {
+        TrapKind::Dart => dart_trap_response(event, own_components, ecs),
+        TrapKind::Pit => pit_trap_response(event, own_components, ecs),
+    };
+    [reveal_delta, trap_delta].concat()
+}
+
+fn reveal_trap(trap: &IndexedData<TrapKind>, own_components: &[&Component], ecs: &ECS) -> Vec<Delta> {
+    let (Some(Component::Secret(secret)), _) = take_component_from_refs(ComponentType::Secret, own_components) else {
+        return vec![];
+    };
+    let Some(own_entity) = ecs.get_entity_id_from_component_id(trap.index) else {
+        return vec![];
+    };
+    let mut deltas = vec![
+        Delta::DeleteComponent(DeleteComponentOrder {
+            component_id: secret.index,
+            entity_id: Some(own_entity),
+        }),
+        Delta::MakeComponent(MakeComponentOrder {
+            component: Component::Image(IndexedData::new_with(ImageHandle::new(trap.data.image()))),
+            entity: EntityIdentifier::new_from_entity(own_entity),
+        }),
+    ];
+    // once known, monsters path around it like any other hazard
+    if let (Some(Component::Collision(collision)), _) = take_component_from_refs(ComponentType::Collision, own_components) {
+        deltas.push(Delta::Change(Component::Collision(collision.make_change(Collision::Hazard))));
+    }
+    deltas
+}
+
+fn dart_trap_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
+    let Some(payload_component) = event.payload.first() else {
+        return vec![];
+    };
+    let Some(entity_id) = ecs.get_entity_id_from_component_id(payload_component.get_id()) else {
+        return vec![];
+    };
+    let (Some(Component::Combat(combat)), _) = take_component_from_refs(ComponentType::Combat, own_components) else {
+        return vec![];
+    };
+
+    let shot = InteractionEvent {
+        event_type: EventType::Shot,
+        payload: own_components
+            .iter()
+            .map(|comp| (*comp).clone())
+            .collect(),
+        attack: combat::calculate_ranged_attack(&combat.data, None),
+    };
+    propagate_event(&shot, entity_id, ecs)
+}
+
+// The player's fall to the next floor is handled by the game after the step.
+fn pit_trap_response(event: &InteractionEvent, _own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
+    let Some(payload_component) = event.payload.first() else {
+        return vec![];
+    };
+    let Some(entity_id) = ecs.get_entity_id_from_component_id(payload_component.get_id()) else {
+        return vec![];
+    };
+    let name = match take_component_from_owned(ComponentType::Name, event.payload.clone()) {
+        (Some(Component::Name(name)), _) => name.data.subject(),
+        _ => "Something".to_string(),
+    };
+
+    if entity_id == ecs.get_player_id() {
+        logger::log_message_with("You fall into a pit!", logger::MessageKind::Warning);
+        vec![]
+    } else if let (true, Some(health)) = (
+        ecs.entity_id_has_component(entity_id, ComponentType::Turn),
+        ecs.get_typed::<Health>(entity_id),
+    ) {
+        logger::log_message(&format!("{} falls into a pit!", name));
+        // dying the usual way lets death responses like a boss's stairs still happen
+        let fall = Health { current: -health.data.current, max: 0 };
+        vec![Delta::Change(Component::Health(health.make_change(fall)))]
+    } else {
+        vec![]
+    }
+}
+
 pub fn retaliate_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
     let EventType::Bump = event.event_type else {
         return vec![];
@@ -570,10 +808,49 @@ pub fn open_door_response(event: &InteractionEvent, own_components: &[&Component
     let los_delta = open_los_blocking_response(event, own_components, ecs);
     let bump_delta = set_close_door_bump_response(event, own_components, ecs);
     let burn_delta = spread_if_on_fire_response(event, own_components, ecs);
-    
+    logger::play_sound(SoundCue::Door);
+
     vec![image_delta, collision_delta, bump_delta, los_delta, burn_delta].concat()
 }
 
+// Only the player can unlock, and it costs them a key.
+pub fn unlock_door_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS) -> Vec<Delta> {
+    let (maybe_player, payload) = take_component_from_owned(ComponentType::Player, event.payload.clone());
+    let (maybe_position, payload) = take_component_from_owned(ComponentType::Position, payload);
+    let (maybe_inventory, _) = take_component_from_owned(ComponentType::Inventory, payload);
+    // closing doors and searching send no position
+    let (Some(Component::Player(_)), Some(_)) = (maybe_player, maybe_position) else {
+        return vec![];
+    };
+    let (maybe_locked, _) = take_component_from_refs(ComponentType::Locked, own_components);
+    let Some(Component::Locked(locked)) = maybe_locked else {
+        return open_door_response(event, own_components, ecs);
+    };
+
+    let key = maybe_inventory.and_then(|inventory| match inventory {
+        Component::Inventory(items) if items.data.items.iter().any(|stack| stack.item_id == KEY_ID) => Some(items),
+        _ => None,
+    });
+    let Some(items) = key else {
+        logger::log_message("It's locked.");
+        return vec![];
+    };
+    logger::log_message("You unlock the door.");
+
+    let used_key = Inventory {
+        items: vec![ItemStack::new(KEY_ID, -1)],
+        ..Default::default()
+    };
+    let unlock_deltas = vec![
+        Delta::Change(Component::Inventory(items.make_change(used_key))),
+        Delta::DeleteComponent(DeleteComponentOrder {
+            component_id: locked.index,
+            entity_id: ecs.get_entity_id_from_component_id(locked.index),
+        }),
+    ];
+    [unlock_deltas, open_door_response(event, own_components, ecs)].concat()
+}
+
 pub fn close_door_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
     let (other_pos, _) = take_component_from_owned(ComponentType::Position, event.payload.clone());
     if let Some(_) = other_pos {
@@ -585,10 +862,74 @@ pub fn close_door_response(event: &InteractionEvent, own_components: &[&Componen
     let collision_delta = close_collision_response(event, own_components, ecs);
     let los_delta = close_los_blocking_response(event, own_components, ecs);
     let bump_delta = set_open_door_bump_response(event, own_components, ecs);
+    logger::play_sound(SoundCue::Door);
 
     vec![image_delta, collision_delta, bump_delta, los_delta].concat()
 }
 
+pub fn reveal_secret_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
+    let (other_pos, _) = take_component_from_owned(ComponentType::Position, event.payload.clone());
+    if other_pos.is_some() {
+        // only react to searching, bumping into it should feel like a wall
+        return vec![];
+    }
+    let (maybe_secret, _) = take_component_from_refs(ComponentType::Secret, own_components);
+    let (maybe_response, _) = take_component_from_refs(ComponentType::BumpResponse, own_components);
+    let (Some(Component::Secret(secret)), Some(Component::BumpResponse(response))) = (maybe_secret, maybe_response) else {
+        return vec![];
+    };
+    let own_entity = response.data.own_entity;
+    logger::log_message("You find a secret door!");
+
+    let image_delta = close_image_response(event, own_components, ecs);
+    let bump_delta = set_open_door_bump_response(event, own_components, ecs);
+    let door_delta = vec![
+        Delta::DeleteComponent(DeleteComponentOrder {
+            component_id: secret.index,
+            entity_id: Some(own_entity),
+        }),
+        Delta::MakeComponent(MakeComponentOrder {
+            component: Component::Door(IndexedData::new_with(())),
+            entity: EntityIdentifier::new_from_entity(own_entity),
+        }),
+        Delta::MakeComponent(MakeComponentOrder {
+            component: Component::Health(IndexedData::new_with(Health::new(6))),
+            entity: EntityIdentifier::new_from_entity(own_entity),
+        }),
+        Delta::MakeComponent(MakeComponentOrder {
+            component: Component::FireResponse(IndexedData::new_with(EventResponse::new_with(default_burn_response))),
+            entity: EntityIdentifier::new_from_entity(own_entity),
+        }),
+    ];
+
+    [image_delta, bump_delta, door_delta].concat()
+}
+
+// Only the player gets the sales pitch.
+pub fn show_wares_response(event: &InteractionEvent, own_components: &[&Component], _ecs: &ECS) -> Vec<Delta> {
+    let is_player = event.payload.iter().any(|component| matches!(component, Component::Player(_)));
+    let Some(shop) = find_typed::<Shop, _>(own_components) else {
+        return vec![];
+    };
+    if !is_player {
+        return vec![];
+    }
+    if shop.data.stock.is_empty() {
+        logger::log_message("The shopkeeper has nothing left to sell.");
+        return vec![];
+    }
+    let wares: Vec<String> = shop.data.stock
+        .iter()
+        .enumerate()
+        .filter_map(|(i, offer)| {
+            let item = ITEM_REGISTRY.get(&offer.item_id)?;
+            Some(format!("{}) {} for {} gold", i + 1, item.name.to_lowercase(), offer.price))
+        })
+        .collect();
+    logger::log_message(&format!("The shopkeeper offers: {}.", wares.join(", ")));
+    vec![]
+}
+
 pub fn pickup_loot_response(event: &InteractionEvent, own_components: &[&Component], ecs: &ECS,) -> Vec<Delta> {
     let inventory_changes = award_inventory_response(event, own_components, ecs);
     if inventory_changes.is_empty() {
@@ -598,3 +939,223 @@ pub fn pickup_loot_response(event: &InteractionEvent, own_components: &[&Compone
 
     vec![inventory_changes, despawning].concat()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        action::PlayerAction,
+        components::{combat::AttackReport, inventory::LootDrop},
+        core::Game,
+        testing::{self, PLAYER_START},
+    };
+
+    // Steps onto a dart trap and says whether it went off.
+    fn step_on_trap(levitating: bool) -> bool {
+        let mut game = testing::arena();
+        if levitating {
+            game.ecs.apply_change(Delta::MakeComponent(MakeComponentOrder {
+                component: Component::DurationEffect(IndexedData::new_with(DurationEffect(5, EffectType::Levitate))),
+                entity: EntityIdentifier::new_from_entity(game.ecs.get_player_id()),
+            }));
+        }
+        let trap = testing::spawn(&mut game, "Dart trap", PLAYER_START + Coordinate { x: 1, y: 0 });
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        assert_eq!(game.ecs.get_player_position(), Some(PLAYER_START + Coordinate { x: 1, y: 0 }));
+        !game.ecs.entity_id_has_component(trap, ComponentType::Secret)
+    }
+
+    fn stairs_count(game: &Game) -> usize {
+        game.ecs.get_all_components(&ComponentType::Stairs).len()
+    }
+
+    #[test]
+    fn boss_stairs_appear_once_the_boss_dies() {
+        let mut game = testing::arena();
+        let boss = testing::spawn(&mut game, "Orc warlord", PLAYER_START + Coordinate { x: 6, y: 0 });
+        game.apply_action(PlayerAction::Wait);
+        assert_eq!(stairs_count(&game), 0);
+
+        let health = game.ecs.get_typed::<Health>(boss).unwrap();
+        let lethal = Health { current: -health.data.current, max: 0 };
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(lethal))));
+        game.apply_action(PlayerAction::Wait);
+        assert_eq!(stairs_count(&game), 1);
+    }
+
+    #[test]
+    fn kills_grant_xp_without_gold() {
+        let mut game = testing::arena();
+        let player_id = game.ecs.get_player_id();
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let xp = game.ecs.get_typed::<Attributes>(player_id).unwrap().data.xp;
+        let coins = game.ecs.get_typed::<Inventory>(player_id).unwrap().data.coins;
+
+        let health = game.ecs.get_typed::<Health>(dog).unwrap();
+        let lethal = Health { current: -health.data.current, max: 0 };
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(lethal))));
+        game.apply_action(PlayerAction::Wait);
+        assert!(game.ecs.get_typed::<Attributes>(player_id).unwrap().data.xp > xp);
+        assert_eq!(game.ecs.get_typed::<Inventory>(player_id).unwrap().data.coins, coins);
+    }
+
+    #[test]
+    fn the_dead_leave_the_right_corpse() {
+        let mut game = testing::arena();
+        let spots = [("Doggo", CorpseKind::Beast), ("Pewpew", CorpseKind::Humanoid)];
+        for (i, (name, kind)) in spots.into_iter().enumerate() {
+            let spot = PLAYER_START + Coordinate { x: 6, y: 2 * i as i32 };
+            let unit = testing::spawn(&mut game, name, spot);
+            let health = game.ecs.get_typed::<Health>(unit).unwrap();
+            let lethal = Health { current: -health.data.current, max: 0 };
+            game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(lethal))));
+            game.apply_action(PlayerAction::Wait);
+            assert_eq!(game.ecs.get_corpse(spot).map(|(_, left)| left), Some(kind));
+        }
+    }
+
+    fn player_kills(game: &Game) -> usize {
+        game.ecs.get_typed::<RunStats>(game.ecs.get_player_id()).unwrap().data.kills.values().sum()
+    }
+
+    #[test]
+    fn only_the_players_kills_count() {
+        let mut game = testing::arena();
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let health = game.ecs.get_typed::<Health>(dog).unwrap();
+        let lethal = Health { current: -health.data.current, max: 0 };
+        game.ecs.apply_change(Delta::Change(Component::Health(health.make_change(lethal))));
+        game.apply_action(PlayerAction::Wait);
+        assert_eq!(player_kills(&game), 0);
+
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let hit = InteractionEvent {
+            event_type: EventType::Bump,
+            attack: Some(AttackReport { damage: 1000, crit_chance: 0.0, ..Default::default() }),
+            payload: game.ecs.get_components_from_entity_id(game.ecs.get_player_id()).into_iter().cloned().collect(),
+        };
+        let deltas = take_damage_response(&hit, &game.ecs.get_components_from_entity_id(dog), &game.ecs);
+        game.ecs.apply_changes(deltas);
+        game.apply_action(PlayerAction::Wait);
+        assert_eq!(player_kills(&game), 1);
+    }
+
+    #[test]
+    fn bosses_falling_into_pits_still_leave_stairs() {
+        let mut game = testing::arena();
+        let spot = PLAYER_START + Coordinate { x: 2, y: 0 };
+        let boss = testing::spawn(&mut game, "Orc warlord", spot);
+        testing::spawn(&mut game, "Pit trap", spot + Coordinate { x: 1, y: 0 });
+
+        let changes = behavior::knockback(PLAYER_START, boss, 1, &game.ecs, &game.map);
+        game.ecs.apply_changes(changes);
+        game.apply_action(PlayerAction::Wait);
+        assert_eq!(stairs_count(&game), 1);
+    }
+
+    fn player_keys(game: &Game) -> isize {
+        let items = &game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap().data.items;
+        items.iter().filter(|stack| stack.item_id == KEY_ID).map(|stack| stack.count).sum()
+    }
+
+    #[test]
+    fn locked_doors_need_a_key() {
+        let mut game = testing::arena();
+        let door_spot = PLAYER_START + Coordinate { x: 1, y: 0 };
+        let door = testing::spawn(&mut game, "Locked door", door_spot);
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        assert!(testing::drain_log().contains(&"It's locked.".to_string()));
+        assert!(game.ecs.entity_id_has_component(door, ComponentType::Locked));
+
+        let inventory = game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap();
+        let key = Inventory { items: vec![ItemStack::new(KEY_ID, 1)], ..Default::default() };
+        game.ecs.apply_change(Delta::Change(Component::Inventory(inventory.make_change(key))));
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        assert!(!game.ecs.entity_id_has_component(door, ComponentType::Locked));
+        assert_eq!(player_keys(&game), 0);
+    }
+
+    #[test]
+    fn typed_accessors_match_the_match_dance() {
+        let mut game = testing::arena();
+        let dog = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let components = game.ecs.get_components_from_entity_id(dog);
+        let (Some(Component::Health(health)), _) = take_component_from_refs(ComponentType::Health, &components) else {
+            panic!("doggo has no health");
+        };
+        let typed = find_typed::<Health, _>(&components).unwrap();
+        assert_eq!((typed.index, typed.data.current), (health.index, health.data.current));
+        assert_eq!(game.ecs.get_typed::<Health>(dog).unwrap().index, health.index);
+
+        let hit = InteractionEvent {
+            event_type: EventType::Bump,
+            attack: Some(AttackReport { damage: 5, crit_chance: 0.0, ..Default::default() }),
+            payload: vec![],
+        };
+        let lost: isize = take_damage_response(&hit, &components, &game.ecs)
+            .iter()
+            .filter_map(|delta| match delta {
+                Delta::Change(Component::Health(change)) if change.index == health.index => Some(change.data.current),
+                _ => None,
+            })
+            .sum();
+        assert!(lost < 0);
+    }
+
+    #[test]
+    fn gold_piles_hand_their_coins_to_the_player() {
+        let mut game = testing::arena();
+        let gold = testing::spawn(&mut game, "Gold", PLAYER_START + Coordinate { x: 6, y: 0 });
+        let coins = game.ecs.get_typed::<Inventory>(gold).unwrap().data.coins;
+        let player_items = game.ecs.get_typed::<Inventory>(game.ecs.get_player_id()).unwrap().index;
+        let pickup = InteractionEvent {
+            event_type: EventType::Bump,
+            attack: None,
+            payload: game.ecs.get_components_from_entity_id(game.ecs.get_player_id()).into_iter().cloned().collect(),
+        };
+        let deltas = award_inventory_response(&pickup, &game.ecs.get_components_from_entity_id(gold), &game.ecs);
+        assert!(deltas.iter().any(|delta| matches!(
+            delta,
+            Delta::Change(Component::Inventory(change)) if change.index == player_items && change.data.coins == coins
+        )));
+        assert!(deltas.iter().any(|delta| matches!(
+            delta,
+            Delta::Change(Component::RunStats(change)) if change.data.gold_collected == coins
+        )));
+    }
+
+    fn dropped_items(chance: f64) -> Vec<u32> {
+        let components = [
+            Component::Position(IndexedData::new_with(PLAYER_START)),
+            Component::DropTable(IndexedData::new_with(DropTable::new(vec![LootDrop::new(KEY_ID, chance)]))),
+        ];
+        let refs: Vec<&Component> = components.iter().collect();
+        let game = testing::arena();
+        drop_inventory_response(&InteractionEvent::default(), &refs, &game.ecs)
+            .iter()
+            .flat_map(|delta| match delta {
+                Delta::MakeEntity(order) => order.components.clone(),
+                _ => vec![],
+            })
+            .filter_map(|component| match component {
+                Component::Inventory(items) => Some(items.data.items.iter().map(|stack| stack.item_id).collect::<Vec<_>>()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    #[test]
+    fn drop_tables_follow_their_chances() {
+        for _ in 0..20 {
+            assert_eq!(dropped_items(1.0), vec![KEY_ID]);
+            assert!(dropped_items(0.0).is_empty());
+        }
+    }
+
+    #[test]
+    fn traps_spring_unless_levitating() {
+        assert!(step_on_trap(false));
+        assert!(!step_on_trap(true));
+    }
+}
@@ -1,17 +1,23 @@
 use std::collections::HashMap;
 
 use phf::phf_map;
-use rand::{thread_rng, Rng};
+use rand::{seq::SliceRandom, thread_rng, Rng};
 
 use crate::{
-    ecs::ecs::{IndexedData, ECS},
+    ecs::ecs::{Delta, IndexedData, ECS},
     ecs::event::EventResponse,
     game::components::attributes::Attributes,
     game::components::behavior::TurnTaker,
     game::components::combat::{Attack, Combat, Health},
     game::components::core::*,
-    game::components::inventory::Inventory,
+    game::components::inventory::{DropTable, Equipment, Inventory, ItemStack, LootDrop, Offer, Shop},
+    game::components::hunger::{Hunger, HUNGER_MAX},
+    game::components::stats::RunStats,
+    game::components::projectile::Projectile,
+    game::difficulty::Difficulty,
+    game::itemdefinitions::{self, BLINK_SCROLL_ID, FIREBALL_SCROLL_ID, FIRE_BOMB_ID, HEALTH_POTION_ID, ITEM_REGISTRY, KEY_ID, LEATHER_ARMOR_ID, RATION_ID, REGEN_POTION_ID, RUSTY_SWORD_ID, SHIELD_SCROLL_ID, SMOKE_BOMB_ID},
     game::responses,
+    game::tileatlas,
     map::utils::Coordinate,
 };
 
@@ -19,56 +25,88 @@ use super::{responses::{retaliate_response, spikes_response, spread_acid_respons
 
 const ENEMY_HP_INCREASE: f64 = 0.2;
 const GOLD_INCREASE: f64 = 0.1;
+const SHOP_STOCK_SIZE: usize = 3;
+const SMOKE_TURNS: isize = 6;
+// the player restocks on every descent, cultists never do
+const PLAYER_ARROWS: isize = 20;
+const CULTIST_ARROWS: isize = 6;
 
-pub static OBJECT_SPAWN_NAMES: phf::Map<&'static str, fn(&mut ECS, Coordinate, usize)> = phf_map!(
+// spawners take the floor depth and the run's difficulty
+pub type SpawnFunction = fn(&mut ECS, Coordinate, usize, Difficulty);
+
+pub static OBJECT_SPAWN_NAMES: phf::Map<&'static str, SpawnFunction> = phf_map!(
     "Doggo" => make_doggo,
     "Heavy" => make_heavy,
     "Pewpew" => make_cultist,
     "Pewpewpet" => make_skelly,
+    "Necromancer" => make_necromancer,
     "Player" => make_player,
     "Chest" => make_chest,
     "Gold" => make_gold_pile,
     "Door" => make_door,
+    "Locked door" => make_locked_door,
+    "Key" => make_key,
     "StairsDown" => make_stairs_down,
     "Corpse" => make_lootable_body,
+    "Carcass" => make_carcass,
     "Spikes" => make_spikes,
+    "Dart trap" => make_dart_trap,
+    "Pit trap" => make_pit_trap,
     "Fire" => make_flame,
     "Acid pool" => make_acid,
+    "Smoke" => make_smoke,
     "Fungus" => make_mushroom,
     "Rat" => make_rat,
     "Critters" => make_critter,
     "Bat" => make_bat,
+    "Sleeping Doggo" => make_sleeping_doggo,
+    "Sleeping Heavy" => make_sleeping_heavy,
+    "Potion" => make_health_potion,
+    "Minotaur" => make_minotaur,
+    "Troll" => make_troll,
+    "Vampire" => make_vampire,
+    "Raptor" => make_raptor,
+    "Ghoul" => make_ghoul,
+    "Fire bomb" => make_fire_bomb,
+    "Smoke bomb" => make_smoke_bomb,
+    "Ration" => make_ration,
+    "Rusty sword" => make_rusty_sword,
+    "Leather armor" => make_leather_armor,
+    "Scroll" => make_random_scroll,
+    "Shopkeeper" => make_shopkeeper,
+    "Orc warlord" => make_orc_warlord,
+    "Eye tyrant" => make_eye_tyrant,
+    "Stone colossus" => make_stone_colossus,
 );
 
-pub fn make_player(ecs: &mut ECS, start: Coordinate, _depth: usize) {
+pub fn make_player(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
     let player_combat = Combat::new(
         Some(Attack::new_melee(1, 7)),
-        Some(Attack::new_ranged(2, 0)),
+        Some(Attack::new_ranged(2, 0).with_ammo(PLAYER_ARROWS)),
     );
 
     let player_health = Health {
         current: 10,
         max: 10,
     };
-    let player_inventory = Inventory { coins: 0 };
+    let player_inventory = Inventory::new(0);
     let player_stats = Attributes {
         strength: 5,
         dexterity: 5,
         level: 1,
+        mana: 10,
+        mana_max: 10,
         ..Default::default()
     };
 
-    let player_image = ImageHandle::new(ImageData {
-        id: 3,
-        depth: 5,
-    });
+    let player_image = ImageHandle::new(tileatlas::image("player"));
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
 
     let components = vec![
         Component::Player(IndexedData::new_with(())),
-        Component::Name(IndexedData::new_with(Name::new("Bartholomew"))),
+        Component::Name(IndexedData::new_with(Name::proper("Bartholomew"))),
         Component::Image(IndexedData::new_with(player_image)),
         Component::Position(IndexedData::new_with(start)),
         Component::Combat(IndexedData::new_with(player_combat)),
@@ -76,6 +114,9 @@ pub fn make_player(ecs: &mut ECS, start: Coordinate, _depth: usize) {
         Component::Inventory(IndexedData::new_with(player_inventory)),
         Component::Collision(IndexedData::new_with(Collision::Blocking)),
         Component::Attributes(IndexedData::new_with(player_stats)),
+        Component::Hunger(IndexedData::new_with(Hunger::new(HUNGER_MAX))),
+        Component::RunStats(IndexedData::new_with(RunStats::default())),
+        Component::Equipment(IndexedData::new_with(Equipment::default())),
         Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
         Component::ShotResponse(IndexedData::new_with(take_damage)),
         Component::FireResponse(IndexedData::new_with(flammable)),
@@ -85,7 +126,7 @@ pub fn make_player(ecs: &mut ECS, start: Coordinate, _depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_doggo(ecs: &mut ECS, start: Coordinate, depth: usize) {
+pub fn make_doggo(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
     let melee = match depth {
         0 => Attack::new_melee(1, 1),
         0..=4 => Attack::new_melee(1, 2),
@@ -96,12 +137,13 @@ pub fn make_doggo(ecs: &mut ECS, start: Coordinate, depth: usize) {
     let combat = Combat::new(Some(melee), None);
     let depth = depth as f64;
     let health =
-        (thread_rng().gen_range(6..=9) as f64 * (1.0 + ENEMY_HP_INCREASE * (depth - 1.0))) as isize;
+        (thread_rng().gen_range(6..=9) as f64 * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
     let health = Health::new(health);
-    let image = ImageData { id: 6, depth: 5 };
+    let image = tileatlas::image("doggo");
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
+    let on_death = EventResponse::new_with(responses::beast_death_response);
 
     let components = vec![
         Component::Monster(IndexedData::new_with(())),
@@ -113,15 +155,83 @@ pub fn make_doggo(ecs: &mut ECS, start: Coordinate, depth: usize) {
         Component::Collision(IndexedData::new_with(Collision::Blocking)),
         Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
         Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(on_death)),
         Component::FireResponse(IndexedData::new_with(flammable)),
-        Component::Turn(IndexedData::new_with(TurnTaker::new_melee(true))),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_pack(true))),
     ];
 
     let new_id = ecs.create_entity();
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_bat(ecs: &mut ECS, start: Coordinate, depth: usize) {
+// Summoned by the player, vanishes when its DurationEffect runs out.
+pub fn wolf_components(start: Coordinate, depth: usize, lifetime: isize) -> Vec<Component> {
+    let melee = match depth {
+        0..=4 => Attack::new_melee(2, 2),
+        5..=9 => Attack::new_melee(3, 3),
+        10..=14 => Attack::new_melee(4, 3),
+        _ => Attack::new_melee(5, 4),
+    };
+    let combat = Combat::new(Some(melee), None);
+    let health = Health::new(10 + 2 * depth as isize);
+    let image = tileatlas::image("spirit_wolf");
+
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+
+    vec![
+        Component::Name(IndexedData::new_with(Name::new("Spirit Wolf"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::Swappable(IndexedData::new_with(())),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_ally(true))),
+        Component::DurationEffect(IndexedData::new_with(DurationEffect(lifetime, EffectType::Summoned))),
+    ]
+}
+
+// Raised from a corpse by the player, crumbles when its DurationEffect runs out.
+pub fn raised_skeleton_components(start: Coordinate, depth: usize, lifetime: isize) -> Vec<Component> {
+    let melee = match depth {
+        0..=4 => Attack::new_melee(1, 2),
+        5..=9 => Attack::new_melee(2, 2),
+        10..=14 => Attack::new_melee(3, 2),
+        _ => Attack::new_melee(4, 2),
+    };
+    let combat = Combat::new(Some(melee), None);
+    let health = Health::new(8 + 2 * depth as isize);
+    let image = tileatlas::image("skeleton");
+
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let take_half_damage = EventResponse::new_with(responses::take_half_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+
+    vec![
+        Component::Name(IndexedData::new_with(Name::new("Raised Skeleton"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::Swappable(IndexedData::new_with(())),
+        Component::ShotResponse(IndexedData::new_with(take_half_damage)),
+        Component::BumpResponse(IndexedData::new_with(take_damage)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_ally(false))),
+        Component::DurationEffect(IndexedData::new_with(DurationEffect(lifetime, EffectType::Summoned))),
+    ]
+}
+
+pub fn make_sleeping_doggo(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    make_doggo(ecs, start, depth, difficulty);
+    put_to_sleep(ecs, start);
+}
+
+pub fn make_bat(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
     let melee = match depth {
         0 => Attack::new_melee(1, 1),
         0..=4 => Attack::new_melee(1, 1),
@@ -132,12 +242,13 @@ pub fn make_bat(ecs: &mut ECS, start: Coordinate, depth: usize) {
     let combat = Combat::new(Some(melee), None);
     let depth = depth as f64;
     let health =
-        (thread_rng().gen_range(4..=6) as f64 * (1.0 + ENEMY_HP_INCREASE * (depth - 1.0))) as isize;
+        (thread_rng().gen_range(4..=6) as f64 * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
     let health = Health::new(health);
-    let image = ImageData { id: 23, depth: 5 };
+    let image = tileatlas::image("bat");
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
+    let on_death = EventResponse::new_with(responses::beast_death_response);
 
     let components = vec![
         Component::Monster(IndexedData::new_with(())),
@@ -149,6 +260,7 @@ pub fn make_bat(ecs: &mut ECS, start: Coordinate, depth: usize) {
         Component::Collision(IndexedData::new_with(Collision::Blocking)),
         Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
         Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(on_death)),
         Component::FireResponse(IndexedData::new_with(flammable)),
         Component::Turn(IndexedData::new_with(TurnTaker::new_fast_melee(false))),
         Component::DurationEffect(IndexedData::new_with(DurationEffect(-1, EffectType::Levitate))),
@@ -158,21 +270,296 @@ pub fn make_bat(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_heavy(ecs: &mut ECS, start: Coordinate, depth: usize) {
+pub fn make_minotaur(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=4 => Attack::new_melee(3, 2),
+        5..=9 => Attack::new_melee(4, 3),
+        10..=14 => Attack::new_melee(5, 4),
+        _ => Attack::new_melee(6, 5),
+    };
+    let combat = Combat::new(Some(melee), None);
+    let depth = depth as f64;
+    let health = (thread_rng().gen_range(16..=19) as f64
+        * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
+    let health = Health::new(health);
+    let image = tileatlas::image("minotaur");
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+    let grant_xp = EventResponse::new_with(responses::grant_xp_response);
+
+    let components = vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Minotaur"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(grant_xp)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_charger(true))),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_troll(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=4 => Attack::new_melee(2, 3),
+        5..=9 => Attack::new_melee(3, 3),
+        10..=14 => Attack::new_melee(4, 4),
+        _ => Attack::new_melee(5, 5),
+    };
+    let combat = Combat::new(Some(melee), None);
+    let depth = depth as f64;
+    let health = (thread_rng().gen_range(14..=17) as f64
+        * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
+    let health = Health::new(health);
+    let image = tileatlas::image("troll");
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+    let grant_xp = EventResponse::new_with(responses::grant_xp_response);
+
+    let components = vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Troll"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(grant_xp)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_melee(true))),
+        Component::DurationEffect(IndexedData::new_with(DurationEffect(-1, EffectType::Regen(2)))),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_vampire(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=9 => Attack::new_melee(2, 3),
+        10..=14 => Attack::new_melee(3, 4),
+        _ => Attack::new_melee(4, 5),
+    };
+    let combat = Combat::new(Some(melee.with_lifesteal(0.5)), None);
+    let depth = depth as f64;
+    let health = (thread_rng().gen_range(10..=13) as f64
+        * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
+    let health = Health::new(health);
+    let image = tileatlas::image("vampire");
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+    let grant_xp = EventResponse::new_with(responses::grant_xp_response);
+
+    let components = vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Vampire"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage)),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(grant_xp)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_melee(true).opening_doors())),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_raptor(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=6 => Attack::new_melee(1, 1),
+        7..=11 => Attack::new_melee(1, 2),
+        _ => Attack::new_melee(2, 2),
+    };
+    let combat = Combat::new(Some(melee.with_bleed(1)), None);
+    let depth = depth as f64;
+    let health = (thread_rng().gen_range(5..=7) as f64
+        * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
+    let health = Health::new(health);
+    let image = tileatlas::image("raptor");
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+    let on_death = EventResponse::new_with(responses::beast_death_response);
+
+    let components = vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Raptor"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage)),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(on_death)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_pack(true))),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_ghoul(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=6 => Attack::new_melee(1, 2),
+        7..=11 => Attack::new_melee(2, 2),
+        _ => Attack::new_melee(3, 3),
+    };
+    let combat = Combat::new(Some(melee), None);
+    let depth = depth as f64;
+    let health = (thread_rng().gen_range(8..=11) as f64
+        * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
+    let health = Health::new(health);
+    let image = tileatlas::image("ghoul");
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+    let grant_xp = EventResponse::new_with(responses::grant_xp_response);
+
+    let components = vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Ghoul"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage)),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(grant_xp)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_ghoul(true))),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+// Bosses guard the stairs, they drop them along with their loot.
+pub fn make_orc_warlord(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=9 => Attack::new_melee(5, 3),
+        10..=14 => Attack::new_melee(6, 4),
+        _ => Attack::new_melee(7, 5),
+    };
+    let combat = Combat::new(Some(melee.with_knockback(1)), None);
+    let health = boss_health(thread_rng().gen_range(30..=34), depth, difficulty);
+    let image = tileatlas::image("orc_warlord");
+    let mut components = boss_components(start, "Orc warlord", image, combat, health, depth, difficulty);
+    components.push(Component::Turn(IndexedData::new_with(TurnTaker::new_charger(true).opening_doors())));
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_eye_tyrant(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=14 => Attack::new_melee(4, 3),
+        _ => Attack::new_melee(5, 4),
+    };
+    let ranged = match depth {
+        0..=14 => Attack::new_ranged(4, 3),
+        _ => Attack::new_ranged(5, 4),
+    };
+    let ranged = Attack {
+        max_range: 5.0,
+        ..ranged
+    };
+    let combat = Combat::new(Some(melee), Some(ranged));
+    let health = boss_health(thread_rng().gen_range(36..=40), depth, difficulty);
+    let image = tileatlas::image("eye_tyrant");
+    let mut components = boss_components(start, "Eye tyrant", image, combat, health, depth, difficulty);
+    components.push(Component::Turn(IndexedData::new_with(TurnTaker::new_mage(true))));
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_stone_colossus(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=19 => Attack::new_melee(8, 6),
+        _ => Attack::new_melee(10, 7),
+    };
+    let combat = Combat::new(Some(melee.with_knockback(2)), None);
+    let health = boss_health(thread_rng().gen_range(48..=54), depth, difficulty);
+    let image = tileatlas::image("stone_colossus");
+    let mut components = boss_components(start, "Stone colossus", image, combat, health, depth, difficulty);
+    components.push(Component::Turn(IndexedData::new_with(TurnTaker::new_slow_melee(true))));
+    components.push(Component::DurationEffect(IndexedData::new_with(DurationEffect(-1, EffectType::Regen(3)))));
+    // big enough to fill a doorway on its own
+    components.push(Component::Size(IndexedData::new_with(Size::square(2))));
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+fn boss_health(base: isize, depth: usize, difficulty: Difficulty) -> Health {
+    let depth = depth as f64;
+    let health = (base as f64 * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
+    Health::new(health)
+}
+
+fn boss_components(
+    start: Coordinate,
+    name: &str,
+    image: ImageData,
+    combat: Combat,
+    health: Health,
+    depth: usize,
+    difficulty: Difficulty,
+) -> Vec<Component> {
+    let coins = (thread_rng().gen_range(60..=80) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth as f64)) as isize;
+    let inventory = Inventory::new(coins);
+
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let drop_stairs = EventResponse::new_with(responses::boss_death_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+
+    vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new(name))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage)),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(drop_stairs)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+    ]
+}
+
+pub fn make_heavy(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
     let melee = match depth {
         0..=4 => Attack::new_melee(3, 3),
         5..=9 => Attack::new_melee(4, 4),
         10..=14 => Attack::new_melee(5, 5),
         _ => Attack::new_melee(6, 6),
     };
-    let combat = Combat::new(Some(melee), None);
+    let combat = Combat::new(Some(melee.with_knockback(1)), None);
     let depth = depth as f64;
     let health = (thread_rng().gen_range(13..=15) as f64
-        * (1.0 + ENEMY_HP_INCREASE * (depth - 1.0))) as isize;
+        * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0))) as isize;
     let health = Health::new(health);
-    let image = ImageData { id: 11, depth: 5 };
+    let image = tileatlas::image("heavy");
     let take_damage = EventResponse::new_with(responses::take_damage_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
+    let grant_xp = EventResponse::new_with(responses::grant_xp_response);
 
     let components = vec![
         Component::Monster(IndexedData::new_with(())),
@@ -184,6 +571,7 @@ pub fn make_heavy(ecs: &mut ECS, start: Coordinate, depth: usize) {
         Component::Collision(IndexedData::new_with(Collision::Blocking)),
         Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
         Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(grant_xp)),
         Component::FireResponse(IndexedData::new_with(flammable)),
         Component::Turn(IndexedData::new_with(TurnTaker::new_slow_melee(true))),
     ];
@@ -192,7 +580,29 @@ pub fn make_heavy(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_skelly(ecs: &mut ECS, start: Coordinate, depth: usize) {
+pub fn make_sleeping_heavy(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    make_heavy(ecs, start, depth, difficulty);
+    put_to_sleep(ecs, start);
+}
+
+fn put_to_sleep(ecs: &mut ECS, start: Coordinate) {
+    let Some(entity_id) = ecs.get_blocking_entity(start) else {
+        return;
+    };
+    let Some(Component::Turn(turn)) = ecs.get_component_from_entity_id(entity_id, ComponentType::Turn) else {
+        return;
+    };
+    let change = turn.make_change(turn.data.clone().asleep());
+    ecs.apply_change(Delta::Change(Component::Turn(change)));
+}
+
+pub fn make_skelly(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let components = skelly_components(start, depth, difficulty);
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn skelly_components(start: Coordinate, depth: usize, difficulty: Difficulty) -> Vec<Component> {
     let melee = match depth {
         0..=4 => Attack::new_melee(1, 2),
         5..=9 => Attack::new_melee(2, 2),
@@ -201,19 +611,20 @@ pub fn make_skelly(ecs: &mut ECS, start: Coordinate, depth: usize) {
     };
     let combat = Combat::new(Some(melee), None);
     let depth = depth as f64;
-    let health = (thread_rng().gen_range(7..=10) as f64 * (1.0 + ENEMY_HP_INCREASE * (depth - 1.0)))
+    let health = (thread_rng().gen_range(7..=10) as f64 * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0)))
         as isize;
     let health = Health::new(health);
-    let image = ImageData { id: 13, depth: 5 };
-    let coins = (thread_rng().gen_range(2..=15) as f64 * (1.0 + GOLD_INCREASE * depth)) as isize;
-    let inventory = Inventory { coins };
+    let image = tileatlas::image("skeleton");
+    let coins = (thread_rng().gen_range(2..=15) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth)) as isize;
+    let inventory = Inventory::new(coins);
+    let drops = DropTable::new(vec![LootDrop::new(RUSTY_SWORD_ID, 0.05)]);
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
     let take_half_damage = EventResponse::new_with(responses::take_half_damage_response);
-    let drop_coins = EventResponse::new_with(responses::drop_inventory_response);
+    let on_death = EventResponse::new_with(responses::monster_death_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
 
-    let components = vec![
+    vec![
         Component::Monster(IndexedData::new_with(())),
         Component::Name(IndexedData::new_with(Name::new("Skeleton"))),
         Component::Image(IndexedData::new_with(ImageHandle::new(image))),
@@ -221,19 +632,63 @@ pub fn make_skelly(ecs: &mut ECS, start: Coordinate, depth: usize) {
         Component::Combat(IndexedData::new_with(combat)),
         Component::Health(IndexedData::new_with(health)),
         Component::Inventory(IndexedData::new_with(inventory)),
+        Component::DropTable(IndexedData::new_with(drops)),
         Component::Collision(IndexedData::new_with(Collision::Blocking)),
         Component::BumpResponse(IndexedData::new_with(take_damage)),
         Component::ShotResponse(IndexedData::new_with(take_half_damage)),
-        Component::DeathResponse(IndexedData::new_with(drop_coins)),
+        Component::DeathResponse(IndexedData::new_with(on_death)),
+        Component::FireResponse(IndexedData::new_with(flammable)),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_melee(false).cowardly(difficulty.flee_threshold()).opening_doors())),
+    ]
+}
+
+pub fn make_necromancer(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let melee = match depth {
+        0..=9 => Attack::new_melee(1, 2),
+        10..=14 => Attack::new_melee(2, 2),
+        _ => Attack::new_melee(3, 2),
+    };
+    let combat = Combat::new(Some(melee), None);
+    let depth = depth as f64;
+    let health = (thread_rng().gen_range(10..=14) as f64 * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0)))
+        as isize;
+    let health = Health::new(health);
+    let image = tileatlas::image("necromancer");
+    let coins = (thread_rng().gen_range(20..=30) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth)) as isize;
+    let inventory = Inventory::new(coins);
+    // necromancers are rare, their notes are worth reading
+    let drops = DropTable::new(vec![
+        LootDrop::new(FIREBALL_SCROLL_ID, 0.2),
+        LootDrop::new(BLINK_SCROLL_ID, 0.2),
+        LootDrop::new(SHIELD_SCROLL_ID, 0.2),
+    ]);
+
+    let take_damage = EventResponse::new_with(responses::take_damage_response);
+    let on_death = EventResponse::new_with(responses::monster_death_response);
+    let flammable = EventResponse::new_with(responses::default_burn_response);
+
+    let components = vec![
+        Component::Monster(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Necromancer"))),
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Combat(IndexedData::new_with(combat)),
+        Component::Health(IndexedData::new_with(health)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::DropTable(IndexedData::new_with(drops)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(take_damage)),
+        Component::ShotResponse(IndexedData::new_with(take_damage)),
+        Component::DeathResponse(IndexedData::new_with(on_death)),
         Component::FireResponse(IndexedData::new_with(flammable)),
-        Component::Turn(IndexedData::new_with(TurnTaker::new_melee(false))),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_summoner(true).opening_doors())),
     ];
 
     let new_id = ecs.create_entity();
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_cultist(ecs: &mut ECS, start: Coordinate, depth: usize) {
+pub fn make_cultist(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
     let melee = match depth {
         0..=4 => Attack::new_melee(2, 2),
         5..=9 => Attack::new_melee(3, 2),
@@ -249,19 +704,21 @@ pub fn make_cultist(ecs: &mut ECS, start: Coordinate, depth: usize) {
     let ranged = Attack {
         max_range: 3.0,
         ..ranged
-    };
+    }
+    .with_ammo(CULTIST_ARROWS);
     let combat = Combat::new(Some(melee), Some(ranged));
     let depth = depth as f64;
-    let health = (thread_rng().gen_range(8..=10) as f64 * (1.0 + ENEMY_HP_INCREASE * (depth - 1.0)))
+    let health = (thread_rng().gen_range(8..=10) as f64 * (1.0 + ENEMY_HP_INCREASE * difficulty.enemy_scaling() * (depth - 1.0)))
         as isize;
     let health = Health::new(health);
-    let image = ImageData { id: 12, depth: 5 };
+    let image = tileatlas::image("cultist");
     let depth = depth as f64;
-    let coins = (thread_rng().gen_range(18..=25) as f64 * (1.0 + GOLD_INCREASE * depth)) as isize;
-    let inventory = Inventory { coins };
+    let coins = (thread_rng().gen_range(18..=25) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth)) as isize;
+    let inventory = Inventory::new(coins);
+    let drops = DropTable::new(vec![LootDrop::new(HEALTH_POTION_ID, 0.15)]);
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
-    let drop_coins = EventResponse::new_with(responses::drop_inventory_response);
+    let on_death = EventResponse::new_with(responses::monster_death_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
 
     let components = vec![
@@ -272,21 +729,22 @@ pub fn make_cultist(ecs: &mut ECS, start: Coordinate, depth: usize) {
         Component::Combat(IndexedData::new_with(combat)),
         Component::Health(IndexedData::new_with(health)),
         Component::Inventory(IndexedData::new_with(inventory)),
+        Component::DropTable(IndexedData::new_with(drops)),
         Component::Collision(IndexedData::new_with(Collision::Blocking)),
         Component::BumpResponse(IndexedData::new_with(take_damage.clone())),
         Component::ShotResponse(IndexedData::new_with(take_damage)),
-        Component::DeathResponse(IndexedData::new_with(drop_coins)),
+        Component::DeathResponse(IndexedData::new_with(on_death)),
         Component::FireResponse(IndexedData::new_with(flammable)),
-        Component::Turn(IndexedData::new_with(TurnTaker::new_mage(true))),
+        Component::Turn(IndexedData::new_with(TurnTaker::new_healer(true).cowardly(difficulty.flee_threshold()).opening_doors())),
     ];
 
     let new_id = ecs.create_entity();
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_door(ecs: &mut ECS, start: Coordinate, _depth: usize) {
-    let open_image = ImageData { id: 10, depth: 7 };
-    let closed_image = ImageData { id: 9, depth: 7 };
+pub fn make_door(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let open_image = tileatlas::image("door_open");
+    let closed_image = tileatlas::image("door_closed");
     let images = ImageHandle {
         current: closed_image.to_owned(),
         states: HashMap::from([("open", open_image), ("closed", closed_image)]),
@@ -311,9 +769,50 @@ pub fn make_door(ecs: &mut ECS, start: Coordinate, _depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_chest(ecs: &mut ECS, start: Coordinate, depth: usize) {
-    let open_image = ImageData { id: 8, depth: 7 };
-    let closed_image = ImageData { id: 7, depth: 7 };
+pub fn make_locked_door(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    make_door(ecs, start, depth, difficulty);
+    let Some(door_id) = ecs.get_blocking_entity(start) else {
+        return;
+    };
+    let Some(Component::BumpResponse(response)) = ecs.get_component_from_entity_id(door_id, ComponentType::BumpResponse) else {
+        return;
+    };
+    let unlock = EventResponse {
+        own_entity: response.data.own_entity,
+        response_function: responses::unlock_door_response,
+    };
+    let change = response.make_change(unlock);
+    ecs.apply_change(Delta::Change(Component::BumpResponse(change)));
+    ecs.add_components_to_entity(door_id, vec![Component::Locked(IndexedData::new_with(()))]);
+}
+
+pub fn make_secret_door(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let wall_image = tileatlas::image("secret_door_wall");
+    let open_image = tileatlas::image("door_open");
+    let closed_image = tileatlas::image("door_closed");
+    let images = ImageHandle {
+        current: wall_image,
+        states: HashMap::from([("open", open_image), ("closed", closed_image)]),
+    };
+
+    let event_response = EventResponse::new_with(responses::reveal_secret_response);
+
+    let components = vec![
+        Component::Secret(IndexedData::new_with(())),
+        Component::Image(IndexedData::new_with(images)),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::LineOfSight(IndexedData::new_with(LoSBlocking::Blocking)),
+        Component::BumpResponse(IndexedData::new_with(event_response)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_chest(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let open_image = tileatlas::image("chest_open");
+    let closed_image = tileatlas::image("chest_closed");
     let images = ImageHandle {
         current: closed_image.to_owned(),
         states: HashMap::from([("open", open_image), ("closed", closed_image)]),
@@ -321,8 +820,33 @@ pub fn make_chest(ecs: &mut ECS, start: Coordinate, depth: usize) {
     let health = Health::new(5);
 
     let depth = depth as f64;
-    let coins = (thread_rng().gen_range(25..=52) as f64 * (1.0 + GOLD_INCREASE * depth)) as isize;
-    let inventory = Inventory { coins };
+    let coins = (thread_rng().gen_range(25..=52) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth)) as isize;
+    let mut inventory = Inventory::new(coins);
+    if thread_rng().gen_bool(0.3) {
+        inventory.items.push(ItemStack::new(HEALTH_POTION_ID, 1));
+    }
+    if thread_rng().gen_bool(0.15) {
+        inventory.items.push(ItemStack::new(FIRE_BOMB_ID, 1));
+    }
+    if thread_rng().gen_bool(0.1) {
+        inventory.items.push(ItemStack::new(REGEN_POTION_ID, 1));
+    }
+    if thread_rng().gen_bool(0.25) {
+        inventory.items.push(ItemStack::new(RATION_ID, 1));
+    }
+    if thread_rng().gen_bool(0.1) {
+        inventory.items.push(ItemStack::new(SMOKE_BOMB_ID, 1));
+    }
+    if thread_rng().gen_bool(0.05) {
+        inventory.items.push(ItemStack::new(RUSTY_SWORD_ID, 1));
+    }
+    if thread_rng().gen_bool(0.05) {
+        inventory.items.push(ItemStack::new(LEATHER_ARMOR_ID, 1));
+    }
+    if thread_rng().gen_bool(0.05) {
+        let scroll_id = [FIREBALL_SCROLL_ID, BLINK_SCROLL_ID, SHIELD_SCROLL_ID].choose(&mut thread_rng()).copied();
+        inventory.items.extend(scroll_id.map(|item_id| ItemStack::new(item_id, 1)));
+    }
     let event_response = EventResponse::new_with(responses::open_chest_response);
     let drop_coins = EventResponse::new_with(responses::drop_inventory_response);
     let flammable = EventResponse::new_with(responses::default_burn_response);
@@ -343,31 +867,46 @@ pub fn make_chest(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_lootable_body(ecs: &mut ECS, start: Coordinate, depth: usize) {
-    let image = ImageData { id: 14, depth: 6 };
+pub fn make_lootable_body(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
     let depth = depth as f64;
-    let health = Health::new(2);
-    let coins = (thread_rng().gen_range(5..=18) as f64 * (1.0 + GOLD_INCREASE * depth)) as isize;
-    let inventory = Inventory { coins };
+    let coins = (thread_rng().gen_range(5..=18) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth)) as isize;
+    let inventory = Inventory::new(coins);
     let award_coins = EventResponse::new_with(responses::pickup_loot_response);
+
+    let mut components = corpse_components(start, CorpseKind::Humanoid);
+    components.push(Component::Inventory(IndexedData::new_with(inventory)));
+    components.push(Component::BumpResponse(IndexedData::new_with(award_coins)));
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+// Picked clean, only good for feeding ghouls.
+pub fn make_carcass(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, corpse_components(start, CorpseKind::Beast));
+}
+
+// What a monster leaves behind, humanoids can be raised and anything can be eaten.
+pub fn corpse_components(start: Coordinate, kind: CorpseKind) -> Vec<Component> {
+    let image = match kind {
+        CorpseKind::Humanoid => tileatlas::image("corpse"),
+        CorpseKind::Beast => tileatlas::image("carcass"),
+    };
     let flammable = EventResponse::new_with(responses::default_burn_response);
 
-    let components = vec![
+    vec![
         Component::Image(IndexedData::new_with(ImageHandle::new(image))),
         Component::Position(IndexedData::new_with(start)),
         Component::Collision(IndexedData::new_with(Collision::Walkable)),
-        Component::Inventory(IndexedData::new_with(inventory)),
-        Component::Health(IndexedData::new_with(health)),
-        Component::BumpResponse(IndexedData::new_with(award_coins)),
+        Component::Health(IndexedData::new_with(Health::new(2))),
+        Component::Corpse(IndexedData::new_with(kind)),
         Component::FireResponse(IndexedData::new_with(flammable)),
-    ];
-
-    let new_id = ecs.create_entity();
-    ecs.add_components_to_entity(new_id, components);
+    ]
 }
 
-pub fn make_spikes(ecs: &mut ECS, start: Coordinate, depth: usize) {
-    let image = ImageData { id: 17, depth: 6 };
+pub fn make_spikes(ecs: &mut ECS, start: Coordinate, depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("spikes");
 
     let melee = match depth {
         0..=4 => Attack::new_melee(3, 1),
@@ -380,7 +919,7 @@ pub fn make_spikes(ecs: &mut ECS, start: Coordinate, depth: usize) {
 
     let components = vec![
         Component::Image(IndexedData::new_with(ImageHandle::new(image))),
-        Component::Name(IndexedData::new_with(Name::new("Spikes"))),
+        Component::Name(IndexedData::new_with(Name::new("Spikes").with_article(Article::Some))),
         Component::Position(IndexedData::new_with(start)),
         Component::Combat(IndexedData::new_with(combat)),
         Component::Collision(IndexedData::new_with(Collision::Hazard)),
@@ -391,24 +930,76 @@ pub fn make_spikes(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_flame(ecs: &mut ECS, start: Coordinate, depth: usize) {
-    let image = ImageData { id: 18, depth: 6 };
+pub fn make_dart_trap(ecs: &mut ECS, start: Coordinate, depth: usize, _difficulty: Difficulty) {
+    let ranged = match depth {
+        0..=4 => Attack::new_ranged(2, 2),
+        5..=9 => Attack::new_ranged(3, 3),
+        _ => Attack::new_ranged(4, 4),
+    };
+    let combat = Combat::new(None, Some(ranged));
+
+    let mut components = trap_components(start, TrapKind::Dart, "Dart trap");
+    components.push(Component::Combat(IndexedData::new_with(combat)));
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_pit_trap(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let components = trap_components(start, TrapKind::Pit, "Pit trap");
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+// hidden traps get their image when revealed
+fn trap_components(start: Coordinate, kind: TrapKind, name: &str) -> Vec<Component> {
+    let trap = EventResponse::new_with(responses::trap_response);
+
+    vec![
+        Component::Trap(IndexedData::new_with(kind)),
+        Component::Secret(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new(name))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::BumpResponse(IndexedData::new_with(trap)),
+    ]
+}
+
+pub fn make_flame(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let components = flame_components(start);
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+// shared with effects that spawn flames through a delta
+pub fn flame_components(start: Coordinate) -> Vec<Component> {
+    let image = tileatlas::image("flame");
     let spread_fire = EventResponse::new_with(spread_fire_response);
 
-    let components = vec![
+    vec![
         Component::Image(IndexedData::new_with(ImageHandle::new(image))),
         Component::Name(IndexedData::new_with(Name::new("Flame"))),
         Component::Position(IndexedData::new_with(start)),
         Component::Collision(IndexedData::new_with(Collision::Hazard)),
         Component::BumpResponse(IndexedData::new_with(spread_fire)),
-    ];
+    ]
+}
 
-    let new_id = ecs.create_entity();
-    ecs.add_components_to_entity(new_id, components);
+pub fn projectile_components(start: Coordinate, projectile: Projectile) -> Vec<Component> {
+    let image = tileatlas::image("projectile");
+
+    vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Projectile"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Projectile(IndexedData::new_with(projectile)),
+    ]
 }
 
-pub fn make_acid(ecs: &mut ECS, start: Coordinate, depth: usize) {
-    let image = ImageData { id: 24, depth: 6 };
+pub fn make_acid(ecs: &mut ECS, start: Coordinate, depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("acid");
     let spread_acid = EventResponse::new_with(spread_acid_response);
 
     let components = vec![
@@ -423,11 +1014,28 @@ pub fn make_acid(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_gold_pile(ecs: &mut ECS, start: Coordinate, depth: usize) {
-    let image = ImageData { id: 15, depth: 6 };
+// Hides whatever is behind it until it clears, walking through is fine.
+pub fn make_smoke(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("smoke");
+
+    // unnamed, so a whole cloud clearing up doesn't flood the log
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::LineOfSight(IndexedData::new_with(LoSBlocking::Blocking)),
+        Component::DurationEffect(IndexedData::new_with(DurationEffect(SMOKE_TURNS, EffectType::Summoned))),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_gold_pile(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let image = tileatlas::image("gold_pile");
     let depth = depth as f64;
-    let coins = (thread_rng().gen_range(9..=25) as f64 * (1.0 + GOLD_INCREASE * depth)) as isize;
-    let inventory = Inventory { coins };
+    let coins = (thread_rng().gen_range(9..=25) as f64 * (1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth)) as isize;
+    let inventory = Inventory::new(coins);
     let award_coins = EventResponse::new_with(responses::pickup_loot_response);
 
     let components = vec![
@@ -442,22 +1050,232 @@ pub fn make_gold_pile(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_stairs_down(ecs: &mut ECS, start: Coordinate, _depth: usize) {
-    let image = ImageData { id: 16, depth: 7 };
+pub fn make_health_potion(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("health_potion");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(HEALTH_POTION_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
 
     let components = vec![
         Component::Image(IndexedData::new_with(ImageHandle::new(image))),
-        Component::Stairs(IndexedData::new_with(())),
+        Component::Name(IndexedData::new_with(Name::new("Health potion"))),
         Component::Position(IndexedData::new_with(start)),
         Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
     ];
 
     let new_id = ecs.create_entity();
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_mushroom(ecs: &mut ECS, start: Coordinate, _depth: usize) {
-    let image = ImageData { id: 22, depth: 6 };
+pub fn make_key(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("key");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(KEY_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Key"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_fire_bomb(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("fire_bomb");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(FIRE_BOMB_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Fire bomb"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_smoke_bomb(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("smoke_bomb");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(SMOKE_BOMB_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Smoke bomb"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_ration(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("ration");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(RATION_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Ration"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_rusty_sword(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("rusty_sword");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(RUSTY_SWORD_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Rusty sword"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_leather_armor(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("leather_armor");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(LEATHER_ARMOR_ID, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Leather armor"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+// Only spells with a scroll in the item registry can be written down.
+pub fn make_scroll(ecs: &mut ECS, start: Coordinate, spell_id: u32) {
+    let Some(item_id) = itemdefinitions::scroll_for_spell(spell_id) else {
+        return;
+    };
+    let image = tileatlas::image("scroll");
+    let inventory = Inventory {
+        items: vec![ItemStack::new(item_id, 1)],
+        ..Default::default()
+    };
+    let pickup = EventResponse::new_with(responses::pickup_loot_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new(ITEM_REGISTRY[&item_id].name))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+        Component::Inventory(IndexedData::new_with(inventory)),
+        Component::BumpResponse(IndexedData::new_with(pickup)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_random_scroll(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let spells: Vec<u32> = ITEM_REGISTRY.values().filter_map(|item| item.scroll).collect();
+    if let Some(spell_id) = spells.choose(&mut thread_rng()) {
+        make_scroll(ecs, start, *spell_id);
+    }
+}
+
+// Stock is rolled fresh for every shop, prices climb with depth like gold does.
+pub fn make_shopkeeper(ecs: &mut ECS, start: Coordinate, depth: usize, difficulty: Difficulty) {
+    let image = tileatlas::image("shopkeeper");
+    let markup = 1.0 + GOLD_INCREASE * difficulty.gold_scaling() * depth as f64;
+    let for_sale: Vec<(u32, isize)> = ITEM_REGISTRY
+        .entries()
+        .filter(|(_, item)| item.price > 0)
+        .map(|(&item_id, item)| (item_id, item.price))
+        .collect();
+    let stock = for_sale
+        .choose_multiple(&mut thread_rng(), SHOP_STOCK_SIZE)
+        .map(|&(item_id, price)| Offer { item_id, price: (price as f64 * markup) as isize })
+        .collect();
+    let show_wares = EventResponse::new_with(responses::show_wares_response);
+
+    let components = vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Name(IndexedData::new_with(Name::new("Shopkeeper"))),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Blocking)),
+        Component::Shop(IndexedData::new_with(Shop { stock })),
+        Component::BumpResponse(IndexedData::new_with(show_wares)),
+    ];
+
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn make_stairs_down(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let components = stairs_down_components(start);
+    let new_id = ecs.create_entity();
+    ecs.add_components_to_entity(new_id, components);
+}
+
+pub fn stairs_down_components(start: Coordinate) -> Vec<Component> {
+    let image = tileatlas::image("stairs_down");
+
+    vec![
+        Component::Image(IndexedData::new_with(ImageHandle::new(image))),
+        Component::Stairs(IndexedData::new_with(())),
+        Component::Position(IndexedData::new_with(start)),
+        Component::Collision(IndexedData::new_with(Collision::Walkable)),
+    ]
+}
+
+pub fn make_mushroom(ecs: &mut ECS, start: Coordinate, _depth: usize, _difficulty: Difficulty) {
+    let image = tileatlas::image("mushroom");
     let health = Health::new(4);
     let flammable = EventResponse::new_with(responses::default_burn_response);
 
@@ -474,14 +1292,14 @@ pub fn make_mushroom(ecs: &mut ECS, start: Coordinate, _depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_critter(ecs: &mut ECS, start: Coordinate, depth: usize) {
+pub fn make_critter(ecs: &mut ECS, start: Coordinate, depth: usize, _difficulty: Difficulty) {
     let health = Health::new(2);
-    let image = ImageData { id: 21, depth: 6 };
+    let image = tileatlas::image("critters");
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
 
     let components = vec![
-        Component::Name(IndexedData::new_with(Name::new("Critters"))),
+        Component::Name(IndexedData::new_with(Name::new("Critters").with_article(Article::Some))),
         Component::Combat(IndexedData::new_with(Combat::default())),
         Component::Image(IndexedData::new_with(ImageHandle::new(image))),
         Component::Position(IndexedData::new_with(start)),
@@ -495,9 +1313,9 @@ pub fn make_critter(ecs: &mut ECS, start: Coordinate, depth: usize) {
     ecs.add_components_to_entity(new_id, components);
 }
 
-pub fn make_rat(ecs: &mut ECS, start: Coordinate, depth: usize) {
+pub fn make_rat(ecs: &mut ECS, start: Coordinate, depth: usize, _difficulty: Difficulty) {
     let health = Health::new(2);
-    let image = ImageData { id: 20, depth: 6 };
+    let image = tileatlas::image("rat");
 
     let take_damage = EventResponse::new_with(responses::take_damage_response);
 
@@ -1,17 +1,43 @@
 use std::thread::LocalKey;
 use phf::{phf_map, Map};
 
-use crate::ecs::ecs::{Delta, EntityIdentifier, IndexedData, MakeComponentOrder, ECS};
+use crate::ecs::ecs::{DeleteComponentOrder, DeleteEntityOrder, Delta, EntityIdentifier, IndexedData, MakeComponentOrder, MakeEntityOrder, ECS};
 use crate::ecs::entity::Entity;
 use crate::ecs::event::{propagate_event, EventResponse, EventType, InteractionEvent};
 use crate::ecs::system::ComponentQuery;
 
+use crate::game::components::{behavior, combat};
+use crate::game::components::effects::{self, StackPolicy};
+use crate::game::components::combat::{AttackReport, DamageType, Health};
+use crate::game::components::behavior::{AIState, TurnTaker};
 use crate::game::components::core::ComponentType;
-use crate::game::components::spells::Spell;
-use crate::game::components::core::{Component, DurationEffect, EffectType, ImageHandle};
+use crate::game::components::spells::{Spell, Targeting};
+use crate::game::components::core::{Component, CorpseKind, DurationEffect, EffectType, ImageHandle};
 
-use crate::game::responses;
-use crate::utils::logger;
+use crate::game::{responses, spawning};
+use crate::map::gamemap::GameMap;
+use crate::map::utils::{orthogonal_neighbors, Coordinate};
+use crate::utils::{logger, los};
+
+const FIREBALL_RADIUS: f32 = 1.0;
+const BLINK_RANGE: f32 = 5.0;
+const TELEKINESIS_DISTANCE: u32 = 3;
+const FAR_SIGHT_ROOMS: usize = 2;
+const FAR_SIGHT_TURNS: usize = 5;
+const SHIELD_ABSORPTION: isize = 12;
+const SHIELD_DURATION: isize = 20;
+const SLEEP_RADIUS: f32 = 1.5;
+const SLEEP_TURNS: isize = 6;
+const CHAIN_LIGHTNING_DAMAGE: isize = 6;
+const CHAIN_LIGHTNING_JUMPS: usize = 3;
+const CHAIN_LIGHTNING_RADIUS: f32 = 3.0;
+const SUMMON_WOLF_TURNS: isize = 25;
+const DISARM_TURNS: isize = 5;
+const DIG_RANGE: f32 = 3.0;
+const RAISE_DEAD_TURNS: isize = 40;
+const GRAPPLE_MAX_HEALTH: isize = 12;
+const GRAPPLE_TURNS: isize = 2;
+const FLING_DAMAGE: isize = 5;
 
 
 pub static SPELL_REGISTRY: Map<u32, &LocalKey<Spell>> = phf_map!(
@@ -21,6 +47,22 @@ pub static SPELL_REGISTRY: Map<u32, &LocalKey<Spell>> = phf_map!(
     3u32 => &STONESKIN,
     4u32 => &BRITTLE,
     5u32 => &FLAMES,
+    6u32 => &FROST,
+    7u32 => &SWIFTNESS,
+    8u32 => &BEFUDDLE,
+    9u32 => &FIREBALL,
+    10u32 => &BLINK,
+    11u32 => &PULL,
+    12u32 => &PUSH,
+    13u32 => &FAR_SIGHT,
+    14u32 => &SHIELD,
+    15u32 => &SLEEP,
+    16u32 => &CHAIN_LIGHTNING,
+    17u32 => &SUMMON_WOLF,
+    18u32 => &DISARM,
+    19u32 => &DIG,
+    20u32 => &RAISE_DEAD,
+    21u32 => &GRAPPLE,
   );
 
 thread_local! {
@@ -59,10 +101,138 @@ thread_local! {
         ImageHandle::new_spell(10, 11), 
         ComponentQuery::new_single(ComponentType::Player), 
         mass_flame);
+
+    pub static FROST: Spell = Spell::new(
+        "Frost", 
+        ImageHandle::new_spell(14, 15), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        frost);
+
+    pub static SWIFTNESS: Spell = Spell::new(
+        "Swiftness", 
+        ImageHandle::new_spell(16, 17), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        swiftness);
+
+    pub static BEFUDDLE: Spell = Spell::new(
+        "Befuddle", 
+        ImageHandle::new_spell(20, 21), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        befuddle)
+        .with_targeting(Targeting::Entity);
+
+    pub static FIREBALL: Spell = Spell::new(
+        "Fireball", 
+        ImageHandle::new_spell(24, 25), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        fireball)
+        .with_targeting(Targeting::Tile)
+        .with_cost(4);
+
+    pub static BLINK: Spell = Spell::new(
+        "Blink", 
+        ImageHandle::new_spell(26, 27), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        blink)
+        .with_targeting(Targeting::Tile)
+        .with_cooldown_turns(12)
+        .with_condition(can_blink_to);
+
+    pub static PULL: Spell = Spell::new(
+        "Pull", 
+        ImageHandle::new_spell(28, 29), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        telekinetic_pull)
+        .with_targeting(Targeting::Entity)
+        .with_cooldown_turns(8);
+
+    pub static PUSH: Spell = Spell::new(
+        "Push", 
+        ImageHandle::new_spell(30, 31), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        telekinetic_push)
+        .with_targeting(Targeting::Entity)
+        .with_cooldown_turns(8);
+
+    pub static FAR_SIGHT: Spell = Spell::new(
+        "Far Sight", 
+        ImageHandle::new_spell(32, 33), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        far_sight)
+        .with_targeting(Targeting::Remote)
+        .with_condition(can_far_see);
+
+    pub static SHIELD: Spell = Spell::new(
+        "Shield", 
+        ImageHandle::new_spell(34, 35), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        shield)
+        .with_cost(3);
+
+    pub static SLEEP: Spell = Spell::new(
+        "Sleep", 
+        ImageHandle::new_spell(36, 37), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        sleep)
+        .with_targeting(Targeting::Tile)
+        .with_cost(3);
+
+    pub static CHAIN_LIGHTNING: Spell = Spell::new(
+        "Chain Lightning", 
+        ImageHandle::new_spell(38, 39), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        chain_lightning)
+        .with_targeting(Targeting::Entity)
+        .with_cost(5);
+
+    pub static SUMMON_WOLF: Spell = Spell::new(
+        "Summon Wolf", 
+        ImageHandle::new_spell(40, 41), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        summon_wolf)
+        .with_condition(can_summon_wolf)
+        .with_cost(6);
+
+    pub static DISARM: Spell = Spell::new(
+        "Disarm", 
+        ImageHandle::new_spell(43, 44), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        disarm)
+        .with_targeting(Targeting::Entity)
+        .with_cooldown_turns(10);
+
+    pub static DIG: Spell = Spell::new(
+        "Dig", 
+        ImageHandle::new_spell(48, 49), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        dig)
+        .with_targeting(Targeting::Tile)
+        .with_condition(can_dig)
+        .with_terrain(dig_through)
+        .with_cooldown_turns(15);
+
+    pub static RAISE_DEAD: Spell = Spell::new(
+        "Raise Dead", 
+        ImageHandle::new_spell(50, 51), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        raise_dead)
+        .with_targeting(Targeting::Tile)
+        .with_condition(can_raise_dead)
+        .with_cost(5);
+
+    pub static GRAPPLE: Spell = Spell::new(
+        "Grapple", 
+        ImageHandle::new_spell(52, 53), 
+        ComponentQuery::new_single(ComponentType::Player), 
+        grapple)
+        .with_targeting(Targeting::Tile)
+        .with_condition(can_grapple)
+        .with_release(fling)
+        .with_cooldown_turns(10);
 }
 
 
-pub fn invisible(entities: &[&Entity], _ecs: &ECS) -> Vec<Delta> {
+pub fn invisible(entities: &[&Entity], _target: Option<Coordinate>, _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
     logger::log_message("You cast invisibility!");
     entities
         .into_iter()
@@ -75,7 +245,7 @@ pub fn invisible(entities: &[&Entity], _ecs: &ECS) -> Vec<Delta> {
         .collect()
 }
 
-pub fn levitate(entities: &[&Entity], _ecs: &ECS) -> Vec<Delta> {
+pub fn levitate(entities: &[&Entity], _target: Option<Coordinate>, _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
     logger::log_message("You cast levitate!");
     entities
         .into_iter()
@@ -88,7 +258,20 @@ pub fn levitate(entities: &[&Entity], _ecs: &ECS) -> Vec<Delta> {
         .collect()
 }
 
-pub fn stoneskin(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
+pub fn swiftness(entities: &[&Entity], _target: Option<Coordinate>, _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    logger::log_message("You cast swiftness!");
+    entities
+        .into_iter()
+        .map(|entity| {
+            Delta::MakeComponent(MakeComponentOrder {
+                component: Component::DurationEffect(IndexedData::new_with(DurationEffect(5, EffectType::Haste))),
+                entity: EntityIdentifier::new_from_entity(entity.index),
+            })
+        })
+        .collect()
+}
+
+pub fn stoneskin(entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
     logger::log_message("You cast stoneskin!");
     entities
         .into_iter()
@@ -100,21 +283,44 @@ pub fn stoneskin(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
                 return vec![];
             };
             let half_damage = EventResponse::new_with(responses::take_half_damage_response);
-            vec![
-                Delta::Change(Component::BumpResponse(melee_response.make_change(half_damage))),
-                Delta::Change(Component::ShotResponse(ranged_response.make_change(half_damage.clone()))),
-                Delta::MakeComponent(MakeComponentOrder {
-                    component: Component::DurationEffect(IndexedData::new_with(DurationEffect(8, EffectType::Stoneskin))),
-                    entity: EntityIdentifier::new_from_entity(entity.index),
-                })
-            ]
-            
+            let components = ecs.get_components_from_entity_id(entity.index);
+            // recasting doesn't prolong it
+            let mut delta = effects::apply_effect(
+                EntityIdentifier::new_from_entity(entity.index),
+                &components,
+                DurationEffect(8, EffectType::Stoneskin),
+                StackPolicy::Ignore,
+            );
+            delta.push(Delta::Change(Component::BumpResponse(melee_response.make_change(half_damage))));
+            delta.push(Delta::Change(Component::ShotResponse(ranged_response.make_change(half_damage.clone()))));
+            delta
+
         })
         .flatten()
         .collect()
 }
 
-pub fn heal(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
+pub fn shield(entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    logger::log_message("You cast shield!");
+    entities
+        .iter()
+        .flat_map(|entity| {
+            let mut delta = vec![];
+            // recasting refreshes the shield rather than stacking a second one
+            let components = ecs.get_components_from_entity_id(entity.index);
+            if let Some(old_shield) = combat::find_shield(&components) {
+                delta.push(Delta::DeleteComponent(DeleteComponentOrder { component_id: old_shield.index, entity_id: None }));
+            }
+            delta.push(Delta::MakeComponent(MakeComponentOrder {
+                component: Component::DurationEffect(IndexedData::new_with(DurationEffect(SHIELD_DURATION, EffectType::Shield(SHIELD_ABSORPTION)))),
+                entity: EntityIdentifier::new_from_entity(entity.index),
+            }));
+            delta
+        })
+        .collect()
+}
+
+pub fn heal(entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
     logger::log_message("You cast heal!");
     entities
         .into_iter()
@@ -127,7 +333,7 @@ pub fn heal(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
         .collect()
 }
 
-pub fn brittle(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
+pub fn brittle(entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
     logger::log_message("You cast brittle!");
     let entity = entities.first().unwrap();
     let Some(Component::Position(index_pos)) = ecs.get_component_from_entity(entity, ComponentType::Position) else {
@@ -153,7 +359,7 @@ pub fn brittle(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
                 response_function: responses::take_double_damage_response,
             };
             
-            logger::log_message(&[&name.data.raw, "shudders."].join(" "));
+            logger::log_message(&[&name.data.subject(), "shudders."].join(" "));
             vec![
                 Delta::Change(Component::BumpResponse(melee_response.make_change(double_damage))),
                 Delta::Change(Component::ShotResponse(ranged_response.make_change(double_damage.clone()))),
@@ -163,7 +369,7 @@ pub fn brittle(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
         .collect()
 }
 
-pub fn mass_flame(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
+pub fn mass_flame(entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
     logger::log_message("You cast mass flame!");
     let entity = entities.first().unwrap();
     let Some(Component::Position(index_pos)) = ecs.get_component_from_entity(entity, ComponentType::Position) else {
@@ -187,3 +393,598 @@ pub fn mass_flame(entities: &[&Entity], ecs: &ECS) -> Vec<Delta> {
         .collect()
 }
 
+pub fn frost(entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    logger::log_message("You cast frost!");
+    let entity = entities.first().unwrap();
+    let Some(Component::Position(index_pos)) = ecs.get_component_from_entity(entity, ComponentType::Position) else {
+        return vec![];
+    };
+
+    ecs.get_entities_in_room(index_pos.data)
+        .into_iter()
+        .filter(|entity| ecs.entity_has_component(entity, ComponentType::Monster))
+        .map(|entity| {
+            if let Some(Component::Name(name)) = ecs.get_component_from_entity_id(entity.index, ComponentType::Name) {
+                logger::log_message(&[&name.data.subject(), "is frozen solid."].join(" "));
+            }
+            Delta::MakeComponent(MakeComponentOrder {
+                component: Component::DurationEffect(IndexedData::new_with(DurationEffect(3, EffectType::Frozen))),
+                entity: EntityIdentifier::new_from_entity(entity.index),
+            })
+        })
+        .collect()
+}
+
+pub fn befuddle(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    logger::log_message("You cast befuddle!");
+    let Some(target_id) = ecs
+        .get_blocking_entity(target)
+        .filter(|&target_id| ecs.entity_id_has_component(target_id, ComponentType::Monster))
+    else {
+        logger::log_message("Nothing here to befuddle.");
+        return vec![];
+    };
+    if let Some(Component::Name(name)) = ecs.get_component_from_entity_id(target_id, ComponentType::Name) {
+        logger::log_message(&[&name.data.subject(), "looks confused."].join(" "));
+    }
+    vec![Delta::MakeComponent(MakeComponentOrder {
+        component: Component::DurationEffect(IndexedData::new_with(DurationEffect(4, EffectType::Confused))),
+        entity: EntityIdentifier::new_from_entity(target_id),
+    })]
+}
+
+pub fn fireball(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    logger::log_message("You cast fireball!");
+
+    let event = InteractionEvent {
+        event_type: EventType::Fire,
+        payload: vec![],
+        attack: None,
+    };
+    let burning = ecs.get_entities_in_radius(target, FIREBALL_RADIUS)
+        .into_iter()
+        .flat_map(|entity_id| propagate_event(&event, entity_id, ecs));
+
+    let reach = FIREBALL_RADIUS as i32;
+    let flames = (-reach..=reach)
+        .flat_map(|x| (-reach..=reach).map(move |y| target + Coordinate { x, y }))
+        .filter(|tile| tile.distance(target) <= FIREBALL_RADIUS)
+        // walls stop the blast
+        .filter(|tile| map.is_tile_passable(*tile))
+        .map(|tile| Delta::MakeEntity(MakeEntityOrder { components: spawning::flame_components(tile) }));
+
+    burning.chain(flames).collect()
+}
+
+pub fn sleep(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    logger::log_message("You cast sleep!");
+    let player_position = ecs.get_player_position();
+
+    ecs.get_entities_in_radius(target, SLEEP_RADIUS)
+        .into_iter()
+        .filter_map(|entity_id| {
+            let Some(Component::Turn(turn)) = ecs.get_component_from_entity_id(entity_id, ComponentType::Turn) else {
+                return None;
+            };
+            let name = match ecs.get_component_from_entity_id(entity_id, ComponentType::Name) {
+                Some(Component::Name(name)) => name.data.subject(),
+                _ => "It".to_string(),
+            };
+            // a monster already in your face is too riled up to doze off
+            let adjacent = match (player_position, ecs.get_component_from_entity_id(entity_id, ComponentType::Position)) {
+                (Some(player), Some(Component::Position(position))) => ecs
+                    .get_footprint(entity_id, position.data)
+                    .into_iter()
+                    .any(|tile| tile.distance(player) < 1.5),
+                _ => false,
+            };
+            if turn.data.state == AIState::Alert && adjacent {
+                logger::log_message(&[&name, "shrugs off the spell."].join(" "));
+                return None;
+            }
+
+            logger::log_message(&[&name, "falls asleep."].join(" "));
+            let new_turn = TurnTaker {
+                state: AIState::Sleeping(SLEEP_TURNS),
+                ..turn.data.clone()
+            };
+            Some(Delta::Change(Component::Turn(turn.make_change(new_turn))))
+        })
+        .collect()
+}
+
+pub fn chain_lightning(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    let Some(first_id) = ecs.get_blocking_entity(target) else {
+        return vec![];
+    };
+    logger::log_message("You cast chain lightning!");
+
+    let mut struck = vec![first_id];
+    let mut source = target;
+    for _ in 0..CHAIN_LIGHTNING_JUMPS {
+        // arcs to the closest monster it hasn't already hit
+        let next = ecs.get_entities_in_radius(source, CHAIN_LIGHTNING_RADIUS)
+            .into_iter()
+            .filter(|entity_id| !struck.contains(entity_id))
+            .filter(|entity_id| ecs.get_component_from_entity_id(*entity_id, ComponentType::Turn).is_some())
+            .find_map(|entity_id| match ecs.get_component_from_entity_id(entity_id, ComponentType::Position) {
+                Some(Component::Position(position)) if los::line_of_sight(source, position.data, map, ecs) => {
+                    Some((entity_id, position.data))
+                }
+                _ => None,
+            });
+        let Some((entity_id, position)) = next else {
+            break;
+        };
+        struck.push(entity_id);
+        source = position;
+    }
+
+    let names: Vec<String> = struck
+        .iter()
+        .map(|entity_id| match ecs.get_component_from_entity_id(*entity_id, ComponentType::Name) {
+            Some(Component::Name(name)) => name.data.definite(),
+            _ => "something".to_string(),
+        })
+        .collect();
+    logger::log_message(&format!("The lightning arcs through {}.", names.join(", ")));
+
+    let mut damage = CHAIN_LIGHTNING_DAMAGE;
+    struck
+        .into_iter()
+        .flat_map(|entity_id| {
+            let event = InteractionEvent {
+                event_type: EventType::Shot,
+                attack: Some(AttackReport {
+                    damage,
+                    damage_type: DamageType::Magical,
+                    hit_message: "shocks",
+                    ..Default::default()
+                }),
+                payload: vec![],
+            };
+            // each jump loses some of its strength
+            damage = (damage * 2 / 3).max(1);
+            propagate_event(&event, entity_id, ecs)
+        })
+        .collect()
+}
+
+pub fn can_blink_to(target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> bool {
+    let (Some(target), Some(origin)) = (target, ecs.get_player_position()) else {
+        return false;
+    };
+    if target.distance(origin) > BLINK_RANGE {
+        logger::log_message("That is too far to blink.");
+        return false;
+    }
+    if !map.is_tile_passable(target) || ecs.is_blocked_by_entity(target) {
+        logger::log_message("You can't blink there, something is in the way.");
+        return false;
+    }
+    true
+}
+
+pub fn blink(entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    let Some(entity) = entities.first() else {
+        return vec![];
+    };
+    let Some(Component::Position(position)) = ecs.get_component_from_entity(entity, ComponentType::Position) else {
+        return vec![];
+    };
+    logger::log_message("You blink!");
+    vec![Delta::Change(Component::Position(position.make_change(target - position.data)))]
+}
+
+pub fn telekinetic_pull(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let (Some(target), Some(origin)) = (target, ecs.get_player_position()) else {
+        return vec![];
+    };
+    let Some(target_id) = ecs.get_blocking_entity(target) else {
+        return vec![];
+    };
+    logger::log_message("You cast pull!");
+    behavior::pull(origin, target_id, TELEKINESIS_DISTANCE, ecs, map)
+}
+
+pub fn telekinetic_push(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let (Some(target), Some(origin)) = (target, ecs.get_player_position()) else {
+        return vec![];
+    };
+    let Some(target_id) = ecs.get_blocking_entity(target) else {
+        return vec![];
+    };
+    logger::log_message("You cast push!");
+    behavior::knockback(origin, target_id, TELEKINESIS_DISTANCE, ecs, map)
+}
+
+pub fn can_far_see(target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> bool {
+    let (Some(target), Some(origin)) = (target, ecs.get_player_position()) else {
+        return false;
+    };
+    match map.room_distance(origin, target) {
+        Some(distance) if distance <= FAR_SIGHT_ROOMS => true,
+        _ => {
+            logger::log_message("Your sight can't reach that far.");
+            false
+        }
+    }
+}
+
+pub fn far_sight(_entities: &[&Entity], target: Option<Coordinate>, _ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    logger::log_message("You cast far sight!");
+    map.reveal_room(target, FAR_SIGHT_TURNS);
+    vec![]
+}
+
+fn free_tile_next_to_player(ecs: &ECS, map: &GameMap) -> Option<Coordinate> {
+    let origin = ecs.get_player_position()?;
+    orthogonal_neighbors(origin)
+        .into_iter()
+        .find(|&tile| map.is_tile_passable(tile) && !ecs.is_blocked_by_entity(tile))
+}
+
+pub fn can_summon_wolf(_target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> bool {
+    if free_tile_next_to_player(ecs, map).is_none() {
+        logger::log_message("There is no room for a wolf here.");
+        return false;
+    }
+    true
+}
+
+pub fn summon_wolf(_entities: &[&Entity], _target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(tile) = free_tile_next_to_player(ecs, map) else {
+        return vec![];
+    };
+    logger::log_message("You summon a spirit wolf!");
+    let components = spawning::wolf_components(tile, map.depth, SUMMON_WOLF_TURNS);
+    vec![Delta::MakeEntity(MakeEntityOrder { components })]
+}
+
+pub fn disarm(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    let Some(target_id) = ecs.get_blocking_entity(target) else {
+        return vec![];
+    };
+    logger::log_message("You cast disarm!");
+    if let Some(Component::Name(name)) = ecs.get_component_from_entity_id(target_id, ComponentType::Name) {
+        logger::log_message(&[&name.data.subject(), "is disarmed."].join(" "));
+    }
+    vec![Delta::MakeComponent(MakeComponentOrder {
+        component: Component::DurationEffect(IndexedData::new_with(DurationEffect(DISARM_TURNS, EffectType::Disarmed))),
+        entity: EntityIdentifier::new_from_entity(target_id),
+    })]
+}
+
+pub fn can_dig(target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> bool {
+    let (Some(target), Some(origin)) = (target, ecs.get_player_position()) else {
+        return false;
+    };
+    if target.distance(origin) > DIG_RANGE {
+        logger::log_message("That is too far to dig.");
+        return false;
+    }
+    if !map.is_tile_diggable(target) {
+        logger::log_message("There is no wall there you could dig through.");
+        return false;
+    }
+    true
+}
+
+// Nothing in the ECS changes, dig_through brings the wall down.
+pub fn dig(_entities: &[&Entity], _target: Option<Coordinate>, _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    logger::log_message("The wall crumbles away!");
+    vec![]
+}
+
+pub fn dig_through(target: Option<Coordinate>, map: &mut GameMap) -> bool {
+    target.is_some_and(|target| map.dig(target))
+}
+
+pub fn can_raise_dead(target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> bool {
+    let Some(target) = target else {
+        return false;
+    };
+    match ecs.get_corpse(target) {
+        None => {
+            logger::log_message("There is no corpse there to raise.");
+            false
+        }
+        Some((_, CorpseKind::Beast)) => {
+            logger::log_message("There is too little left of that to raise.");
+            false
+        }
+        Some(_) if ecs.is_blocked_by_entity(target) => {
+            logger::log_message("Something is standing on that corpse.");
+            false
+        }
+        Some(_) => true,
+    }
+}
+
+pub fn raise_dead(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let Some(target) = target else {
+        return vec![];
+    };
+    let Some((corpse_id, CorpseKind::Humanoid)) = ecs.get_corpse(target) else {
+        return vec![];
+    };
+    logger::log_message("The corpse rises to fight for you!");
+    let components = spawning::raised_skeleton_components(target, map.depth, RAISE_DEAD_TURNS);
+    let mut deltas = responses::drop_carried_loot(corpse_id, ecs);
+    deltas.push(Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(corpse_id)));
+    deltas.push(Delta::MakeEntity(MakeEntityOrder { components }));
+    deltas
+}
+
+pub fn can_grapple(target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> bool {
+    let Some(target_id) = target.and_then(|target| ecs.get_blocking_entity(target)) else {
+        logger::log_message("There is nothing there to grab.");
+        return false;
+    };
+    if !ecs.entity_id_has_component(target_id, ComponentType::Turn) {
+        logger::log_message("That won't budge.");
+        return false;
+    }
+    let too_heavy = ecs.entity_id_has_component(target_id, ComponentType::Size)
+        || ecs.get_typed::<Health>(target_id).is_some_and(|health| health.data.max > GRAPPLE_MAX_HEALTH);
+    if too_heavy {
+        logger::log_message("That is too heavy to lift.");
+        return false;
+    }
+    true
+}
+
+pub fn grapple(_entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+    let Some(target_id) = target.and_then(|target| ecs.get_blocking_entity(target)) else {
+        return vec![];
+    };
+    if let Some(Component::Name(name)) = ecs.get_component_from_entity_id(target_id, ComponentType::Name) {
+        logger::log_message(&format!("You lift {} into the air! Cast again to fling it.", name.data.definite()));
+    }
+    vec![Delta::MakeComponent(MakeComponentOrder {
+        component: Component::DurationEffect(IndexedData::new_with(DurationEffect(GRAPPLE_TURNS, EffectType::Grappled))),
+        entity: EntityIdentifier::new_from_entity(target_id),
+    })]
+}
+
+// The second half of grapple, entities is whatever the first cast lifted.
+pub fn fling(entities: &[&Entity], target: Option<Coordinate>, ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+    let (Some(target), Some(entity)) = (target, entities.first()) else {
+        return vec![];
+    };
+    let components = ecs.get_components_from_entity_id(entity.index);
+    let Some(grip) = components.iter().find_map(|component| match component {
+        Component::DurationEffect(effect) if effect.data.1 == EffectType::Grappled => Some(effect),
+        _ => None,
+    }) else {
+        logger::log_message("It has slipped out of your grip.");
+        return vec![];
+    };
+    let mut deltas = vec![Delta::DeleteComponent(DeleteComponentOrder { component_id: grip.index, entity_id: None })];
+    deltas.extend(behavior::fling(entity.index, target, FLING_DAMAGE, ecs, map));
+    deltas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{
+        action::PlayerAction,
+        components::{combat::Health, inventory::Inventory, spells::CooldownState},
+        core::Game,
+        testing::{self, PLAYER_START},
+    };
+    use crate::utils::{
+        los::line_of_sight,
+        pathfinding::{find_path, PathOptions},
+    };
+
+    fn player_spell(game: &Game, index: i32) -> Spell {
+        game.ecs.get_player_spells()[index as usize].data.clone()
+    }
+
+    #[test]
+    fn blink_moves_and_reveals_the_destination() {
+        let mut game = testing::arena();
+        let index = testing::give_spell(&mut game, BLINK.with(|spell| spell.clone()));
+        game.map.explored.borrow_mut().clear();
+
+        let target = PLAYER_START + Coordinate { x: 4, y: 0 };
+        game.cast_spell_command(index, Some(target));
+        assert_eq!(game.ecs.get_player_position(), Some(target));
+        assert!(game.map.explored.borrow().contains(&target));
+        assert!(matches!(player_spell(&game, index).castable, CooldownState::OnCooldown(_)));
+    }
+
+    fn position_of(game: &Game, entity_id: usize) -> Coordinate {
+        game.ecs.get_typed::<Coordinate>(entity_id).unwrap().data
+    }
+
+    #[test]
+    fn pulled_monsters_stop_at_walls() {
+        let mut game = testing::arena();
+        let doggo = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 4, y: 0 });
+        testing::wall(&mut game, PLAYER_START + Coordinate { x: 2, y: 0 });
+
+        let target = position_of(&game, doggo);
+        let changes = telekinetic_pull(&[], Some(target), &game.ecs, &game.map);
+        game.ecs.apply_changes(changes);
+        assert_eq!(position_of(&game, doggo), PLAYER_START + Coordinate { x: 3, y: 0 });
+    }
+
+    #[test]
+    fn pushed_monsters_stop_at_walls() {
+        let mut game = testing::arena();
+        let edge = game.map.width as i32 - 1;
+        let doggo = testing::spawn(&mut game, "Doggo", Coordinate { x: edge - 2, y: PLAYER_START.y });
+
+        let target = position_of(&game, doggo);
+        let changes = telekinetic_push(&[], Some(target), &game.ecs, &game.map);
+        game.ecs.apply_changes(changes);
+        assert_eq!(position_of(&game, doggo), Coordinate { x: edge - 1, y: PLAYER_START.y });
+    }
+
+    fn health_changes(changes: &[Delta]) -> usize {
+        changes.iter().filter(|delta| matches!(delta, Delta::Change(Component::Health(_)))).count()
+    }
+
+    #[test]
+    fn chain_lightning_arcs_through_a_cluster() {
+        let mut game = testing::arena();
+        let first = PLAYER_START + Coordinate { x: 4, y: 0 };
+        for offset in [Coordinate { x: 0, y: 0 }, Coordinate { x: 1, y: 1 }, Coordinate { x: 2, y: 0 }] {
+            testing::spawn(&mut game, "Doggo", first + offset);
+        }
+
+        let changes = chain_lightning(&[], Some(first), &game.ecs, &game.map);
+        assert_eq!(health_changes(&changes), 3);
+    }
+
+    #[test]
+    fn chain_lightning_needs_something_to_jump_to() {
+        let mut game = testing::arena();
+        let first = PLAYER_START + Coordinate { x: 4, y: 0 };
+        testing::spawn(&mut game, "Doggo", first);
+        testing::spawn(&mut game, "Doggo", first + Coordinate { x: 8, y: 0 });
+
+        let changes = chain_lightning(&[], Some(first), &game.ecs, &game.map);
+        assert_eq!(health_changes(&changes), 1);
+    }
+
+    fn cross_spikes(levitating: bool) -> isize {
+        let mut game = testing::arena();
+        if levitating {
+            let index = testing::give_spell(&mut game, LEVITATE.with(|spell| spell.clone()));
+            game.cast_spell_command(index, None);
+        }
+        testing::spawn(&mut game, "Spikes", PLAYER_START + Coordinate { x: 1, y: 0 });
+        let health = |game: &Game| game.ecs.get_typed::<Health>(game.ecs.get_player_id()).unwrap().data.current;
+        let before = health(&game);
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        before - health(&game)
+    }
+
+    #[test]
+    fn levitation_floats_over_spikes() {
+        assert!(cross_spikes(false) > 0);
+        assert_eq!(cross_spikes(true), 0);
+    }
+
+    #[test]
+    fn blink_refuses_bad_targets() {
+        let mut game = testing::arena();
+        let index = testing::give_spell(&mut game, BLINK.with(|spell| spell.clone()));
+        let blocked = PLAYER_START + Coordinate { x: 2, y: 0 };
+        testing::spawn(&mut game, "Doggo", blocked);
+
+        game.cast_spell_command(index, Some(blocked));
+        game.cast_spell_command(index, Some(PLAYER_START + Coordinate { x: 6, y: 0 }));
+        assert_eq!(game.ecs.get_player_position(), Some(PLAYER_START));
+        assert!(matches!(player_spell(&game, index).castable, CooldownState::Available));
+    }
+
+    #[test]
+    fn digging_opens_a_way_through() {
+        let mut game = testing::arena();
+        for y in 1..17 {
+            testing::wall(&mut game, Coordinate { x: 6, y });
+        }
+        let beyond = PLAYER_START + Coordinate { x: 4, y: 0 };
+        let options = PathOptions::default();
+        assert!(find_path(PLAYER_START, beyond, &game.map, &game.ecs, options).is_none());
+        assert!(!line_of_sight(PLAYER_START, beyond, &game.map, &game.ecs));
+
+        let index = testing::give_spell(&mut game, DIG.with(|spell| spell.clone()));
+        let generation = game.ecs.get_nav_generation();
+        game.cast_spell_command(index, Some(Coordinate { x: 6, y: 4 }));
+        assert!(game.ecs.get_nav_generation() > generation);
+        assert!(find_path(PLAYER_START, beyond, &game.map, &game.ecs, options).is_some());
+        assert!(line_of_sight(PLAYER_START, beyond, &game.map, &game.ecs));
+    }
+
+    #[test]
+    fn raising_the_dead_needs_a_corpse() {
+        let mut game = testing::arena();
+        let index = testing::give_spell(&mut game, RAISE_DEAD.with(|spell| spell.clone()));
+        let body = PLAYER_START + Coordinate { x: 2, y: 0 };
+        let corpse = testing::spawn(&mut game, "Corpse", body);
+        let coins = game.ecs.get_typed::<Inventory>(corpse).unwrap().data.coins;
+        testing::drain_log();
+
+        game.cast_spell_command(index, Some(PLAYER_START + Coordinate { x: 0, y: 2 }));
+        assert!(testing::drain_log().contains(&"There is no corpse there to raise.".to_string()));
+        assert!(matches!(player_spell(&game, index).castable, CooldownState::Available));
+
+        game.cast_spell_command(index, Some(body));
+        assert!(game.ecs.get_corpse(body).is_none());
+        let raised = game.ecs.get_blocking_entity(body).unwrap();
+        assert!(game.ecs.entity_id_has_component(raised, ComponentType::Turn));
+        assert!(!game.ecs.entity_id_has_component(raised, ComponentType::Monster));
+        // the corpse's coins are left on the floor rather than raised with it
+        let left = game
+            .ecs
+            .get_all_entities_in_tile(body)
+            .into_iter()
+            .filter_map(|entity_id| game.ecs.get_typed::<Inventory>(entity_id))
+            .map(|inventory| inventory.data.coins)
+            .sum::<isize>();
+        assert_eq!(left, coins);
+    }
+
+    fn health_of(game: &Game, entity_id: usize) -> isize {
+        game.ecs.get_typed::<Health>(entity_id).unwrap().data.current
+    }
+
+    #[test]
+    fn flung_monsters_hurt_what_they_land_on() {
+        let mut game = testing::arena();
+        let index = testing::give_spell(&mut game, GRAPPLE.with(|spell| spell.clone()));
+        let doggo = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 2, y: 0 });
+        let heavy = testing::spawn(&mut game, "Heavy", PLAYER_START + Coordinate { x: 2, y: 4 });
+        let (doggo_health, heavy_health) = (health_of(&game, doggo), health_of(&game, heavy));
+
+        game.cast_spell_command(index, Some(position_of(&game, doggo)));
+        assert!(player_spell(&game, index).held.is_some());
+        game.cast_spell_command(index, Some(position_of(&game, heavy)));
+        assert!(player_spell(&game, index).held.is_none());
+        assert!(health_of(&game, doggo) < doggo_health);
+        assert!(health_of(&game, heavy) < heavy_health);
+    }
+
+    #[test]
+    fn a_grip_is_not_handed_down_to_a_reused_id() {
+        let mut game = testing::arena();
+        let index = testing::give_spell(&mut game, GRAPPLE.with(|spell| spell.clone()));
+        let doggo = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 2, y: 0 });
+        game.cast_spell_command(index, Some(position_of(&game, doggo)));
+        let held = player_spell(&game, index).held.unwrap();
+        assert_eq!(game.ecs.get_entity_id_from_identifier(held), Some(doggo));
+
+        game.ecs.remove_entity(doggo);
+        let newcomer = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 0, y: 3 });
+        assert_eq!(newcomer, doggo);
+        assert_eq!(game.ecs.get_entity_id_from_identifier(held), None);
+    }
+}
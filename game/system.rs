@@ -3,25 +3,33 @@ use std::collections::{HashMap, HashSet};
 use crate::{
     ecs::{
         ecs::{DeleteComponentOrder, DeleteEntityOrder, Delta, IndexedData, ECS},
-        entity::{take_component_from_owned, take_component_from_refs},
+        entity::{find_typed, take_component_from_owned, take_component_from_refs},
         event::{self, propagate_event, EventResponse, EventType, InteractionEvent},
         system::{ComponentQuery, System},
     },
     game::{
+        action::GameEvent,
         archetype,
         components::{
-            attributes::{get_xp_to_next, Attributes},
+            attributes::{self, Attributes},
             core::*,
-        }, responses,
+            hunger,
+            stats,
+        },
+        core::VISION_RADIUS,
+        responses,
     },
-    map::{gamemap::GameMap, utils::Coordinate},
-    utils::{logger, pathfinding},
+    map::{gamemap::{GameMap, EXPLORE_FILL_BUDGET}, utils::Coordinate},
+    utils::{logger, los, pathfinding},
 };
 
-use super::components::combat::Health;
+use super::components::{behavior::{self, AIState, TurnTaker}, combat::{Combat, Health}, spells::CooldownState};
 
 #[derive(Default)]
-pub struct UnitCull {}
+pub struct UnitCull {
+    // names of the monsters culled this run
+    deaths: Vec<String>,
+}
 
 impl System for UnitCull {
     fn get_requirements(&self) -> ComponentQuery {
@@ -35,14 +43,16 @@ impl System for UnitCull {
         if let (Some(Component::Health(health)), _) =
             take_component_from_refs(ComponentType::Health, components)
         {
-            if health.data.current <= 0 {
+            let entity_id = ecs.get_entity_id_from_component_id(health.index).unwrap();
+            // the player's body stays put so the game can report on the death
+            if health.data.is_dead() && entity_id != ecs.get_player_id() {
                 let event = InteractionEvent {
                     event_type: EventType::Death,
                     attack: None,
                     payload: vec![],
                 };
-                let entity_id = ecs.get_entity_id_from_component_id(health.index).unwrap();
                 let mut event_results = event::propagate_event(&event, entity_id, ecs);
+                self.record_death(entity_id, ecs);
                 event_results.push(Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(
                     entity_id,
                 )));
@@ -51,6 +61,22 @@ impl System for UnitCull {
         }
         vec![]
     }
+
+    fn take_events(&mut self) -> Vec<GameEvent> {
+        self.deaths.drain(..).map(|name| GameEvent::MonsterDied { name }).collect()
+    }
+}
+
+impl UnitCull {
+    // Kills are credited by the hit that landed them, this is only for listeners.
+    fn record_death(&mut self, entity_id: usize, ecs: &ECS) {
+        if !ecs.entity_id_has_component(entity_id, ComponentType::Monster) {
+            return;
+        }
+        if let Some(name) = ecs.get_typed::<Name>(entity_id) {
+            self.deaths.push(name.data.raw.clone());
+        }
+    }
 }
 
 pub type NavigationGrid = HashMap<Coordinate, Coordinate>;
@@ -58,6 +84,40 @@ pub type NavigationGrid = HashMap<Coordinate, Coordinate>;
 pub struct MonsterTurns {
     safe_nav_grid: NavigationGrid,
     hazard_nav_grid: NavigationGrid,
+    // the safe grid with closed doors treated as open, for units that open them
+    door_nav_grid: NavigationGrid,
+    // tiles next to the player that pack members are holding or heading for
+    claimed_tiles: HashSet<Coordinate>,
+    // player position and ECS nav generation the grids were built for
+    grid_key: Option<(Coordinate, usize)>,
+    // turn component ids of units the player has come across
+    active: HashSet<usize>,
+    player_position: Option<Coordinate>,
+    // blows struck this turn, for listeners
+    events: Vec<GameEvent>,
+}
+
+impl MonsterTurns {
+    fn force_rebuild(&mut self) {
+        self.grid_key = None;
+    }
+
+    // Units in unexplored parts of the floor stay dormant until the player
+    // explores their tile or sees them, then they keep taking turns.
+    fn is_active(&mut self, turn_id: usize, position: Coordinate, ecs: &ECS, map: &GameMap) -> bool {
+        if self.active.contains(&turn_id) {
+            return true;
+        }
+        let in_view = self.player_position.is_some_and(|player| {
+            player.distance(position) <= VISION_RADIUS as f32
+                && los::line_of_sight(player, position, map, ecs)
+        });
+        let reached = in_view || map.explored.borrow().contains(&position);
+        if reached {
+            self.active.insert(turn_id);
+        }
+        reached
+    }
 }
 
 impl System for MonsterTurns {
@@ -66,10 +126,27 @@ impl System for MonsterTurns {
     }
 
     fn run_pre_loop(&mut self, ecs: &ECS, map: &GameMap) {
+        self.player_position = ecs.get_player_position();
         let Some(player_report) = ecs.get_player_report() else {
             return;
         };
         let player_position = player_report.position.data;
+        self.claimed_tiles = map
+            .neighbors(player_position)
+            .into_iter()
+            .filter(|&tile| {
+                ecs.get_all_entities_in_tile(tile)
+                    .into_iter()
+                    .any(|entity_id| ecs.entity_id_has_component(entity_id, ComponentType::Turn))
+            })
+            .collect();
+
+        let grid_key = Some((player_position, ecs.get_nav_generation()));
+        if self.grid_key == grid_key {
+            return;
+        }
+        self.grid_key = grid_key;
+
         let heuristic = |_| 0;
         let ignore_units = true;
         let ignore_doors = false;
@@ -98,44 +175,353 @@ impl System for MonsterTurns {
             ignore_doors,
             ignore_hazards,
         );
+
+        let ignore_doors = true;
+
+        self.door_nav_grid = pathfinding::calculate_pathing_grid(
+            player_position,
+            player_position,
+            map,
+            ecs,
+            heuristic,
+            ignore_units,
+            ignore_doors,
+            ignore_hazards,
+        );
     }
 
     fn run_next(&mut self, components: &[&Component], ecs: &ECS, map: &GameMap) -> Vec<Delta> {
-        if let (Some(Component::Turn(data)), _) =
-            take_component_from_refs(ComponentType::Turn, components)
+        let (maybe_turn, _) = take_component_from_refs(ComponentType::Turn, components);
+        let (maybe_position, _) = take_component_from_refs(ComponentType::Position, components);
+        if let (Some(Component::Turn(data)), Some(Component::Position(position))) =
+            (maybe_turn, maybe_position)
         {
-            data.data.process_turn(components, ecs, map, &self.safe_nav_grid, &self.hazard_nav_grid)
+            if !self.is_active(data.index, position.data, ecs, map) {
+                return vec![];
+            }
+            // a locked door ahead sends even door openers the long way round
+            let through_doors = data.data.opens_doors
+                && !self
+                    .door_nav_grid
+                    .get(&position.data)
+                    .and_then(|&dir| ecs.get_blocking_entity(position.data + dir))
+                    .is_some_and(|door_id| ecs.entity_id_has_component(door_id, ComponentType::Locked));
+            let (safe_grid, hazard_grid) = match through_doors {
+                true => (&self.door_nav_grid, &self.door_nav_grid),
+                false => (&self.safe_nav_grid, &self.hazard_nav_grid),
+            };
+            let (deltas, events) =
+                data.data.process_turn(components, ecs, map, safe_grid, hazard_grid, &mut self.claimed_tiles);
+            self.events.extend(events);
+            deltas
         } else {
             vec![]
         }
     }
+
+    fn new_floor_update(&mut self, _ecs: &ECS, _map: &GameMap) {
+        self.force_rebuild();
+        self.active.clear();
+    }
+
+    fn take_events(&mut self) -> Vec<GameEvent> {
+        self.events.drain(..).collect()
+    }
+}
+
+const ALERT_RADIUS: f32 = 6.0;
+const NOTICE_RADIUS: f32 = 2.0;
+
+#[derive(Default)]
+pub struct Alert {
+    alert_sources: Vec<Coordinate>,
+    player_position: Option<Coordinate>,
+    // keyed by turn component id, entity ids get recycled
+    sleeping: HashSet<usize>,
+    last_health: HashMap<usize, isize>,
+}
+
+impl System for Alert {
+    fn get_requirements(&self) -> ComponentQuery {
+        archetype::TURNTAKER.with(|query| query.clone())
+    }
+
+    fn run_pre_loop(&mut self, ecs: &ECS, _map: &GameMap) {
+        // Monsters that woke up or got hurt since last turn alert their neighbors
+        self.alert_sources.clear();
+        let mut sleeping = HashSet::new();
+        let mut last_health = HashMap::new();
+
+        let query = archetype::TURNTAKER.with(|query| query.clone());
+        for entity in ecs.get_entities_matching_query(&query) {
+            let components = ecs.get_components_from_entity_id(entity.index);
+            let (maybe_turn, components) = take_component_from_refs(ComponentType::Turn, &components);
+            let (maybe_position, components) =
+                take_component_from_refs(ComponentType::Position, &components);
+            let (maybe_health, _components) =
+                take_component_from_refs(ComponentType::Health, &components);
+            let (Some(Component::Turn(turn)), Some(Component::Position(position))) =
+                (maybe_turn, maybe_position)
+            else {
+                continue;
+            };
+
+            let is_asleep = matches!(turn.data.state, AIState::Sleeping(_));
+            if is_asleep {
+                sleeping.insert(turn.index);
+            } else if self.sleeping.contains(&turn.index) {
+                self.alert_sources.push(position.data);
+            }
+
+            if let Some(Component::Health(health)) = maybe_health {
+                let hurt = self
+                    .last_health
+                    .get(&turn.index)
+                    .is_some_and(|old| health.data.current < *old);
+                if hurt {
+                    self.alert_sources.push(position.data);
+                }
+                last_health.insert(turn.index, health.data.current);
+            }
+        }
+
+        // A visible player sneaking too close also counts
+        if let (Some(position), false) = (ecs.get_player_position(), behavior::is_player_invisible(ecs)) {
+            self.player_position = Some(position);
+        } else {
+            self.player_position = None;
+        }
+
+        self.sleeping = sleeping;
+        self.last_health = last_health;
+    }
+
+    fn run_next(&mut self, components: &[&Component], ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+        let (maybe_turn, components) = take_component_from_refs(ComponentType::Turn, components);
+        let (maybe_position, _components) =
+            take_component_from_refs(ComponentType::Position, &components);
+        let (Some(Component::Turn(turn)), Some(Component::Position(position))) =
+            (maybe_turn, maybe_position)
+        else {
+            return vec![];
+        };
+        let AIState::Sleeping(duration) = turn.data.state else {
+            return vec![];
+        };
+
+        let can_see = |source: Coordinate| {
+            source == position.data || los::line_of_sight(source, position.data, map, ecs)
+        };
+        let alerted = self
+            .alert_sources
+            .iter()
+            .any(|source| source.distance(position.data) <= ALERT_RADIUS && can_see(*source));
+        // magical sleep only breaks on a disturbance, not by seeing the player
+        let natural_sleep = duration < 0;
+        let noticed = natural_sleep && self.player_position.is_some_and(|player| {
+            player.distance(position.data) <= NOTICE_RADIUS && can_see(player)
+        });
+        if !(alerted || noticed) {
+            return vec![];
+        }
+
+        let new_turn = TurnTaker {
+            state: AIState::Alert,
+            ..turn.data.clone()
+        };
+        vec![Delta::Change(Component::Turn(turn.make_change(new_turn)))]
+    }
+
+    fn new_floor_update(&mut self, _ecs: &ECS, _map: &GameMap) {
+        self.alert_sources.clear();
+        self.sleeping.clear();
+        self.last_health.clear();
+    }
 }
 
 #[derive(Default)]
-pub struct PlayerCheck {}
+pub struct Hunger {}
 
-impl System for PlayerCheck {
+impl System for Hunger {
     fn get_requirements(&self) -> ComponentQuery {
-        archetype::PLAYER.with(|query| query.clone())
+        ComponentQuery {
+            required: vec![ComponentType::Hunger, ComponentType::Health],
+            optional: vec![],
+        }
     }
 
     fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
-        let Some(self_report) = archetype::make_unit_report(components) else {
+        let (maybe_hunger, components) =
+            take_component_from_refs(ComponentType::Hunger, components);
+        let (maybe_health, _components) =
+            take_component_from_refs(ComponentType::Health, &components);
+        let (Some(Component::Hunger(hunger)), Some(Component::Health(health))) = (maybe_hunger, maybe_health) else {
             return vec![];
         };
-        if let Some(stats) = self_report.stats {
-            if stats.data.xp >= get_xp_to_next(&stats.data) {
-                let new_level = stats.data.level + 1;
-                logger::log_message(&format!("You have reached level {}!", new_level));
-                return vec![Delta::Change(Component::Attributes(stats.make_change(
-                    Attributes {
-                        level_pending: true,
-                        ..Default::default()
-                    },
-                )))];
+
+        if hunger.data.is_starving() {
+            logger::log_message_with("You are starving!", logger::MessageKind::Warning);
+            let damage = Health { current: -1, max: 0 };
+            let mut delta = vec![Delta::Change(Component::Health(health.make_change(damage)))];
+            delta.extend(stats::record_death_cause(&components, &health.data, 1, || "Starved to death".to_string()));
+            return delta;
+        }
+
+        if hunger.data.current == hunger::HUNGRY_THRESHOLD {
+            logger::log_message_with("You are getting hungry.", logger::MessageKind::Warning);
+        }
+        let tick = hunger::Hunger { current: -1, max: 0 };
+        vec![Delta::Change(Component::Hunger(hunger.make_change(tick)))]
+    }
+}
+
+const LAVA_DAMAGE: isize = 2;
+
+#[derive(Default)]
+pub struct Terrain {}
+
+impl System for Terrain {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery::new_single(ComponentType::Position)
+    }
+
+    fn run_next(&mut self, components: &[&Component], ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+        let (Some(Component::Position(position)), _) = take_component_from_refs(ComponentType::Position, components) else {
+            return vec![];
+        };
+        let in_water = map.tile_is_water(position.data);
+        let in_lava = map.tile_is_lava(position.data);
+        if !(in_water || in_lava) || behavior::is_levitating(components) {
+            return vec![];
+        }
+        let name = match take_component_from_refs(ComponentType::Name, components) {
+            (Some(Component::Name(name)), _) => Some(name.data.subject()),
+            _ => None,
+        };
+
+        if in_water {
+            let Some(burning) = find_effect(components, |effect| effect == EffectType::Burning) else {
+                return vec![];
+            };
+            if let Some(name) = name {
+                logger::log_message(&[&name, "is doused."].join(" "));
             }
+            return vec![Delta::DeleteComponent(DeleteComponentOrder{component_id: burning.index, entity_id: None})];
         }
-        vec![]
+
+        let (Some(Component::Health(health)), _) = take_component_from_refs(ComponentType::Health, components) else {
+            return vec![];
+        };
+        let Some(entity_id) = ecs.get_entity_id_from_component_id(position.index) else {
+            return vec![];
+        };
+        if let Some(name) = name {
+            logger::log_message(&[&name, "is burned by lava."].join(" "));
+        }
+        let fire = InteractionEvent {
+            event_type: EventType::Fire,
+            attack: None,
+            payload: vec![],
+        };
+        let damage = Health { current: -LAVA_DAMAGE, max: 0 };
+        let mut delta = propagate_event(&fire, entity_id, ecs);
+        delta.push(Delta::Change(Component::Health(health.make_change(damage))));
+        delta.extend(stats::record_death_cause(components, &health.data, LAVA_DAMAGE, || "Burned by lava".to_string()));
+        delta
+    }
+}
+
+#[derive(Default)]
+pub struct Projectiles {}
+
+impl System for Projectiles {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery {
+            required: vec![ComponentType::Projectile, ComponentType::Position],
+            optional: vec![],
+        }
+    }
+
+    fn run_next(&mut self, components: &[&Component], ecs: &ECS, map: &GameMap) -> Vec<Delta> {
+        let (maybe_projectile, components) =
+            take_component_from_refs(ComponentType::Projectile, components);
+        let (maybe_position, _components) =
+            take_component_from_refs(ComponentType::Position, &components);
+        let (Some(Component::Projectile(projectile)), Some(Component::Position(position))) =
+            (maybe_projectile, maybe_position)
+        else {
+            return vec![];
+        };
+        let Some(entity_id) = ecs.get_entity_id_from_component_id(projectile.index) else {
+            return vec![];
+        };
+        let remove_self = Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(entity_id));
+        // a miss at point blank has nowhere to fly
+        if projectile.data.path.is_empty() {
+            logger::log_message("The shot misses.");
+            return vec![remove_self];
+        }
+
+        let mut current = position.data;
+        for (steps, &tile) in projectile.data.path.iter().take(projectile.data.speed).enumerate() {
+            if !map.is_tile_passable(tile) {
+                logger::log_message("The shot hits a wall.");
+                return vec![remove_self];
+            }
+            if let Some(target) = ecs.get_blocking_entity(tile) {
+                if projectile.data.astray {
+                    let name = match ecs.get_component_from_entity_id(target, ComponentType::Name) {
+                        Some(Component::Name(name)) => name.data.definite(),
+                        _ => "something".to_string(),
+                    };
+                    logger::log_message(&format!("The shot goes astray and hits {}!", name));
+                }
+                let mut delta = propagate_event(&projectile.data.event, target, ecs);
+                delta.push(remove_self);
+                return delta;
+            }
+            current = tile;
+
+            if steps + 1 == projectile.data.path.len() {
+                logger::log_message("The shot misses.");
+                return vec![remove_self];
+            }
+        }
+
+        let advanced = projectile.data.advanced(projectile.data.speed);
+        vec![
+            Delta::Change(Component::Position(position.make_change(current - position.data))),
+            Delta::Change(Component::Projectile(projectile.make_change(advanced))),
+        ]
+    }
+}
+
+#[derive(Default)]
+pub struct ManaRegen {
+    turns: usize,
+}
+
+impl System for ManaRegen {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery::new_single(ComponentType::Attributes)
+    }
+
+    fn run_pre_loop(&mut self, _ecs: &ECS, _map: &GameMap) {
+        self.turns += 1;
+    }
+
+    fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+        if !self.turns.is_multiple_of(attributes::MANA_REGEN_TURNS) {
+            return vec![];
+        }
+        let (Some(Component::Attributes(stats)), _) = take_component_from_refs(ComponentType::Attributes, components) else {
+            return vec![];
+        };
+        if stats.data.mana >= stats.data.mana_max {
+            return vec![];
+        }
+        let regen = Attributes { mana: 1, level_pending: stats.data.level_pending, ..Default::default() };
+        vec![Delta::Change(Component::Attributes(stats.make_change(regen)))]
     }
 }
 
@@ -173,7 +559,7 @@ impl System for Exploration {
         if col_data.data == Collision::Walkable && !self.open_doors.contains(&door_id) {
             map.explore_room(pos_data.data);
             // the floodfill covers hallways and miss-generated areas
-            map.explore_flood_fill(pos_data.data, ecs);
+            map.explore_flood_fill(pos_data.data, ecs, EXPLORE_FILL_BUDGET);
             self.open_doors.insert(door_id);
         }
         return vec![];
@@ -195,14 +581,7 @@ impl System for Fire {
     }
 
     fn run_next(&mut self, components: &[&Component], ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
-        let (maybe_burning, components) =
-            take_component_from_refs(ComponentType::DurationEffect, components);
-        let Some(Component::DurationEffect(indexed_effect)) = maybe_burning else {
-            return vec![];
-        };
-
-        let DurationEffect(_, effect) = indexed_effect.data;
-        let EffectType::Burning = effect else {
+        let Some(indexed_effect) = find_effect(components, |effect| effect == EffectType::Burning) else {
             return vec![];
         };
 
@@ -244,14 +623,7 @@ impl System for Acid {
     }
 
     fn run_next(&mut self, components: &[&Component], ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
-        let (maybe_acid, _components) =
-            take_component_from_refs(ComponentType::DurationEffect, components);
-        let Some(Component::DurationEffect(indexed_effect)) = maybe_acid else {
-            return vec![];
-        };
-
-        let DurationEffect(_, effect) = indexed_effect.data;
-        let EffectType::Acid = effect else {
+        let Some(indexed_effect) = find_effect(components, |effect| effect == EffectType::Acid) else {
             return vec![];
         };
 
@@ -265,18 +637,92 @@ impl System for Acid {
                 let damage_data = Health {current: -2, ..Default::default()};
 
                 if let Some(Component::Name(name_data)) = maybe_name {
-                    logger::log_message(&[&name_data.data.raw, "is burned by acid."].join(" "));
+                    logger::log_message(&[&name_data.data.subject(), "is burned by acid."].join(" "));
                 };
 
                 delta.push(
                     Delta::Change(Component::Health(health.make_change(damage_data)))
                 );
+                delta.extend(stats::record_death_cause(components, &health.data, 2, || "Dissolved by acid".to_string()));
             }
         };
         delta
     }
 }
 
+// Each tick hurts as much as there are stacks, then one of them closes up.
+#[derive(Default)]
+pub struct Bleed {}
+impl System for Bleed {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery {
+            required: vec![ComponentType::DurationEffect, ComponentType::Health],
+            optional: vec![],
+        }
+    }
+
+    fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+        let Some(indexed_effect) = find_effect(components, |effect| matches!(effect, EffectType::Bleed(_))) else {
+            return vec![];
+        };
+        let DurationEffect(_, EffectType::Bleed(stacks)) = indexed_effect.data else {
+            return vec![];
+        };
+        let (Some(Component::Health(health)), _) = take_component_from_refs(ComponentType::Health, components) else {
+            return vec![];
+        };
+        if stacks <= 0 {
+            return vec![];
+        }
+
+        if let (Some(Component::Name(name)), _) = take_component_from_refs(ComponentType::Name, components) {
+            logger::log_message(&format!("{} bleeds for {} damage.", name.data.subject(), stacks));
+        }
+        let mut delta = vec![
+            Delta::Change(Component::Health(health.make_change(Health { current: -stacks, max: 0 }))),
+            Delta::Change(Component::DurationEffect(indexed_effect.make_change(DurationEffect(0, EffectType::Bleed(-1))))),
+        ];
+        delta.extend(stats::record_death_cause(components, &health.data, stacks, || "Bled to death".to_string()));
+        delta
+    }
+}
+
+#[derive(Default)]
+pub struct Regen {}
+impl System for Regen {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery {
+            required: vec![ComponentType::DurationEffect],
+            optional: vec![],
+        }
+    }
+
+    fn run_next(&mut self, components: &[&Component], ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+        let Some(indexed_effect) = find_effect(components, |effect| matches!(effect, EffectType::Regen(_))) else {
+            return vec![];
+        };
+        let DurationEffect(_, EffectType::Regen(amount)) = indexed_effect.data else {
+            return vec![];
+        };
+        // fire shuts down regeneration while it lasts
+        if find_effect(components, |effect| effect == EffectType::Burning).is_some() {
+            return vec![];
+        }
+        let Some(entity_id) = ecs.get_entity_id_from_component_id(indexed_effect.index) else {
+            return vec![];
+        };
+
+        let Some(Component::Health(health)) = ecs.get_component_from_entity_id(entity_id, ComponentType::Health) else {
+            return vec![];
+        };
+        let healed = amount.min(health.data.max - health.data.current);
+        if healed <= 0 {
+            return vec![];
+        }
+        vec![Delta::Change(Component::Health(health.make_change(Health { current: healed, max: 0 })))]
+    }
+}
+
 #[derive(Default)]
 pub struct Stoneskin {}
 impl System for Stoneskin {
@@ -288,16 +734,11 @@ impl System for Stoneskin {
     }
 
     fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
-        let (maybe_burning, components) =
-            take_component_from_refs(ComponentType::DurationEffect, components);
-        let Some(Component::DurationEffect(indexed_effect)) = maybe_burning else {
+        let Some(indexed_effect) = find_effect(components, |effect| effect == EffectType::Stoneskin) else {
             return vec![];
         };
 
-        let DurationEffect(duration, effect) = indexed_effect.data;
-        let EffectType::Stoneskin = effect else {
-            return vec![];
-        };
+        let DurationEffect(duration, _) = indexed_effect.data;
 
         if duration == 0 {
             let (Some(Component::BumpResponse(melee_response)), components) = take_component_from_refs(ComponentType::BumpResponse, &components) else {
@@ -318,8 +759,8 @@ impl System for Stoneskin {
 }
 
 #[derive(Default)]
-pub struct Duration {}
-impl System for Duration {
+pub struct Shield {}
+impl System for Shield {
     fn get_requirements(&self) -> ComponentQuery {
         ComponentQuery {
             required: vec![ComponentType::DurationEffect],
@@ -328,45 +769,124 @@ impl System for Duration {
     }
 
     fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
-        // bug here, if you have more than one duration effect this might not happen
-        let (maybe_effect, _components) =
-            take_component_from_refs(ComponentType::DurationEffect, components);
-        let Some(Component::DurationEffect(indexed_effect)) = maybe_effect else {
+        let Some(indexed_effect) = find_effect(components, |effect| matches!(effect, EffectType::Shield(_))) else {
             return vec![];
         };
-        let (maybe_name, _components) =
-            take_component_from_refs(ComponentType::Name, components);
+        let DurationEffect(_, EffectType::Shield(left)) = indexed_effect.data else {
+            return vec![];
+        };
+        if left > 0 {
+            return vec![];
+        }
 
-        let DurationEffect(duration, effect) = indexed_effect.data;
+        if let (Some(Component::Name(name)), _) = take_component_from_refs(ComponentType::Name, components) {
+            logger::log_message(&[&name.data.subject(), "'s shield breaks."].join(""));
+        }
+        vec![Delta::DeleteComponent(DeleteComponentOrder{component_id: indexed_effect.index, entity_id: None})]
+    }
+}
 
-        if duration == 0 {
-            let action = match effect {
-                EffectType::Burning => {
-                    "stops burning."
-                },
-                EffectType::Levitate => {
-                    "stops levitating."
-                },
-                EffectType::Invisible => {
-                    "is no longer invisible."
-                },
-                EffectType::Stoneskin => {
-                    "lost stoneskin."
-                },
-                _ => {"lost an effect."}
-            };
-            match maybe_name {
-                Some(Component::Name(name)) => logger::log_message(&[&name.data.raw, action].join(" ")),
-                _ => {}
-            };
-            vec![Delta::DeleteComponent(DeleteComponentOrder{component_id: indexed_effect.index, entity_id: None})]
-        } else {
-            vec![Delta::Change(Component::DurationEffect(indexed_effect.make_change(DurationEffect(-1, effect))))]
+#[derive(Default)]
+pub struct Duration {}
+impl System for Duration {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery {
+            required: vec![ComponentType::DurationEffect],
+            optional: vec![],
         }
+    }
+
+    fn run_next(&mut self, components: &[&Component], ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+        let (maybe_name, _components) =
+            take_component_from_refs(ComponentType::Name, components);
 
+        duration_effects(components)
+            .filter_map(|indexed_effect| {
+                let DurationEffect(duration, effect) = indexed_effect.data;
+                // depleted shields are cleaned up by their own system
+                if let EffectType::Shield(left) = effect {
+                    if left <= 0 {
+                        return None;
+                    }
+                }
+                // bleeding runs on stacks rather than time
+                if let EffectType::Bleed(stacks) = effect {
+                    if stacks > 0 {
+                        return None;
+                    }
+                }
+                if duration != 0 {
+                    return Some(Delta::Change(Component::DurationEffect(indexed_effect.make_change(DurationEffect(-1, EffectType::None)))));
+                }
+
+                let action = match effect {
+                    EffectType::Burning => {
+                        "stops burning."
+                    },
+                    EffectType::Levitate => {
+                        "stops levitating."
+                    },
+                    EffectType::Invisible => {
+                        "is no longer invisible."
+                    },
+                    EffectType::Stoneskin => {
+                        "lost stoneskin."
+                    },
+                    EffectType::Frozen => {
+                        "thaws out."
+                    },
+                    EffectType::Haste => {
+                        "slows down."
+                    },
+                    EffectType::Confused => {
+                        "is no longer confused."
+                    },
+                    EffectType::Regen(_) => {
+                        "stops regenerating."
+                    },
+                    EffectType::Shield(_) => {
+                        "lost their shield."
+                    },
+                    EffectType::Bleed(_) => {
+                        "stops bleeding."
+                    },
+                    EffectType::Disarmed => {
+                        "is no longer disarmed."
+                    },
+                    EffectType::Summoned => {
+                        "fades away."
+                    },
+                    EffectType::Grappled => {
+                        "breaks free."
+                    },
+                    _ => {"lost an effect."}
+                };
+                match maybe_name {
+                    Some(Component::Name(name)) => logger::log_message(&[&name.data.subject(), action].join(" ")),
+                    _ => {}
+                };
+                if let EffectType::Summoned = effect {
+                    let entity_id = ecs.get_entity_id_from_component_id(indexed_effect.index)?;
+                    return Some(Delta::DeleteEntity(DeleteEntityOrder::new_from_entity(entity_id)));
+                }
+                Some(Delta::DeleteComponent(DeleteComponentOrder{component_id: indexed_effect.index, entity_id: None}))
+            })
+            .collect()
     }
 }
 
+// entities can carry several effects at once
+fn duration_effects<'a, 'b>(components: &'b [&'a Component]) -> impl Iterator<Item = &'a IndexedData<DurationEffect>> + 'b where 'a: 'b {
+    components.iter().filter_map(|component| match component {
+        Component::DurationEffect(indexed_effect) => Some(indexed_effect),
+        _ => None,
+    })
+}
+
+fn find_effect<'a>(components: &[&'a Component], matches: fn(EffectType) -> bool) -> Option<&'a IndexedData<DurationEffect>> {
+    duration_effects(components).find(|indexed_effect| matches(indexed_effect.data.1))
+}
+
 #[derive(Default)]
 pub struct Cooldowns {}
 impl System for Cooldowns {
@@ -386,4 +906,145 @@ impl System for Cooldowns {
             })
             .collect()
     }
-}
\ No newline at end of file
+}
+
+// The player stocks up on arrows between floors.
+#[derive(Default)]
+pub struct Restock {}
+impl System for Restock {
+    fn get_requirements(&self) -> ComponentQuery {
+        ComponentQuery::new()
+            .require(ComponentType::Player)
+            .require(ComponentType::Combat)
+    }
+
+    fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+        let Some(combat) = find_typed::<Combat, _>(components) else {
+            return vec![];
+        };
+        match combat.data.restock_ammo() {
+            Some(restocked) => vec![Delta::Change(Component::Combat(combat.make_change(restocked)))],
+            None => vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SpellCooldowns {}
+impl System for SpellCooldowns {
+    fn get_requirements(&self) -> ComponentQuery {
+        archetype::CASTER.with(|query| query.clone())
+    }
+
+    fn run_next(&mut self, components: &[&Component], _ecs: &ECS, _map: &GameMap) -> Vec<Delta> {
+        components
+            .iter()
+            .filter_map(|component| match component {
+                Component::Spell(spell_index) if matches!(spell_index.data.castable, CooldownState::OnCooldown(_)) => {
+                    Some(Delta::Change(Component::Spell(spell_index.make_change(spell_index.data.tick_cooldown()))))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    use crate::ecs::ecs::{EntityIdentifier, MakeComponentOrder};
+    use crate::game::{action::PlayerAction, testing::{self, PLAYER_START}};
+
+    fn player_health(ecs: &ECS) -> Health {
+        ecs.get_typed::<Health>(ecs.get_player_id()).unwrap().data
+    }
+
+    #[test]
+    fn idle_player_starves() {
+        let mut game = testing::arena();
+        let full_health = player_health(&game.ecs).current;
+        for _ in 0..hunger::HUNGER_MAX {
+            game.apply_action(PlayerAction::Wait);
+        }
+        assert_eq!(player_health(&game.ecs).current, full_health);
+        testing::drain_log();
+
+        game.apply_action(PlayerAction::Wait);
+        game.apply_action(PlayerAction::Wait);
+        assert!(player_health(&game.ecs).current < full_health);
+        assert!(testing::drain_log().iter().any(|message| message.starts_with("You are starving!")));
+    }
+
+    #[test]
+    fn point_blank_misses_dont_linger() {
+        let mut game = testing::arena();
+        let target = PLAYER_START + Coordinate { x: 1, y: 1 };
+        let doggo = testing::spawn(&mut game, "Doggo", target);
+        let full_health = game.ecs.get_typed::<Health>(doggo).unwrap().data.current;
+
+        // a zero roll always misses
+        game.shoot_command(target, &mut StepRng::new(0, 0));
+        assert!(game.ecs.get_all_components(&ComponentType::Projectile).is_empty());
+        assert_eq!(game.ecs.get_typed::<Health>(doggo).unwrap().data.current, full_health);
+    }
+
+    #[test]
+    fn bleed_tapers_off_a_stack_at_a_time() {
+        let mut game = testing::arena();
+        let doggo = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 6, y: 0 });
+        game.ecs.apply_change(Delta::MakeComponent(MakeComponentOrder {
+            component: Component::DurationEffect(IndexedData::new_with(DurationEffect(0, EffectType::Bleed(3)))),
+            entity: EntityIdentifier::new_from_entity(doggo),
+        }));
+
+        let mut bleed = Bleed::default();
+        let mut losses = vec![];
+        for _ in 0..4 {
+            let before = game.ecs.get_typed::<Health>(doggo).unwrap().data.current;
+            let components = game.ecs.get_components_from_entity_id(doggo);
+            let deltas = bleed.run_next(&components, &game.ecs, &game.map);
+            game.ecs.apply_changes(deltas);
+            losses.push(before - game.ecs.get_typed::<Health>(doggo).unwrap().data.current);
+        }
+        assert_eq!(losses, vec![3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn disarmed_monsters_bite_once_it_wears_off() {
+        let mut game = testing::arena();
+        let full_health = player_health(&game.ecs).current;
+        let doggo = testing::spawn(&mut game, "Doggo", PLAYER_START + Coordinate { x: 1, y: 0 });
+        game.ecs.apply_change(Delta::MakeComponent(MakeComponentOrder {
+            component: Component::DurationEffect(IndexedData::new_with(DurationEffect(3, EffectType::Disarmed))),
+            entity: EntityIdentifier::new_from_entity(doggo),
+        }));
+        for _ in 0..2 {
+            game.apply_action(PlayerAction::Wait);
+            assert_eq!(player_health(&game.ecs).current, full_health);
+        }
+        for _ in 0..10 {
+            game.apply_action(PlayerAction::Wait);
+        }
+        assert!(player_health(&game.ecs).current < full_health);
+    }
+
+    #[test]
+    fn nav_grids_are_reused_while_nothing_changes() {
+        let mut game = testing::arena();
+        let mut turns = MonsterTurns::default();
+        turns.run_pre_loop(&game.ecs, &game.map);
+        assert!(!turns.safe_nav_grid.is_empty());
+
+        // a reused grid stays exactly as it was left, even emptied out
+        turns.safe_nav_grid.clear();
+        game.apply_action(PlayerAction::Wait);
+        turns.run_pre_loop(&game.ecs, &game.map);
+        assert!(turns.safe_nav_grid.is_empty());
+
+        testing::wall(&mut game, PLAYER_START + Coordinate { x: 3, y: 3 });
+        turns.run_pre_loop(&game.ecs, &game.map);
+        assert!(!turns.safe_nav_grid.is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashSet;
+
+use crate::{
+    ecs::ecs::{Delta, EntityIdentifier, IndexedData, MakeComponentOrder},
+    game::{
+        components::{core::Component, spells::Spell},
+        core::Game,
+        difficulty::Difficulty,
+        spawning::OBJECT_SPAWN_NAMES,
+    },
+    map::{
+        tile::{GameTile, FLOOR_TILE_ID, WALL_TILE_ID},
+        utils::Coordinate,
+    },
+    utils::logger,
+};
+
+pub const PLAYER_START: Coordinate = Coordinate { x: 4, y: 4 };
+
+// A floor that is one big walled room with only the player in it.
+pub fn arena() -> Game {
+    let mut game = Game::new(32, 18, Difficulty::default());
+    let player_id = game.ecs.get_player_id();
+    let (width, height) = (game.map.width as i32, game.map.height as i32);
+    for x in 0..width {
+        for y in 0..height {
+            let coord = Coordinate { x, y };
+            for entity_id in game.ecs.get_all_entities_in_tile(coord) {
+                if entity_id != player_id {
+                    game.ecs.remove_entity(entity_id);
+                }
+            }
+            let on_edge = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+            let root_tile = if on_edge { WALL_TILE_ID } else { FLOOR_TILE_ID };
+            game.map.set_game_tile(coord, GameTile { root_tile });
+        }
+    }
+    game.ecs.set_player_position(PLAYER_START);
+    game.ecs.mark_terrain_changed();
+    drain_log();
+    game
+}
+
+// Spawns by name and returns the new entity's id.
+pub fn spawn(game: &mut Game, name: &str, coord: Coordinate) -> usize {
+    let before: HashSet<usize> = game.ecs.get_all_entities_in_tile(coord).into_iter().collect();
+    let make = OBJECT_SPAWN_NAMES.get(name).expect("unknown spawn name");
+    make(&mut game.ecs, coord, 1, game.map.difficulty);
+    game.ecs
+        .get_all_entities_in_tile(coord)
+        .into_iter()
+        .find(|entity_id| !before.contains(entity_id))
+        .expect("nothing spawned")
+}
+
+// Teaches the player a spell and returns its index in the spell list.
+pub fn give_spell(game: &mut Game, spell: Spell) -> i32 {
+    game.ecs.apply_change(Delta::MakeComponent(MakeComponentOrder {
+        component: Component::Spell(IndexedData::new_with(spell)),
+        entity: EntityIdentifier::new_from_entity(game.ecs.get_player_id()),
+    }));
+    game.ecs.get_player_spells().len() as i32 - 1
+}
+
+pub fn wall(game: &mut Game, coord: Coordinate) {
+    game.map.set_game_tile(coord, GameTile { root_tile: WALL_TILE_ID });
+    game.ecs.mark_terrain_changed();
+}
+
+pub fn drain_log() -> Vec<String> {
+    let mut messages = vec![];
+    logger::LOG.with(|log| {
+        while let Some((message, _)) = log.next_message() {
+            messages.push(message);
+        }
+    });
+    messages
+}
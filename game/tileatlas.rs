@@ -0,0 +1,167 @@
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::game::components::core::ImageData;
+
+const DEFAULT_TILE_SIZE: f32 = 32.0;
+
+// What each image id on the frontend depicts, the ids index TileImages in the slint file.
+const DEFAULT_IMAGES: &[(&str, ImageData)] = &[
+    // units
+    ("player", ImageData { id: 3, depth: 5 }),
+    ("doggo", ImageData { id: 6, depth: 5 }),
+    ("heavy", ImageData { id: 11, depth: 5 }),
+    ("cultist", ImageData { id: 12, depth: 5 }),
+    ("skeleton", ImageData { id: 13, depth: 5 }),
+    ("bat", ImageData { id: 23, depth: 5 }),
+    ("minotaur", ImageData { id: 27, depth: 5 }),
+    ("troll", ImageData { id: 28, depth: 5 }),
+    ("necromancer", ImageData { id: 33, depth: 5 }),
+    ("spirit_wolf", ImageData { id: 34, depth: 5 }),
+    ("orc_warlord", ImageData { id: 37, depth: 5 }),
+    ("eye_tyrant", ImageData { id: 38, depth: 5 }),
+    ("stone_colossus", ImageData { id: 39, depth: 5 }),
+    ("vampire", ImageData { id: 41, depth: 5 }),
+    ("raptor", ImageData { id: 51, depth: 5 }),
+    ("shopkeeper", ImageData { id: 56, depth: 5 }),
+    ("ghoul", ImageData { id: 59, depth: 5 }),
+    ("rat", ImageData { id: 20, depth: 6 }),
+    ("critters", ImageData { id: 21, depth: 6 }),
+    ("mushroom", ImageData { id: 22, depth: 6 }),
+    // furniture
+    ("secret_door_wall", ImageData { id: 2, depth: 7 }),
+    ("chest_closed", ImageData { id: 7, depth: 7 }),
+    ("chest_open", ImageData { id: 8, depth: 7 }),
+    ("door_closed", ImageData { id: 9, depth: 7 }),
+    ("door_open", ImageData { id: 10, depth: 7 }),
+    ("stairs_down", ImageData { id: 16, depth: 7 }),
+    // things on the floor
+    ("corpse", ImageData { id: 14, depth: 6 }),
+    ("carcass", ImageData { id: 14, depth: 6 }),
+    ("gold_pile", ImageData { id: 15, depth: 6 }),
+    ("drop_pile", ImageData { id: 15, depth: 6 }),
+    ("spikes", ImageData { id: 17, depth: 6 }),
+    ("flame", ImageData { id: 18, depth: 6 }),
+    ("acid", ImageData { id: 24, depth: 6 }),
+    ("smoke", ImageData { id: 58, depth: 4 }),
+    ("health_potion", ImageData { id: 25, depth: 6 }),
+    ("fire_bomb", ImageData { id: 26, depth: 6 }),
+    ("smoke_bomb", ImageData { id: 60, depth: 6 }),
+    ("ration", ImageData { id: 29, depth: 6 }),
+    ("dart_trap", ImageData { id: 35, depth: 6 }),
+    ("pit_trap", ImageData { id: 36, depth: 6 }),
+    ("key", ImageData { id: 40, depth: 6 }),
+    ("rusty_sword", ImageData { id: 54, depth: 6 }),
+    ("leather_armor", ImageData { id: 55, depth: 6 }),
+    ("scroll", ImageData { id: 57, depth: 6 }),
+    ("projectile", ImageData { id: 30, depth: 8 }),
+    // effect overlays
+    ("burning_overlay", ImageData { id: 19, depth: 6 }),
+    ("stoneskin_overlay", ImageData { id: 42, depth: 6 }),
+    ("levitate_overlay", ImageData { id: 43, depth: 6 }),
+    ("acid_overlay", ImageData { id: 44, depth: 6 }),
+    ("frozen_overlay", ImageData { id: 45, depth: 6 }),
+    ("haste_overlay", ImageData { id: 46, depth: 6 }),
+    ("confused_overlay", ImageData { id: 47, depth: 6 }),
+    ("regen_overlay", ImageData { id: 48, depth: 6 }),
+    ("shield_overlay", ImageData { id: 49, depth: 6 }),
+    ("invisible_overlay", ImageData { id: 50, depth: 6 }),
+    ("bleed_overlay", ImageData { id: 52, depth: 6 }),
+    ("disarmed_overlay", ImageData { id: 53, depth: 6 }),
+];
+
+thread_local! {
+    static ATLAS: RefCell<TileAtlas> = RefCell::new(TileAtlas::default());
+}
+
+// Looks up images by name, so a reskin only has to touch the atlas.
+#[derive(Debug, Clone)]
+pub struct TileAtlas {
+    pub tile_size: f32,
+    images: HashMap<String, ImageData>,
+}
+
+impl TileAtlas {
+    pub fn get(&self, name: &str) -> Option<ImageData> {
+        self.images.get(name).copied()
+    }
+
+    pub fn set(&mut self, name: &str, image: ImageData) {
+        self.images.insert(name.to_string(), image);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.images.keys().map(|name| name.as_str())
+    }
+}
+
+impl TileAtlas {
+    // One "name id depth" per line, only names the atlas already knows can be reskinned.
+    pub fn with_overrides(mut self, text: &str) -> Result<Self, String> {
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let [name, id, depth] = parts[..] else {
+                return Err(format!("expected \"name id depth\", got \"{}\"", line));
+            };
+            if !self.names().any(|known| known == name) {
+                return Err(format!("no image named {} in the tile atlas", name));
+            }
+            let (Ok(id), Ok(depth)) = (id.parse(), depth.parse()) else {
+                return Err(format!("bad id or depth for {}", name));
+            };
+            self.set(name, ImageData { id, depth });
+        }
+        Ok(self)
+    }
+}
+
+impl Default for TileAtlas {
+    fn default() -> Self {
+        TileAtlas {
+            tile_size: DEFAULT_TILE_SIZE,
+            images: DEFAULT_IMAGES
+                .iter()
+                .map(|&(name, image)| (name.to_string(), image))
+                .collect(),
+        }
+    }
+}
+
+// An unknown name is a typo in the calling code, not something to recover from.
+pub fn image(name: &str) -> ImageData {
+    ATLAS
+        .with(|atlas| atlas.borrow().get(name))
+        .unwrap_or_else(|| panic!("no image named {} in the tile atlas", name))
+}
+
+pub fn tile_size() -> f32 {
+    ATLAS.with(|atlas| atlas.borrow().tile_size)
+}
+
+pub fn set_atlas(atlas: TileAtlas) {
+    ATLAS.with(|current| *current.borrow_mut() = atlas);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::{spawning::OBJECT_SPAWN_NAMES, testing}, map::utils::Coordinate};
+
+    #[test]
+    fn every_spawn_has_an_image() {
+        // image() panics on names the atlas doesn't know
+        let mut game = testing::arena();
+        for (i, name) in OBJECT_SPAWN_NAMES.keys().enumerate() {
+            let coord = Coordinate { x: 2 + (i as i32 % 14) * 2, y: 2 + (i as i32 / 14) * 3 };
+            testing::spawn(&mut game, name, coord);
+        }
+    }
+
+    #[test]
+    fn overrides_only_touch_known_names() {
+        let atlas = TileAtlas::default().with_overrides("# reskin\ndoggo 60 5\n").unwrap();
+        assert_eq!(atlas.get("doggo").map(|image| image.id), Some(60));
+        assert_eq!(atlas.get("rat").map(|image| image.id), Some(20));
+        assert!(TileAtlas::default().with_overrides("doge 60 5").is_err());
+        assert!(TileAtlas::default().with_overrides("doggo sixty 5").is_err());
+    }
+}
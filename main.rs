@@ -1,8 +1,18 @@
+use crate::ecs::event::EventType;
+use crate::game::action::{GameEvent, MinimapCell, PlayerAction, TurnOutcome};
+use crate::game::components::{attributes::XpCurve, core::ComponentType};
 use crate::game::core::Game;
+use crate::game::difficulty::Difficulty;
+use crate::game::input::{InputAction, InputMap};
+use crate::game::tileatlas;
 
 use map::utils::Coordinate;
+use slint::{Model, Timer, TimerMode};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
 use utils::logger::MessageLog;
-use utils::logger::LOG;
+use utils::logger::{self, MessageKind, LOG};
 
 mod ecs;
 mod game;
@@ -15,10 +25,49 @@ slint::include_modules!();
 
 const GRID_WIDTH: usize = (16.0 * 2.0) as usize;
 const GRID_HEIGHT: usize = (9.0 * 2.0) as usize;
-const TILESET_SIZE: f32 = 32.0;
+const LOG_LENGTH: usize = 50;
+const TRAVEL_STEP_MS: u64 = 80;
+const HEADLESS_MAX_ACTIONS: usize = 5000;
 
 fn main() {
-    let game = Game::new(GRID_WIDTH, GRID_HEIGHT);
+    // `--headless <games>` plays bot games without a window and prints how they went
+    let args: Vec<String> = std::env::args().collect();
+    let difficulty = arg_value(&args, "--difficulty")
+        .and_then(Difficulty::from_name)
+        .unwrap_or_default();
+    // `--xp-growth <power>` makes each level cost more than the last
+    let xp_curve = XpCurve {
+        growth: arg_value(&args, "--xp-growth")
+            .and_then(|growth| growth.parse().ok())
+            .unwrap_or(XpCurve::default().growth),
+        ..Default::default()
+    };
+    // `--atlas <file>` reskins images, see TileAtlas::with_overrides
+    if let Some(path) = arg_value(&args, "--atlas") {
+        let overrides = std::fs::read_to_string(path).map_err(|err| err.to_string());
+        match overrides.and_then(|text| tileatlas::TileAtlas::default().with_overrides(&text)) {
+            Ok(atlas) => tileatlas::set_atlas(atlas),
+            Err(err) => eprintln!("Ignoring tile atlas {}: {}", path, err),
+        }
+    }
+    // `--print-floor` prints a freshly generated floor as ASCII, handy for eyeballing the generator
+    if args.iter().any(|arg| arg == "--print-floor") {
+        let game = Game::new(GRID_WIDTH, GRID_HEIGHT, difficulty);
+        println!("{}", game.map.to_ascii(&game.ecs));
+        return;
+    }
+    if let Some(games) = arg_value(&args, "--headless") {
+        run_headless(games.parse().unwrap_or(1), difficulty, xp_curve);
+        return;
+    }
+
+    let mut game = Game::new(GRID_WIDTH, GRID_HEIGHT, difficulty);
+    game.set_xp_curve(xp_curve);
+    game.set_diagonal_movement(args.iter().any(|arg| arg == "--diagonal"));
+    // `--opportunity-attacks` lets adjacent monsters swing at a player who steps away
+    game.set_player_provokes_attacks(args.iter().any(|arg| arg == "--opportunity-attacks"));
+    // `--manual-pickup` leaves loot on the floor until picked up with `g`
+    game.set_auto_pickup(!args.iter().any(|arg| arg == "--manual-pickup"));
 
     let main_window = initialize_main_window();
     update_game_info(&game, &main_window);
@@ -28,62 +77,302 @@ fn main() {
     main_window.run().unwrap();
 }
 
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|index| args.get(index + 1))
+        .map(|value| value.as_str())
+}
+
+fn run_headless(games: usize, difficulty: Difficulty, xp_curve: XpCurve) {
+    for game_number in 1..=games {
+        let mut game = Game::new(GRID_WIDTH, GRID_HEIGHT, difficulty);
+        game.set_xp_curve(xp_curve);
+        game.set_explore_stops_at_stairs(true);
+        // events wait here until the turn is over, so they can be checked against the game
+        let heard = Rc::new(RefCell::new(Vec::new()));
+        let listener = heard.clone();
+        game.subscribe(move |event| listener.borrow_mut().push(event.clone()));
+        let mut tally = HeadlessTally::default();
+        let mut outcome = TurnOutcome::default();
+        for _ in 0..HEADLESS_MAX_ACTIONS {
+            let last_turn = outcome.turn;
+            outcome = game.step_headless(bot_action(&game));
+            // nothing happened, let the monsters come
+            if outcome.player_alive && outcome.turn == last_turn {
+                outcome = game.step_headless(PlayerAction::Wait);
+            }
+            for event in heard.borrow_mut().drain(..) {
+                tally.hear(event, game.ecs.get_player_id());
+            }
+            if !outcome.player_alive {
+                break;
+            }
+        }
+        let result = match outcome.player_alive {
+            true => "survived",
+            false => "died",
+        };
+        println!(
+            "game {}: {} on depth {} after {} turns, {}",
+            game_number,
+            result,
+            outcome.depth,
+            outcome.turn,
+            tally.summary()
+        );
+        // the last turn's log says how it ended
+        if !outcome.player_alive {
+            for message in &outcome.messages {
+                println!("  {}", message);
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct HeadlessTally {
+    kills: usize,
+    last_kill: Option<String>,
+    hits_taken: usize,
+    level: isize,
+    floors: usize,
+}
+
+impl HeadlessTally {
+    fn hear(&mut self, event: GameEvent, player_id: usize) {
+        match event {
+            GameEvent::MonsterDied { name } => {
+                self.kills += 1;
+                self.last_kill = Some(name);
+            }
+            GameEvent::Interaction { event_type, entity_id } => {
+                if entity_id == player_id && matches!(event_type, EventType::Bump | EventType::Shot) {
+                    self.hits_taken += 1;
+                }
+            }
+            GameEvent::PlayerLeveled { level } => self.level = level,
+            GameEvent::FloorChanged { depth } => self.floors = self.floors.max(depth),
+        }
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = format!("{} kills, struck {} times", self.kills, self.hits_taken);
+        if let Some(name) = &self.last_kill {
+            summary += &format!(", last kill {}", name);
+        }
+        if self.level > 0 {
+            summary += &format!(", reached level {}", self.level);
+        }
+        if self.floors > 0 {
+            summary += &format!(", went down to depth {}", self.floors);
+        }
+        summary
+    }
+}
+
+// Fights whatever it sees, explores until it finds the stairs, then goes down.
+fn bot_action(game: &Game) -> PlayerAction {
+    if game.is_player_ready_for_level() {
+        return PlayerAction::LevelUp(0, 1);
+    }
+    let Some(position) = game.ecs.get_player_position() else {
+        return PlayerAction::Wait;
+    };
+    let has = |tile: Coordinate, comp_type: ComponentType| {
+        game.ecs
+            .get_all_entities_in_tile(tile)
+            .into_iter()
+            .any(|entity_id| game.ecs.entity_id_has_component(entity_id, comp_type))
+    };
+    let nearest_monster = game
+        .get_visible_tiles()
+        .into_iter()
+        .filter(|&tile| has(tile, ComponentType::Monster))
+        .min_by_key(|&tile| (tile - position).x.abs() + (tile - position).y.abs());
+    if let Some(monster) = nearest_monster {
+        return PlayerAction::Position(monster);
+    }
+    if has(position, ComponentType::Stairs) {
+        return PlayerAction::Descend;
+    }
+    if !game.is_floor_explored() {
+        return PlayerAction::AutoExplore;
+    }
+    let stairs = game
+        .map
+        .explored
+        .borrow()
+        .iter()
+        .copied()
+        .find(|&tile| has(tile, ComponentType::Stairs));
+    match stairs {
+        Some(stairs) => PlayerAction::Position(stairs),
+        None => PlayerAction::Wait,
+    }
+}
+
 fn initialize_main_window() -> MainWindow {
     let window = MainWindow::new().unwrap();
-    window.set_tile_size(TILESET_SIZE);
+    window.set_tile_size(tileatlas::tile_size());
     window.set_grid_width(GRID_WIDTH as i32);
     window.set_grid_height(GRID_HEIGHT as i32);
+    window.set_log_lines(std::rc::Rc::new(slint::VecModel::<LogLine>::default()).into());
     window
 }
 
-fn set_up_input(mut game: Game, window: &MainWindow) {
+// What the next map click picks a target for.
+enum PendingTarget {
+    Spell(i32),
+    Scroll(usize), // item index
+}
+
+fn set_up_input(game: Game, window: &MainWindow) {
+    let game = Rc::new(RefCell::new(game));
+    // examine waits for the next map click to pick its tile
+    let pending_examine = Rc::new(Cell::new(false));
+    // so does disarming a trap
+    let pending_disarm = Rc::new(Cell::new(false));
+    // travel plays out one step per tick rather than all at once
+    let travel_timer = Rc::new(Timer::default());
+
+    let weak_window = window.as_weak();
+    let key_game = game.clone();
+    let key_examine = pending_examine.clone();
+    let key_disarm = pending_disarm.clone();
+    let key_timer = travel_timer.clone();
+    let input_map = InputMap::default();
+    window.on_received_key(move |key| {
+        let mut game = key_game.borrow_mut();
+        match input_map.dispatch(&key, &mut *game) {
+            Some(InputAction::Examine) => {
+                logger::log_message("Choose a tile to examine.");
+                key_examine.set(true);
+            }
+            Some(InputAction::Disarm) => {
+                logger::log_message("Choose a trap to disarm.");
+                key_disarm.set(true);
+            }
+            Some(InputAction::RevealAll) => {
+                game.toggle_reveal_all();
+                logger::log_message(match game.is_revealing_all() {
+                    true => "The whole floor is revealed.",
+                    false => "The floor is hidden again.",
+                });
+            }
+            Some(InputAction::ToggleDiagonal) => {
+                let enabled = !game.is_diagonal_movement();
+                game.set_diagonal_movement(enabled);
+                logger::log_message(match enabled {
+                    true => "You can now move diagonally.",
+                    false => "You only move straight again.",
+                });
+            }
+            Some(_) => {}
+            None => return,
+        }
+        if game.is_traveling() {
+            start_travel(&key_timer, key_game.clone(), weak_window.clone());
+        }
+        refresh_window(&game, &weak_window.unwrap());
+    });
+
     let weak_window = window.as_weak();
+    let travel_game = game.clone();
+    // aimed spells and scrolls wait for the next map click to pick their target
+    let mut pending_target: Option<PendingTarget> = None;
     window.on_received_input(move |command, x, y| {
         // Main game loop
-        match command {
-            InputCommand::Direction => {
-                game.step_command(Coordinate { x, y });
+        let mut game = game.borrow_mut();
+        let examining = pending_examine.take();
+        let disarming = pending_disarm.take();
+        match (command, pending_target.take()) {
+            (InputCommand::Quit, _) => {
+                close_window(&weak_window.unwrap());
             }
-            InputCommand::Position => {
-                game.target_command(Coordinate { x, y });
+            (InputCommand::Spell, _) if game.spell_needs_target(x) => {
+                logger::log_message("Choose a target.");
+                pending_target = Some(PendingTarget::Spell(x));
             }
-            InputCommand::Shoot => {
-                game.shoot_command(Coordinate { x, y });
+            (InputCommand::UseItem, _) if game.item_is_gear(x as usize) => {
+                game.apply_action(PlayerAction::Equip(x as usize));
             }
-            InputCommand::Spell => {
-                game.cast_spell_command(x);
+            (InputCommand::UseItem, _) if game.item_needs_target(x as usize) => {
+                logger::log_message("Choose a target.");
+                pending_target = Some(PendingTarget::Scroll(x as usize));
             }
-            InputCommand::Descend => {
-                game.descend_command();
+            (InputCommand::Position, _) if examining => {
+                game.apply_action(PlayerAction::Examine(Coordinate { x, y }));
             }
-            InputCommand::CloseDoors => {
-                game.close_doors_command();
+            (InputCommand::Position, _) if disarming => {
+                game.apply_action(PlayerAction::Disarm(Coordinate { x, y }));
             }
-            InputCommand::Wait => {
-                game.wait_command();
+            (InputCommand::Position, Some(PendingTarget::Spell(spell_id))) => {
+                game.apply_action(PlayerAction::Spell(spell_id, Some(Coordinate { x, y })));
             }
-            InputCommand::LevelUp => {
-                let (stat, amount) = (x, y);
-                game.level_up_command(stat, amount);
+            (InputCommand::Position, Some(PendingTarget::Scroll(item_index))) => {
+                game.apply_action(PlayerAction::ReadScroll(item_index, Some(Coordinate { x, y })));
             }
-            InputCommand::Quit => {
-                close_window(&weak_window.unwrap());
+            _ => {
+                game.apply_action(to_player_action(command, x, y));
             }
-            InputCommand::Restart => {
-                game = Game::new(GRID_WIDTH, GRID_HEIGHT);
+        }
+        if game.is_traveling() {
+            start_travel(&travel_timer, travel_game.clone(), weak_window.clone());
+        }
+        refresh_window(&game, &weak_window.unwrap());
+    });
+}
+
+fn start_travel(timer: &Rc<Timer>, game: Rc<RefCell<Game>>, weak_window: slint::Weak<MainWindow>) {
+    let weak_timer = Rc::downgrade(timer);
+    timer.start(TimerMode::Repeated, Duration::from_millis(TRAVEL_STEP_MS), move || {
+        let mut game = game.borrow_mut();
+        // any other input clears the path
+        if !game.is_traveling() || !game.is_player_alive() {
+            if let Some(timer) = weak_timer.upgrade() {
+                timer.stop();
             }
-            _ => {}
+            return;
         }
-        update_game_info(&game, &weak_window.unwrap());
-        LOG.with(|log| display_messages(&log, &weak_window.unwrap()));
-        display_popup(&game, &weak_window.unwrap());
-        update_tile_map(&game, &weak_window.unwrap());
+        game.apply_action(PlayerAction::Travel);
+        refresh_window(&game, &weak_window.unwrap());
     });
 }
 
+fn refresh_window(game: &Game, window: &MainWindow) {
+    update_game_info(game, window);
+    LOG.with(|log| display_messages(&log, window));
+    for cue in game.drain_sound_cues() {
+        window.invoke_play_sound(cue.name().into());
+    }
+    display_popup(game, window);
+    update_tile_map(game, window);
+}
+
+fn to_player_action(command: InputCommand, x: i32, y: i32) -> PlayerAction {
+    match command {
+        InputCommand::Direction => PlayerAction::Direction(Coordinate { x, y }),
+        InputCommand::Position => PlayerAction::Position(Coordinate { x, y }),
+        InputCommand::Shoot => PlayerAction::Shoot(Coordinate { x, y }),
+        InputCommand::Throw => PlayerAction::Throw(Coordinate { x, y }),
+        InputCommand::Wait => PlayerAction::Wait,
+        InputCommand::Quit => PlayerAction::Quit,
+        InputCommand::Restart => PlayerAction::Restart,
+        InputCommand::LevelUp => PlayerAction::LevelUp(x, y),
+        InputCommand::Descend => PlayerAction::Descend,
+        InputCommand::CloseDoors => PlayerAction::CloseDoors,
+        InputCommand::Search => PlayerAction::Search,
+        InputCommand::Examine => PlayerAction::Examine(Coordinate { x, y }),
+        InputCommand::Start => PlayerAction::Start,
+        InputCommand::Spell => PlayerAction::Spell(x, None),
+        InputCommand::UseItem => PlayerAction::UseItem(x as usize),
+    }
+}
+
 fn display_popup(game: &Game, window: &MainWindow) {
-    if !game.is_player_alive() {
-        window.invoke_display_death_popup();
+    if let Some(report) = game.get_death_report() {
+        window.invoke_display_death_popup(format!("{}\n{}", report.describe(), report.summary()).into());
     }
     if game.is_player_ready_for_level() {
         let (spell_id, spell_name, spell_image) = Game::get_level_up_spell();
@@ -92,8 +381,26 @@ fn display_popup(game: &Game, window: &MainWindow) {
 }
 
 fn display_messages(message_log: &MessageLog, window: &MainWindow) {
-    while let Some(msg) = message_log.next_message() {
-        window.invoke_display_message(msg.into());
+    let lines = window.get_log_lines();
+    let Some(lines) = lines.as_any().downcast_ref::<slint::VecModel<LogLine>>() else {
+        return;
+    };
+    // newest on top
+    while let Some((msg, kind)) = message_log.next_message() {
+        lines.insert(0, LogLine { text: msg.into(), color: message_color(kind) });
+    }
+    while lines.row_count() > LOG_LENGTH {
+        lines.remove(LOG_LENGTH);
+    }
+}
+
+fn message_color(kind: MessageKind) -> slint::Color {
+    match kind {
+        MessageKind::Info => slint::Color::from_rgb_u8(0x20, 0x46, 0x31),
+        MessageKind::Combat => slint::Color::from_rgb_u8(0x8c, 0x2f, 0x1c),
+        MessageKind::Warning => slint::Color::from_rgb_u8(0xb0, 0x5a, 0x00),
+        MessageKind::Reward => slint::Color::from_rgb_u8(0x1e, 0x5a, 0x8c),
+        MessageKind::Death => slint::Color::from_rgb_u8(0x5c, 0x0a, 0x28),
     }
 }
 
@@ -112,15 +419,33 @@ fn update_game_info(game: &Game, window: &MainWindow) {
         hp_max,
         strength,
         dexterity,
+        armor,
         melee_damage,
         melee_crit,
         ranged_damage,
         ranged_crit,
         spell_names,
         spell_icons,
+        spell_cooldowns,
+        item_names,
+        item_icons,
+        hunger_current,
+        hunger_max,
+        mana_current,
+        mana_max,
+        arrows,
     ) = game.get_player_info();
 
+    // turn based cooldowns count down next to the name
     let spell_names: Vec<slint::SharedString> = spell_names
+        .into_iter()
+        .zip(spell_cooldowns)
+        .map(|(str, turns)| match turns {
+            turns if turns > 0 => slint::SharedString::from(format!("{} ({})", str, turns)),
+            _ => slint::SharedString::from(str),
+        })
+        .collect();
+    let item_names: Vec<slint::SharedString> = item_names
         .into_iter()
         .map(|str| slint::SharedString::from(str))
         .collect();
@@ -128,6 +453,7 @@ fn update_game_info(game: &Game, window: &MainWindow) {
     let depth = game.get_map_info();
 
     window.set_depth(depth);
+    window.set_difficulty(game.get_difficulty().name().into());
     window.set_character_name(name.into());
     window.set_player_level(level);
     window.set_player_coins(coins);
@@ -137,28 +463,81 @@ fn update_game_info(game: &Game, window: &MainWindow) {
     window.set_player_health_max(hp_max);
     window.set_player_strength(strength);
     window.set_player_dexterity(dexterity);
+    window.set_player_armor(armor);
     window.set_player_melee_damage(melee_damage.into());
     window.set_player_melee_crit(melee_crit);
     window.set_player_ranged_damage(ranged_damage.into());
     window.set_player_ranged_crit(ranged_crit);
+    window.set_player_hunger_current(hunger_current);
+    window.set_player_hunger_max(hunger_max);
+    window.set_player_mana_current(mana_current);
+    window.set_player_mana_max(mana_max);
+    window.set_player_arrows(arrows);
     window.set_spell_icons(std::rc::Rc::new(slint::VecModel::from(spell_icons)).into());
     window.set_spell_names(std::rc::Rc::new(slint::VecModel::from(spell_names)).into());
+    window.set_item_icons(std::rc::Rc::new(slint::VecModel::from(item_icons)).into());
+    window.set_item_names(std::rc::Rc::new(slint::VecModel::from(item_names)).into());
 }
 
 fn update_tile_map(game: &Game, window: &MainWindow) {
     // Updates frontend's internal data for tiles, which triggers redraw.
+    let visible = game.get_visible_tiles();
+    let width = game.map.width;
+    // deeper floors are bigger than the first
+    window.set_grid_width(width as i32);
+    window.set_grid_height(game.map.height as i32);
     let tiles: Vec<TileGraphics> = game
         .get_image_ids_for_map()
         .into_iter()
-        .map(|vec| std::rc::Rc::new(slint::VecModel::from(vec)))
-        .map(|vec_model| TileGraphics {
-            image_ids: vec_model.into(),
+        .enumerate()
+        .map(|(i, vec)| {
+            let coord = Coordinate {
+                x: (i % width) as i32,
+                y: (i / width) as i32,
+            };
+            TileGraphics {
+                image_ids: std::rc::Rc::new(slint::VecModel::from(vec)).into(),
+                visible: visible.contains(&coord),
+            }
         })
         .collect();
 
     let tiles = std::rc::Rc::new(slint::VecModel::from(tiles));
 
     window.set_memory_tiles(tiles.into());
+    update_minimap(game, window);
+}
+
+fn update_minimap(game: &Game, window: &MainWindow) {
+    let rows = game.get_minimap();
+    let width = rows.first().map_or(0, |row| row.len());
+    // the room the player stands in is lit up, corridors aren't part of any room
+    let room = game.get_current_room_bounds();
+    let in_current_room = |x: i32, y: i32| {
+        room.is_some_and(|(left, top, right, bottom)| (left..=right).contains(&x) && (top..=bottom).contains(&y))
+    };
+    let cells: Vec<slint::Color> = rows
+        .into_iter()
+        .enumerate()
+        .flat_map(|(y, row)| row.into_iter().enumerate().map(move |(x, cell)| (x as i32, y as i32, cell)))
+        .map(|(x, y, cell)| match cell {
+            MinimapCell::Floor if in_current_room(x, y) => slint::Color::from_rgb_u8(0x8f, 0xb8, 0x54),
+            cell => minimap_color(cell),
+        })
+        .collect();
+    window.set_minimap_width(width as i32);
+    window.set_minimap_cells(std::rc::Rc::new(slint::VecModel::from(cells)).into());
+}
+
+fn minimap_color(cell: MinimapCell) -> slint::Color {
+    match cell {
+        MinimapCell::Unexplored => slint::Color::from_argb_u8(0, 0, 0, 0),
+        MinimapCell::Floor => slint::Color::from_rgb_u8(0x69, 0x90, 0x3b),
+        MinimapCell::Wall => slint::Color::from_rgb_u8(0xb8, 0xcd, 0x55),
+        MinimapCell::Door => slint::Color::from_rgb_u8(0xb0, 0x5a, 0x00),
+        MinimapCell::Stairs => slint::Color::from_rgb_u8(0x1e, 0x5a, 0x8c),
+        MinimapCell::PlayerHere => slint::Color::from_rgb_u8(0xff, 0xff, 0xff),
+    }
 }
 
 // Spell ideas
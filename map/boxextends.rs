@@ -5,8 +5,8 @@ use std::collections::{HashMap, HashSet};
 
 use super::mapbuilder::Axis;
 use crate::{
-    ecs::ecs::ECS, game::spawning, game::spawning::OBJECT_SPAWN_NAMES, map::utils::Coordinate,
-    map::utils::Euclidian,
+    ecs::ecs::{Delta, ECS}, game::components::core::{Component, ComponentType}, game::difficulty::Difficulty,
+    game::spawning, game::spawning::OBJECT_SPAWN_NAMES, map::utils::Coordinate, map::utils::Euclidian,
 };
 
 // Tracks areas on the grid and supports overlapping and orthogonal adjacency checks.
@@ -282,6 +282,14 @@ pub struct Room {
     pub extends: BoxExtends,
     pub spawn_table: Option<HashMap<&'static str, Range>>,
     pub door_locations: Vec<Coordinate>,
+    // subset of door_locations that spawn hidden until searched for
+    pub secret_doors: Vec<Coordinate>,
+    // subset of door_locations that need a key
+    pub locked_doors: Vec<Coordinate>,
+    // floor tiles of irregular rooms such as caverns, empty for box rooms
+    pub tiles: Vec<Coordinate>,
+    // water or lava painted over the floor, nothing spawns here
+    pub pool: Vec<Coordinate>,
 }
 
 impl Room {
@@ -290,15 +298,53 @@ impl Room {
             extends,
             spawn_table: None,
             door_locations: vec![],
+            secret_doors: vec![],
+            locked_doors: vec![],
+            tiles: vec![],
+            pool: vec![],
         }
     }
 
-    fn spawn_doors(&self, ecs: &mut ECS, depth: usize) {
+    pub fn new_cavern(extends: BoxExtends, tiles: Vec<Coordinate>) -> Self {
+        Self {
+            extends,
+            spawn_table: None,
+            door_locations: vec![],
+            secret_doors: vec![],
+            locked_doors: vec![],
+            tiles,
+            pool: vec![],
+        }
+    }
+
+    pub fn contains_point(&self, coord: Coordinate) -> bool {
+        if self.tiles.is_empty() {
+            return self.extends.contains_point(coord);
+        }
+        // include the surrounding walls like box rooms do
+        self.tiles.iter().any(|tile| tile.distance(coord) < 1.5)
+    }
+
+    pub fn get_floor_area(&self) -> i32 {
+        if self.tiles.is_empty() {
+            self.extends.get_inner_area()
+        } else {
+            self.tiles.len() as i32
+        }
+    }
+
+    fn spawn_doors(&self, ecs: &mut ECS, depth: usize, difficulty: Difficulty) {
         for coord in &self.door_locations {
             if ecs.is_blocked_by_entity(*coord) {
                 continue;
             }
-            spawning::make_door(ecs, *coord, depth);
+            if self.secret_doors.contains(coord) {
+                spawning::make_secret_door(ecs, *coord, depth, difficulty);
+            } else if self.locked_doors.contains(coord) {
+                spawning::make_locked_door(ecs, *coord, depth, difficulty);
+            } else {
+                spawning::make_door(ecs, *coord, depth, difficulty);
+            }
         }
     }
 
@@ -319,28 +365,102 @@ impl Room {
         y_max: i32,
 
     ) -> Option<Coordinate> {
-        let mut coord = Coordinate {
-            x: rng.gen_range(x_min..=x_max),
-            y: rng.gen_range(y_min..=y_max),
-        };
+        let mut coord = self.random_coordinate(rng, x_min, x_max, y_min, y_max);
         let mut attempts = 1;
         // Ensure location is unoccupied. TODO: doesn't terminate if area is full
         while occupied.contains(&coord) || self.adjacent_to_door(coord) {
             if attempts >= 5 {
                 return None;
             }
-            coord = Coordinate {
-                x: rng.gen_range(x_min..=x_max),
-                y: rng.gen_range(y_min..=y_max),
-            };
+            coord = self.random_coordinate(rng, x_min, x_max, y_min, y_max);
             attempts += 1;
         }
         Some(coord)
     }
 
-    pub fn spawn_entities(&self, ecs: &mut ECS, depth: usize) {
+    fn scan_free_coordinate(
+        &self,
+        occupied: &HashSet<Coordinate>,
+        x_min: i32,
+        x_max: i32,
+        y_min: i32,
+        y_max: i32,
+    ) -> Option<Coordinate> {
+        let free: Vec<Coordinate> = self
+            .floor_coordinates(x_min, x_max, y_min, y_max)
+            .into_iter()
+            .filter(|coord| !occupied.contains(coord))
+            .collect();
+        free.iter()
+            .find(|coord| !self.adjacent_to_door(**coord))
+            .or(free.first())
+            .copied()
+    }
+
+    fn floor_coordinates(&self, x_min: i32, x_max: i32, y_min: i32, y_max: i32) -> Vec<Coordinate> {
+        match self.tiles.is_empty() {
+            true => (x_min..=x_max)
+                .flat_map(|x| (y_min..=y_max).map(move |y| Coordinate { x, y }))
+                .collect(),
+            false => self.tiles.clone(),
+        }
+    }
+
+    // Large units need their whole footprint on free floor, they get moved somewhere it fits.
+    fn settle_footprint(
+        &self,
+        ecs: &mut ECS,
+        coord: Coordinate,
+        occupied: &mut HashSet<Coordinate>,
+        floor: &HashSet<Coordinate>,
+    ) {
+        let large = ecs
+            .get_all_entities_in_tile(coord)
+            .into_iter()
+            .find(|&entity_id| ecs.entity_id_has_component(entity_id, ComponentType::Size));
+        let Some(entity_id) = large else {
+            occupied.insert(coord);
+            return;
+        };
+        let offsets = ecs.get_footprint(entity_id, Coordinate::default());
+        let fits = |anchor: Coordinate| {
+            offsets
+                .iter()
+                .all(|offset| floor.contains(&(anchor + *offset)) && !occupied.contains(&(anchor + *offset)))
+        };
+        let mut anchors = floor.iter().copied().collect::<Vec<_>>();
+        anchors.sort_by_key(|anchor| (anchor.y, anchor.x));
+        let anchor = match fits(coord) {
+            true => coord,
+            false => anchors.into_iter().find(|&anchor| fits(anchor)).unwrap_or(coord),
+        };
+        if let Some(position) = ecs.get_typed::<Coordinate>(entity_id) {
+            let change = position.make_change(anchor - coord);
+            ecs.apply_change(Delta::Change(Component::Position(change)));
+        }
+        occupied.extend(offsets.iter().map(|offset| anchor + *offset));
+    }
+
+    fn random_coordinate(
+        &self,
+        rng: &mut ThreadRng,
+        x_min: i32,
+        x_max: i32,
+        y_min: i32,
+        y_max: i32,
+    ) -> Coordinate {
+        match self.tiles.choose(rng) {
+            Some(coord) => *coord,
+            None => Coordinate {
+                x: rng.gen_range(x_min..=x_max),
+                y: rng.gen_range(y_min..=y_max),
+            },
+        }
+    }
+
+    pub fn spawn_entities(&self, ecs: &mut ECS, depth: usize, difficulty: Difficulty) {
         let mut rng = thread_rng();
-        let mut occupied = HashSet::<Coordinate>::new();
+        let mut occupied: HashSet<Coordinate> = self.pool.iter().copied().collect();
 
         // Floor area coordinate bounds
         let x_min = self.extends.top_left.x + 1;
@@ -348,6 +468,7 @@ impl Room {
 
         let y_min = self.extends.top_left.y + 1;
         let y_max = self.extends.bottom_right.y - 1;
+        let floor: HashSet<Coordinate> = self.floor_coordinates(x_min, x_max, y_min, y_max).into_iter().collect();
 
         if let Some(table) = &self.spawn_table {
             // Process spawning table
@@ -355,9 +476,10 @@ impl Room {
                 if name == "Player" && ecs.has_player() {
                     let coord =
                         self.get_free_coordinate(&occupied, &mut rng, x_min, x_max, y_min, y_max);
-                    let coord = match coord {
-                        Some(coord) => coord,
-                        None => Coordinate { x: x_min + x_max / 2, y: y_min + y_max / 2 }
+                    let coord = match (coord, self.tiles.first()) {
+                        (Some(coord), _) => coord,
+                        (None, Some(tile)) => *tile,
+                        (None, None) => Coordinate { x: x_min + x_max / 2, y: y_min + y_max / 2 }
                     };
                     ecs.set_player_position(coord);
                     continue;
@@ -366,21 +488,25 @@ impl Room {
                 if let Some(spawn_func) = OBJECT_SPAWN_NAMES.get(name) {
                     // Generate amount
                     let amount = rng.gen_range(min..=max);
-                    for _ in 0..amount {
+                    for count in 0..amount {
                         // Initial location to spawn
-                        let coord = self
+                        let mut coord = self
                             .get_free_coordinate(&occupied, &mut rng, x_min, x_max, y_min, y_max);
+                        // the minimum is a promise, stairs and bosses depend on it
+                        if coord.is_none() && count < min {
+                            coord = self.scan_free_coordinate(&occupied, x_min, x_max, y_min, y_max);
+                        }
                         let Some(coord) = coord else {
                             continue;
                         };
-                        (spawn_func)(ecs, coord, depth);
-                        occupied.insert(coord);
+                        (spawn_func)(ecs, coord, depth, difficulty);
+                        self.settle_footprint(ecs, coord, &mut occupied, &floor);
                     }
                 }
             }
         }
 
-        self.spawn_doors(ecs, depth);
+        self.spawn_doors(ecs, depth, difficulty);
     }
 }
 
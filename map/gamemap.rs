@@ -1,27 +1,49 @@
-use petgraph::Graph;
+use petgraph::{algo, graph::NodeIndex, Graph};
 use serde::{Deserialize, Serialize};
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::{HashMap, HashSet, VecDeque},
 };
 
 use crate::{
     ecs::ecs::ECS,
+    game::{
+        components::core::{ComponentType, Name},
+        difficulty::Difficulty,
+    },
     map::{
         boxextends::Room,
-        tile::{GameTile, TILE_NOT_FOUND, TILE_REGISTRY},
-        utils::Coordinate,
+        tile::{GameTile, FLOOR_TILE_ID, LAVA_TILE_ID, TILE_NOT_FOUND, TILE_REGISTRY, WATER_TILE_ID},
+        utils::{diagonal_neighbors, orthogonal_neighbors, Coordinate, DIAGONAL_DIRECTIONS, ORTHOGONAL_DIRECTIONS},
     },
+    utils::los,
 };
 
+const LOS_CACHE_LIMIT: usize = 1 << 16;
+// enough for a room's worth of corridor, not the whole floor behind open doors
+pub const EXPLORE_FILL_BUDGET: usize = 80;
+
 #[derive(Clone)]
 pub struct GameMap {
     pub map: HashMap<Coordinate, GameTile>,
     pub explored: RefCell<HashSet<Coordinate>>,
+    pub visible: RefCell<HashSet<Coordinate>>,
+    // tiles seen from afar, with the turns left until they fade
+    pub revealed: RefCell<HashMap<Coordinate, usize>>,
+    // line of sight results, valid for one ECS los generation
+    pub los_cache: RefCell<HashMap<(Coordinate, Coordinate), bool>>,
+    pub los_generation: Cell<usize>,
     pub graph: Graph<Room, (), petgraph::Undirected>,
     pub width: usize,
     pub height: usize,
     pub depth: usize,
+    pub difficulty: Difficulty,
+    // opt-in 8-directional movement for the player and monsters
+    pub diagonal_movement: bool,
+    // how many layouts the builder went through, for debugging
+    pub generation_attempts: usize,
+    // draws the whole floor without touching what's explored, for debugging
+    pub reveal_all: bool,
 }
 
 impl GameMap {
@@ -35,7 +57,7 @@ impl GameMap {
                     y: (i / self.width) as i32,
                 };
 
-                if !self.explored.borrow_mut().contains(&coord) {
+                if !self.is_tile_shown(coord) {
                     let im_id = TILE_REGISTRY[&TILE_NOT_FOUND.index].image.id;
                     let im_depth = TILE_REGISTRY[&TILE_NOT_FOUND.index].image.depth;
                     return vec![vec![im_id, im_depth]];
@@ -68,8 +90,144 @@ impl GameMap {
         }
     }
 
+    pub fn tile_is_water(&self, coord: Coordinate) -> bool {
+        self.map
+            .get(&coord)
+            .is_some_and(|tile| tile.root_tile == WATER_TILE_ID)
+    }
+
+    pub fn tile_is_lava(&self, coord: Coordinate) -> bool {
+        self.map
+            .get(&coord)
+            .is_some_and(|tile| tile.root_tile == LAVA_TILE_ID)
+    }
+
+    pub fn movement_directions(&self) -> Vec<Coordinate> {
+        match self.diagonal_movement {
+            true => [ORTHOGONAL_DIRECTIONS, DIAGONAL_DIRECTIONS].concat(),
+            false => ORTHOGONAL_DIRECTIONS.to_vec(),
+        }
+    }
+
+    // The tiles a unit could step to from coord, before checking what's in them.
+    pub fn neighbors(&self, coord: Coordinate) -> Vec<Coordinate> {
+        match self.diagonal_movement {
+            true => [orthogonal_neighbors(coord), diagonal_neighbors(coord)].concat(),
+            false => orthogonal_neighbors(coord).to_vec(),
+        }
+    }
+
+    // Diagonal steps need the mode enabled and may not cut past a wall corner.
+    pub fn is_step_allowed(&self, from: Coordinate, direction: Coordinate) -> bool {
+        if direction.x == 0 || direction.y == 0 {
+            return true;
+        }
+        self.diagonal_movement
+            && self.is_tile_passable(from + Coordinate { x: direction.x, y: 0 })
+            && self.is_tile_passable(from + Coordinate { x: 0, y: direction.y })
+    }
+
+    // Furthest distance that still counts as standing next to something.
+    pub fn adjacent_range(&self) -> f32 {
+        match self.diagonal_movement {
+            true => 1.5,
+            false => 1.1,
+        }
+    }
+
+    pub fn describe_tile(&self, coord: Coordinate) -> &'static str {
+        if self.tile_is_water(coord) {
+            "water"
+        } else if self.tile_is_lava(coord) {
+            "lava"
+        } else if self.is_tile_passable(coord) {
+            "floor"
+        } else {
+            "wall"
+        }
+    }
+
+    // One character per tile, for eyeballing a generated floor regardless of what's explored.
+    pub fn to_ascii(&self, ecs: &ECS) -> String {
+        let player_position = ecs.get_player_position();
+        (0..self.height as i32)
+            .map(|y| {
+                (0..self.width as i32)
+                    .map(|x| self.ascii_tile(Coordinate { x, y }, player_position, ecs))
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn ascii_tile(&self, coord: Coordinate, player_position: Option<Coordinate>, ecs: &ECS) -> char {
+        if player_position == Some(coord) {
+            return '@';
+        }
+        let entities = ecs.get_all_entities_in_tile(coord);
+        let has = |component_type| entities.iter().any(|&entity_id| ecs.entity_id_has_component(entity_id, component_type));
+        // monsters go by the first letter of their name
+        if let Some(&monster_id) = entities
+            .iter()
+            .find(|&&entity_id| ecs.entity_id_has_component(entity_id, ComponentType::Monster))
+        {
+            let letter = ecs.get_typed::<Name>(monster_id).and_then(|name| name.data.raw.chars().next());
+            return letter.unwrap_or('m').to_ascii_lowercase();
+        }
+        if has(ComponentType::Stairs) {
+            '>'
+        } else if has(ComponentType::Door) {
+            '+'
+        } else if self.tile_is_water(coord) {
+            '~'
+        } else if self.tile_is_lava(coord) {
+            '^'
+        } else if self.is_tile_passable(coord) {
+            '.'
+        } else {
+            '#'
+        }
+    }
+
+    // Inner walls can be dug through, the outer edge holds the floor together.
+    pub fn is_tile_diggable(&self, coord: Coordinate) -> bool {
+        let on_edge = coord.x <= 0 || coord.y <= 0 || coord.x >= self.width as i32 - 1 || coord.y >= self.height as i32 - 1;
+        !on_edge && self.map.contains_key(&coord) && !self.is_tile_passable(coord)
+    }
+
+    // Turns the wall into floor, returns whether there was one to dig.
+    pub fn dig(&mut self, coord: Coordinate) -> bool {
+        if !self.is_tile_diggable(coord) {
+            return false;
+        }
+        self.set_game_tile(coord, GameTile { root_tile: FLOOR_TILE_ID });
+        true
+    }
+
     pub fn set_game_tile(&mut self, coord: Coordinate, tile: GameTile) {
         self.map.insert(coord, tile);
+        self.invalidate_los_cache();
+    }
+
+    pub fn get_cached_los(&self, origin: Coordinate, destination: Coordinate, generation: usize) -> Option<bool> {
+        if self.los_generation.get() != generation {
+            self.invalidate_los_cache();
+            self.los_generation.set(generation);
+            return None;
+        }
+        self.los_cache.borrow().get(&(origin, destination)).copied()
+    }
+
+    pub fn cache_los(&self, origin: Coordinate, destination: Coordinate, visible: bool) {
+        let mut cache = self.los_cache.borrow_mut();
+        if cache.len() >= LOS_CACHE_LIMIT {
+            cache.clear();
+        }
+        cache.insert((origin, destination), visible);
+    }
+
+    pub fn invalidate_los_cache(&self) {
+        self.los_cache.borrow_mut().clear();
     }
 
     pub fn get_game_tile(&self, coord: Coordinate) -> Option<&GameTile> {
@@ -79,20 +237,42 @@ impl GameMap {
     pub fn create_empty(width: usize, height: usize) -> GameMap {
         let map = HashMap::<Coordinate, GameTile>::new();
         let explored = RefCell::new(HashSet::<Coordinate>::new());
+        let visible = RefCell::new(HashSet::<Coordinate>::new());
+        let revealed = RefCell::new(HashMap::<Coordinate, usize>::new());
+        let los_cache = RefCell::new(HashMap::<(Coordinate, Coordinate), bool>::new());
         let graph = Graph::default();
 
         GameMap {
             map,
             explored,
+            visible,
+            revealed,
+            los_cache,
+            los_generation: Cell::new(0),
             width,
             height,
             graph,
             depth: 0,
+            difficulty: Difficulty::default(),
+            diagonal_movement: false,
+            generation_attempts: 0,
+            reveal_all: false,
         }
     }
 
     pub fn explore_room(&self, coord: Coordinate) {
         for room in self.get_room(coord) {
+            if !room.tiles.is_empty() {
+                let mut explored = self.explored.borrow_mut();
+                for tile in &room.tiles {
+                    for i in -1..=1 {
+                        for j in -1..=1 {
+                            explored.insert(*tile + Coordinate { x: i, y: j });
+                        }
+                    }
+                }
+                continue;
+            }
             let Coordinate { x: x_min, y: y_min } = room.extends.top_left;
             let Coordinate { x: x_max, y: y_max } = room.extends.bottom_right;
             for i in x_min..=x_max {
@@ -103,51 +283,116 @@ impl GameMap {
         }
     }
 
-    pub fn explore_flood_fill(&self, coord: Coordinate, ecs: &ECS) {
-        let mut explored = self.explored.borrow_mut();
-        let adjacent = vec![
-            Coordinate { x: 1, y: 0 },
-            Coordinate { x: -1, y: 0 },
-            Coordinate { x: 0, y: 1 },
-            Coordinate { x: 0, y: -1 },
-        ];
+    pub fn update_visible(&self, origin: Coordinate, radius: i32, ecs: &ECS) {
+        let mut visible = self.visible.borrow_mut();
+        visible.clear();
+        visible.insert(origin);
+        visible.extend(self.revealed.borrow().keys());
+
+        for i in -radius..=radius {
+            for j in -radius..=radius {
+                let coord = origin + Coordinate { x: i, y: j };
+                if coord == origin || coord.distance(origin) > radius as f32 {
+                    continue;
+                }
+                if los::line_of_sight(origin, coord, self, ecs) {
+                    visible.insert(coord);
+                }
+            }
+        }
+    }
+
+    pub fn reveal_room(&self, coord: Coordinate, turns: usize) {
+        self.explore_room(coord);
+        let mut revealed = self.revealed.borrow_mut();
+        for room in self.get_room(coord) {
+            let tiles: Vec<Coordinate> = if room.tiles.is_empty() {
+                let Coordinate { x: x_min, y: y_min } = room.extends.top_left;
+                let Coordinate { x: x_max, y: y_max } = room.extends.bottom_right;
+                (x_min..=x_max)
+                    .flat_map(|x| (y_min..=y_max).map(move |y| Coordinate { x, y }))
+                    .collect()
+            } else {
+                room.tiles.clone()
+            };
+            for tile in tiles {
+                revealed.insert(tile, turns);
+            }
+        }
+    }
+
+    pub fn tick_revealed(&self) {
+        let mut revealed = self.revealed.borrow_mut();
+        revealed.retain(|_, turns| *turns > 1);
+        revealed.values_mut().for_each(|turns| *turns -= 1);
+    }
+
+    pub fn get_room_index(&self, coord: Coordinate) -> Option<NodeIndex> {
+        self.graph
+            .node_indices()
+            .find(|index| self.graph[*index].contains_point(coord))
+    }
 
+    // Number of corridors between the rooms at a and b, if they are connected.
+    pub fn room_distance(&self, a: Coordinate, b: Coordinate) -> Option<usize> {
+        let start = self.get_room_index(a)?;
+        let goal = self.get_room_index(b)?;
+        let distances = algo::dijkstra(&self.graph, start, Some(goal), |_| 1);
+        distances.get(&goal).copied()
+    }
+
+    pub fn is_tile_visible(&self, coord: Coordinate) -> bool {
+        self.visible.borrow().contains(&coord)
+    }
+
+    // What gets drawn, reveal_all only changes the view and not what the player knows.
+    pub fn is_tile_shown(&self, coord: Coordinate) -> bool {
+        self.reveal_all || self.explored.borrow().contains(&coord)
+    }
+
+    pub fn is_tile_shown_in_view(&self, coord: Coordinate) -> bool {
+        self.reveal_all || self.is_tile_visible(coord)
+    }
+
+    // Stops at walls, closed doors and the edge of the map, and after budget newly explored tiles.
+    pub fn explore_flood_fill(&self, coord: Coordinate, ecs: &ECS, budget: usize) {
+        let mut explored = self.explored.borrow_mut();
         let start = coord;
         let mut fill_queue: VecDeque<Coordinate> = VecDeque::new();
 
         fill_queue.push_front(start);
-        let unvisited_neighbors = adjacent.iter().filter_map(|dir| {
-            if !explored.contains(&(start + *dir)) {
-                Some(start + *dir)
-            } else {
-                None
-            }
-        });
+        let unvisited_neighbors = orthogonal_neighbors(start)
+            .into_iter()
+            .filter(|neighbor| !explored.contains(neighbor));
 
         for unvisited in unvisited_neighbors {
             fill_queue.push_front(unvisited);
         }
 
+        let mut revealed = 0;
         while let Some(current) = fill_queue.pop_back() {
-            explored.insert(current);
-
-            let unvisited_neighbors: Vec<Coordinate> = adjacent
-                .iter()
-                .filter_map(|dir| {
-                    if !explored.contains(&(current + *dir)) {
-                        Some(current + *dir)
-                    } else {
-                        None
-                    }
-                })
+            if revealed >= budget {
+                break;
+            }
+            // nothing past the edge blocks sight, so it would never end
+            if !self.map.contains_key(&current) {
+                continue;
+            }
+            if explored.insert(current) {
+                revealed += 1;
+            }
+
+            let unvisited_neighbors: Vec<Coordinate> = orthogonal_neighbors(current)
+                .into_iter()
+                .filter(|neighbor| !explored.contains(neighbor))
                 .collect();
 
             if ecs.is_blocked_by_door(current) || self.is_tile_los_blocking(current) {
                 // explore corners before we terminate
                 for unvisited in unvisited_neighbors {
-                    let visited_neighbors = adjacent
+                    let visited_neighbors = orthogonal_neighbors(unvisited)
                         .iter()
-                        .filter(|dir| explored.contains(&(unvisited + **dir)))
+                        .filter(|neighbor| explored.contains(neighbor))
                         .count();
                     if visited_neighbors >= 2 && self.is_tile_los_blocking(unvisited) {
                         explored.insert(unvisited);
@@ -162,11 +407,30 @@ impl GameMap {
         }
     }
 
+    // Every tile that can be walked to from start, doors and other entities don't count.
+    pub fn reachable_tiles(&self, start: Coordinate) -> HashSet<Coordinate> {
+        let mut reachable = HashSet::from([start]);
+        let mut fill_queue = VecDeque::from([start]);
+        while let Some(current) = fill_queue.pop_front() {
+            for next in orthogonal_neighbors(current) {
+                if self.is_tile_passable(next) && reachable.insert(next) {
+                    fill_queue.push_back(next);
+                }
+            }
+        }
+        reachable
+    }
+
+    // Corridors lie outside every room, so they get None.
+    pub fn get_room_at(&self, coord: Coordinate) -> Option<&Room> {
+        self.graph.node_weights().find(|room| room.contains_point(coord))
+    }
+
     pub fn get_room(&self, coord: Coordinate) -> Vec<&Room> {
         self.graph
             .node_weights()
             .into_iter()
-            .filter(|room| room.extends.contains_point(coord))
+            .filter(|room| room.contains_point(coord))
             .collect()
     }
 }
@@ -175,3 +439,111 @@ impl GameMap {
 pub struct ImageId {
     index: i32, // for compatibility with slint
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{game::testing, map::boxextends::BoxExtends};
+
+    fn room(left: i32, top: i32, right: i32, bottom: i32) -> Room {
+        Room::new(BoxExtends {
+            top_left: Coordinate { x: left, y: top },
+            bottom_right: Coordinate { x: right, y: bottom },
+        })
+    }
+
+    #[test]
+    fn rooms_are_found_by_their_tiles_and_corridors_are_not() {
+        let mut map = GameMap::create_empty(32, 18);
+        map.graph.add_node(room(0, 0, 8, 8));
+        map.graph.add_node(room(14, 0, 22, 8));
+
+        let found = map.get_room_at(Coordinate { x: 17, y: 4 }).unwrap();
+        assert_eq!(found.extends.top_left, Coordinate { x: 14, y: 0 });
+        let found = map.get_room_at(Coordinate { x: 3, y: 3 }).unwrap();
+        assert_eq!(found.extends.top_left, Coordinate { x: 0, y: 0 });
+        // the corridor between them
+        assert!(map.get_room_at(Coordinate { x: 11, y: 4 }).is_none());
+    }
+
+    #[test]
+    fn neighbors_follow_the_movement_mode() {
+        let mut map = GameMap::create_empty(32, 18);
+        let center = Coordinate { x: 5, y: 5 };
+        let orthogonal: HashSet<Coordinate> = map.neighbors(center).into_iter().collect();
+        let expected = [(5, 4), (5, 6), (4, 5), (6, 5)].map(|(x, y)| Coordinate { x, y });
+        assert_eq!(orthogonal, HashSet::from(expected));
+
+        map.diagonal_movement = true;
+        let all: HashSet<Coordinate> = map.neighbors(center).into_iter().collect();
+        assert_eq!(all.len(), 8);
+        assert!(all.is_superset(&orthogonal));
+        assert!(all.iter().all(|tile| tile.distance(center) < 1.5 && *tile != center));
+    }
+
+    #[test]
+    fn exploring_stops_at_the_next_closed_door() {
+        let mut game = testing::arena();
+        for (x, door_y) in [(10, 4), (20, 4)] {
+            for y in 1..17 {
+                let tile = Coordinate { x, y };
+                if y == door_y {
+                    testing::spawn(&mut game, "Door", tile);
+                } else {
+                    testing::wall(&mut game, tile);
+                }
+            }
+        }
+        game.map.explored.borrow_mut().clear();
+
+        game.map.explore_flood_fill(Coordinate { x: 11, y: 4 }, &game.ecs, EXPLORE_FILL_BUDGET);
+        let explored = game.map.explored.borrow();
+        assert!(explored.contains(&Coordinate { x: 15, y: 8 }));
+        assert!(!explored.contains(&Coordinate { x: 25, y: 4 }));
+    }
+
+    #[test]
+    fn exploring_stops_at_the_budget() {
+        let game = testing::arena();
+        game.map.explored.borrow_mut().clear();
+        game.map.explore_flood_fill(Coordinate { x: 10, y: 8 }, &game.ecs, 5);
+        assert_eq!(game.map.explored.borrow().len(), 5);
+    }
+
+    #[test]
+    fn ascii_matches_the_golden_floor() {
+        let mut game = testing::arena();
+        for y in 1..17 {
+            let tile = Coordinate { x: 10, y };
+            if y == 4 {
+                testing::spawn(&mut game, "Door", tile);
+            } else {
+                testing::wall(&mut game, tile);
+            }
+        }
+        testing::spawn(&mut game, "Doggo", Coordinate { x: 6, y: 4 });
+        testing::spawn(&mut game, "StairsDown", Coordinate { x: 20, y: 10 });
+
+        let golden = "
+        ################################
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #...@.d...+....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#.........>..........#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        #.........#....................#
+        ################################";
+        let golden: Vec<&str> = golden.lines().skip(1).map(str::trim).collect();
+        assert_eq!(game.map.to_ascii(&game.ecs), golden.join("\n"));
+    }
+}
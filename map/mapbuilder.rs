@@ -1,17 +1,33 @@
 use petgraph::graph::{Graph, NodeIndex};
-use petgraph::visit::IntoNodeReferences;
+use petgraph::visit::{Bfs, IntoNodeReferences};
 use petgraph::{algo, Undirected};
-use rand::{thread_rng, Rng};
+use rand::{seq::SliceRandom, thread_rng, Rng};
 use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::{
+    game::difficulty::Difficulty,
     map::boxextends::{BoxExtends, Room},
     map::gamemap::GameMap,
     map::tile::GameTile,
-    map::utils::Coordinate,
+    map::utils::{orthogonal_neighbors, Coordinate},
     map::utils::Euclidian,
 };
 
+const SECRET_DOOR_CHANCE: f64 = 0.1;
+const LOCKED_ROOM_CHANCE: f64 = 0.3;
+const POOL_CHANCE: f64 = 0.3;
+const POOL_MIN_SIDE: i32 = 5;
+const LAVA_CHANCE_PER_DEPTH: f64 = 0.1;
+const LAVA_CHANCE_MAX: f64 = 0.5;
+// floors grow every few levels, up to a limit
+const FLOORS_PER_GROWTH: usize = 3;
+const MAX_GROWTH_STEPS: usize = 4;
+const GROWTH_WIDTH: usize = 4;
+const GROWTH_HEIGHT: usize = 2;
+const DEEP_BSP_DEPTH: usize = 7;
+// after this many tries the last layout gets stitched together instead
+const MAX_GENERATION_ATTEMPTS: usize = 50;
+
 pub type RoomGraph = Graph<Room, (), petgraph::Undirected>;
 
 pub enum Axis {
@@ -19,16 +35,81 @@ pub enum Axis {
     Vertical,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapStyle {
+    Rooms,
+    Caves,
+}
+
+impl MapStyle {
+    pub fn from_depth(depth: usize) -> Self {
+        // every other floor is a cave
+        if depth.is_multiple_of(2) {
+            MapStyle::Caves
+        } else {
+            MapStyle::Rooms
+        }
+    }
+}
+
+// Size of a floor and how many times its space gets split into rooms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapParams {
+    pub width: usize,
+    pub height: usize,
+    pub bsp_depth: usize,
+}
+
+impl MapParams {
+    pub fn new(width: usize, height: usize) -> Self {
+        MapParams {
+            width,
+            height,
+            bsp_depth: 4,
+        }
+    }
+
+    // Deeper floors are larger and more subdivided than these base params.
+    pub fn for_depth(&self, depth: usize) -> Self {
+        let growth = (depth.saturating_sub(1) / FLOORS_PER_GROWTH).min(MAX_GROWTH_STEPS);
+        let extra_split = usize::from(depth >= DEEP_BSP_DEPTH);
+        MapParams {
+            width: self.width + growth * GROWTH_WIDTH,
+            height: self.height + growth * GROWTH_HEIGHT,
+            bsp_depth: self.bsp_depth + extra_split,
+        }
+    }
+}
+
 pub struct MapBuilder {
     // this a bit is awkward, should I remove the struct?
 }
 
 impl MapBuilder {
-    pub fn generate_new(size_x: usize, size_y: usize, depth: usize) -> (GameMap, RoomGraph) {
+    pub fn generate(
+        params: MapParams,
+        depth: usize,
+        style: MapStyle,
+        difficulty: Difficulty,
+    ) -> (GameMap, RoomGraph) {
+        match style {
+            MapStyle::Rooms => MapBuilder::generate_new(params, depth, difficulty),
+            MapStyle::Caves => MapBuilder::generate_caves(params, depth, difficulty),
+        }
+    }
+
+    pub fn generate_new(
+        params: MapParams,
+        depth: usize,
+        difficulty: Difficulty,
+    ) -> (GameMap, RoomGraph) {
+        let MapParams { width: size_x, height: size_y, bsp_depth } = params;
         let mut bsp: RoomGraph;
         let mut graph: RoomGraph;
+        let mut attempts = 0;
         loop {
-            bsp = MapBuilder::binary_space_partitioning(size_x, size_y, 4);
+            attempts += 1;
+            bsp = MapBuilder::binary_space_partitioning(size_x, size_y, bsp_depth);
             graph = MapBuilder::make_rooms_from_bsp(&bsp);
             graph = MapBuilder::prune_small_rooms(&graph, 5);
             graph = MapBuilder::make_connected_graph(&graph, 3);
@@ -38,14 +119,321 @@ impl MapBuilder {
             if islands == 1 {
                 break;
             }
+            if attempts >= MAX_GENERATION_ATTEMPTS {
+                graph = MapBuilder::connect_islands(&graph, size_x, size_y);
+                break;
+            }
         }
 
-        let map = MapBuilder::draw_rooms_to_map(&graph, size_x, size_y, depth);
-        let map = MapBuilder::flood_fill_spawn_tables(&map, 8, 25);
+        let mut map = MapBuilder::draw_rooms_to_map(&graph, size_x, size_y, depth);
+        map.generation_attempts = attempts;
+        let map = MapBuilder::flood_fill_spawn_tables(&map, 8, 25, boss_room_for_depth(depth), difficulty);
+        let map = MapBuilder::add_pools_to_rooms(&map);
         let map = MapBuilder::add_doors_to_rooms(&map);
+        let map = MapBuilder::lock_treasure_room(&map);
         (map, bsp)
     }
 
+    pub fn generate_caves(
+        params: MapParams,
+        depth: usize,
+        difficulty: Difficulty,
+    ) -> (GameMap, RoomGraph) {
+        let MapParams { width: size_x, height: size_y, bsp_depth } = params;
+        let mut floor: HashSet<Coordinate>;
+        let mut graph: RoomGraph;
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            floor = MapBuilder::seed_cave_floor(size_x, size_y, 0.45);
+            for _ in 0..5 {
+                floor = MapBuilder::smooth_cave_floor(&floor, size_x, size_y);
+            }
+            graph = MapBuilder::make_caverns_from_floor(&floor, 8);
+            if graph.node_count() >= 3 {
+                break;
+            }
+        }
+        graph = MapBuilder::connect_caverns(&graph);
+
+        let mut map = MapBuilder::draw_caves_to_map(&graph, size_x, size_y, depth);
+        map.generation_attempts = attempts;
+        let map = MapBuilder::flood_fill_spawn_tables(&map, 20, 60, boss_room_for_depth(depth), difficulty);
+        // the ecs only needs the bsp as a spatial index, caverns don't have to line up with it
+        let bsp = MapBuilder::binary_space_partitioning(size_x, size_y, bsp_depth);
+        (map, bsp)
+    }
+
+    fn seed_cave_floor(size_x: usize, size_y: usize, wall_chance: f64) -> HashSet<Coordinate> {
+        // Map edges are always wall.
+        let mut rng = thread_rng();
+        let mut floor = HashSet::new();
+        for x in 1..size_x as i32 - 1 {
+            for y in 1..size_y as i32 - 1 {
+                if !rng.gen_bool(wall_chance) {
+                    floor.insert(Coordinate { x, y });
+                }
+            }
+        }
+        floor
+    }
+
+    fn smooth_cave_floor(
+        floor: &HashSet<Coordinate>,
+        size_x: usize,
+        size_y: usize,
+    ) -> HashSet<Coordinate> {
+        // One cellular automata pass, out of bounds counts as wall.
+        let mut new_floor = HashSet::new();
+        for x in 1..size_x as i32 - 1 {
+            for y in 1..size_y as i32 - 1 {
+                let coord = Coordinate { x, y };
+                let mut walls = 0;
+                for i in -1..=1 {
+                    for j in -1..=1 {
+                        if (i, j) != (0, 0) && !floor.contains(&(coord + Coordinate { x: i, y: j }))
+                        {
+                            walls += 1;
+                        }
+                    }
+                }
+
+                let is_wall = !floor.contains(&coord);
+                if !(walls >= 5 || (is_wall && walls >= 4)) {
+                    new_floor.insert(coord);
+                }
+            }
+        }
+        new_floor
+    }
+
+    fn make_caverns_from_floor(floor: &HashSet<Coordinate>, min_size: usize) -> RoomGraph {
+        // Each connected region of floor becomes a room node, tiny regions are dropped.
+        let mut graph = RoomGraph::default();
+        let mut visited: HashSet<Coordinate> = HashSet::new();
+
+        let mut sorted_floor: Vec<&Coordinate> = floor.iter().collect();
+        sorted_floor.sort_unstable();
+
+        for start in sorted_floor {
+            if visited.contains(start) {
+                continue;
+            }
+            let mut tiles = vec![];
+            let mut fill_queue: VecDeque<Coordinate> = VecDeque::new();
+            fill_queue.push_front(*start);
+            visited.insert(*start);
+
+            while let Some(current) = fill_queue.pop_back() {
+                tiles.push(current);
+                for next in orthogonal_neighbors(current) {
+                    if floor.contains(&next) && visited.insert(next) {
+                        fill_queue.push_front(next);
+                    }
+                }
+            }
+
+            if tiles.len() < min_size {
+                continue;
+            }
+
+            let extends = BoxExtends {
+                top_left: Coordinate {
+                    x: tiles.iter().map(|tile| tile.x).min().unwrap() - 1,
+                    y: tiles.iter().map(|tile| tile.y).min().unwrap() - 1,
+                },
+                bottom_right: Coordinate {
+                    x: tiles.iter().map(|tile| tile.x).max().unwrap() + 1,
+                    y: tiles.iter().map(|tile| tile.y).max().unwrap() + 1,
+                },
+            };
+            graph.add_node(Room::new_cavern(extends, tiles));
+        }
+
+        graph
+    }
+
+    fn connect_caverns(room_graph: &RoomGraph) -> RoomGraph {
+        // Spanning tree over cavern centers, always joins the closest unconnected pair.
+        let mut new_graph = RoomGraph::default();
+        new_graph.clone_from(room_graph);
+        new_graph.clear_edges();
+
+        let mut connected: Vec<NodeIndex> = vec![];
+        let mut unconnected: Vec<NodeIndex> = new_graph.node_indices().collect();
+        if let Some(first) = unconnected.pop() {
+            connected.push(first);
+        }
+
+        while !unconnected.is_empty() {
+            let mut closest = (connected[0], 0, f32::MAX);
+            for a in &connected {
+                for (position, b) in unconnected.iter().enumerate() {
+                    let distance = new_graph[*a].extends.distance_to(new_graph[*b].extends);
+                    if distance < closest.2 {
+                        closest = (*a, position, distance);
+                    }
+                }
+            }
+            let (a, position, _) = closest;
+            let b = unconnected.swap_remove(position);
+            new_graph.add_edge(a, b, ());
+            connected.push(b);
+        }
+
+        new_graph
+    }
+
+    fn connect_islands(room_graph: &RoomGraph, size_x: usize, size_y: usize) -> RoomGraph {
+        // Keeps the edges there are and joins each stray island by its closest pair of rooms.
+        let mut new_graph = RoomGraph::default();
+        new_graph.clone_from(room_graph);
+        if new_graph.node_count() == 0 {
+            // nothing survived pruning, the whole floor becomes one room
+            let extends = BoxExtends {
+                top_left: Coordinate { x: 0, y: 0 },
+                bottom_right: Coordinate {
+                    x: size_x as i32 - 1,
+                    y: size_y as i32 - 1,
+                },
+            };
+            new_graph.add_node(Room::new(extends));
+            return new_graph;
+        }
+
+        loop {
+            let mut connected: HashSet<NodeIndex> = HashSet::new();
+            let mut bfs = Bfs::new(&new_graph, NodeIndex::new(0));
+            while let Some(node) = bfs.next(&new_graph) {
+                connected.insert(node);
+            }
+            if connected.len() == new_graph.node_count() {
+                return new_graph;
+            }
+
+            let mut closest = None;
+            let mut closest_distance = f32::MAX;
+            for a in &connected {
+                for b in new_graph.node_indices().filter(|node| !connected.contains(node)) {
+                    let distance = new_graph[*a].extends.distance_to(new_graph[b].extends);
+                    if distance < closest_distance {
+                        closest = Some((*a, b));
+                        closest_distance = distance;
+                    }
+                }
+            }
+            if let Some((a, b)) = closest {
+                new_graph.add_edge(a, b, ());
+            }
+        }
+    }
+
+    fn draw_caves_to_map(
+        graph: &RoomGraph,
+        size_x: usize,
+        size_y: usize,
+        depth: usize,
+    ) -> GameMap {
+        let mut map = GameMap::create_empty(size_x, size_y);
+        map.depth = depth;
+
+        // Fill everything with rock, then carve the caverns
+        for x in 0..size_x as i32 {
+            for y in 0..size_y as i32 {
+                map.set_game_tile(
+                    Coordinate { x, y },
+                    GameTile {
+                        root_tile: super::tile::WALL_TILE_ID,
+                    },
+                );
+            }
+        }
+        let cavern_floor: HashSet<Coordinate> = graph
+            .node_weights()
+            .flat_map(|room| room.tiles.iter().copied())
+            .collect();
+        for coord in &cavern_floor {
+            map.set_game_tile(
+                *coord,
+                GameTile {
+                    root_tile: super::tile::FLOOR_TILE_ID,
+                },
+            );
+        }
+
+        // Drawing corridors between closest tiles of neighboring caverns
+        let mut corridors = vec![];
+        for index in graph.edge_indices() {
+            let (room_a, room_b) = graph.edge_endpoints(index).unwrap();
+            let (start, end) =
+                MapBuilder::closest_tiles(&graph[room_a].tiles, &graph[room_b].tiles);
+            let corner = Coordinate {
+                x: end.x,
+                y: start.y,
+            };
+            MapBuilder::draw_horizontal_corridor(start, corner, &mut map);
+            MapBuilder::draw_vertical_corridor(corner, end, &mut map);
+            corridors.push((room_a, room_b, MapBuilder::l_shaped_path(start, end)));
+        }
+
+        // Doors go where corridors leave a cavern, so exploration works like in rooms
+        let mut new_graph = graph.clone();
+        for (room_a, room_b, path) in corridors {
+            let is_door = |coord: &&Coordinate| {
+                !cavern_floor.contains(*coord)
+                    && MapBuilder::check_door_conditions(**coord, &map)
+            };
+            if let Some(door) = path.iter().find(is_door) {
+                new_graph[room_a].door_locations.push(*door);
+            }
+            if let Some(door) = path.iter().rev().find(is_door) {
+                // short corridors only get one door
+                let doubled = new_graph[room_a]
+                    .door_locations
+                    .iter()
+                    .any(|other| other.distance(*door) <= 1.0);
+                if !doubled {
+                    new_graph[room_b].door_locations.push(*door);
+                }
+            }
+        }
+        map.graph = new_graph;
+        map
+    }
+
+    fn closest_tiles(tiles_a: &[Coordinate], tiles_b: &[Coordinate]) -> (Coordinate, Coordinate) {
+        let mut closest = (tiles_a[0], tiles_b[0]);
+        let mut best_distance = f32::MAX;
+        for a in tiles_a {
+            for b in tiles_b {
+                let distance = a.distance(*b);
+                if distance < best_distance {
+                    best_distance = distance;
+                    closest = (*a, *b);
+                }
+            }
+        }
+        closest
+    }
+
+    fn l_shaped_path(start: Coordinate, end: Coordinate) -> Vec<Coordinate> {
+        // Same route as a horizontal then vertical corridor.
+        let mut path = vec![];
+        let step_x = (end.x - start.x).signum();
+        let step_y = (end.y - start.y).signum();
+        let mut current = start;
+        path.push(current);
+        while current.x != end.x {
+            current.x += step_x;
+            path.push(current);
+        }
+        while current.y != end.y {
+            current.y += step_y;
+            path.push(current);
+        }
+        path
+    }
+
     fn binary_space_partitioning(size_x: usize, size_y: usize, max_depth: usize) -> RoomGraph {
         // Recursive algorithm for generating a binary space partitioning on BoxExtends.
         // Allows overlapping walls.
@@ -246,9 +634,35 @@ impl MapBuilder {
                 &graph.node_weight(room_b).unwrap().extends,
             )
         }
+        MapBuilder::seal_open_floor(&mut map);
         map
     }
 
+    fn seal_open_floor(map: &mut GameMap) {
+        // corridors cutting through walls can leave floor next to the void
+        let floor: Vec<Coordinate> = map
+            .map
+            .keys()
+            .filter(|coord| map.is_tile_passable(**coord))
+            .copied()
+            .collect();
+        for coord in floor {
+            for i in -1..=1 {
+                for j in -1..=1 {
+                    let neighbor = coord + Coordinate { x: i, y: j };
+                    if map.get_game_tile(neighbor).is_none() {
+                        map.set_game_tile(
+                            neighbor,
+                            GameTile {
+                                root_tile: super::tile::WALL_TILE_ID,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
     fn draw_room(room_box: BoxExtends, map: &mut GameMap) {
         let (left, top) = (room_box.top_left.x, room_box.top_left.y);
         let (right, bottom) = (room_box.bottom_right.x, room_box.bottom_right.y);
@@ -335,11 +749,15 @@ impl MapBuilder {
 
             MapBuilder::draw_horizontal_corridor(corridor_start, corridor_end, map);
         } else {
-            // println!("Corridor not drawn");
-            // println!("From Location: {}, {}", box_a.top_left.x, box_a.top_left.y);
-            // println!("From dimensions: {}, {}", box_a.bottom_right.x - box_a.top_left.x, box_a.bottom_right.y - box_a.top_left.y);
-            // println!("To Location: {}, {}", box_b.top_left.x, box_b.top_left.y);
-            // println!("To dimensions: {}, {}", box_b.bottom_right.x - box_b.top_left.x, box_b.bottom_right.y - box_b.top_left.y);
+            // no straight line fits, so bend once between the room centers
+            let start = box_a.position();
+            let end = box_b.position();
+            let corner = Coordinate {
+                x: end.x,
+                y: start.y,
+            };
+            MapBuilder::draw_horizontal_corridor(start, corner, map);
+            MapBuilder::draw_vertical_corridor(corner, end, map);
         }
     }
 
@@ -455,20 +873,18 @@ impl MapBuilder {
         if !map.is_tile_passable(coord) {
             return false;
         }
-        if map.is_tile_passable(coord + Coordinate { x: 0, y: 1 })
-            && map.is_tile_passable(coord + Coordinate { x: 0, y: -1 })
-        {
+        let [up, down, left, right] = orthogonal_neighbors(coord);
+        if map.is_tile_passable(up) && map.is_tile_passable(down) {
             return true;
         }
-        if map.is_tile_passable(coord + Coordinate { x: 1, y: 0 })
-            && map.is_tile_passable(coord + Coordinate { x: -1, y: 0 })
-        {
+        if map.is_tile_passable(left) && map.is_tile_passable(right) {
             return true;
         }
         false
     }
 
     fn add_doors_to_rooms(map: &GameMap) -> GameMap {
+        let mut rng = thread_rng();
         let mut new_graph: RoomGraph = Graph::default();
         new_graph.clone_from(&map.graph);
 
@@ -502,8 +918,15 @@ impl MapBuilder {
                 }
             }
 
+            let secret_doors = door_locations
+                .iter()
+                .filter(|_| rng.gen_bool(SECRET_DOOR_CHANCE))
+                .copied()
+                .collect();
+
             let new_room = Room {
                 door_locations,
+                secret_doors,
                 ..room.clone()
             };
             new_graph[node] = new_room;
@@ -513,10 +936,114 @@ impl MapBuilder {
         new_map
     }
 
+    // Locks a dead end holding a chest, its key spawns in some other room.
+    fn lock_treasure_room(map: &GameMap) -> GameMap {
+        let mut rng = thread_rng();
+        let mut new_map = map.clone();
+        if !rng.gen_bool(LOCKED_ROOM_CHANCE) {
+            return new_map;
+        }
+
+        let spawns = |node: NodeIndex, name: &str| {
+            map.graph[node]
+                .spawn_table
+                .as_ref()
+                .is_some_and(|table| table.contains_key(name))
+        };
+        // a dead end can't cut the rest of the floor off
+        let candidates: Vec<NodeIndex> = map
+            .graph
+            .node_indices()
+            .filter(|&node| map.graph.neighbors(node).count() == 1)
+            .filter(|&node| spawns(node, "Chest") && !spawns(node, "Player") && !spawns(node, "StairsDown"))
+            .collect();
+        let Some(&locked_node) = candidates.choose(&mut rng) else {
+            return new_map;
+        };
+        let key_nodes: Vec<NodeIndex> = map
+            .graph
+            .node_indices()
+            .filter(|&node| node != locked_node && map.graph[node].spawn_table.is_some())
+            .collect();
+        let Some(&key_node) = key_nodes.choose(&mut rng) else {
+            return new_map;
+        };
+
+        let locked_room = &map.graph[locked_node];
+        let locked_doors: Vec<Coordinate> = locked_room
+            .door_locations
+            .iter()
+            .filter(|door| !locked_room.secret_doors.contains(door))
+            .copied()
+            .collect();
+        if locked_doors.is_empty() {
+            return new_map;
+        }
+        // rooms sharing a doorway have to agree on it being locked
+        for room in new_map.graph.node_weights_mut() {
+            let shared = room.door_locations.iter().filter(|door| locked_doors.contains(door));
+            room.locked_doors.extend(shared);
+        }
+        if let Some(table) = new_map.graph[key_node].spawn_table.as_mut() {
+            table.insert("Key", (1, 1));
+        }
+        new_map
+    }
+
+    fn add_pools_to_rooms(map: &GameMap) -> GameMap {
+        let mut rng = thread_rng();
+        let mut new_map = map.clone();
+        let lava_chance = (LAVA_CHANCE_PER_DEPTH * map.depth as f64).min(LAVA_CHANCE_MAX);
+
+        for node in map.graph.node_indices() {
+            let room = &map.graph[node];
+            // the starting room stays dry
+            let is_start = room
+                .spawn_table
+                .as_ref()
+                .is_some_and(|table| table.contains_key("Player"));
+            if is_start || !rng.gen_bool(POOL_CHANCE) {
+                continue;
+            }
+
+            let (left, top) = (room.extends.top_left.x, room.extends.top_left.y);
+            let (right, bottom) = (room.extends.bottom_right.x, room.extends.bottom_right.y);
+            let shortest_side = (right - left - 1).min(bottom - top - 1);
+            if shortest_side < POOL_MIN_SIDE {
+                continue;
+            }
+
+            // a round pool in the middle, leaving a dry path along the walls
+            let center = Coordinate {
+                x: (left + right) / 2,
+                y: (top + bottom) / 2,
+            };
+            let radius = shortest_side as f32 / 2.0 - 1.5;
+            let tile = match rng.gen_bool(lava_chance) {
+                true => super::tile::LAVA_TILE_ID,
+                false => super::tile::WATER_TILE_ID,
+            };
+            let pool: Vec<Coordinate> = (left + 1..right)
+                .flat_map(|x| (top + 1..bottom).map(move |y| Coordinate { x, y }))
+                .filter(|coord| coord.distance(center) <= radius)
+                .collect();
+            for coord in &pool {
+                new_map.set_game_tile(*coord, GameTile { root_tile: tile });
+            }
+            new_map.graph[node] = Room {
+                pool,
+                ..room.clone()
+            };
+        }
+        new_map
+    }
+
     fn flood_fill_spawn_tables(
         map: &GameMap,
         lower_size_threshold: i32,
         upper_size_threshold: i32,
+        boss_room: Option<RoomTemplate<3>>,
+        difficulty: Difficulty,
     ) -> GameMap {
         let mut new_graph: RoomGraph = Graph::default();
         new_graph.clone_from(&map.graph);
@@ -529,6 +1056,7 @@ impl MapBuilder {
         let (start_index, _) = top_left_corners[0];
         let mut visited: HashSet<NodeIndex> = HashSet::new();
         let mut fill_queue: VecDeque<NodeIndex> = VecDeque::new();
+        let mut shop_placed = false;
 
         fill_queue.push_front(start_index);
         while let Some(index) = fill_queue.pop_back() {
@@ -545,16 +1073,29 @@ impl MapBuilder {
             let mut spawn_table: HashMap<&str, (usize, usize)> = HashMap::new();
             if index == start_index {
                 spawn_table.insert("Player", (1, 1));
-            } else if new_graph[index].extends.get_inner_area() <= lower_size_threshold {
-                spawn_table = get_spawn_table(SMALL_ROOMS, map.depth);
-            } else if new_graph[index].extends.get_inner_area() >= upper_size_threshold {
-                spawn_table = get_spawn_table(HUGE_ROOMS, map.depth);
+            } else if new_graph[index].get_floor_area() <= lower_size_threshold {
+                spawn_table = get_spawn_table(SMALL_ROOMS, map.depth, difficulty);
+            } else if new_graph[index].get_floor_area() >= upper_size_threshold {
+                spawn_table = get_spawn_table(HUGE_ROOMS, map.depth, difficulty);
             } else {
-                spawn_table = get_spawn_table(GENERIC_ROOMS, map.depth);
+                spawn_table = get_spawn_table(GENERIC_ROOMS, map.depth, difficulty);
             }
 
-            if fill_queue.is_empty() {
-                spawn_table.insert("StairsDown", (1, 1));
+            // on shop floors the first room past the start is the shop
+            if is_shop_depth(map.depth) && !shop_placed && index != start_index && !fill_queue.is_empty() {
+                spawn_table = get_spawn_table([SHOP_ROOM], map.depth, difficulty);
+                shop_placed = true;
+            }
+
+            // the boss holds the stairs until it dies
+            match boss_room {
+                Some(boss_room) if fill_queue.is_empty() && index != start_index => {
+                    spawn_table = get_spawn_table([boss_room], map.depth, difficulty);
+                }
+                _ if fill_queue.is_empty() => {
+                    spawn_table.insert("StairsDown", (1, 1));
+                }
+                _ => {}
             }
 
             new_graph[index] = Room {
@@ -565,14 +1106,36 @@ impl MapBuilder {
 
         GameMap {
             graph: new_graph,
+            difficulty,
             ..map.clone()
         }
     }
 }
 
+pub fn is_boss_depth(depth: usize) -> bool {
+    depth > 0 && depth.is_multiple_of(BOSS_DEPTH_INTERVAL)
+}
+
+pub fn is_shop_depth(depth: usize) -> bool {
+    depth > 0 && depth.is_multiple_of(SHOP_DEPTH_INTERVAL) && !is_boss_depth(depth)
+}
+
+// The toughest boss the depth allows, the last one repeats further down.
+fn boss_room_for_depth(depth: usize) -> Option<RoomTemplate<3>> {
+    if !is_boss_depth(depth) {
+        return None;
+    }
+    BOSS_ROOMS
+        .iter()
+        .rev()
+        .find(|template| template.depth_requirement <= depth)
+        .copied()
+}
+
 fn get_spawn_table<const W: usize, const H: usize>(
     templates: [RoomTemplate<W>; H],
     depth: usize,
+    difficulty: Difficulty,
 ) -> HashMap<&'static str, (usize, usize)> {
     let mut spawn_table: HashMap<&'static str, (usize, usize)> = HashMap::new();
     let eligible_tables: Vec<RoomTemplate<W>> = templates
@@ -587,7 +1150,7 @@ fn get_spawn_table<const W: usize, const H: usize>(
         .collect();
     let template_id = thread_rng().gen_range(0..eligible_tables.len());
     for SpawnEntry(name, range) in &eligible_tables[template_id] {
-        spawn_table.insert(name, range);
+        spawn_table.insert(name, difficulty.scale_spawn_range(range));
     }
     spawn_table
 }
@@ -648,7 +1211,50 @@ impl<const C: usize> IntoIterator for &RoomTemplate<C> {
     }
 }
 
-const SMALL_ROOMS: [RoomTemplate<3>; 9] = [
+const BOSS_DEPTH_INTERVAL: usize = 5;
+const SHOP_DEPTH_INTERVAL: usize = 3;
+
+const SHOP_ROOM: RoomTemplate<3> = RoomTemplate::new(
+    [
+        // Shop
+        SpawnEntry("Shopkeeper", (1, 1)),
+        SpawnEntry("", (0, 0)),
+        SpawnEntry("", (0, 0)),
+    ],
+    1,
+);
+
+const BOSS_ROOMS: [RoomTemplate<3>; 3] = [
+    RoomTemplate::new(
+        [
+            // Warlord and his spoils
+            SpawnEntry("Orc warlord", (1, 1)),
+            SpawnEntry("Gold", (2, 3)),
+            SpawnEntry("Corpse", (1, 3)),
+        ],
+        5,
+    ),
+    RoomTemplate::new(
+        [
+            // Eye tyrant and its thralls
+            SpawnEntry("Eye tyrant", (1, 1)),
+            SpawnEntry("Pewpewpet", (2, 2)),
+            SpawnEntry("Chest", (1, 1)),
+        ],
+        10,
+    ),
+    RoomTemplate::new(
+        [
+            // Colossus guarding a hoard
+            SpawnEntry("Stone colossus", (1, 1)),
+            SpawnEntry("Chest", (1, 2)),
+            SpawnEntry("Gold", (2, 4)),
+        ],
+        15,
+    ),
+];
+
+const SMALL_ROOMS: [RoomTemplate<3>; 10] = [
     RoomTemplate::new(
         [
             // Stray doggo
@@ -662,7 +1268,7 @@ const SMALL_ROOMS: [RoomTemplate<3>; 9] = [
         [
             // Stray bat
             SpawnEntry("Bat", (1, 1)),
-            SpawnEntry("", (0, 0)),
+            SpawnEntry("Ration", (0, 1)),
             SpawnEntry("", (0, 0)),
         ],
         1,
@@ -681,7 +1287,7 @@ const SMALL_ROOMS: [RoomTemplate<3>; 9] = [
             // Mini treasure room
             SpawnEntry("Rat", (0, 1)),
             SpawnEntry("Gold", (0, 2)),
-            SpawnEntry("", (0, 0)),
+            SpawnEntry("Potion", (0, 1)),
         ],
         1,
     ),
@@ -701,7 +1307,7 @@ const SMALL_ROOMS: [RoomTemplate<3>; 9] = [
             // Small treasure room
             SpawnEntry("Pewpewpet", (1, 2)),
             SpawnEntry("Gold", (1, 2)),
-            SpawnEntry("", (0, 0)),
+            SpawnEntry("Ration", (0, 1)),
         ],
         3,
     ),
@@ -733,15 +1339,24 @@ const SMALL_ROOMS: [RoomTemplate<3>; 9] = [
         ],
         6,
     ),
+    RoomTemplate::new(
+        [
+            // Trapped treasure room
+            SpawnEntry("Chest", (1, 1)),
+            SpawnEntry("Dart trap", (1, 2)),
+            SpawnEntry("Pit trap", (0, 1)),
+        ],
+        4,
+    ),
 ];
 
-const GENERIC_ROOMS: [RoomTemplate<5>; 12] = [
+const GENERIC_ROOMS: [RoomTemplate<5>; 22] = [
     RoomTemplate::new(
         [
             // DOGGO hunting party
             SpawnEntry("Doggo", (1, 2)),
             SpawnEntry("Corpse", (0, 3)),
-            SpawnEntry("", (0, 0)), 
+            SpawnEntry("Ration", (0, 1)),
             SpawnEntry("", (0, 0)),
             SpawnEntry("", (0, 0)),
         ],
@@ -752,7 +1367,7 @@ const GENERIC_ROOMS: [RoomTemplate<5>; 12] = [
             // Bat room
             SpawnEntry("Bat", (1, 2)),
             SpawnEntry("Gold", (0, 2)),
-            SpawnEntry("", (0, 0)), 
+            SpawnEntry("Ration", (0, 1)),
             SpawnEntry("", (0, 0)),
             SpawnEntry("", (0, 0)),
         ],
@@ -764,8 +1379,8 @@ const GENERIC_ROOMS: [RoomTemplate<5>; 12] = [
             SpawnEntry("Corpse", (1, 2)),
             SpawnEntry("Gold", (0, 3)),
             SpawnEntry("Rat", (0, 1)),
-            SpawnEntry("", (0, 0)), 
-            SpawnEntry("", (0, 0)),
+            SpawnEntry("Potion", (0, 1)),
+            SpawnEntry("Fire bomb", (0, 1)),
         ],
         1,
     ),
@@ -780,6 +1395,17 @@ const GENERIC_ROOMS: [RoomTemplate<5>; 12] = [
         ],
         1,
     ),
+    RoomTemplate::new(
+        [
+            // Napping doggos
+            SpawnEntry("Sleeping Doggo", (2, 3)),
+            SpawnEntry("Gold", (1, 2)),
+            SpawnEntry("Corpse", (0, 1)),
+            SpawnEntry("", (0, 0)),
+            SpawnEntry("", (0, 0)),
+        ],
+        1,
+    ),
 
     RoomTemplate::new(
         [
@@ -872,17 +1498,116 @@ const GENERIC_ROOMS: [RoomTemplate<5>; 12] = [
         ],
         4,
     ),
+    RoomTemplate::new(
+        [
+            // Minotaur den
+            SpawnEntry("Minotaur", (1, 1)),
+            SpawnEntry("Corpse", (2, 3)),
+            SpawnEntry("Gold", (0, 2)),
+            SpawnEntry("", (0, 0)),
+            SpawnEntry("", (0, 0)),
+        ],
+        5,
+    ),
+    RoomTemplate::new(
+        [
+            // Troll cave
+            SpawnEntry("Troll", (1, 1)),
+            SpawnEntry("Corpse", (1, 3)),
+            SpawnEntry("Rat", (0, 2)),
+            SpawnEntry("Gold", (0, 1)),
+            SpawnEntry("", (0, 0)),
+        ],
+        4,
+    ),
+    RoomTemplate::new(
+        [
+            // Necromancer's study
+            SpawnEntry("Necromancer", (1, 1)),
+            SpawnEntry("Corpse", (2, 3)),
+            SpawnEntry("Chest", (0, 1)),
+            SpawnEntry("", (0, 0)),
+            SpawnEntry("", (0, 0)),
+        ],
+        6,
+    ),
+    RoomTemplate::new(
+        [
+            // Trapped hall
+            SpawnEntry("Dart trap", (1, 3)),
+            SpawnEntry("Pit trap", (1, 2)),
+            SpawnEntry("Gold", (1, 2)),
+            SpawnEntry("Corpse", (0, 2)),
+            SpawnEntry("Pewpewpet", (0, 1)),
+        ],
+        5,
+    ),
+    RoomTemplate::new(
+        [
+            // Vampire crypt
+            SpawnEntry("Vampire", (1, 1)),
+            SpawnEntry("Corpse", (1, 3)),
+            SpawnEntry("Bat", (0, 2)),
+            SpawnEntry("Chest", (0, 1)),
+            SpawnEntry("", (0, 0)),
+        ],
+        6,
+    ),
+    RoomTemplate::new(
+        [
+            // Raptor nest
+            SpawnEntry("Raptor", (2, 3)),
+            SpawnEntry("Corpse", (1, 2)),
+            SpawnEntry("Gold", (0, 1)),
+            SpawnEntry("", (0, 0)),
+            SpawnEntry("", (0, 0)),
+        ],
+        4,
+    ),
+    RoomTemplate::new(
+        [
+            // Abandoned armory
+            SpawnEntry("Pewpewpet", (1, 2)),
+            SpawnEntry("Rusty sword", (0, 1)),
+            SpawnEntry("Leather armor", (0, 1)),
+            SpawnEntry("Gold", (0, 1)),
+            SpawnEntry("", (0, 0)),
+        ],
+        2,
+    ),
+    RoomTemplate::new(
+        [
+            // Dusty library
+            SpawnEntry("Pewpewpet", (1, 2)),
+            SpawnEntry("Scroll", (1, 2)),
+            SpawnEntry("Corpse", (0, 1)),
+            SpawnEntry("", (0, 0)),
+            SpawnEntry("", (0, 0)),
+        ],
+        3,
+    ),
+    RoomTemplate::new(
+        [
+            // Ghoul larder
+            SpawnEntry("Ghoul", (1, 2)),
+            SpawnEntry("Carcass", (1, 3)),
+            SpawnEntry("Corpse", (0, 1)),
+            SpawnEntry("", (0, 0)),
+            SpawnEntry("", (0, 0)),
+        ],
+        4,
+    ),
 ];
 
-const HUGE_ROOMS: [RoomTemplate<5>; 8] = [
+const HUGE_ROOMS: [RoomTemplate<5>; 11] = [
     RoomTemplate::new(
         [
             // Huge Bat room
             SpawnEntry("Bat", (2, 4)),
             SpawnEntry("Rat", (0, 1)),
             SpawnEntry("Gold", (1, 4)),
-            SpawnEntry("", (0, 0)),
-            SpawnEntry("", (0, 0)),
+            SpawnEntry("Potion", (0, 1)),
+            SpawnEntry("Fire bomb", (0, 1)),
         ],
         1,
     ),
@@ -934,6 +1659,18 @@ const HUGE_ROOMS: [RoomTemplate<5>; 8] = [
         3,
     ),
 
+    RoomTemplate::new(
+        [
+            // Sleeping heavy hoard
+            SpawnEntry("Sleeping Heavy", (1, 2)),
+            SpawnEntry("Chest", (1, 2)),
+            SpawnEntry("Gold", (2, 3)),
+            SpawnEntry("Corpse", (1, 2)),
+            SpawnEntry("", (0, 0)),
+        ],
+        3,
+    ),
+
     RoomTemplate::new(
         [
             // Big cultist room
@@ -969,4 +1706,63 @@ const HUGE_ROOMS: [RoomTemplate<5>; 8] = [
         ],
         7,
     ),
+
+    RoomTemplate::new(
+        [
+            // Minotaur labyrinth
+            SpawnEntry("Minotaur", (1, 2)),
+            SpawnEntry("Corpse", (2, 4)),
+            SpawnEntry("Chest", (1, 1)),
+            SpawnEntry("Spikes", (1, 3)),
+            SpawnEntry("", (0, 0)),
+        ],
+        6,
+    ),
+
+    RoomTemplate::new(
+        [
+            // Crypt
+            SpawnEntry("Necromancer", (1, 1)),
+            SpawnEntry("Pewpewpet", (1, 2)),
+            SpawnEntry("Corpse", (2, 4)),
+            SpawnEntry("Chest", (1, 1)),
+            SpawnEntry("", (0, 0)),
+        ],
+        8,
+    ),
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn average_rooms(params: MapParams) -> f64 {
+        let runs = 20;
+        let total: usize = (0..runs)
+            .map(|_| {
+                let (map, _) = MapBuilder::generate_new(params, 1, Difficulty::default());
+                map.graph.node_count()
+            })
+            .sum();
+        total as f64 / runs as f64
+    }
+
+    #[test]
+    fn larger_params_make_more_rooms() {
+        let small = MapParams::new(32, 18);
+        let large = small.for_depth(12);
+        assert!(large.width > small.width && large.bsp_depth > small.bsp_depth);
+        assert!(average_rooms(large) > average_rooms(small));
+    }
+
+    #[test]
+    fn generation_terminates_at_every_size() {
+        let base = MapParams::new(32, 18);
+        for depth in [1, 4, 7, 13] {
+            let params = base.for_depth(depth);
+            let (map, _) = MapBuilder::generate_new(params, depth, Difficulty::default());
+            assert_eq!((map.width, map.height), (params.width, params.height));
+            assert!(map.generation_attempts <= MAX_GENERATION_ATTEMPTS);
+        }
+    }
+}
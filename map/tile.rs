@@ -10,12 +10,16 @@ pub static TILE_REGISTRY: Map<u32, &'static RootTile> = phf_map!(
   3u32 => &RootTile {image: ImageData { id: 5, depth: 10 }, passable: true, los_blocking: false},
   4u32 => &RootTile {image: ImageData { id: 4, depth: 10 }, passable: false, los_blocking: true},
   5u32 => &RootTile {image: ImageData { id: 6, depth: 10 }, passable: false, los_blocking: true},
+  6u32 => &RootTile {image: ImageData { id: 31, depth: 10 }, passable: true, los_blocking: false},
+  7u32 => &RootTile {image: ImageData { id: 32, depth: 10 }, passable: true, los_blocking: false},
 );
 
 pub const FLOOR_TILE_ID: TileID = TileID { index: 0 };
 pub const WALL_TILE_ID: TileID = TileID { index: 2 };
 pub const PATH_TEST_TILE: TileID = TileID { index: 3 };
 pub const TILE_NOT_FOUND: TileID = TileID { index: 4 };
+pub const WATER_TILE_ID: TileID = TileID { index: 6 };
+pub const LAVA_TILE_ID: TileID = TileID { index: 7 };
 
 // slated for removal
 #[derive(Default, Serialize, Deserialize, Clone, Debug)]
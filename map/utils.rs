@@ -4,6 +4,21 @@ pub const UP: Coordinate = Coordinate{x: 0, y:-1 };
 pub const DOWN: Coordinate = Coordinate{x: 0, y:1 };
 pub const LEFT: Coordinate = Coordinate{x: 1, y:0 };
 pub const RIGHT: Coordinate = Coordinate{x: -1, y:0 };
+pub const UP_LEFT: Coordinate = Coordinate{x: 1, y:-1 };
+pub const UP_RIGHT: Coordinate = Coordinate{x: -1, y:-1 };
+pub const DOWN_LEFT: Coordinate = Coordinate{x: 1, y:1 };
+pub const DOWN_RIGHT: Coordinate = Coordinate{x: -1, y:1 };
+
+pub const ORTHOGONAL_DIRECTIONS: [Coordinate; 4] = [UP, DOWN, LEFT, RIGHT];
+pub const DIAGONAL_DIRECTIONS: [Coordinate; 4] = [UP_LEFT, UP_RIGHT, DOWN_LEFT, DOWN_RIGHT];
+
+pub fn orthogonal_neighbors(coord: Coordinate) -> [Coordinate; 4] {
+    ORTHOGONAL_DIRECTIONS.map(|dir| coord + dir)
+}
+
+pub fn diagonal_neighbors(coord: Coordinate) -> [Coordinate; 4] {
+    DIAGONAL_DIRECTIONS.map(|dir| coord + dir)
+}
 
 pub fn reverse_direction(direction: &Coordinate) -> Coordinate {
     Coordinate {
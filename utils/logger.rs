@@ -7,28 +7,129 @@ thread_local!(
     pub static LOG: MessageLog = MessageLog::new();
 );
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageKind {
+    #[default]
+    Info,
+    Combat,
+    Warning,
+    Reward,
+    Death,
+}
+
+// Abstract sounds for the frontend to play, collected alongside messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCue {
+    Hit,
+    Door,
+    Spell,
+    Death,
+    Gold,
+}
+
+impl SoundCue {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoundCue::Hit => "hit",
+            SoundCue::Door => "door",
+            SoundCue::Spell => "spell",
+            SoundCue::Death => "death",
+            SoundCue::Gold => "gold",
+        }
+    }
+}
+
+struct QueuedMessage {
+    text: String,
+    kind: MessageKind,
+    count: usize,
+}
+
+// Identical consecutive messages are stored once with a repeat count.
 pub struct MessageLog {
-    message_queue: RefCell<VecDeque<String>>,
+    message_queue: RefCell<VecDeque<QueuedMessage>>,
+    // kept after the queue is drained, for throttling
+    last_message: RefCell<String>,
+    sound_cues: RefCell<Vec<SoundCue>>,
 }
 
 impl MessageLog {
     pub fn new() -> Self {
         MessageLog {
             message_queue: RefCell::new(VecDeque::new()),
+            last_message: RefCell::new(String::new()),
+            sound_cues: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn queue_message(&self, msg: &str) {
-        self.message_queue.borrow_mut().push_back(msg.to_string());
+    pub fn queue_message(&self, msg: &str, kind: MessageKind) {
+        *self.last_message.borrow_mut() = msg.to_string();
+        let mut queue = self.message_queue.borrow_mut();
+        match queue.back_mut() {
+            Some(last) if last.text == msg && last.kind == kind => last.count += 1,
+            _ => queue.push_back(QueuedMessage {
+                text: msg.to_string(),
+                kind,
+                count: 1,
+            }),
+        }
+    }
+
+    // Skips the message if it was also the last one logged, even if already shown.
+    pub fn queue_message_throttled(&self, msg: &str, kind: MessageKind) {
+        if *self.last_message.borrow() != msg {
+            self.queue_message(msg, kind);
+        }
+    }
+
+    pub fn next_message(&self) -> Option<(String, MessageKind)> {
+        self.message_queue.borrow_mut().pop_front().map(|msg| match msg.count {
+            1 => (msg.text, msg.kind),
+            _ => (format!("{} (x{})", msg.text, msg.count), msg.kind),
+        })
+    }
+
+    // The same sound twice in one turn would only play on top of itself.
+    pub fn queue_sound(&self, cue: SoundCue) {
+        let mut cues = self.sound_cues.borrow_mut();
+        if !cues.contains(&cue) {
+            cues.push(cue);
+        }
     }
 
-    pub fn next_message(&self) -> Option<String> {
-        self.message_queue.borrow_mut().pop_front()
+    pub fn drain_sound_cues(&self) -> Vec<SoundCue> {
+        self.sound_cues.take()
     }
 }
 
 pub fn log_message(msg: &str) {
-    LOG.with(|log| log.queue_message(msg));
+    log_message_with(msg, MessageKind::Info);
+}
+
+pub fn log_message_with(msg: &str, kind: MessageKind) {
+    LOG.with(|log| log.queue_message(msg, kind));
+}
+
+pub fn log_message_throttled(msg: &str) {
+    LOG.with(|log| log.queue_message_throttled(msg, MessageKind::Info));
+}
+
+pub fn play_sound(cue: SoundCue) {
+    LOG.with(|log| log.queue_sound(cue));
+}
+
+pub fn play_damage_sound(lethal: bool) {
+    match lethal {
+        true => play_sound(SoundCue::Death),
+        false => play_sound(SoundCue::Hit),
+    }
+}
+
+fn damage_kind(lethal: bool) -> MessageKind {
+    match lethal {
+        true => MessageKind::Death,
+        false => MessageKind::Combat,
+    }
 }
 
 pub fn generate_attack_message(
@@ -36,29 +137,59 @@ pub fn generate_attack_message(
     defender: &Name,
     hit_message: &str,
     damage_taken: isize,
-) -> String {
-    vec![
-        &attacker.raw,
+    lethal: bool,
+) -> (String, MessageKind) {
+    let msg = [
+        &attacker.subject(),
         hit_message,
-        &defender.raw,
+        &defender.definite(),
         "for",
         &damage_taken.to_string(),
     ]
-    .join(" ")
+    .join(" ");
+    (msg, damage_kind(lethal))
+}
+
+pub fn generate_take_damage_message(defender: &Name, damage_taken: isize, lethal: bool) -> (String, MessageKind) {
+    let msg = [&defender.subject(), "took", &damage_taken.to_string(), "damage."].join(" ");
+    (msg, damage_kind(lethal))
 }
 
-pub fn generate_take_damage_message(defender: &Name, damage_taken: isize) -> String {
-    vec![&defender.raw, "took", &damage_taken.to_string(), "damage."].join(" ")
+pub fn generate_receive_gold_message(amount: isize) -> (String, MessageKind) {
+    let msg = ["You found", &amount.to_string(), "gold!"].join(" ");
+    (msg, MessageKind::Reward)
 }
 
-pub fn generate_receive_gold_message(amount: isize) -> String {
-    vec!["You found", &amount.to_string(), "gold!"].join(" ")
+pub fn generate_receive_item_message(item_name: &str, amount: isize) -> (String, MessageKind) {
+    let msg = match amount {
+        1 => ["You found a", item_name, "!"].join(" "),
+        _ => ["You found", &amount.to_string(), item_name, "!"].join(" "),
+    };
+    (msg, MessageKind::Reward)
 }
 
-pub fn generate_is_burning_message(defender: &Name, damage_taken: isize) -> String {
-    vec![&defender.raw, "is burning! Took", &damage_taken.to_string(), "damage."].join(" ")
+pub fn generate_is_burning_message(defender: &Name, damage_taken: isize) -> (String, MessageKind) {
+    let msg = [&defender.subject(), "is burning! Took", &damage_taken.to_string(), "damage."].join(" ");
+    (msg, MessageKind::Combat)
 }
 
-pub fn generate_on_fire_message(defender: &Name) -> String {
-    vec![&defender.raw, "catches on fire!"].join(" ")
-}
\ No newline at end of file
+pub fn generate_on_fire_message(defender: &Name) -> (String, MessageKind) {
+    let msg = [&defender.subject(), "catches on fire!"].join(" ");
+    (msg, MessageKind::Combat)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_messages_use_articles() {
+        let skeleton = Name::new("Skeleton");
+        let hero = Name::proper("Bartholomew");
+        let (msg, _) = generate_attack_message(&skeleton, &hero, "hits", 2, false);
+        assert_eq!(msg, "The skeleton hits Bartholomew for 2");
+        let (msg, _) = generate_attack_message(&hero, &skeleton, "hits", 3, true);
+        assert_eq!(msg, "Bartholomew hits the skeleton for 3");
+        assert_eq!(Name::new("Acid pool").indefinite(), "an acid pool");
+        assert_eq!(hero.indefinite(), "Bartholomew");
+    }
+}
@@ -6,9 +6,16 @@ pub fn line_of_sight(
     map: &GameMap,
     ecs: &ECS,
 ) -> bool {
+    if let Some(visible) = map.get_cached_los(origin, destination, ecs.get_los_generation()) {
+        return visible;
+    }
     let full_line = linetrace(origin, destination);
-    let line_between = &full_line[1..full_line.len() - 1];
-    !los_block_on_line(line_between, map, ecs)
+    let visible = match full_line.get(1..full_line.len() - 1) {
+        Some(line_between) => !los_block_on_line(line_between, map, ecs),
+        None => true,
+    };
+    map.cache_los(origin, destination, visible);
+    visible
 }
 
 fn los_block_on_line(line: &[Coordinate], map: &GameMap, ecs: &ECS) -> bool {
@@ -21,7 +28,8 @@ fn collision_on_line(line: &[Coordinate], map: &GameMap, ecs: &ECS) -> bool {
         .any(|point| !map.is_tile_passable(*point) || ecs.is_blocked_by_entity(*point))
 }
 
-fn linetrace(origin: Coordinate, destination: Coordinate) -> Vec<Coordinate> {
+// Ordered points from origin to destination, both ends included.
+pub fn linetrace(origin: Coordinate, destination: Coordinate) -> Vec<Coordinate> {
     let mut current_point = origin;
     let mut results: Vec<Coordinate> = Vec::new();
 
@@ -57,3 +65,23 @@ fn linetrace(origin: Coordinate, destination: Coordinate) -> Vec<Coordinate> {
 
     results
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{action::PlayerAction, testing::{self, PLAYER_START}};
+
+    #[test]
+    fn opening_a_door_clears_cached_sight() {
+        let mut game = testing::arena();
+        let door = PLAYER_START + Coordinate { x: 2, y: 0 };
+        let beyond = PLAYER_START + Coordinate { x: 5, y: 0 };
+        testing::spawn(&mut game, "Door", door);
+        assert!(!line_of_sight(PLAYER_START, beyond, &game.map, &game.ecs));
+
+        // one step up to the door, then bump it open
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        game.apply_action(PlayerAction::Direction(Coordinate { x: 1, y: 0 }));
+        assert!(line_of_sight(PLAYER_START, beyond, &game.map, &game.ecs));
+    }
+}
@@ -71,12 +71,7 @@ where
     let return_early = true;
     let origin_h_value = heuristic(origin);
 
-    let neighbors = vec![
-        Coordinate { x: 0, y: 1 },
-        Coordinate { x: 0, y: -1 },
-        Coordinate { x: 1, y: 0 },
-        Coordinate { x: -1, y: 0 },
-    ];
+    let neighbors = map.movement_directions();
 
     let mut open = PriorityQueue::new();
     let mut closed: HashMap<Coordinate, NodeData> = HashMap::new();
@@ -92,9 +87,12 @@ where
         &destination,
         heuristic,
         return_early,
-        ignore_units,
-        ignore_doors,
-        ignore_hazards,
+        PathOptions {
+            ignore_units,
+            ignore_doors,
+            ignore_hazards,
+            explored_only: false,
+        },
         map,
         ecs,
     );
@@ -106,6 +104,63 @@ where
     calculate_sequence(last_node, closed, origin)
 }
 
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathOptions {
+    pub ignore_units: bool,
+    pub ignore_doors: bool,
+    pub ignore_hazards: bool,
+    pub explored_only: bool,
+}
+
+// One-off A* query, returns the tiles to walk through in order, goal included.
+pub fn find_path(
+    start: Coordinate,
+    goal: Coordinate,
+    map: &GameMap,
+    ecs: &ECS,
+    options: PathOptions,
+) -> Option<Vec<Coordinate>> {
+    if !map.is_tile_passable(goal) {
+        return None;
+    }
+    if start == goal {
+        return Some(vec![]);
+    }
+    let heuristic = astar_heuristic_factory(goal);
+    let mut open = PriorityQueue::new();
+    let closed: HashMap<Coordinate, NodeData> = HashMap::new();
+    let last_node = (start, NodeData::new(heuristic(start)));
+    open.push(start, Reverse(last_node.1));
+
+    let (last_node, closed) = fill_path_map(
+        open,
+        closed,
+        last_node,
+        &map.movement_directions(),
+        &goal,
+        heuristic,
+        true,
+        options,
+        map,
+        ecs,
+    );
+    if last_node.0 != goal {
+        return None;
+    }
+
+    let mut path = vec![goal];
+    let mut parent = last_node.1.parent;
+    while let Some(coord) = parent {
+        if coord == start {
+            break;
+        }
+        path.push(coord);
+        parent = closed.get(&coord).and_then(|data| data.parent);
+    }
+    path.reverse();
+    Some(path)
+}
+
 fn calculate_sequence(
     mut last_node: (Coordinate, NodeData),
     closed: HashMap<Coordinate, NodeData>,
@@ -135,22 +190,23 @@ fn get_passable(
     neighbors: &[Coordinate],
     visited_coord: &Coordinate,
     destination: &Coordinate,
-    ignore_units: bool,
-    ignore_doors: bool,
+    options: PathOptions,
     map: &GameMap,
     ecs: &ECS,
 ) -> Vec<Coordinate> {
     neighbors
         .iter()
+        .filter(|&&dir| map.is_step_allowed(*visited_coord, dir))
         .map(|dir| *visited_coord + *dir)
+        .filter(|coord| !options.explored_only || map.explored.borrow().contains(coord))
         .filter(|&coord| {
             let blocking_entity = ecs.get_blocking_entity(coord);
             (
                 map.is_tile_passable(coord) &&
                 (
                     blocking_entity.is_none()
-                    || ignore_units && ecs.entity_id_has_component(blocking_entity.unwrap(), ComponentType::Monster) // only one blocking entity, so if it's monster ignore
-                    || ignore_doors && ecs.entity_id_has_component(blocking_entity.unwrap(), ComponentType::Door)
+                    || options.ignore_units && ecs.entity_id_has_component(blocking_entity.unwrap(), ComponentType::Monster) // only one blocking entity, so if it's monster ignore
+                    || options.ignore_doors && ecs.entity_id_has_component(blocking_entity.unwrap(), ComponentType::Door)
                 )
             )
                 || coord == *destination
@@ -165,16 +221,14 @@ fn fill_path_map<F>(
     destination: &Coordinate,
     heuristic: F,
     return_early: bool,
-    ignore_units: bool,
-    ignore_doors: bool,
-    ignore_hazards: bool,
+    options: PathOptions,
     map: &GameMap,
     ecs: &ECS,
 ) -> ((Coordinate, NodeData), HashMap<Coordinate, NodeData>)
 where
     F: Fn(Coordinate) -> usize,
 {
-    let hazard_cost = match ignore_hazards {
+    let hazard_cost = match options.ignore_hazards {
         true => 1,
         false => 3,
     };
@@ -192,8 +246,7 @@ where
             neighbors,
             &visited_coord,
             &destination,
-            ignore_units,
-            ignore_doors,
+            options,
             map,
             ecs,
         );
@@ -203,7 +256,7 @@ where
             if closed.contains_key(&neighbor_coord) {
                 continue;
             }
-            let cost = match ecs.has_hazard(neighbor_coord) {
+            let cost = match ecs.has_hazard(neighbor_coord) || map.tile_is_lava(neighbor_coord) {
                 true => hazard_cost,
                 false => 1,
             };
@@ -252,12 +305,7 @@ where
     let return_early = false;
     let origin_h_value = heuristic(origin);
 
-    let neighbors = vec![
-        Coordinate { x: 0, y: 1 },
-        Coordinate { x: 0, y: -1 },
-        Coordinate { x: 1, y: 0 },
-        Coordinate { x: -1, y: 0 },
-    ];
+    let neighbors = map.movement_directions();
 
     let mut open = PriorityQueue::new();
     let mut closed: HashMap<Coordinate, NodeData> = HashMap::new();
@@ -273,9 +321,12 @@ where
         &destination,
         heuristic,
         return_early,
-        ignore_units,
-        ignore_doors,
-        ignore_hazards,
+        PathOptions {
+            ignore_units,
+            ignore_doors,
+            ignore_hazards,
+            explored_only: false,
+        },
         map,
         ecs,
     );
@@ -306,3 +357,39 @@ pub fn astar_heuristic_factory(pl_pos: Coordinate) -> impl Fn(Coordinate) -> usi
         ((coordinate.x - pl_pos.x).abs() + (coordinate.y - pl_pos.y).abs()) as usize
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::testing::{self, PLAYER_START};
+
+    #[test]
+    fn paths_go_the_short_way_around_walls() {
+        let mut game = testing::arena();
+        for y in 1..=6 {
+            testing::wall(&mut game, Coordinate { x: 8, y });
+        }
+        let goal = PLAYER_START + Coordinate { x: 8, y: 0 };
+
+        let path = find_path(PLAYER_START, goal, &game.map, &game.ecs, PathOptions::default()).unwrap();
+        // down under the wall's end at y 7 and back up
+        assert_eq!(path.len(), 8 + 3 + 3);
+        assert_eq!(path.last(), Some(&goal));
+        let mut previous = PLAYER_START;
+        for &tile in &path {
+            assert_eq!(previous.distance(tile), 1.0);
+            assert!(game.map.is_tile_passable(tile));
+            previous = tile;
+        }
+    }
+
+    #[test]
+    fn walled_off_goals_have_no_path() {
+        let mut game = testing::arena();
+        let goal = PLAYER_START + Coordinate { x: 8, y: 0 };
+        for tile in game.map.neighbors(goal) {
+            testing::wall(&mut game, tile);
+        }
+        assert!(find_path(PLAYER_START, goal, &game.map, &game.ecs, PathOptions::default()).is_none());
+    }
+}